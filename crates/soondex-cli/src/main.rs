@@ -0,0 +1,588 @@
+//! Admin tool for Soondex pools. Every command either signs and sends with a
+//! local keypair, or with `--unsigned` prints a base64 transaction for a
+//! multisig to sign.
+
+use std::path::PathBuf;
+
+use anchor_spl::associated_token::{
+    get_associated_token_address_with_program_id, spl_associated_token_account::instruction as ata_instruction,
+};
+use anchor_spl::token_2022::spl_token_2022::{self, extension::StateWithExtensions, state::Mint};
+use anyhow::{anyhow, bail, Context as _, Result};
+use base64::Engine;
+use clap::{Parser, Subcommand};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    message::Message,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::Transaction,
+};
+use soondex::{DcaVault, LimitOrder, LiquidityPool, LongTermOrder, LpVault};
+use soondex_client::{instructions, pda, state, PoolKeys};
+
+#[derive(Parser)]
+#[clap(name = "soondex-cli", version, about)]
+struct Cli {
+    /// RPC endpoint
+    #[clap(long, short = 'u', global = true, default_value = "http://127.0.0.1:8899")]
+    url: String,
+    /// Keypair that signs and pays; defaults to the Solana CLI keypair
+    #[clap(long, short = 'k', global = true)]
+    keypair: Option<PathBuf>,
+    /// Print a base64 transaction for `--authority` to sign instead of sending it
+    #[clap(long, global = true)]
+    unsigned: bool,
+    /// Pool admin, e.g. a multisig vault; defaults to the keypair
+    #[clap(long, global = true)]
+    authority: Option<Pubkey>,
+    /// Fee payer of unsigned transactions; defaults to the authority
+    #[clap(long, global = true, requires = "unsigned")]
+    fee_payer: Option<Pubkey>,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a pool and its vaults; the authority becomes its super admin
+    InitPool {
+        #[clap(long)]
+        mint_a: Pubkey,
+        #[clap(long)]
+        mint_b: Pubkey,
+        /// Fee tier in basis points (1, 5, 25 or 100), part of the pool address
+        #[clap(long)]
+        fee_rate: u64,
+        /// Receives the pool creation fee
+        #[clap(long)]
+        protocol_wallet: Pubkey,
+    },
+    /// Let another key change pool settings
+    AddAdmin {
+        #[clap(long)]
+        pool: Pubkey,
+        admin: Pubkey,
+    },
+    RemoveAdmin {
+        #[clap(long)]
+        pool: Pubkey,
+        admin: Pubkey,
+    },
+    /// Change the swap fee, in basis points; at most the pool's fee tier
+    SetFee {
+        #[clap(long)]
+        pool: Pubkey,
+        swap_fee_rate: u64,
+    },
+    /// Limit swap size and price impact; zero disables a limit
+    SetGuardrails {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Largest swap input as a share of the input reserve, in basis points
+        #[clap(long, default_value_t = 0)]
+        max_input_bps: u64,
+        /// Largest price impact of a single swap, in basis points
+        #[clap(long, default_value_t = 0)]
+        max_price_impact_bps: u64,
+        /// Cap on swap input per slot, summed as basis points of the input reserve
+        #[clap(long, default_value_t = 0)]
+        max_slot_volume_bps: u64,
+    },
+    /// Cap the referral fee swaps may pay integrators
+    SetMaxReferralFee {
+        #[clap(long)]
+        pool: Pubkey,
+        /// In basis points of the swap input
+        max_referral_fee_bps: u64,
+    },
+    /// Change the LP tokens a first deposit must mint
+    SetMinInitialLiquidity {
+        #[clap(long)]
+        pool: Pubkey,
+        min_initial_liquidity: u64,
+    },
+    /// Transfer reward tokens into the pool's reward account
+    FundRewards {
+        #[clap(long)]
+        pool: Pubkey,
+        #[clap(long)]
+        mint: Pubkey,
+        /// Amount in the mint's base units
+        #[clap(long)]
+        amount: u64,
+        /// Token account to fund from; defaults to the authority's associated account
+        #[clap(long)]
+        source: Option<Pubkey>,
+        /// Also set the reward rate, in basis points per second
+        #[clap(long)]
+        reward_rate: Option<u64>,
+    },
+    /// Stop swaps, deposits, staking and flash loans
+    Pause {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Resume a paused pool
+    Unpause {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Set the reserves to the vault balances
+    Sync {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Send vault balances above the reserves to a wallet's associated token accounts
+    Skim {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Defaults to the authority
+        #[clap(long)]
+        recipient: Option<Pubkey>,
+    },
+    /// Fill a limit order whose limit price the pool has reached, collecting the keeper fee
+    FillLimitOrder { order: Pubkey },
+    /// Cancel one of the authority's limit orders and refund its escrow
+    CancelLimitOrder { order: Pubkey },
+    /// Enable long-term orders on a pool
+    InitTwamm {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Settle a pool's long-term orders up to now
+    ExecuteTwamm {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Pay out a long-term order's proceeds; anyone may do this once the order has expired
+    WithdrawLongTermOrder { order: Pubkey },
+    /// Cancel one of the authority's long-term orders, refunding what is unsold
+    CancelLongTermOrder { order: Pubkey },
+    /// Run a DCA vault's next purchase once its interval has elapsed, collecting the keeper fee
+    ExecuteDca { vault: Pubkey },
+    /// Create a pool's auto-compounding LP vault, which sells rewards through `reward_pool`
+    InitLpVault {
+        #[clap(long)]
+        pool: Pubkey,
+        /// Pool pairing the reward mint with one of the pool's tokens
+        #[clap(long)]
+        reward_pool: Pubkey,
+        /// Owner of the token account performance fees are paid to; defaults to the authority
+        #[clap(long)]
+        fee_recipient: Option<Pubkey>,
+        /// Share of harvested rewards kept as a fee, in basis points
+        #[clap(long)]
+        performance_fee_bps: u64,
+        /// Worst price harvest swaps accept, in basis points below the TWAP since the last harvest
+        #[clap(long, default_value_t = 100)]
+        max_slippage_bps: u64,
+    },
+    /// Reinvest a pool's LP vault rewards
+    HarvestLpVault {
+        #[clap(long)]
+        pool: Pubkey,
+    },
+    /// Decode and print a pool
+    InspectPool { pool: Pubkey },
+}
+
+struct Context {
+    rpc: RpcClient,
+    keypair: Option<Keypair>,
+    authority: Pubkey,
+    fee_payer: Pubkey,
+    unsigned: bool,
+}
+
+impl Context {
+    fn new(cli: &Cli) -> Result<Self> {
+        let rpc = RpcClient::new_with_commitment(cli.url.clone(), CommitmentConfig::confirmed());
+        let keypair = if cli.unsigned && cli.authority.is_some() {
+            None
+        } else {
+            let path = match &cli.keypair {
+                Some(path) => path.clone(),
+                None => PathBuf::from(std::env::var("HOME")?).join(".config/solana/id.json"),
+            };
+            let keypair =
+                read_keypair_file(&path).map_err(|error| anyhow!("reading keypair {}: {error}", path.display()))?;
+            Some(keypair)
+        };
+
+        let authority = match (cli.authority, &keypair) {
+            (Some(authority), Some(keypair)) if authority != keypair.pubkey() => {
+                bail!("--authority differs from the keypair; pass --unsigned to build for another signer")
+            }
+            (Some(authority), _) => authority,
+            (None, Some(keypair)) => keypair.pubkey(),
+            (None, None) => unreachable!("a keypair is loaded whenever --authority is omitted"),
+        };
+        let fee_payer = cli.fee_payer.unwrap_or(authority);
+        Ok(Self { rpc, keypair, authority, fee_payer, unsigned: cli.unsigned })
+    }
+
+    /// Sends `instructions`, or prints them as an unsigned transaction
+    fn submit(&self, instructions: &[Instruction]) -> Result<()> {
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let message = Message::new_with_blockhash(instructions, Some(&self.fee_payer), &blockhash);
+        let mut transaction = Transaction::new_unsigned(message);
+
+        if self.unsigned {
+            let bytes = bincode::serialize(&transaction)?;
+            println!("{}", base64::engine::general_purpose::STANDARD.encode(bytes));
+            return Ok(());
+        }
+
+        let keypair = self.keypair.as_ref().expect("signed transactions load the keypair");
+        transaction.try_sign(&[keypair], blockhash)?;
+        let signature = self.rpc.send_and_confirm_transaction_with_spinner(&transaction)?;
+        println!("{signature}");
+        Ok(())
+    }
+
+    fn token_program(&self, mint: &Pubkey) -> Result<Pubkey> {
+        Ok(self.rpc.get_account(mint).with_context(|| format!("fetching mint {mint}"))?.owner)
+    }
+
+    fn pool(&self, address: &Pubkey) -> Result<(PoolKeys, LiquidityPool)> {
+        let account = self.rpc.get_account(address).with_context(|| format!("fetching pool {address}"))?;
+        let pool = state::liquidity_pool(&account.data).map_err(|error| anyhow!("{address} is not a pool: {error}"))?;
+        let keys = PoolKeys::from_pool(
+            &pool,
+            self.token_program(&pool.token_x_mint)?,
+            self.token_program(&pool.token_y_mint)?,
+        );
+        Ok((keys, pool))
+    }
+
+    fn limit_order(&self, address: &Pubkey) -> Result<LimitOrder> {
+        let account = self.rpc.get_account(address).with_context(|| format!("fetching limit order {address}"))?;
+        state::limit_order(&account.data).map_err(|error| anyhow!("{address} is not a limit order: {error}"))
+    }
+
+    fn long_term_order(&self, address: &Pubkey) -> Result<LongTermOrder> {
+        let account = self.rpc.get_account(address).with_context(|| format!("fetching long-term order {address}"))?;
+        state::long_term_order(&account.data).map_err(|error| anyhow!("{address} is not a long-term order: {error}"))
+    }
+
+    fn dca_vault(&self, address: &Pubkey) -> Result<DcaVault> {
+        let account = self.rpc.get_account(address).with_context(|| format!("fetching DCA vault {address}"))?;
+        state::dca_vault(&account.data).map_err(|error| anyhow!("{address} is not a DCA vault: {error}"))
+    }
+
+    fn lp_vault(&self, address: &Pubkey) -> Result<LpVault> {
+        let account = self.rpc.get_account(address).with_context(|| format!("fetching LP vault {address}"))?;
+        state::lp_vault(&account.data).map_err(|error| anyhow!("{address} is not an LP vault: {error}"))
+    }
+
+    fn pool_keys(&self, address: &Pubkey) -> Result<PoolKeys> {
+        Ok(self.pool(address)?.0)
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let context = Context::new(&cli)?;
+    let authority = context.authority;
+
+    match cli.command {
+        Command::InitPool { mint_a, mint_b, fee_rate, protocol_wallet } => {
+            let keys = PoolKeys::new(mint_a, mint_b, fee_rate);
+            let keys = keys
+                .with_token_program(keys.token_x_mint, context.token_program(&keys.token_x_mint)?)
+                .with_token_program(keys.token_y_mint, context.token_program(&keys.token_y_mint)?);
+            let registry = pda::pool_registry_address().0;
+            let pool_count = match context.rpc.get_account_with_commitment(&registry, context.rpc.commitment())?.value {
+                Some(account) => state::pool_registry(&account.data).map_err(|error| anyhow!("{error}"))?.pool_count,
+                None => 0,
+            };
+
+            let pool = keys.address();
+            eprintln!("pool {pool}");
+            context.submit(&[
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &pool,
+                    &keys.token_x_mint,
+                    &keys.token_x_program,
+                ),
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &pool,
+                    &keys.token_y_mint,
+                    &keys.token_y_program,
+                ),
+                instructions::initialize_pool(&keys, authority, protocol_wallet, pool_count),
+            ])
+        }
+        Command::AddAdmin { pool, admin } => {
+            context.submit(&[instructions::manage_admin(&context.pool_keys(&pool)?, authority, admin, true)])
+        }
+        Command::RemoveAdmin { pool, admin } => {
+            context.submit(&[instructions::manage_admin(&context.pool_keys(&pool)?, authority, admin, false)])
+        }
+        Command::SetFee { pool, swap_fee_rate } => {
+            context.submit(&[instructions::set_swap_fee(&context.pool_keys(&pool)?, authority, swap_fee_rate)])
+        }
+        Command::SetGuardrails { pool, max_input_bps, max_price_impact_bps, max_slot_volume_bps } => {
+            context.submit(&[instructions::set_swap_guardrails(
+                &context.pool_keys(&pool)?,
+                authority,
+                max_input_bps,
+                max_price_impact_bps,
+                max_slot_volume_bps,
+            )])
+        }
+        Command::SetMaxReferralFee { pool, max_referral_fee_bps } => context.submit(&[
+            instructions::set_max_referral_fee(&context.pool_keys(&pool)?, authority, max_referral_fee_bps),
+        ]),
+        Command::SetMinInitialLiquidity { pool, min_initial_liquidity } => context.submit(&[
+            instructions::set_min_initial_liquidity(&context.pool_keys(&pool)?, authority, min_initial_liquidity),
+        ]),
+        Command::FundRewards { pool, mint, amount, source, reward_rate } => {
+            let keys = context.pool_keys(&pool)?;
+            let mint_account = context.rpc.get_account(&mint).with_context(|| format!("fetching mint {mint}"))?;
+            let token_program = mint_account.owner;
+            let decimals = StateWithExtensions::<Mint>::unpack(&mint_account.data)?.base.decimals;
+            let source =
+                source.unwrap_or_else(|| get_associated_token_address_with_program_id(&authority, &mint, &token_program));
+            let destination = get_associated_token_address_with_program_id(&pool, &mint, &token_program);
+
+            let mut ixs = vec![
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &pool,
+                    &mint,
+                    &token_program,
+                ),
+                spl_token_2022::instruction::transfer_checked(
+                    &token_program,
+                    &source,
+                    &mint,
+                    &destination,
+                    &authority,
+                    &[],
+                    amount,
+                    decimals,
+                )?,
+            ];
+            if let Some(reward_rate) = reward_rate {
+                ixs.push(instructions::set_reward_rate(&keys, authority, reward_rate));
+            }
+            context.submit(&ixs)
+        }
+        Command::Pause { pool } => {
+            context.submit(&[instructions::set_paused(&context.pool_keys(&pool)?, authority, true)])
+        }
+        Command::Unpause { pool } => {
+            context.submit(&[instructions::set_paused(&context.pool_keys(&pool)?, authority, false)])
+        }
+        Command::Sync { pool } => context.submit(&[instructions::sync(&context.pool_keys(&pool)?, authority)]),
+        Command::Skim { pool, recipient } => {
+            let keys = context.pool_keys(&pool)?;
+            let recipient = recipient.unwrap_or(authority);
+            context.submit(&[
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &recipient,
+                    &keys.token_x_mint,
+                    &keys.token_x_program,
+                ),
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &recipient,
+                    &keys.token_y_mint,
+                    &keys.token_y_program,
+                ),
+                instructions::skim(&keys, authority, recipient),
+            ])
+        }
+        Command::FillLimitOrder { order } => {
+            let limit_order = context.limit_order(&order)?;
+            let keys = context.pool_keys(&limit_order.pool)?;
+            let input_program = keys.token_program(&limit_order.input_mint);
+            let output_program = keys.token_program(&limit_order.output_mint);
+            context.submit(&[
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &authority,
+                    &limit_order.input_mint,
+                    &input_program,
+                ),
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &limit_order.owner,
+                    &limit_order.output_mint,
+                    &output_program,
+                ),
+                instructions::fill_limit_order(
+                    &keys,
+                    authority,
+                    get_associated_token_address_with_program_id(&authority, &limit_order.input_mint, &input_program),
+                    limit_order.owner,
+                    limit_order.input_mint,
+                    limit_order.order_id,
+                ),
+            ])
+        }
+        Command::CancelLimitOrder { order } => {
+            let limit_order = context.limit_order(&order)?;
+            if limit_order.owner != authority {
+                bail!("{order} belongs to {}", limit_order.owner);
+            }
+            let keys = context.pool_keys(&limit_order.pool)?;
+            context.submit(&[instructions::cancel_limit_order(
+                &keys,
+                authority,
+                limit_order.input_mint,
+                limit_order.order_id,
+            )])
+        }
+        Command::InitTwamm { pool } => {
+            context.submit(&[instructions::initialize_twamm(&context.pool_keys(&pool)?, authority)])
+        }
+        Command::ExecuteTwamm { pool } => {
+            context.submit(&[instructions::execute_twamm(&context.pool_keys(&pool)?, authority)])
+        }
+        Command::WithdrawLongTermOrder { order } => {
+            let long_term_order = context.long_term_order(&order)?;
+            let keys = context.pool_keys(&long_term_order.pool)?;
+            context.submit(&[
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &long_term_order.owner,
+                    &long_term_order.output_mint,
+                    &keys.token_program(&long_term_order.output_mint),
+                ),
+                instructions::withdraw_long_term_order(
+                    &keys,
+                    authority,
+                    long_term_order.owner,
+                    long_term_order.input_mint,
+                    long_term_order.order_id,
+                ),
+            ])
+        }
+        Command::CancelLongTermOrder { order } => {
+            let long_term_order = context.long_term_order(&order)?;
+            if long_term_order.owner != authority {
+                bail!("{order} belongs to {}", long_term_order.owner);
+            }
+            let keys = context.pool_keys(&long_term_order.pool)?;
+            context.submit(&[
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &authority,
+                    &long_term_order.output_mint,
+                    &keys.token_program(&long_term_order.output_mint),
+                ),
+                instructions::cancel_long_term_order(
+                    &keys,
+                    authority,
+                    long_term_order.input_mint,
+                    long_term_order.order_id,
+                ),
+            ])
+        }
+        Command::ExecuteDca { vault } => {
+            let dca_vault = context.dca_vault(&vault)?;
+            let keys = context.pool_keys(&dca_vault.pool)?;
+            let input_program = keys.token_program(&dca_vault.input_mint);
+            context.submit(&[
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &authority,
+                    &dca_vault.input_mint,
+                    &input_program,
+                ),
+                instructions::execute_dca(
+                    &keys,
+                    authority,
+                    get_associated_token_address_with_program_id(&authority, &dca_vault.input_mint, &input_program),
+                    dca_vault.owner,
+                    dca_vault.input_mint,
+                    dca_vault.vault_id,
+                ),
+            ])
+        }
+        Command::InitLpVault { pool, reward_pool, fee_recipient, performance_fee_bps, max_slippage_bps } => {
+            context.submit(&[instructions::initialize_lp_vault(
+                &context.pool_keys(&pool)?,
+                authority,
+                &context.pool_keys(&reward_pool)?,
+                fee_recipient.unwrap_or(authority),
+                performance_fee_bps,
+                max_slippage_bps,
+            )])
+        }
+        Command::HarvestLpVault { pool } => {
+            let keys = context.pool_keys(&pool)?;
+            let lp_vault = context.lp_vault(&keys.lp_vault())?;
+            let reward_keys = context.pool_keys(&lp_vault.reward_pool)?;
+            let reward_program = reward_keys.token_program(&lp_vault.reward_mint);
+            context.submit(&[
+                ata_instruction::create_associated_token_account_idempotent(
+                    &context.fee_payer,
+                    &lp_vault.fee_recipient,
+                    &lp_vault.reward_mint,
+                    &reward_program,
+                ),
+                instructions::harvest_lp_vault(
+                    &keys,
+                    authority,
+                    &reward_keys,
+                    get_associated_token_address_with_program_id(
+                        &lp_vault.fee_recipient,
+                        &lp_vault.reward_mint,
+                        &reward_program,
+                    ),
+                ),
+            ])
+        }
+        Command::InspectPool { pool } => {
+            let (keys, liquidity_pool) = context.pool(&pool)?;
+            print_pool(&context, &pool, &keys, &liquidity_pool);
+            Ok(())
+        }
+    }
+}
+
+fn print_pool(context: &Context, address: &Pubkey, keys: &PoolKeys, pool: &LiquidityPool) {
+    let vault_balance = |vault: &Pubkey| {
+        context
+            .rpc
+            .get_token_account_balance(vault)
+            .map(|balance| balance.amount)
+            .unwrap_or_else(|_| "missing".to_string())
+    };
+    let admins: Vec<String> = pool.admins.iter().map(Pubkey::to_string).collect();
+
+    println!("Pool                {address}");
+    println!("Authority           {}", pool.authority);
+    println!("Super admin         {}", pool.super_admin);
+    println!("Admins              {}", admins.join(", "));
+    println!("Token X mint        {} ({})", pool.token_x_mint, keys.token_x_program);
+    println!("Token Y mint        {} ({})", pool.token_y_mint, keys.token_y_program);
+    println!("Fee tier            {} bps", pool.fee_rate);
+    println!("Swap fee            {} bps", pool.swap_fee_rate);
+    println!("Paused              {}", pool.paused);
+    println!("Reserve X           {}", pool.token_x_reserve);
+    println!("Reserve Y           {}", pool.token_y_reserve);
+    println!("Vault X             {} ({})", keys.vault_x(), vault_balance(&keys.vault_x()));
+    println!("Vault Y             {} ({})", keys.vault_y(), vault_balance(&keys.vault_y()));
+    println!("LP supply           {}", pool.lp_token_supply);
+    println!("LP positions        {}", pool.lp_tokens.len());
+    println!("Min first deposit   {} LP", pool.min_initial_liquidity);
+    println!("Max swap input      {} bps of reserve", pool.max_swap_input_bps);
+    println!("Max price impact    {} bps", pool.max_price_impact_bps);
+    println!("Max slot volume     {} bps of reserve", pool.max_slot_volume_bps);
+    println!("Max referral fee    {} bps", pool.max_referral_fee_bps);
+    println!("Long-term orders    {}", pool.twamm_enabled);
+    println!("Reward rate         {} bps/s", pool.reward_rate);
+    println!("Total staked        {}", pool.total_staked);
+    println!("Flash loan active   {}", pool.flash_loan_active);
+    println!("Event sequence      {}", pool.event_sequence);
+}
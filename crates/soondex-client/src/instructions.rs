@@ -0,0 +1,866 @@
+//! Typed builders for every instruction in `mod soondex`
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{instruction::Instruction, system_program, sysvar};
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::associated_token;
+use anchor_spl::token::spl_token::native_mint;
+use soondex::{accounts, instruction};
+
+use crate::pda::{next_pool_registry_page, pool_registry_address, pool_registry_page_address};
+use crate::PoolKeys;
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: soondex::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// The temporary WSOL account is only needed when a side is paid in lamports
+fn temp_wsol_account(
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token_x_account: Option<Pubkey>,
+    user_token_y_account: Option<Pubkey>,
+) -> Option<Pubkey> {
+    (user_token_x_account.is_none() || user_token_y_account.is_none())
+        .then(|| keys.temp_wsol_account(user))
+}
+
+/// `pool_count` is the registry's current count, which selects the page the pool is appended to.
+/// The pool's vaults, `keys.vault_x()` and `keys.vault_y()`, must already exist
+pub fn initialize_pool(keys: &PoolKeys, payer: Pubkey, protocol_wallet: Pubkey, pool_count: u64) -> Instruction {
+    build(
+        accounts::InitializePool {
+            liquidity_pool: keys.address(),
+            pool_registry: pool_registry_address().0,
+            pool_registry_page: pool_registry_page_address(next_pool_registry_page(pool_count)).0,
+            payer,
+            system_program: system_program::ID,
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            pool_token_x_account: keys.vault_x(),
+            pool_token_y_account: keys.vault_y(),
+            protocol_wallet,
+        },
+        instruction::InitializePool {
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            fee_rate: keys.fee_rate,
+        },
+    )
+}
+
+/// `registry_page` is the pool's `LiquidityPool::registry_page`, the page it is unlisted from
+pub fn remove_pool(keys: &PoolKeys, authority: Pubkey, registry_page: u64) -> Instruction {
+    build(
+        accounts::RemovePool {
+            liquidity_pool: keys.address(),
+            pool_registry_page: pool_registry_page_address(registry_page).0,
+            authority,
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+        },
+        instruction::RemovePool {
+            _token_x_mint: keys.token_x_mint,
+            _token_y_mint: keys.token_y_mint,
+        },
+    )
+}
+
+pub fn manage_admin(keys: &PoolKeys, authority: Pubkey, admin_address: Pubkey, is_add: bool) -> Instruction {
+    build(
+        accounts::ManageAdmin {
+            liquidity_pool: keys.address(),
+            authority,
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+        },
+        instruction::ManageAdmin { admin_address, is_add },
+    )
+}
+
+fn update_pool_config(keys: &PoolKeys, authority: Pubkey) -> accounts::UpdatePoolConfig {
+    accounts::UpdatePoolConfig { liquidity_pool: keys.address(), authority }
+}
+
+pub fn set_swap_fee(keys: &PoolKeys, authority: Pubkey, swap_fee_rate: u64) -> Instruction {
+    build(update_pool_config(keys, authority), instruction::SetSwapFee { swap_fee_rate })
+}
+
+pub fn set_reward_rate(keys: &PoolKeys, authority: Pubkey, reward_rate: u64) -> Instruction {
+    build(update_pool_config(keys, authority), instruction::SetRewardRate { reward_rate })
+}
+
+pub fn set_paused(keys: &PoolKeys, authority: Pubkey, paused: bool) -> Instruction {
+    build(update_pool_config(keys, authority), instruction::SetPaused { paused })
+}
+
+/// Swap limits in basis points; zero disables each
+pub fn set_swap_guardrails(
+    keys: &PoolKeys,
+    authority: Pubkey,
+    max_swap_input_bps: u64,
+    max_price_impact_bps: u64,
+    max_slot_volume_bps: u64,
+) -> Instruction {
+    build(
+        update_pool_config(keys, authority),
+        instruction::SetSwapGuardrails { max_swap_input_bps, max_price_impact_bps, max_slot_volume_bps },
+    )
+}
+
+/// Referral fee cap in basis points of the swap input
+pub fn set_max_referral_fee(keys: &PoolKeys, authority: Pubkey, max_referral_fee_bps: u64) -> Instruction {
+    build(update_pool_config(keys, authority), instruction::SetMaxReferralFee { max_referral_fee_bps })
+}
+
+pub fn set_min_initial_liquidity(keys: &PoolKeys, authority: Pubkey, min_initial_liquidity: u64) -> Instruction {
+    build(update_pool_config(keys, authority), instruction::SetMinInitialLiquidity { min_initial_liquidity })
+}
+
+/// User token accounts for a liquidity operation; `None` pays or receives native SOL as lamports
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LiquidityAccounts {
+    pub user_token_x_account: Option<Pubkey>,
+    pub user_token_y_account: Option<Pubkey>,
+}
+
+pub fn add_liquidity(
+    keys: &PoolKeys,
+    user: Pubkey,
+    user_accounts: LiquidityAccounts,
+    amount_x: u64,
+    amount_y: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    let LiquidityAccounts { user_token_x_account, user_token_y_account } = user_accounts;
+    build(
+        accounts::ProvideLiquidity {
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            liquidity_pool: keys.address(),
+            user,
+            user_token_x_account,
+            user_token_y_account,
+            pool_token_x_account: keys.vault_x(),
+            pool_token_y_account: keys.vault_y(),
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+            temp_wsol_account: temp_wsol_account(keys, &user, user_token_x_account, user_token_y_account),
+            system_program: system_program::ID,
+            twamm: keys.twamm_account(),
+        },
+        instruction::AddLiquidity {
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            amount_x,
+            amount_y,
+            deadline,
+        },
+    )
+}
+
+pub fn remove_liquidity(
+    keys: &PoolKeys,
+    user: Pubkey,
+    user_accounts: LiquidityAccounts,
+    amount_x: u64,
+    amount_y: u64,
+    deadline: Option<i64>,
+) -> Instruction {
+    let LiquidityAccounts { user_token_x_account, user_token_y_account } = user_accounts;
+    build(
+        accounts::RemoveLiquidity {
+            liquidity_pool: keys.address(),
+            user,
+            user_token_x_account,
+            user_token_y_account,
+            pool_token_x_account: keys.vault_x(),
+            pool_token_y_account: keys.vault_y(),
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            temp_wsol_account: temp_wsol_account(keys, &user, user_token_x_account, user_token_y_account),
+            system_program: system_program::ID,
+            twamm: keys.twamm_account(),
+        },
+        instruction::RemoveLiquidity {
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            amount_x,
+            amount_y,
+            deadline,
+        },
+    )
+}
+
+/// Swap arguments; a `None` user account pays or receives native SOL as lamports
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapParams {
+    pub input_mint: Pubkey,
+    pub user_token_in: Option<Pubkey>,
+    pub user_token_out: Option<Pubkey>,
+    pub amount_in: u64,
+    pub minimum_amount_out: u64,
+    /// Unix timestamp after which the swap fails with `Expired`
+    pub deadline: Option<i64>,
+    /// Integrator credited with the swap
+    pub referral: Option<Referral>,
+    /// Extra accounts the mints' transfer hooks need, passed ahead of any callback accounts
+    pub transfer_hook_accounts: Vec<AccountMeta>,
+}
+
+/// Referrer's token account for the input mint and the fee it takes from the input
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Referral {
+    pub token_account: Pubkey,
+    pub fee_bps: u64,
+}
+
+/// Flash swap receiver and the accounts forwarded to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlashSwapCallback {
+    pub program: Pubkey,
+    pub data: Vec<u8>,
+    pub accounts: Vec<AccountMeta>,
+}
+
+pub fn swap_tokens(
+    keys: &PoolKeys,
+    user: Pubkey,
+    params: SwapParams,
+    callback: Option<FlashSwapCallback>,
+) -> Instruction {
+    let output_mint = if params.input_mint == keys.token_x_mint {
+        keys.token_y_mint
+    } else {
+        keys.token_x_mint
+    };
+    let needs_temp_wsol = (params.user_token_in.is_none() && callback.is_none())
+        || params.user_token_out.is_none();
+
+    let mut ix = build(
+        accounts::SwapTokens {
+            liquidity_pool: keys.address(),
+            user,
+            user_token_in: params.user_token_in,
+            user_token_out: params.user_token_out,
+            pool_token_x: keys.vault_x(),
+            pool_token_y: keys.vault_y(),
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            temp_wsol_account: needs_temp_wsol.then(|| keys.temp_wsol_account(&user)),
+            system_program: system_program::ID,
+            native_mint: native_mint::ID,
+            callback_program: callback.as_ref().map(|callback| callback.program),
+            twamm: keys.twamm_account(),
+            referrer_token: params.referral.map(|referral| referral.token_account),
+        },
+        instruction::SwapTokens {
+            input_token: params.input_mint,
+            output_token: output_mint,
+            amount_in: params.amount_in,
+            minimum_amount_out: params.minimum_amount_out,
+            callback_data: callback.as_ref().map(|callback| callback.data.clone()),
+            deadline: params.deadline,
+            referral_fee_bps: params.referral.map_or(0, |referral| referral.fee_bps),
+            hook_account_count: params.transfer_hook_accounts.len() as u8,
+        },
+    );
+    ix.accounts.extend(params.transfer_hook_accounts);
+    if let Some(callback) = callback {
+        ix.accounts.extend(callback.accounts);
+    }
+    ix
+}
+
+/// Token accounts used by `stake` and `unstake`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StakeAccounts {
+    pub token_mint: Pubkey,
+    pub user_token_account: Pubkey,
+    pub pool_token_account: Pubkey,
+    pub token_program: Pubkey,
+}
+
+pub fn stake(keys: &PoolKeys, user: Pubkey, stake_accounts: StakeAccounts, amount: u64) -> Instruction {
+    build(
+        accounts::Stake {
+            liquidity_pool: keys.address(),
+            user_state: keys.user_state(&user),
+            user,
+            user_token_account: stake_accounts.user_token_account,
+            pool_token_account: stake_accounts.pool_token_account,
+            token_mint: stake_accounts.token_mint,
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            token_program: stake_accounts.token_program,
+            system_program: system_program::ID,
+        },
+        instruction::Stake { amount },
+    )
+}
+
+pub fn unstake(keys: &PoolKeys, user: Pubkey, stake_accounts: StakeAccounts, amount: u64) -> Instruction {
+    build(
+        accounts::Unstake {
+            liquidity_pool: keys.address(),
+            user_state: keys.user_state(&user),
+            user,
+            user_token_account: stake_accounts.user_token_account,
+            pool_token_account: stake_accounts.pool_token_account,
+            token_mint: stake_accounts.token_mint,
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            token_program: stake_accounts.token_program,
+            system_program: system_program::ID,
+        },
+        instruction::Unstake { amount },
+    )
+}
+
+/// Reward token accounts used by `claim_rewards` and `withdraw_lp_vault`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RewardAccounts {
+    pub reward_mint: Pubkey,
+    pub user_reward_account: Pubkey,
+    pub pool_reward_account: Pubkey,
+    pub token_program: Pubkey,
+}
+
+pub fn claim_rewards(keys: &PoolKeys, user: Pubkey, reward_accounts: RewardAccounts) -> Instruction {
+    build(
+        accounts::ClaimRewards {
+            liquidity_pool: keys.address(),
+            user_state: keys.user_state(&user),
+            user,
+            user_reward_account: reward_accounts.user_reward_account,
+            pool_reward_account: reward_accounts.pool_reward_account,
+            reward_mint: reward_accounts.reward_mint,
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            token_program: reward_accounts.token_program,
+            system_program: system_program::ID,
+        },
+        instruction::ClaimRewards {},
+    )
+}
+
+/// Must be followed by `flash_repay` for the same pool later in the transaction
+pub fn flash_borrow(
+    keys: &PoolKeys,
+    user: Pubkey,
+    user_token_x_account: Pubkey,
+    user_token_y_account: Pubkey,
+    amount_x: u64,
+    amount_y: u64,
+) -> Instruction {
+    build(
+        accounts::FlashBorrow {
+            liquidity_pool: keys.address(),
+            user,
+            user_token_x_account,
+            user_token_y_account,
+            pool_token_x_account: keys.vault_x(),
+            pool_token_y_account: keys.vault_y(),
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+            instructions_sysvar: sysvar::instructions::ID,
+        },
+        instruction::FlashBorrow { amount_x, amount_y },
+    )
+}
+
+pub fn flash_repay(
+    keys: &PoolKeys,
+    user: Pubkey,
+    user_token_x_account: Pubkey,
+    user_token_y_account: Pubkey,
+) -> Instruction {
+    build(
+        accounts::FlashRepay {
+            liquidity_pool: keys.address(),
+            user,
+            user_token_x_account,
+            user_token_y_account,
+            pool_token_x_account: keys.vault_x(),
+            pool_token_y_account: keys.vault_y(),
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+        },
+        instruction::FlashRepay {},
+    )
+}
+
+/// Permissionless; any signer may resync the reserves to the vault balances
+pub fn sync(keys: &PoolKeys, user: Pubkey) -> Instruction {
+    build(
+        accounts::SyncReserves {
+            liquidity_pool: keys.address(),
+            user,
+            pool_token_x_account: keys.vault_x(),
+            pool_token_y_account: keys.vault_y(),
+        },
+        instruction::Sync {},
+    )
+}
+
+/// Sends the vaults' excess over the reserves to `recipient`'s associated token accounts
+pub fn skim(keys: &PoolKeys, user: Pubkey, recipient: Pubkey) -> Instruction {
+    build(
+        accounts::Skim {
+            liquidity_pool: keys.address(),
+            user,
+            pool_token_x_account: keys.vault_x(),
+            pool_token_y_account: keys.vault_y(),
+            recipient_token_x_account: associated_token::get_associated_token_address_with_program_id(
+                &recipient,
+                &keys.token_x_mint,
+                &keys.token_x_program,
+            ),
+            recipient_token_y_account: associated_token::get_associated_token_address_with_program_id(
+                &recipient,
+                &keys.token_y_mint,
+                &keys.token_y_program,
+            ),
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+        },
+        instruction::Skim {},
+    )
+}
+
+/// The owner's associated token account for `mint`
+fn owner_token_account(keys: &PoolKeys, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    associated_token::get_associated_token_address_with_program_id(owner, mint, &keys.token_program(mint))
+}
+
+/// Escrows `amount_in` of `input_mint` from the owner's associated token account
+pub fn place_limit_order(
+    keys: &PoolKeys,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    order_id: u64,
+    amount_in: u64,
+    min_amount_out: u64,
+    expiry: Option<i64>,
+) -> Instruction {
+    build(
+        accounts::PlaceLimitOrder {
+            liquidity_pool: keys.address(),
+            limit_order: keys.limit_order(&owner, order_id),
+            owner,
+            owner_token_in: owner_token_account(keys, &owner, &input_mint),
+            escrow: keys.limit_order_escrow(&owner, order_id, &input_mint),
+            input_mint,
+            input_token_program: keys.token_program(&input_mint),
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::PlaceLimitOrder { order_id, amount_in, min_amount_out, expiry },
+    )
+}
+
+/// Fills `owner`'s order, paying the output to the owner's associated token account and the
+/// keeper fee to `keeper_token_in`
+pub fn fill_limit_order(
+    keys: &PoolKeys,
+    keeper: Pubkey,
+    keeper_token_in: Pubkey,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    order_id: u64,
+) -> Instruction {
+    let output_mint = if input_mint == keys.token_x_mint { keys.token_y_mint } else { keys.token_x_mint };
+    build(
+        accounts::FillLimitOrder {
+            liquidity_pool: keys.address(),
+            limit_order: keys.limit_order(&owner, order_id),
+            owner,
+            owner_token_out: owner_token_account(keys, &owner, &output_mint),
+            escrow: keys.limit_order_escrow(&owner, order_id, &input_mint),
+            keeper,
+            keeper_token_in,
+            pool_token_x: keys.vault_x(),
+            pool_token_y: keys.vault_y(),
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+            twamm: keys.twamm_account(),
+        },
+        instruction::FillLimitOrder {},
+    )
+}
+
+/// Refunds the escrow to the owner's associated token account
+pub fn cancel_limit_order(keys: &PoolKeys, owner: Pubkey, input_mint: Pubkey, order_id: u64) -> Instruction {
+    build(
+        accounts::CancelLimitOrder {
+            liquidity_pool: keys.address(),
+            limit_order: keys.limit_order(&owner, order_id),
+            owner,
+            owner_token_in: owner_token_account(keys, &owner, &input_mint),
+            escrow: keys.limit_order_escrow(&owner, order_id, &input_mint),
+            input_mint,
+            input_token_program: keys.token_program(&input_mint),
+        },
+        instruction::CancelLimitOrder {},
+    )
+}
+
+pub fn initialize_twamm(keys: &PoolKeys, authority: Pubkey) -> Instruction {
+    build(
+        accounts::InitializeTwamm {
+            liquidity_pool: keys.address(),
+            twamm: keys.twamm(),
+            authority,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeTwamm {},
+    )
+}
+
+/// Permissionless; settles the pool's long-term orders up to now
+pub fn execute_twamm(keys: &PoolKeys, user: Pubkey) -> Instruction {
+    build(
+        accounts::ExecuteTwamm { liquidity_pool: keys.address(), twamm: keys.twamm(), user },
+        instruction::ExecuteTwamm {},
+    )
+}
+
+/// Sells `amount_in` of `input_mint` from the owner's associated token account over `intervals`
+/// order intervals
+pub fn place_long_term_order(
+    keys: &PoolKeys,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    order_id: u64,
+    amount_in: u64,
+    intervals: u64,
+) -> Instruction {
+    build(
+        accounts::PlaceLongTermOrder {
+            liquidity_pool: keys.address(),
+            twamm: keys.twamm(),
+            long_term_order: keys.long_term_order(&owner, order_id),
+            owner,
+            owner_token_in: owner_token_account(keys, &owner, &input_mint),
+            pool_token_in: if input_mint == keys.token_x_mint { keys.vault_x() } else { keys.vault_y() },
+            input_mint,
+            input_token_program: keys.token_program(&input_mint),
+            system_program: system_program::ID,
+        },
+        instruction::PlaceLongTermOrder { order_id, amount_in, intervals },
+    )
+}
+
+fn manage_long_term_order_accounts(
+    keys: &PoolKeys,
+    user: Pubkey,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    order_id: u64,
+) -> accounts::ManageLongTermOrder {
+    let output_mint = if input_mint == keys.token_x_mint { keys.token_y_mint } else { keys.token_x_mint };
+    accounts::ManageLongTermOrder {
+        liquidity_pool: keys.address(),
+        twamm: keys.twamm(),
+        long_term_order: keys.long_term_order(&owner, order_id),
+        user,
+        owner,
+        owner_token_in: owner_token_account(keys, &owner, &input_mint),
+        owner_token_out: owner_token_account(keys, &owner, &output_mint),
+        pool_token_x: keys.vault_x(),
+        pool_token_y: keys.vault_y(),
+        token_x_mint: keys.token_x_mint,
+        token_y_mint: keys.token_y_mint,
+        token_x_program: keys.token_x_program,
+        token_y_program: keys.token_y_program,
+    }
+}
+
+/// Pays the order's proceeds to the owner's associated token account. `user` must be the
+/// owner until the order expires
+pub fn withdraw_long_term_order(
+    keys: &PoolKeys,
+    user: Pubkey,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    order_id: u64,
+) -> Instruction {
+    build(
+        manage_long_term_order_accounts(keys, user, owner, input_mint, order_id),
+        instruction::WithdrawLongTermOrder {},
+    )
+}
+
+/// Pays the order's proceeds and refunds its unsold input to the owner's associated token accounts
+pub fn cancel_long_term_order(keys: &PoolKeys, owner: Pubkey, input_mint: Pubkey, order_id: u64) -> Instruction {
+    build(
+        manage_long_term_order_accounts(keys, owner, owner, input_mint, order_id),
+        instruction::CancelLongTermOrder {},
+    )
+}
+
+/// DCA schedule: buy with `amount_per_execution` every `interval` seconds, accepting at
+/// worst `max_slippage_bps` below the pool's TWAP since the previous execution
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DcaSchedule {
+    pub amount_per_execution: u64,
+    pub interval: i64,
+    pub max_slippage_bps: u64,
+}
+
+/// Deposits `amount` of `input_mint` from the owner's associated token account into a new DCA vault
+pub fn open_dca_vault(
+    keys: &PoolKeys,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    vault_id: u64,
+    amount: u64,
+    schedule: DcaSchedule,
+) -> Instruction {
+    let output_mint = if input_mint == keys.token_x_mint { keys.token_y_mint } else { keys.token_x_mint };
+    build(
+        accounts::OpenDcaVault {
+            liquidity_pool: keys.address(),
+            dca_vault: keys.dca_vault(&owner, vault_id),
+            owner,
+            owner_token_in: owner_token_account(keys, &owner, &input_mint),
+            input_vault: keys.dca_vault_token_account(&owner, vault_id, &input_mint),
+            output_vault: keys.dca_vault_token_account(&owner, vault_id, &output_mint),
+            input_mint,
+            output_mint,
+            input_token_program: keys.token_program(&input_mint),
+            output_token_program: keys.token_program(&output_mint),
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::OpenDcaVault {
+            vault_id,
+            amount,
+            amount_per_execution: schedule.amount_per_execution,
+            interval: schedule.interval,
+            max_slippage_bps: schedule.max_slippage_bps,
+        },
+    )
+}
+
+/// Runs `owner`'s next DCA purchase, paying the keeper fee to `keeper_token_in`
+pub fn execute_dca(
+    keys: &PoolKeys,
+    keeper: Pubkey,
+    keeper_token_in: Pubkey,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    vault_id: u64,
+) -> Instruction {
+    let output_mint = if input_mint == keys.token_x_mint { keys.token_y_mint } else { keys.token_x_mint };
+    build(
+        accounts::ExecuteDca {
+            liquidity_pool: keys.address(),
+            dca_vault: keys.dca_vault(&owner, vault_id),
+            input_vault: keys.dca_vault_token_account(&owner, vault_id, &input_mint),
+            output_vault: keys.dca_vault_token_account(&owner, vault_id, &output_mint),
+            keeper,
+            keeper_token_in,
+            pool_token_x: keys.vault_x(),
+            pool_token_y: keys.vault_y(),
+            token_x_mint: keys.token_x_mint,
+            token_y_mint: keys.token_y_mint,
+            token_x_program: keys.token_x_program,
+            token_y_program: keys.token_y_program,
+            twamm: keys.twamm_account(),
+        },
+        instruction::ExecuteDca {},
+    )
+}
+
+fn manage_dca_vault_accounts(
+    keys: &PoolKeys,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    vault_id: u64,
+) -> accounts::ManageDcaVault {
+    let output_mint = if input_mint == keys.token_x_mint { keys.token_y_mint } else { keys.token_x_mint };
+    accounts::ManageDcaVault {
+        liquidity_pool: keys.address(),
+        dca_vault: keys.dca_vault(&owner, vault_id),
+        owner,
+        owner_token_in: owner_token_account(keys, &owner, &input_mint),
+        owner_token_out: owner_token_account(keys, &owner, &output_mint),
+        input_vault: keys.dca_vault_token_account(&owner, vault_id, &input_mint),
+        output_vault: keys.dca_vault_token_account(&owner, vault_id, &output_mint),
+        input_mint,
+        output_mint,
+        input_token_program: keys.token_program(&input_mint),
+        output_token_program: keys.token_program(&output_mint),
+    }
+}
+
+/// Sends everything the vault has bought and `input_amount` of its unspent input to the
+/// owner's associated token accounts
+pub fn withdraw_dca_vault(
+    keys: &PoolKeys,
+    owner: Pubkey,
+    input_mint: Pubkey,
+    vault_id: u64,
+    input_amount: u64,
+) -> Instruction {
+    build(
+        manage_dca_vault_accounts(keys, owner, input_mint, vault_id),
+        instruction::WithdrawDcaVault { input_amount },
+    )
+}
+
+/// Empties the vault into the owner's associated token accounts and closes it
+pub fn close_dca_vault(keys: &PoolKeys, owner: Pubkey, input_mint: Pubkey, vault_id: u64) -> Instruction {
+    build(manage_dca_vault_accounts(keys, owner, input_mint, vault_id), instruction::CloseDcaVault {})
+}
+
+/// Splits the reward pool's mints into (reward mint, bridge mint), where the bridge mint
+/// is the one the reward pool shares with `keys`
+fn reward_route(keys: &PoolKeys, reward_keys: &PoolKeys) -> (Pubkey, Pubkey) {
+    let x_is_bridge = reward_keys.token_x_mint == keys.token_x_mint || reward_keys.token_x_mint == keys.token_y_mint;
+    if x_is_bridge {
+        (reward_keys.token_y_mint, reward_keys.token_x_mint)
+    } else {
+        (reward_keys.token_x_mint, reward_keys.token_y_mint)
+    }
+}
+
+/// Creates the pool's LP vault, whose harvests sell rewards through `reward_keys`' pool
+pub fn initialize_lp_vault(
+    keys: &PoolKeys,
+    authority: Pubkey,
+    reward_keys: &PoolKeys,
+    fee_recipient: Pubkey,
+    performance_fee_bps: u64,
+    max_slippage_bps: u64,
+) -> Instruction {
+    let (reward_mint, _) = reward_route(keys, reward_keys);
+    build(
+        accounts::InitializeLpVault {
+            liquidity_pool: keys.address(),
+            lp_vault: keys.lp_vault(),
+            reward_pool: reward_keys.address(),
+            reward_mint,
+            authority,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeLpVault { fee_recipient, performance_fee_bps, max_slippage_bps },
+    )
+}
+
+/// Moves `lp_amount` of the owner's LP position into the pool's LP vault
+pub fn deposit_lp_vault(keys: &PoolKeys, owner: Pubkey, lp_amount: u64) -> Instruction {
+    build(
+        accounts::ManageLpVault {
+            liquidity_pool: keys.address(),
+            lp_vault: keys.lp_vault(),
+            vault_position: keys.vault_position(&owner),
+            owner,
+            system_program: system_program::ID,
+        },
+        instruction::DepositLpVault { lp_amount },
+    )
+}
+
+/// Redeems `shares` of the pool's LP vault into the owner's LP position, paying the
+/// position's pending rewards in the vault's reward mint to `reward_accounts`
+pub fn withdraw_lp_vault(keys: &PoolKeys, owner: Pubkey, reward_accounts: RewardAccounts, shares: u64) -> Instruction {
+    build(
+        accounts::WithdrawLpVault {
+            liquidity_pool: keys.address(),
+            lp_vault: keys.lp_vault(),
+            vault_position: keys.vault_position(&owner),
+            owner,
+            owner_reward_account: reward_accounts.user_reward_account,
+            pool_reward_account: reward_accounts.pool_reward_account,
+            reward_mint: reward_accounts.reward_mint,
+            token_program: reward_accounts.token_program,
+        },
+        instruction::WithdrawLpVault { shares },
+    )
+}
+
+/// Reinvests the pool's LP vault rewards, paying the performance fee to `fee_recipient_token`
+pub fn harvest_lp_vault(
+    keys: &PoolKeys,
+    keeper: Pubkey,
+    reward_keys: &PoolKeys,
+    fee_recipient_token: Pubkey,
+) -> Instruction {
+    let (reward_mint, bridge_mint) = reward_route(keys, reward_keys);
+    let reward_token_program = reward_keys.token_program(&reward_mint);
+    let bridge_token_program = keys.token_program(&bridge_mint);
+    let reward_vault = |mint: &Pubkey| {
+        if *mint == reward_keys.token_x_mint {
+            reward_keys.vault_x()
+        } else {
+            reward_keys.vault_y()
+        }
+    };
+    build(
+        accounts::HarvestLpVault {
+            liquidity_pool: keys.address(),
+            lp_vault: keys.lp_vault(),
+            reward_pool: reward_keys.address(),
+            keeper,
+            pool_reward_account: keys.pool_token_account(&reward_mint, &reward_token_program),
+            fee_recipient_token,
+            reward_pool_token_in: reward_vault(&reward_mint),
+            reward_pool_token_out: reward_vault(&bridge_mint),
+            pool_token_bridge: if bridge_mint == keys.token_x_mint { keys.vault_x() } else { keys.vault_y() },
+            reward_mint,
+            bridge_mint,
+            reward_token_program,
+            bridge_token_program,
+            twamm: keys.twamm_account(),
+            reward_twamm: reward_keys.twamm_account(),
+        },
+        instruction::HarvestLpVault {},
+    )
+}
+
+fn quote_accounts(keys: &PoolKeys) -> accounts::Quote {
+    accounts::Quote {
+        liquidity_pool: keys.address(),
+        token_x_mint: keys.token_x_mint,
+        token_y_mint: keys.token_y_mint,
+        twamm: keys.twamm_account(),
+    }
+}
+
+/// Simulate and decode the result with [`crate::state::return_data`]
+/// `referral_fee_bps` is the referral fee the swap would pay, zero without a referrer
+pub fn quote_swap(keys: &PoolKeys, input_token: Pubkey, amount_in: u64, referral_fee_bps: u64) -> Instruction {
+    build(quote_accounts(keys), instruction::QuoteSwap { input_token, amount_in, referral_fee_bps })
+}
+
+pub fn quote_swap_exact_out(keys: &PoolKeys, input_token: Pubkey, amount_out: u64) -> Instruction {
+    build(quote_accounts(keys), instruction::QuoteSwapExactOut { input_token, amount_out })
+}
+
+pub fn quote_add_liquidity(keys: &PoolKeys, amount_x: u64, amount_y: u64) -> Instruction {
+    build(quote_accounts(keys), instruction::QuoteAddLiquidity { amount_x, amount_y })
+}
+
+pub fn quote_remove_liquidity(keys: &PoolKeys, amount_x: u64, amount_y: u64) -> Instruction {
+    build(quote_accounts(keys), instruction::QuoteRemoveLiquidity { amount_x, amount_y })
+}
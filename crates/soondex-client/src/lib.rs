@@ -0,0 +1,10 @@
+//! Off-chain client for the Soondex program: PDA derivation, instruction
+//! builders, account deserializers and quotes that use the on-chain math.
+
+pub mod instructions;
+pub mod pda;
+pub mod quote;
+pub mod state;
+
+pub use pda::PoolKeys;
+pub use soondex::{self, ID as PROGRAM_ID};
@@ -0,0 +1,232 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token;
+use soondex::{
+    LiquidityPool, DCA_VAULT_SEED, LIMIT_ORDER_SEED, LOCKED_LIQUIDITY_SEED, LONG_TERM_ORDER_SEED, LP_VAULT_SEED,
+    POOLS_PER_REGISTRY_PAGE, POOL_REGISTRY_SEED, POOL_SEED, TEMP_WSOL_SEED, TWAMM_SEED, USER_STATE_SEED,
+    VAULT_POSITION_SEED,
+};
+
+/// Returns the two mints in the order the program requires (`token_x_mint < token_y_mint`)
+pub fn canonical_mints(mint_a: Pubkey, mint_b: Pubkey) -> (Pubkey, Pubkey) {
+    if mint_a < mint_b {
+        (mint_a, mint_b)
+    } else {
+        (mint_b, mint_a)
+    }
+}
+
+pub fn pool_address(token_x_mint: &Pubkey, token_y_mint: &Pubkey, fee_rate: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            POOL_SEED,
+            token_x_mint.as_ref(),
+            token_y_mint.as_ref(),
+            fee_rate.to_le_bytes().as_ref(),
+        ],
+        &soondex::ID,
+    )
+}
+
+pub fn user_state_address(pool: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[USER_STATE_SEED, pool.as_ref(), user.as_ref()], &soondex::ID)
+}
+
+/// Pool vaults are the pool's associated token accounts
+pub fn vault_address(pool: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(pool, mint, token_program)
+}
+
+pub fn temp_wsol_address(pool: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TEMP_WSOL_SEED, pool.as_ref(), user.as_ref()], &soondex::ID)
+}
+
+/// Owner of the `MINIMUM_LIQUIDITY` position locked by the pool's first deposit
+pub fn locked_liquidity_address(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LOCKED_LIQUIDITY_SEED, pool.as_ref()], &soondex::ID)
+}
+
+pub fn limit_order_address(pool: &Pubkey, owner: &Pubkey, order_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[LIMIT_ORDER_SEED, pool.as_ref(), owner.as_ref(), order_id.to_le_bytes().as_ref()],
+        &soondex::ID,
+    )
+}
+
+/// A limit order's escrow is the order's associated token account for the input mint
+pub fn limit_order_escrow_address(order: &Pubkey, input_mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(order, input_mint, token_program)
+}
+
+pub fn twamm_address(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TWAMM_SEED, pool.as_ref()], &soondex::ID)
+}
+
+pub fn long_term_order_address(pool: &Pubkey, owner: &Pubkey, order_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[LONG_TERM_ORDER_SEED, pool.as_ref(), owner.as_ref(), order_id.to_le_bytes().as_ref()],
+        &soondex::ID,
+    )
+}
+
+pub fn dca_vault_address(pool: &Pubkey, owner: &Pubkey, vault_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DCA_VAULT_SEED, pool.as_ref(), owner.as_ref(), vault_id.to_le_bytes().as_ref()],
+        &soondex::ID,
+    )
+}
+
+pub fn lp_vault_address(pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LP_VAULT_SEED, pool.as_ref()], &soondex::ID)
+}
+
+pub fn vault_position_address(vault: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_POSITION_SEED, vault.as_ref(), owner.as_ref()], &soondex::ID)
+}
+
+pub fn pool_registry_address() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_REGISTRY_SEED], &soondex::ID)
+}
+
+pub fn pool_registry_page_address(page: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_REGISTRY_SEED, page.to_le_bytes().as_ref()], &soondex::ID)
+}
+
+/// Registry page the next pool is appended to, given the registry's current `pool_count`
+pub fn next_pool_registry_page(pool_count: u64) -> u64 {
+    pool_count / POOLS_PER_REGISTRY_PAGE
+}
+
+/// Everything needed to address a pool and its vaults
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolKeys {
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub fee_rate: u64,
+    pub token_x_program: Pubkey,
+    pub token_y_program: Pubkey,
+    /// Whether the pool has long-term orders, whose state trading instructions must pass
+    pub twamm_enabled: bool,
+}
+
+impl PoolKeys {
+    /// Orders the mints canonically; both sides default to the legacy token program
+    pub fn new(mint_a: Pubkey, mint_b: Pubkey, fee_rate: u64) -> Self {
+        let (token_x_mint, token_y_mint) = canonical_mints(mint_a, mint_b);
+        Self {
+            token_x_mint,
+            token_y_mint,
+            fee_rate,
+            token_x_program: token::ID,
+            token_y_program: token::ID,
+            twamm_enabled: false,
+        }
+    }
+
+    /// Keys of an existing pool account
+    pub fn from_pool(pool: &LiquidityPool, token_x_program: Pubkey, token_y_program: Pubkey) -> Self {
+        Self {
+            token_x_mint: pool.token_x_mint,
+            token_y_mint: pool.token_y_mint,
+            fee_rate: pool.fee_rate,
+            token_x_program,
+            token_y_program,
+            twamm_enabled: pool.twamm_enabled,
+        }
+    }
+
+    /// Sets the token program owning `mint`, e.g. Token-2022
+    pub fn with_token_program(mut self, mint: Pubkey, token_program: Pubkey) -> Self {
+        if mint == self.token_x_mint {
+            self.token_x_program = token_program;
+        }
+        if mint == self.token_y_mint {
+            self.token_y_program = token_program;
+        }
+        self
+    }
+
+    pub fn address(&self) -> Pubkey {
+        pool_address(&self.token_x_mint, &self.token_y_mint, self.fee_rate).0
+    }
+
+    pub fn vault_x(&self) -> Pubkey {
+        vault_address(&self.address(), &self.token_x_mint, &self.token_x_program)
+    }
+
+    pub fn vault_y(&self) -> Pubkey {
+        vault_address(&self.address(), &self.token_y_mint, &self.token_y_program)
+    }
+
+    pub fn user_state(&self, user: &Pubkey) -> Pubkey {
+        user_state_address(&self.address(), user).0
+    }
+
+    pub fn temp_wsol_account(&self, user: &Pubkey) -> Pubkey {
+        temp_wsol_address(&self.address(), user).0
+    }
+
+    pub fn locked_liquidity(&self) -> Pubkey {
+        locked_liquidity_address(&self.address()).0
+    }
+
+    /// Token program of the pool's `mint` side
+    pub fn token_program(&self, mint: &Pubkey) -> Pubkey {
+        if *mint == self.token_x_mint {
+            self.token_x_program
+        } else {
+            self.token_y_program
+        }
+    }
+
+    pub fn limit_order(&self, owner: &Pubkey, order_id: u64) -> Pubkey {
+        limit_order_address(&self.address(), owner, order_id).0
+    }
+
+    pub fn limit_order_escrow(&self, owner: &Pubkey, order_id: u64, input_mint: &Pubkey) -> Pubkey {
+        limit_order_escrow_address(&self.limit_order(owner, order_id), input_mint, &self.token_program(input_mint))
+    }
+
+    pub fn twamm(&self) -> Pubkey {
+        twamm_address(&self.address()).0
+    }
+
+    /// The TWAMM state trading instructions pass, if the pool has one
+    pub fn twamm_account(&self) -> Option<Pubkey> {
+        self.twamm_enabled.then(|| self.twamm())
+    }
+
+    pub fn long_term_order(&self, owner: &Pubkey, order_id: u64) -> Pubkey {
+        long_term_order_address(&self.address(), owner, order_id).0
+    }
+
+    pub fn dca_vault(&self, owner: &Pubkey, vault_id: u64) -> Pubkey {
+        dca_vault_address(&self.address(), owner, vault_id).0
+    }
+
+    /// A DCA vault keeps its balances in its associated token accounts
+    pub fn dca_vault_token_account(&self, owner: &Pubkey, vault_id: u64, mint: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(&self.dca_vault(owner, vault_id), mint, &self.token_program(mint))
+    }
+
+    pub fn lp_vault(&self) -> Pubkey {
+        lp_vault_address(&self.address()).0
+    }
+
+    pub fn vault_position(&self, owner: &Pubkey) -> Pubkey {
+        vault_position_address(&self.lp_vault(), owner).0
+    }
+
+    /// The pool's associated token account for `mint`, e.g. its reward vault
+    pub fn pool_token_account(&self, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+        vault_address(&self.address(), mint, token_program)
+    }
+
+    /// The user's associated token accounts for the X and Y mints
+    pub fn user_token_accounts(&self, user: &Pubkey) -> (Pubkey, Pubkey) {
+        (
+            get_associated_token_address_with_program_id(user, &self.token_x_mint, &self.token_x_program),
+            get_associated_token_address_with_program_id(user, &self.token_y_mint, &self.token_y_program),
+        )
+    }
+}
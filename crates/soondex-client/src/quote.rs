@@ -0,0 +1,81 @@
+//! Quotes computed locally with the program's own math. These ignore Token-2022
+//! transfer fees; simulate the `quote_*` instructions for mints that charge them.
+//!
+//! Swaps in a pool with long-term orders first settle the sales pending since the
+//! last execution, so quote from a pool read after `execute_twamm` (sent in the same
+//! transaction as the swap for an exact match), or simulate the `quote_*`
+//! instructions, which settle them first. A referral fee comes out of the input
+//! before the swap: quote `amount_in` minus `soondex_math::swap_fee(amount_in, fee_bps)`
+//! here, or pass `fee_bps` to the `quote_swap` instruction.
+
+use anchor_lang::prelude::*;
+use soondex::{
+    AddLiquidityQuote, ErrorCode, LiquidityPool, RemoveLiquidityQuote, SwapQuote, UserState,
+    FLASH_LOAN_FEE_RATE,
+};
+
+fn swap_reserves(pool: &LiquidityPool, input_mint: &Pubkey) -> Result<(u64, u64)> {
+    if *input_mint == pool.token_x_mint {
+        Ok((pool.token_x_reserve, pool.token_y_reserve))
+    } else if *input_mint == pool.token_y_mint {
+        Ok((pool.token_y_reserve, pool.token_x_reserve))
+    } else {
+        err!(ErrorCode::InvalidToken)
+    }
+}
+
+pub fn swap(pool: &LiquidityPool, input_mint: &Pubkey, amount_in: u64) -> Result<SwapQuote> {
+    let (reserve_in, reserve_out) = swap_reserves(pool, input_mint)?;
+    let fee_amount = soondex_math::swap_fee(amount_in, pool.swap_fee_rate).map_err(ErrorCode::from)?;
+    let amount_out = soondex_math::swap_output(amount_in - fee_amount, reserve_in, reserve_out)
+        .map_err(ErrorCode::from)?;
+
+    Ok(SwapQuote {
+        amount_in,
+        amount_in_received: amount_in,
+        fee_amount,
+        amount_out,
+        amount_out_received: amount_out,
+    })
+}
+
+pub fn swap_exact_out(pool: &LiquidityPool, input_mint: &Pubkey, amount_out: u64) -> Result<SwapQuote> {
+    let (reserve_in, reserve_out) = swap_reserves(pool, input_mint)?;
+    let amount_in = soondex_math::swap_input(amount_out, reserve_in, reserve_out, pool.swap_fee_rate)
+        .map_err(ErrorCode::from)?;
+    swap(pool, input_mint, amount_in)
+}
+
+pub fn add_liquidity(pool: &LiquidityPool, amount_x: u64, amount_y: u64) -> Result<AddLiquidityQuote> {
+    require!(amount_x > 0 && amount_y > 0, ErrorCode::InvalidLiquidityAmount);
+
+    Ok(AddLiquidityQuote {
+        amount_x_received: amount_x,
+        amount_y_received: amount_y,
+        lp_tokens: pool.lp_tokens_for_deposit(amount_x, amount_y)?.0,
+    })
+}
+
+pub fn remove_liquidity(pool: &LiquidityPool, amount_x: u64, amount_y: u64) -> Result<RemoveLiquidityQuote> {
+    Ok(RemoveLiquidityQuote {
+        lp_tokens: pool.lp_tokens_to_burn(amount_x, amount_y)?,
+        amount_x_received: amount_x,
+        amount_y_received: amount_y,
+    })
+}
+
+/// Rewards `unstake` would pay out at `now`. It pays everything the stake has earned,
+/// however much is unstaked
+pub fn unstake_rewards(pool: &LiquidityPool, user_state: &UserState, now: i64) -> Result<u64> {
+    let reward_per_token_delta = pool
+        .reward_per_token_cumulative_at(now)?
+        .checked_sub(user_state.reward_checkpoint)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let rewards = soondex_math::accrued_rewards(user_state.amount_staked, reward_per_token_delta)
+        .map_err(ErrorCode::from)?;
+    Ok(rewards.checked_add(user_state.rewards_earned).ok_or(ErrorCode::MathOverflow)?)
+}
+
+pub fn flash_loan_fee(amount: u64) -> Result<u64> {
+    Ok(soondex_math::flash_loan_fee(amount, FLASH_LOAN_FEE_RATE).map_err(ErrorCode::from)?)
+}
@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use soondex::{
+    DcaVault, LimitOrder, LiquidityPool, LongTermOrder, LpVault, PoolRegistry, PoolRegistryPage, TwammState,
+    UserState, VaultPosition,
+};
+
+/// Deserializes an Anchor account, checking its discriminator
+pub fn deserialize_account<T: AccountDeserialize>(data: &[u8]) -> Result<T> {
+    let mut data = data;
+    T::try_deserialize(&mut data)
+}
+
+pub fn liquidity_pool(data: &[u8]) -> Result<LiquidityPool> {
+    deserialize_account(data)
+}
+
+pub fn user_state(data: &[u8]) -> Result<UserState> {
+    deserialize_account(data)
+}
+
+pub fn limit_order(data: &[u8]) -> Result<LimitOrder> {
+    deserialize_account(data)
+}
+
+pub fn twamm_state(data: &[u8]) -> Result<TwammState> {
+    deserialize_account(data)
+}
+
+pub fn long_term_order(data: &[u8]) -> Result<LongTermOrder> {
+    deserialize_account(data)
+}
+
+pub fn dca_vault(data: &[u8]) -> Result<DcaVault> {
+    deserialize_account(data)
+}
+
+pub fn lp_vault(data: &[u8]) -> Result<LpVault> {
+    deserialize_account(data)
+}
+
+pub fn vault_position(data: &[u8]) -> Result<VaultPosition> {
+    deserialize_account(data)
+}
+
+pub fn pool_registry(data: &[u8]) -> Result<PoolRegistry> {
+    deserialize_account(data)
+}
+
+pub fn pool_registry_page(data: &[u8]) -> Result<PoolRegistryPage> {
+    deserialize_account(data)
+}
+
+/// Decodes the return data of a simulated quote instruction
+pub fn return_data<T: AnchorDeserialize>(data: &[u8]) -> Result<T> {
+    T::try_from_slice(data).map_err(|_| ErrorCode::AccountDidNotDeserialize.into())
+}
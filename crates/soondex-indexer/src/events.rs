@@ -0,0 +1,81 @@
+//! Decoding of the program's `#[event]` types from `emit!` log data
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use soondex::{
+    AdminUpdated, DcaExecuted, DcaVaultOpened, DcaVaultWithdrawn, ExcessSkimmed, FlashLoanBorrowed, FlashLoanRepaid,
+    LimitOrderCancelled, LimitOrderFilled, LimitOrderPlaced, LiquidityProvided, LiquidityRemoved, LongTermOrderPlaced,
+    LongTermOrderWithdrawn, LpVaultDeposited, LpVaultHarvested, LpVaultInitialized, LpVaultWithdrawn,
+    PoolConfigUpdated, PoolInitialized, PoolRemovedEvent, ReservesSynced, RewardsClaimed, TokensStaked, TokensSwapped,
+    TokensUnstaked, VirtualOrdersExecuted,
+};
+
+macro_rules! pool_events {
+    ($($event:ident),* $(,)?) => {
+        /// Every event the program emits
+        pub enum PoolEvent {
+            $($event($event),)*
+        }
+
+        impl PoolEvent {
+            /// Decodes `discriminator || borsh(event)`; `None` for data that is not a Soondex event
+            pub fn decode(data: &[u8]) -> Option<Self> {
+                let (discriminator, payload) = (data.get(..8)?, data.get(8..)?);
+                $(
+                    if discriminator == $event::DISCRIMINATOR {
+                        return $event::try_from_slice(payload).ok().map(PoolEvent::$event);
+                    }
+                )*
+                None
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(PoolEvent::$event(_) => stringify!($event),)*
+                }
+            }
+
+            pub fn pool(&self) -> Pubkey {
+                match self {
+                    $(PoolEvent::$event(event) => event.pool,)*
+                }
+            }
+
+            pub fn sequence(&self) -> u64 {
+                match self {
+                    $(PoolEvent::$event(event) => event.sequence,)*
+                }
+            }
+        }
+    };
+}
+
+pool_events!(
+    PoolInitialized,
+    PoolRemovedEvent,
+    AdminUpdated,
+    PoolConfigUpdated,
+    LiquidityProvided,
+    LiquidityRemoved,
+    TokensSwapped,
+    TokensStaked,
+    TokensUnstaked,
+    RewardsClaimed,
+    FlashLoanBorrowed,
+    FlashLoanRepaid,
+    ReservesSynced,
+    ExcessSkimmed,
+    LimitOrderPlaced,
+    LimitOrderFilled,
+    LimitOrderCancelled,
+    VirtualOrdersExecuted,
+    LongTermOrderPlaced,
+    LongTermOrderWithdrawn,
+    DcaVaultOpened,
+    DcaExecuted,
+    DcaVaultWithdrawn,
+    LpVaultInitialized,
+    LpVaultDeposited,
+    LpVaultWithdrawn,
+    LpVaultHarvested,
+);
@@ -0,0 +1,10 @@
+//! Decodes Soondex program events from transaction logs into SQLite: raw
+//! event history, per-pool state, OHLCV candles and sequence gaps.
+
+pub mod events;
+pub mod logs;
+pub mod store;
+
+pub use events::PoolEvent;
+pub use logs::{program_events, TransactionLogs};
+pub use store::{Candle, Database, PoolStats, DEFAULT_RESOLUTIONS};
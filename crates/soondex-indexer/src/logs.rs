@@ -0,0 +1,80 @@
+//! Transaction logs, from RPC or a recorded JSON-lines fixture, and the events
+//! they carry
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::events::PoolEvent;
+
+/// One successful transaction's log messages; a fixture holds one per line
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TransactionLogs {
+    pub signature: String,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    pub logs: Vec<String>,
+}
+
+/// Events emitted by the program itself, in log order. Data logged by any
+/// other program in the transaction, including Soondex's CPI targets, is skipped
+pub fn program_events(logs: &[String]) -> Vec<PoolEvent> {
+    let program = soondex::ID.to_string();
+    let mut invocations: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        let Some(message) = line.strip_prefix("Program ") else {
+            continue;
+        };
+        if let Some(data) = message.strip_prefix("data: ") {
+            if invocations.last() == Some(&program.as_str()) {
+                if let Some(event) = STANDARD.decode(data).ok().and_then(|bytes| PoolEvent::decode(&bytes)) {
+                    events.push(event);
+                }
+            }
+            continue;
+        }
+
+        let mut words = message.split(' ');
+        match (words.next(), words.next()) {
+            (Some(program_id), Some("invoke")) => invocations.push(program_id),
+            (Some(_), Some("success" | "failed:")) => {
+                invocations.pop();
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+pub fn read_fixture(path: &Path) -> Result<Vec<TransactionLogs>> {
+    let contents = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| format!("{} line {}", path.display(), index + 1))
+        })
+        .collect()
+}
+
+/// Appends `transactions` to a fixture so a live run can be replayed later
+pub fn append_fixture(path: &Path, transactions: &[TransactionLogs]) -> Result<()> {
+    let file = File::options()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    for transaction in transactions {
+        serde_json::to_writer(&mut writer, transaction)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(writer.flush()?)
+}
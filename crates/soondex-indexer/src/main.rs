@@ -0,0 +1,152 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use solana_transaction_status::UiTransactionEncoding;
+use soondex_indexer::{logs, Database, TransactionLogs, DEFAULT_RESOLUTIONS};
+
+/// Indexes Soondex program events into SQLite
+#[derive(Parser)]
+#[clap(name = "soondex-indexer", version, about)]
+struct Cli {
+    /// SQLite database, created if missing
+    #[clap(long, global = true, default_value = "soondex.db")]
+    db: PathBuf,
+    /// Candle widths in seconds
+    #[clap(long, global = true, value_delimiter = ',', default_values_t = DEFAULT_RESOLUTIONS.to_vec())]
+    resolutions: Vec<i64>,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Index a recorded JSON-lines log, one transaction per line
+    Replay { log: PathBuf },
+    /// Index transactions newer than the last run from an RPC node
+    Rpc {
+        #[clap(long, short = 'u', default_value = "http://127.0.0.1:8899")]
+        url: String,
+        /// Also append every fetched transaction to this log for later replay
+        #[clap(long)]
+        record: Option<PathBuf>,
+        /// Keep polling at this interval in seconds instead of exiting
+        #[clap(long)]
+        follow: Option<u64>,
+    },
+    /// Print TVL, volume, fees and fee APR per pool
+    Stats {
+        /// Trailing window in seconds, ending at the newest indexed block time
+        #[clap(long, default_value_t = 86_400)]
+        window: i64,
+    },
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut db = Database::open(&cli.db, &cli.resolutions)?;
+
+    match cli.command {
+        Command::Replay { log } => {
+            let transactions = logs::read_fixture(&log)?;
+            let mut indexed = 0;
+            for transaction in &transactions {
+                indexed += db.index_transaction(transaction)?;
+            }
+            eprintln!("indexed {indexed} events from {} transactions", transactions.len());
+        }
+        Command::Rpc { url, record, follow } => {
+            let rpc = RpcClient::new_with_commitment(url, CommitmentConfig::confirmed());
+            loop {
+                let (transactions, newest) = fetch_new_transactions(&rpc, db.cursor()?)?;
+                if let Some(record) = &record {
+                    logs::append_fixture(record, &transactions)?;
+                }
+                let mut indexed = 0;
+                for transaction in &transactions {
+                    indexed += db.index_transaction(transaction)?;
+                    db.set_cursor(&transaction.signature)?;
+                }
+                // Failed transactions are skipped but still move the cursor past them
+                if let Some(newest) = newest {
+                    db.set_cursor(&newest)?;
+                }
+                eprintln!("indexed {indexed} events from {} transactions", transactions.len());
+                match follow {
+                    Some(seconds) => thread::sleep(Duration::from_secs(seconds)),
+                    None => break,
+                }
+            }
+        }
+        Command::Stats { window } => {
+            println!(
+                "{:<44} {:>20} {:>20} {:>14} {:>20} {:>20} {:>20} {:>8} {:>10}",
+                "pool", "reserve_x", "reserve_y", "price", "tvl_y", "volume_x", "volume_y", "swaps", "fee_apr_%",
+            );
+            for stats in db.pool_stats(window)? {
+                println!(
+                    "{:<44} {:>20} {:>20} {:>14.6} {:>20.0} {:>20} {:>20} {:>8} {:>10.2}",
+                    stats.pool,
+                    stats.token_x_reserve,
+                    stats.token_y_reserve,
+                    stats.price,
+                    stats.tvl_y,
+                    stats.volume_x,
+                    stats.volume_y,
+                    stats.swaps,
+                    stats.fee_apr * 100.0,
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Successful program transactions after `cursor`, oldest first, and the
+/// newest signature seen including failed ones
+fn fetch_new_transactions(rpc: &RpcClient, cursor: Option<String>) -> Result<(Vec<TransactionLogs>, Option<String>)> {
+    let until = cursor.map(|signature| Signature::from_str(&signature)).transpose()?;
+    let mut statuses = Vec::new();
+    let mut before = None;
+    loop {
+        let page = rpc.get_signatures_for_address_with_config(
+            &soondex::ID,
+            GetConfirmedSignaturesForAddress2Config { before, until, limit: None, commitment: None },
+        )?;
+        let Some(last) = page.last() else { break };
+        before = Some(Signature::from_str(&last.signature)?);
+        statuses.extend(page);
+    }
+
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    let newest = statuses.first().map(|status| status.signature.clone());
+    let mut transactions = Vec::new();
+    for status in statuses.into_iter().rev().filter(|status| status.err.is_none()) {
+        let signature = Signature::from_str(&status.signature)?;
+        let transaction = rpc
+            .get_transaction_with_config(&signature, config)
+            .with_context(|| format!("fetching transaction {signature}"))?;
+        let logs = transaction
+            .transaction
+            .meta
+            .and_then(|meta| Option::<Vec<String>>::from(meta.log_messages))
+            .unwrap_or_default();
+        transactions.push(TransactionLogs {
+            signature: status.signature,
+            slot: transaction.slot,
+            block_time: transaction.block_time,
+            logs,
+        });
+    }
+    Ok((transactions, newest))
+}
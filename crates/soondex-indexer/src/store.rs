@@ -0,0 +1,709 @@
+//! SQLite schema and the per-event writes that keep it up to date
+
+use std::path::Path;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::Serialize;
+
+use soondex::TokensSwapped;
+
+use crate::events::PoolEvent;
+use crate::logs::{program_events, TransactionLogs};
+
+/// Candle widths in seconds: one minute, one hour, one day
+pub const DEFAULT_RESOLUTIONS: &[i64] = &[60, 3_600, 86_400];
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    block_time INTEGER,
+    pool TEXT NOT NULL,
+    sequence INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    PRIMARY KEY (signature, event_index)
+);
+CREATE INDEX IF NOT EXISTS events_pool ON events (pool, sequence);
+
+CREATE TABLE IF NOT EXISTS pools (
+    pool TEXT PRIMARY KEY,
+    token_x_mint TEXT,
+    token_y_mint TEXT,
+    fee_rate INTEGER,
+    swap_fee_rate INTEGER,
+    reward_rate INTEGER,
+    paused INTEGER NOT NULL DEFAULT 0,
+    min_initial_liquidity INTEGER,
+    max_swap_input_bps INTEGER,
+    max_price_impact_bps INTEGER,
+    max_slot_volume_bps INTEGER,
+    twamm_enabled INTEGER NOT NULL DEFAULT 0,
+    max_referral_fee_bps INTEGER,
+    removed INTEGER NOT NULL DEFAULT 0,
+    token_x_reserve INTEGER NOT NULL DEFAULT 0,
+    token_y_reserve INTEGER NOT NULL DEFAULT 0,
+    lp_token_supply INTEGER NOT NULL DEFAULT 0,
+    total_staked INTEGER NOT NULL DEFAULT 0,
+    last_sequence INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS swaps (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    block_time INTEGER,
+    pool TEXT NOT NULL,
+    user TEXT NOT NULL,
+    x_to_y INTEGER NOT NULL,
+    input_amount INTEGER NOT NULL,
+    output_amount INTEGER NOT NULL,
+    fee_amount INTEGER NOT NULL,
+    token_x_reserve INTEGER NOT NULL,
+    token_y_reserve INTEGER NOT NULL,
+    sequence INTEGER NOT NULL,
+    referrer TEXT,
+    referral_fee INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (signature, event_index)
+);
+CREATE INDEX IF NOT EXISTS swaps_pool ON swaps (pool, block_time);
+
+CREATE TABLE IF NOT EXISTS liquidity (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    block_time INTEGER,
+    pool TEXT NOT NULL,
+    user TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    token_x_amount INTEGER NOT NULL,
+    token_y_amount INTEGER NOT NULL,
+    lp_tokens INTEGER NOT NULL,
+    token_x_reserve INTEGER NOT NULL,
+    token_y_reserve INTEGER NOT NULL,
+    lp_token_supply INTEGER NOT NULL,
+    sequence INTEGER NOT NULL,
+    PRIMARY KEY (signature, event_index)
+);
+
+CREATE TABLE IF NOT EXISTS staking (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    block_time INTEGER,
+    pool TEXT NOT NULL,
+    user TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    rewards INTEGER NOT NULL,
+    sequence INTEGER NOT NULL,
+    PRIMARY KEY (signature, event_index)
+);
+
+CREATE TABLE IF NOT EXISTS flash_loans (
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    block_time INTEGER,
+    pool TEXT NOT NULL,
+    user TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    amount_x INTEGER NOT NULL,
+    amount_y INTEGER NOT NULL,
+    fee_x INTEGER NOT NULL,
+    fee_y INTEGER NOT NULL,
+    sequence INTEGER NOT NULL,
+    PRIMARY KEY (signature, event_index)
+);
+
+CREATE TABLE IF NOT EXISTS candles (
+    pool TEXT NOT NULL,
+    resolution INTEGER NOT NULL,
+    bucket_start INTEGER NOT NULL,
+    open REAL NOT NULL,
+    high REAL NOT NULL,
+    low REAL NOT NULL,
+    close REAL NOT NULL,
+    volume_x INTEGER NOT NULL,
+    volume_y INTEGER NOT NULL,
+    fees_x INTEGER NOT NULL,
+    fees_y INTEGER NOT NULL,
+    swaps INTEGER NOT NULL,
+    token_x_reserve INTEGER NOT NULL,
+    token_y_reserve INTEGER NOT NULL,
+    PRIMARY KEY (pool, resolution, bucket_start)
+);
+
+CREATE TABLE IF NOT EXISTS sequence_gaps (
+    pool TEXT NOT NULL,
+    expected INTEGER NOT NULL,
+    found INTEGER NOT NULL,
+    signature TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS cursor (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    signature TEXT NOT NULL
+);
+";
+
+/// Where an event sits in the chain, shared by every row it produces
+struct Position<'a> {
+    signature: &'a str,
+    event_index: i64,
+    block_time: Option<i64>,
+}
+
+/// One OHLCV bucket; prices are Y per X after each swap
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Candle {
+    pub pool: String,
+    pub resolution: i64,
+    pub bucket_start: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume_x: u64,
+    pub volume_y: u64,
+    pub fees_x: u64,
+    pub fees_y: u64,
+    pub swaps: u64,
+}
+
+/// A pool's latest reserves and its activity over a trailing window
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PoolStats {
+    pub pool: String,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub price: f64,
+    /// Both reserves valued in Y
+    pub tvl_y: f64,
+    pub volume_x: u64,
+    pub volume_y: u64,
+    pub fees_x: u64,
+    pub fees_y: u64,
+    /// Window fees valued in Y over TVL, annualized
+    pub fee_apr: f64,
+    pub swaps: u64,
+}
+
+pub struct Database {
+    connection: Connection,
+    resolutions: Vec<i64>,
+}
+
+impl Database {
+    pub fn open(path: &Path, resolutions: &[i64]) -> Result<Self> {
+        Self::with_connection(Connection::open(path)?, resolutions)
+    }
+
+    pub fn open_in_memory(resolutions: &[i64]) -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?, resolutions)
+    }
+
+    fn with_connection(connection: Connection, resolutions: &[i64]) -> Result<Self> {
+        anyhow::ensure!(resolutions.iter().all(|&resolution| resolution > 0), "candle resolutions must be positive");
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection, resolutions: resolutions.to_vec() })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Indexes one transaction atomically and returns how many events were new.
+    /// Events already stored are skipped, so replaying a log twice is a no-op
+    pub fn index_transaction(&mut self, transaction: &TransactionLogs) -> Result<usize> {
+        let db = self.connection.transaction()?;
+        let mut indexed = 0;
+        for (event_index, event) in program_events(&transaction.logs).iter().enumerate() {
+            let position = Position {
+                signature: &transaction.signature,
+                event_index: event_index as i64,
+                block_time: transaction.block_time,
+            };
+            let inserted = db.execute(
+                "INSERT OR IGNORE INTO events (signature, event_index, slot, block_time, pool, sequence, kind)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    position.signature,
+                    position.event_index,
+                    transaction.slot as i64,
+                    position.block_time,
+                    event.pool().to_string(),
+                    event.sequence() as i64,
+                    event.name(),
+                ],
+            )?;
+            if inserted == 0 {
+                continue;
+            }
+            apply(&db, &self.resolutions, &position, event)?;
+            indexed += 1;
+        }
+        db.commit()?;
+        Ok(indexed)
+    }
+
+    /// Newest signature indexed from RPC; the next poll starts after it
+    pub fn cursor(&self) -> Result<Option<String>> {
+        Ok(self.connection.query_row("SELECT signature FROM cursor WHERE id = 0", [], |row| row.get(0)).optional()?)
+    }
+
+    pub fn set_cursor(&self, signature: &str) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO cursor (id, signature) VALUES (0, ?1) ON CONFLICT (id) DO UPDATE SET signature = excluded.signature",
+            [signature],
+        )?;
+        Ok(())
+    }
+
+    pub fn candles(&self, pool: &str, resolution: i64) -> Result<Vec<Candle>> {
+        let mut statement = self.connection.prepare(
+            "SELECT pool, resolution, bucket_start, open, high, low, close, volume_x, volume_y, fees_x, fees_y, swaps
+             FROM candles WHERE pool = ?1 AND resolution = ?2 ORDER BY bucket_start",
+        )?;
+        let candles = statement.query_map(params![pool, resolution], |row| {
+            Ok(Candle {
+                pool: row.get(0)?,
+                resolution: row.get(1)?,
+                bucket_start: row.get(2)?,
+                open: row.get(3)?,
+                high: row.get(4)?,
+                low: row.get(5)?,
+                close: row.get(6)?,
+                volume_x: row.get::<_, i64>(7)? as u64,
+                volume_y: row.get::<_, i64>(8)? as u64,
+                fees_x: row.get::<_, i64>(9)? as u64,
+                fees_y: row.get::<_, i64>(10)? as u64,
+                swaps: row.get::<_, i64>(11)? as u64,
+            })
+        })?;
+        Ok(candles.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Stats over the `window` seconds before the newest indexed block time,
+    /// so results depend only on the indexed data and not on the wall clock
+    pub fn pool_stats(&self, window: i64) -> Result<Vec<PoolStats>> {
+        let now: Option<i64> = self.connection.query_row("SELECT max(block_time) FROM events", [], |row| row.get(0))?;
+        let since = now.unwrap_or(0) - window;
+        let mut statement = self.connection.prepare(
+            "SELECT p.pool, p.token_x_reserve, p.token_y_reserve,
+                    coalesce(sum(CASE WHEN s.x_to_y THEN s.input_amount ELSE s.output_amount END), 0),
+                    coalesce(sum(CASE WHEN s.x_to_y THEN s.output_amount ELSE s.input_amount END), 0),
+                    coalesce(sum(CASE WHEN s.x_to_y THEN s.fee_amount ELSE 0 END), 0),
+                    coalesce(sum(CASE WHEN s.x_to_y THEN 0 ELSE s.fee_amount END), 0),
+                    count(s.pool)
+             FROM pools p LEFT JOIN swaps s ON s.pool = p.pool AND s.block_time > ?1
+             WHERE p.removed = 0
+             GROUP BY p.pool ORDER BY p.pool",
+        )?;
+        let stats = statement.query_map([since], |row| {
+            let token_x_reserve = row.get::<_, i64>(1)? as u64;
+            let token_y_reserve = row.get::<_, i64>(2)? as u64;
+            let fees_x = row.get::<_, i64>(5)? as u64;
+            let fees_y = row.get::<_, i64>(6)? as u64;
+            let price = price(token_x_reserve, token_y_reserve);
+            let tvl_y = token_x_reserve as f64 * price + token_y_reserve as f64;
+            let fee_value = fees_x as f64 * price + fees_y as f64;
+            let fee_apr = if tvl_y > 0.0 && window > 0 {
+                fee_value / tvl_y * (365.0 * 86_400.0 / window as f64)
+            } else {
+                0.0
+            };
+            Ok(PoolStats {
+                pool: row.get(0)?,
+                token_x_reserve,
+                token_y_reserve,
+                price,
+                tvl_y,
+                volume_x: row.get::<_, i64>(3)? as u64,
+                volume_y: row.get::<_, i64>(4)? as u64,
+                fees_x,
+                fees_y,
+                fee_apr,
+                swaps: row.get::<_, i64>(7)? as u64,
+            })
+        })?;
+        Ok(stats.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+fn price(token_x_reserve: u64, token_y_reserve: u64) -> f64 {
+    if token_x_reserve == 0 {
+        0.0
+    } else {
+        token_y_reserve as f64 / token_x_reserve as f64
+    }
+}
+
+fn apply(db: &Transaction, resolutions: &[i64], position: &Position, event: &PoolEvent) -> Result<()> {
+    let pool = event.pool().to_string();
+    db.execute("INSERT OR IGNORE INTO pools (pool) VALUES (?1)", [&pool])?;
+    track_sequence(db, position, &pool, event.sequence())?;
+
+    match event {
+        PoolEvent::PoolInitialized(event) => {
+            db.execute(
+                "UPDATE pools SET token_x_mint = ?2, token_y_mint = ?3, fee_rate = ?4, removed = 0 WHERE pool = ?1",
+                params![pool, event.token_x_mint.to_string(), event.token_y_mint.to_string(), event.fee_rate as i64],
+            )?;
+        }
+        PoolEvent::PoolRemovedEvent(_) => {
+            db.execute("UPDATE pools SET removed = 1 WHERE pool = ?1", [&pool])?;
+        }
+        PoolEvent::AdminUpdated(_) => {}
+        PoolEvent::PoolConfigUpdated(event) => {
+            db.execute(
+                "UPDATE pools SET swap_fee_rate = ?2, reward_rate = ?3, paused = ?4, min_initial_liquidity = ?5,
+                 max_swap_input_bps = ?6, max_price_impact_bps = ?7, max_slot_volume_bps = ?8,
+                 twamm_enabled = ?9, max_referral_fee_bps = ?10 WHERE pool = ?1",
+                params![
+                    pool,
+                    event.swap_fee_rate as i64,
+                    event.reward_rate as i64,
+                    event.paused,
+                    event.min_initial_liquidity as i64,
+                    event.max_swap_input_bps as i64,
+                    event.max_price_impact_bps as i64,
+                    event.max_slot_volume_bps as i64,
+                    event.twamm_enabled,
+                    event.max_referral_fee_bps as i64,
+                ],
+            )?;
+        }
+        PoolEvent::LiquidityProvided(event) => {
+            insert_liquidity(
+                db,
+                position,
+                &pool,
+                &event.user,
+                "provided",
+                [event.token_x_amount, event.token_y_amount, event.lp_tokens_minted],
+                [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply],
+                event.sequence,
+            )?;
+            set_mints(db, &pool, &event.token_x_mint, &event.token_y_mint)?;
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        PoolEvent::LiquidityRemoved(event) => {
+            insert_liquidity(
+                db,
+                position,
+                &pool,
+                &event.user,
+                "removed",
+                [event.token_x_amount, event.token_y_amount, event.lp_tokens_burned],
+                [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply],
+                event.sequence,
+            )?;
+            set_mints(db, &pool, &event.token_x_mint, &event.token_y_mint)?;
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        PoolEvent::TokensSwapped(event) => insert_swap(db, resolutions, position, &pool, event)?,
+        PoolEvent::TokensStaked(event) => {
+            insert_staking(db, position, &pool, &event.user, "staked", event.amount, 0, event.sequence)?;
+            db.execute("UPDATE pools SET total_staked = ?2 WHERE pool = ?1", params![pool, event.total_staked as i64])?;
+        }
+        PoolEvent::TokensUnstaked(event) => {
+            insert_staking(db, position, &pool, &event.user, "unstaked", event.amount, event.rewards, event.sequence)?;
+            db.execute("UPDATE pools SET total_staked = ?2 WHERE pool = ?1", params![pool, event.total_staked as i64])?;
+        }
+        PoolEvent::RewardsClaimed(event) => {
+            insert_staking(db, position, &pool, &event.user, "claimed", 0, event.amount, event.sequence)?;
+        }
+        PoolEvent::FlashLoanBorrowed(event) => {
+            insert_flash_loan(db, position, &pool, &event.user, "borrowed", [event.amount_x, event.amount_y, 0, 0], event.sequence)?;
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        PoolEvent::FlashLoanRepaid(event) => {
+            insert_flash_loan(
+                db,
+                position,
+                &pool,
+                &event.user,
+                "repaid",
+                [event.amount_x, event.amount_y, event.fee_x, event.fee_y],
+                event.sequence,
+            )?;
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        PoolEvent::ReservesSynced(event) => {
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        PoolEvent::ExcessSkimmed(_) => {}
+        // A filled limit order is a swap on the owner's behalf
+        PoolEvent::LimitOrderFilled(event) => {
+            let swap = TokensSwapped {
+                pool: event.pool,
+                user: event.owner,
+                input_mint: event.input_mint,
+                output_mint: event.output_mint,
+                input_amount: event.input_amount,
+                output_amount: event.output_amount,
+                fee_amount: event.fee_amount,
+                token_x_reserve: event.token_x_reserve,
+                token_y_reserve: event.token_y_reserve,
+                lp_token_supply: event.lp_token_supply,
+                referrer: None,
+                referral_fee: 0,
+                sequence: event.sequence,
+            };
+            insert_swap(db, resolutions, position, &pool, &swap)?;
+        }
+        PoolEvent::LimitOrderPlaced(_) | PoolEvent::LimitOrderCancelled(_) => {}
+        // Long-term orders trade with the pool whenever their virtual orders execute
+        PoolEvent::VirtualOrdersExecuted(event) => {
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        PoolEvent::LongTermOrderPlaced(event) => {
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        PoolEvent::LongTermOrderWithdrawn(event) => {
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        // Each DCA execution is a swap on the vault owner's behalf
+        PoolEvent::DcaExecuted(event) => {
+            let swap = TokensSwapped {
+                pool: event.pool,
+                user: event.owner,
+                input_mint: event.input_mint,
+                output_mint: event.output_mint,
+                input_amount: event.input_amount,
+                output_amount: event.output_amount,
+                fee_amount: event.fee_amount,
+                token_x_reserve: event.token_x_reserve,
+                token_y_reserve: event.token_y_reserve,
+                lp_token_supply: event.lp_token_supply,
+                referrer: None,
+                referral_fee: 0,
+                sequence: event.sequence,
+            };
+            insert_swap(db, resolutions, position, &pool, &swap)?;
+        }
+        PoolEvent::DcaVaultOpened(_) | PoolEvent::DcaVaultWithdrawn(_) => {}
+        // The harvest's swaps arrive as `TokensSwapped`; the reinvestment is liquidity the vault provides
+        PoolEvent::LpVaultHarvested(event) => {
+            insert_liquidity(
+                db,
+                position,
+                &pool,
+                &event.vault,
+                "provided",
+                [event.token_x_amount, event.token_y_amount, event.lp_tokens_minted],
+                [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply],
+                event.sequence,
+            )?;
+            set_reserves(db, &pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+        }
+        // Moving LP positions in or out of a vault leaves the reserves unchanged
+        PoolEvent::LpVaultInitialized(_) | PoolEvent::LpVaultDeposited(_) | PoolEvent::LpVaultWithdrawn(_) => {}
+    }
+    Ok(())
+}
+
+/// Records a gap whenever a pool's sequence doesn't follow the last one seen,
+/// e.g. when the indexer missed a transaction
+fn track_sequence(db: &Transaction, position: &Position, pool: &str, sequence: u64) -> Result<()> {
+    let last: Option<i64> =
+        db.query_row("SELECT last_sequence FROM pools WHERE pool = ?1", [pool], |row| row.get(0))?;
+    if let Some(last) = last {
+        if sequence as i64 != last + 1 {
+            db.execute(
+                "INSERT INTO sequence_gaps (pool, expected, found, signature) VALUES (?1, ?2, ?3, ?4)",
+                params![pool, last + 1, sequence as i64, position.signature],
+            )?;
+        }
+    }
+    db.execute(
+        "UPDATE pools SET last_sequence = max(coalesce(last_sequence, 0), ?2) WHERE pool = ?1",
+        params![pool, sequence as i64],
+    )?;
+    Ok(())
+}
+
+fn set_mints(db: &Transaction, pool: &str, token_x_mint: &Pubkey, token_y_mint: &Pubkey) -> Result<()> {
+    db.execute(
+        "UPDATE pools SET token_x_mint = ?2, token_y_mint = ?3 WHERE pool = ?1 AND token_x_mint IS NULL",
+        params![pool, token_x_mint.to_string(), token_y_mint.to_string()],
+    )?;
+    Ok(())
+}
+
+/// `[token_x_reserve, token_y_reserve, lp_token_supply]` after the event
+fn set_reserves(db: &Transaction, pool: &str, reserves: [u64; 3]) -> Result<()> {
+    db.execute(
+        "UPDATE pools SET token_x_reserve = ?2, token_y_reserve = ?3, lp_token_supply = ?4 WHERE pool = ?1",
+        params![pool, reserves[0] as i64, reserves[1] as i64, reserves[2] as i64],
+    )?;
+    Ok(())
+}
+
+/// Records a swap row and folds it into every candle resolution
+fn insert_swap(
+    db: &Transaction,
+    resolutions: &[i64],
+    position: &Position,
+    pool: &str,
+    event: &TokensSwapped,
+) -> Result<()> {
+    // Pools store their mints in ascending order, so the smaller one is X
+    let x_to_y = event.input_mint < event.output_mint;
+    db.execute(
+        "INSERT INTO swaps (signature, event_index, block_time, pool, user, x_to_y, input_amount, output_amount,
+                            fee_amount, token_x_reserve, token_y_reserve, sequence, referrer, referral_fee)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![
+            position.signature,
+            position.event_index,
+            position.block_time,
+            pool,
+            event.user.to_string(),
+            x_to_y,
+            event.input_amount as i64,
+            event.output_amount as i64,
+            event.fee_amount as i64,
+            event.token_x_reserve as i64,
+            event.token_y_reserve as i64,
+            event.sequence as i64,
+            event.referrer.map(|referrer| referrer.to_string()),
+            event.referral_fee as i64,
+        ],
+    )?;
+    set_reserves(db, pool, [event.token_x_reserve, event.token_y_reserve, event.lp_token_supply])?;
+
+    let (volume_x, volume_y, fees_x, fees_y) = if x_to_y {
+        (event.input_amount, event.output_amount, event.fee_amount, 0)
+    } else {
+        (event.output_amount, event.input_amount, 0, event.fee_amount)
+    };
+    // Swaps without a block time can't be bucketed; they still count towards stats
+    if let Some(block_time) = position.block_time {
+        let price = price(event.token_x_reserve, event.token_y_reserve);
+        for &resolution in resolutions {
+            db.execute(
+                "INSERT INTO candles (pool, resolution, bucket_start, open, high, low, close, volume_x, volume_y,
+                                      fees_x, fees_y, swaps, token_x_reserve, token_y_reserve)
+                 VALUES (?1, ?2, ?3, ?4, ?4, ?4, ?4, ?5, ?6, ?7, ?8, 1, ?9, ?10)
+                 ON CONFLICT (pool, resolution, bucket_start) DO UPDATE SET
+                     high = max(high, excluded.high),
+                     low = min(low, excluded.low),
+                     close = excluded.close,
+                     volume_x = volume_x + excluded.volume_x,
+                     volume_y = volume_y + excluded.volume_y,
+                     fees_x = fees_x + excluded.fees_x,
+                     fees_y = fees_y + excluded.fees_y,
+                     swaps = swaps + 1,
+                     token_x_reserve = excluded.token_x_reserve,
+                     token_y_reserve = excluded.token_y_reserve",
+                params![
+                    pool,
+                    resolution,
+                    block_time - block_time.rem_euclid(resolution),
+                    price,
+                    volume_x as i64,
+                    volume_y as i64,
+                    fees_x as i64,
+                    fees_y as i64,
+                    event.token_x_reserve as i64,
+                    event.token_y_reserve as i64,
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn insert_liquidity(
+    db: &Transaction,
+    position: &Position,
+    pool: &str,
+    user: &Pubkey,
+    kind: &str,
+    amounts: [u64; 3],
+    reserves: [u64; 3],
+    sequence: u64,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO liquidity (signature, event_index, block_time, pool, user, kind, token_x_amount, token_y_amount,
+                                lp_tokens, token_x_reserve, token_y_reserve, lp_token_supply, sequence)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            position.signature,
+            position.event_index,
+            position.block_time,
+            pool,
+            user.to_string(),
+            kind,
+            amounts[0] as i64,
+            amounts[1] as i64,
+            amounts[2] as i64,
+            reserves[0] as i64,
+            reserves[1] as i64,
+            reserves[2] as i64,
+            sequence as i64,
+        ],
+    )?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn insert_staking(
+    db: &Transaction,
+    position: &Position,
+    pool: &str,
+    user: &Pubkey,
+    kind: &str,
+    amount: u64,
+    rewards: u64,
+    sequence: u64,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO staking (signature, event_index, block_time, pool, user, kind, amount, rewards, sequence)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            position.signature,
+            position.event_index,
+            position.block_time,
+            pool,
+            user.to_string(),
+            kind,
+            amount as i64,
+            rewards as i64,
+            sequence as i64,
+        ],
+    )?;
+    Ok(())
+}
+
+/// `amounts` is `[amount_x, amount_y, fee_x, fee_y]`
+fn insert_flash_loan(
+    db: &Transaction,
+    position: &Position,
+    pool: &str,
+    user: &Pubkey,
+    kind: &str,
+    amounts: [u64; 4],
+    sequence: u64,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO flash_loans (signature, event_index, block_time, pool, user, kind, amount_x, amount_y, fee_x, fee_y,
+                                  sequence)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            position.signature,
+            position.event_index,
+            position.block_time,
+            pool,
+            user.to_string(),
+            kind,
+            amounts[0] as i64,
+            amounts[1] as i64,
+            amounts[2] as i64,
+            amounts[3] as i64,
+            sequence as i64,
+        ],
+    )?;
+    Ok(())
+}
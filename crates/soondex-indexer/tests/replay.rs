@@ -0,0 +1,147 @@
+use std::path::Path;
+
+use soondex_indexer::{logs, program_events, Database, TransactionLogs, DEFAULT_RESOLUTIONS};
+
+const POOL: &str = "cGfHiC6Kgg3FpFZvgwGcswsCRtp4aBP2fzuXRQPizuN";
+const START: i64 = 1_700_000_040;
+
+fn fixture() -> Vec<TransactionLogs> {
+    logs::read_fixture(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/logs.jsonl")).unwrap()
+}
+
+fn replay(transactions: &[TransactionLogs]) -> (Database, usize) {
+    let mut db = Database::open_in_memory(DEFAULT_RESOLUTIONS).unwrap();
+    let indexed = transactions.iter().map(|transaction| db.index_transaction(transaction).unwrap()).sum();
+    (db, indexed)
+}
+
+/// Every row of every table, in a stable order
+fn dump(db: &Database) -> Vec<String> {
+    let connection = db.connection();
+    let mut tables = connection.prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name").unwrap();
+    let tables: Vec<String> = tables.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect();
+
+    let mut rows = Vec::new();
+    for table in tables {
+        let mut statement = connection.prepare(&format!("SELECT * FROM {table}")).unwrap();
+        let columns = statement.column_count();
+        let mut table_rows: Vec<String> = statement
+            .query_map([], |row| {
+                let values = (0..columns)
+                    .map(|index| row.get::<_, rusqlite::types::Value>(index))
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                Ok(format!("{table} {values:?}"))
+            })
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        table_rows.sort();
+        rows.extend(table_rows);
+    }
+    rows
+}
+
+fn price(token_x_reserve: u64, token_y_reserve: u64) -> f64 {
+    token_y_reserve as f64 / token_x_reserve as f64
+}
+
+#[test]
+fn replay_is_deterministic_and_idempotent() {
+    let transactions = fixture();
+    let (mut first, indexed) = replay(&transactions);
+    let (second, _) = replay(&transactions);
+    assert_eq!(indexed, transactions.len());
+    assert_eq!(dump(&first), dump(&second));
+
+    let before = dump(&first);
+    for transaction in &transactions {
+        assert_eq!(first.index_transaction(transaction).unwrap(), 0);
+    }
+    assert_eq!(dump(&first), before);
+}
+
+#[test]
+fn only_data_logged_by_the_program_is_decoded() {
+    // Each fixture transaction CPIs into the token program, which logs a copy of the event
+    let swap = &fixture()[2];
+    assert_eq!(swap.logs.iter().filter(|line| line.starts_with("Program data: ")).count(), 2);
+    let events = program_events(&swap.logs);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].name(), "TokensSwapped");
+    assert_eq!(events[0].pool().to_string(), POOL);
+}
+
+#[test]
+fn swaps_roll_up_into_ohlcv_candles() {
+    let (db, _) = replay(&fixture());
+    let p1 = price(1_010_000, 3_961_000);
+    let p2 = price(990_500, 4_041_000);
+    let p3 = price(1_040_500, 3_851_000);
+    let p4 = price(1_035_600, 3_871_000);
+
+    let minutes = db.candles(POOL, 60).unwrap();
+    assert_eq!(minutes.len(), 3);
+    assert_eq!(
+        minutes.iter().map(|candle| candle.bucket_start).collect::<Vec<_>>(),
+        [START, START + 60, START + 3_660]
+    );
+    let first = &minutes[0];
+    assert_eq!((first.open, first.high, first.low, first.close), (p1, p2, p1, p2));
+    assert_eq!((first.volume_x, first.volume_y), (10_000 + 19_500, 39_000 + 80_000));
+    assert_eq!((first.fees_x, first.fees_y, first.swaps), (30, 240, 2));
+
+    let hours = db.candles(POOL, 3_600).unwrap();
+    assert_eq!(hours.len(), 2);
+    let first = &hours[0];
+    assert_eq!(first.bucket_start, START - START.rem_euclid(3_600));
+    assert_eq!((first.open, first.high, first.low, first.close), (p1, p2, p3, p3));
+    assert_eq!((first.fees_x, first.fees_y, first.swaps), (180, 240, 3));
+    assert_eq!((hours[1].open, hours[1].close, hours[1].swaps), (p4, p4, 1));
+
+    let days = db.candles(POOL, 86_400).unwrap();
+    assert_eq!(days.len(), 1);
+    assert_eq!((days[0].volume_x, days[0].volume_y, days[0].swaps), (84_400, 329_000, 4));
+}
+
+#[test]
+fn pool_state_and_sequence_gaps_are_tracked() {
+    let (db, _) = replay(&fixture());
+    let connection = db.connection();
+
+    let pool: (u64, i64, i64, i64, i64, i64) = connection
+        .query_row(
+            "SELECT fee_rate, swap_fee_rate, token_x_reserve, token_y_reserve, lp_token_supply, total_staked
+             FROM pools WHERE pool = ?1",
+            [POOL],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+        )
+        .unwrap();
+    assert_eq!(pool, (30, 25, 935_600, 3_471_000, 1_800_000, 500_000));
+
+    let gaps: Vec<(i64, i64, String)> = connection
+        .prepare("SELECT expected, found, signature FROM sequence_gaps")
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(gaps, [(9, 10, "sig009".to_string())]);
+}
+
+#[test]
+fn stats_use_the_trailing_window_of_indexed_time() {
+    let (db, _) = replay(&fixture());
+
+    // The newest event is at START + 3_800; a 1000s window only holds the last swap
+    let recent = &db.pool_stats(1_000).unwrap()[0];
+    assert_eq!((recent.swaps, recent.volume_x, recent.volume_y), (1, 4_900, 20_000));
+    assert_eq!((recent.fees_x, recent.fees_y), (0, 60));
+
+    let day = &db.pool_stats(86_400).unwrap()[0];
+    assert_eq!((day.token_x_reserve, day.token_y_reserve), (935_600, 3_471_000));
+    assert_eq!((day.fees_x, day.fees_y, day.swaps), (180, 300, 4));
+    let price = price(935_600, 3_471_000);
+    let tvl_y = 935_600.0 * price + 3_471_000.0;
+    assert_eq!(day.tvl_y, tvl_y);
+    assert!((day.fee_apr - (180.0 * price + 300.0) / tvl_y * 365.0).abs() < 1e-12);
+}
@@ -0,0 +1,326 @@
+//! Pool math for Soondex, free of Anchor and `std` so the on-chain program,
+//! clients and simulators share one implementation.
+//!
+//! Every function states its rounding direction. Rounding always favours the
+//! pool: amounts paid out round down, amounts charged round up.
+
+#![no_std]
+
+use core::fmt;
+use integer_sqrt::IntegerSquareRoot;
+
+/// Denominator for rates expressed in basis points
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// The standard fee tier in basis points, 0.25%
+pub const TOTAL_FEE_RATE: u64 = 25;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathError {
+    /// An intermediate or final value does not fit its type
+    Overflow,
+    /// A divisor such as a reserve or the LP supply is zero
+    DivisionByZero,
+    /// An input amount that must be positive is zero
+    ZeroAmount,
+    /// The computed output rounds down to zero
+    ZeroOutput,
+    /// The pool cannot cover the requested amount
+    InsufficientLiquidity,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            MathError::Overflow => "math overflow",
+            MathError::DivisionByZero => "division by zero",
+            MathError::ZeroAmount => "amount must be positive",
+            MathError::ZeroOutput => "output rounds down to zero",
+            MathError::InsufficientLiquidity => "insufficient liquidity",
+        };
+        f.write_str(message)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, MathError>;
+
+fn to_u64(value: u128) -> Result<u64> {
+    value.try_into().map_err(|_| MathError::Overflow)
+}
+
+/// `value * numerator / denominator`, rounded down
+fn mul_div_floor(value: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    if denominator == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    to_u64((value as u128) * (numerator as u128) / (denominator as u128))
+}
+
+/// `value * numerator / denominator`, rounded up
+fn mul_div_ceil(value: u64, numerator: u64, denominator: u64) -> Result<u64> {
+    if denominator == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    let product = (value as u128) * (numerator as u128);
+    to_u64(product.div_ceil(denominator as u128))
+}
+
+/// Swap fee charged on `amount_in` at `fee_bps`. Rounds down
+pub fn swap_fee(amount_in: u64, fee_bps: u64) -> Result<u64> {
+    mul_div_floor(amount_in, fee_bps, BPS_DENOMINATOR)
+}
+
+/// Constant-product output for `amount_in` after fees. Rounds down
+pub fn swap_output(amount_in: u64, reserve_in: u64, reserve_out: u64) -> Result<u64> {
+    if amount_in == 0 {
+        return Err(MathError::ZeroAmount);
+    }
+    if reserve_in == 0 || reserve_out == 0 {
+        return Err(MathError::InsufficientLiquidity);
+    }
+
+    let k = (reserve_in as u128) * (reserve_out as u128);
+    let new_reserve_in = (reserve_in as u128)
+        .checked_add(amount_in as u128)
+        .ok_or(MathError::Overflow)?;
+    // Rounding the remaining output reserve up rounds the amount paid out down,
+    // so k never decreases
+    let new_reserve_out = k.div_ceil(new_reserve_in);
+    let amount_out = reserve_out as u128 - new_reserve_out;
+
+    if amount_out == 0 {
+        return Err(MathError::ZeroOutput);
+    }
+    to_u64(amount_out)
+}
+
+/// Smallest input, before a `fee_bps` swap fee, whose output is at least
+/// `amount_out`. Rounds up
+pub fn swap_input(amount_out: u64, reserve_in: u64, reserve_out: u64, fee_bps: u64) -> Result<u64> {
+    if amount_out == 0 {
+        return Err(MathError::ZeroAmount);
+    }
+    if amount_out >= reserve_out {
+        return Err(MathError::InsufficientLiquidity);
+    }
+    if fee_bps >= BPS_DENOMINATOR {
+        return Err(MathError::Overflow);
+    }
+
+    // Smallest input for which ceil(k / (reserve_in + input)) <= reserve_out - amount_out
+    let k = (reserve_in as u128) * (reserve_out as u128);
+    let amount_in_after_fees = k
+        .div_ceil(reserve_out as u128 - amount_out as u128)
+        .checked_sub(reserve_in as u128)
+        .ok_or(MathError::Overflow)?;
+
+    // Gross up for the swap fee
+    let fee_denominator = (BPS_DENOMINATOR - fee_bps) as u128;
+    let amount_in = amount_in_after_fees
+        .checked_mul(BPS_DENOMINATOR as u128)
+        .ok_or(MathError::Overflow)?
+        .div_ceil(fee_denominator);
+    to_u64(amount_in)
+}
+
+/// Shortfall of `amount_out` against the spot price `reserve_out / reserve_in`
+/// for `amount_in`, in basis points. Pass the input after fees to measure the
+/// impact alone. Rounds up
+pub fn price_impact_bps(amount_in: u64, amount_out: u64, reserve_in: u64, reserve_out: u64) -> Result<u64> {
+    if amount_in == 0 {
+        return Err(MathError::ZeroAmount);
+    }
+    if reserve_in == 0 || reserve_out == 0 {
+        return Err(MathError::InsufficientLiquidity);
+    }
+
+    // Output at the spot price and output received, both scaled by reserve_in
+    let at_spot = (amount_in as u128) * (reserve_out as u128);
+    let received = (amount_out as u128) * (reserve_in as u128);
+    let shortfall = at_spot.saturating_sub(received);
+    let impact = match shortfall.checked_mul(BPS_DENOMINATOR as u128) {
+        Some(scaled) => scaled.div_ceil(at_spot),
+        // Only reachable when at_spot is near u128::MAX, where scaling the
+        // divisor down instead loses nothing that matters
+        None => shortfall.div_ceil(at_spot / BPS_DENOMINATOR as u128),
+    };
+    to_u64(impact)
+}
+
+/// `amount` as a share of `reserve` in basis points. Rounds up
+pub fn share_of_reserve_bps(amount: u64, reserve: u64) -> Result<u64> {
+    mul_div_ceil(amount, BPS_DENOMINATOR, reserve)
+}
+
+/// LP tokens minted for depositing `amount_x` and `amount_y`. The first
+/// deposit mints `sqrt(amount_x * amount_y)`; later deposits mint the smaller
+/// of the two proportional shares. Rounds down
+pub fn lp_tokens_to_mint(
+    amount_x: u64,
+    amount_y: u64,
+    reserve_x: u64,
+    reserve_y: u64,
+    lp_supply: u64,
+) -> Result<u64> {
+    if lp_supply == 0 {
+        return to_u64(((amount_x as u128) * (amount_y as u128)).integer_sqrt());
+    }
+
+    let from_x = mul_div_floor(amount_x, lp_supply, reserve_x)?;
+    let from_y = mul_div_floor(amount_y, lp_supply, reserve_y)?;
+    Ok(from_x.min(from_y))
+}
+
+/// LP tokens burned for withdrawing `amount_x` and `amount_y`: the larger of
+/// the two proportional shares. Rounds up
+pub fn lp_tokens_to_burn(
+    amount_x: u64,
+    amount_y: u64,
+    reserve_x: u64,
+    reserve_y: u64,
+    lp_supply: u64,
+) -> Result<u64> {
+    if amount_x > reserve_x || amount_y > reserve_y {
+        return Err(MathError::InsufficientLiquidity);
+    }
+
+    let from_x = mul_div_ceil(amount_x, lp_supply, reserve_x)?;
+    let from_y = mul_div_ceil(amount_y, lp_supply, reserve_y)?;
+    let lp_tokens = from_x.max(from_y);
+    if lp_tokens > lp_supply {
+        return Err(MathError::InsufficientLiquidity);
+    }
+    Ok(lp_tokens)
+}
+
+/// Vault shares issued for depositing `lp_amount` into a vault whose position
+/// holds `vault_lp` LP tokens against `total_shares`. An empty vault issues one
+/// share per LP token. Rounds down
+pub fn vault_shares_to_mint(lp_amount: u64, vault_lp: u64, total_shares: u64) -> Result<u64> {
+    if total_shares == 0 {
+        return Ok(lp_amount);
+    }
+    mul_div_floor(lp_amount, total_shares, vault_lp)
+}
+
+/// LP tokens returned for redeeming `shares` of a vault whose position holds
+/// `vault_lp` LP tokens against `total_shares`. Rounds down
+pub fn vault_shares_to_lp(shares: u64, vault_lp: u64, total_shares: u64) -> Result<u64> {
+    if shares > total_shares {
+        return Err(MathError::InsufficientLiquidity);
+    }
+    mul_div_floor(shares, vault_lp, total_shares)
+}
+
+/// Outcome of settling one period of long-term (TWAMM) orders against a pool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TwammSettlement {
+    /// X paid to the orders selling Y
+    pub proceeds_x: u64,
+    /// Y paid to the orders selling X
+    pub proceeds_y: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+}
+
+/// Settles `sold_x` and `sold_y` sold by long-term orders over one period. The two
+/// flows are first matched against each other at the spot price; only the unmatched
+/// remainder trades against the pool, after a `fee_bps` fee. Selling at a constant
+/// rate into a constant-product pool with no other trades is equivalent to one swap
+/// of the total, so one-sided periods settle exactly. Rounds both sides' proceeds down
+pub fn twamm_settle(sold_x: u64, sold_y: u64, reserve_x: u64, reserve_y: u64, fee_bps: u64) -> Result<TwammSettlement> {
+    if reserve_x == 0 || reserve_y == 0 {
+        return Err(MathError::InsufficientLiquidity);
+    }
+
+    // Compare both sides' value at the spot price; the side worth more has a remainder
+    if (sold_x as u128) * (reserve_y as u128) >= (sold_y as u128) * (reserve_x as u128) {
+        let (matched_x, proceeds_y, reserve_x, reserve_y) =
+            settle_remainder(sold_x, sold_y, reserve_x, reserve_y, fee_bps)?;
+        Ok(TwammSettlement { proceeds_x: matched_x, proceeds_y, reserve_x, reserve_y })
+    } else {
+        let (matched_y, proceeds_x, reserve_y, reserve_x) =
+            settle_remainder(sold_y, sold_x, reserve_y, reserve_x, fee_bps)?;
+        Ok(TwammSettlement { proceeds_x, proceeds_y: matched_y, reserve_x, reserve_y })
+    }
+}
+
+/// Matches all of `sold_out` against `sold_in` at the spot price and swaps the rest
+/// of `sold_in` through the pool. Returns the `sold_in` paid to the other side, the
+/// `out` side's total proceeds and the new reserves
+fn settle_remainder(
+    sold_in: u64,
+    sold_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u64,
+) -> Result<(u64, u64, u64, u64)> {
+    let matched_in = mul_div_floor(sold_out, reserve_in, reserve_out)?;
+    let remainder = sold_in.checked_sub(matched_in).ok_or(MathError::Overflow)?;
+    let remainder_after_fees = remainder - swap_fee(remainder, fee_bps)?;
+    // Dust too small to buy anything stays in the pool
+    let swapped_out = match swap_output(remainder_after_fees, reserve_in, reserve_out) {
+        Ok(amount_out) => amount_out,
+        Err(MathError::ZeroAmount | MathError::ZeroOutput) => 0,
+        Err(error) => return Err(error),
+    };
+
+    Ok((
+        matched_in,
+        sold_out.checked_add(swapped_out).ok_or(MathError::Overflow)?,
+        reserve_in.checked_add(remainder).ok_or(MathError::Overflow)?,
+        reserve_out - swapped_out,
+    ))
+}
+
+/// Spot price of the `reserve_base` token in units of the `reserve_quote` token,
+/// as a Q64.64 fixed-point number. Rounds down
+pub fn price_q64(reserve_base: u64, reserve_quote: u64) -> Result<u128> {
+    if reserve_base == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+    Ok(((reserve_quote as u128) << 64) / reserve_base as u128)
+}
+
+/// Value of `amount` at a Q64.64 `price` such as a TWAP from [`price_q64`]
+/// accumulators. Rounds down
+pub fn amount_at_price_q64(amount: u64, price: u128) -> Result<u64> {
+    let whole = (amount as u128).checked_mul(price >> 64).ok_or(MathError::Overflow)?;
+    let fraction = ((amount as u128) * (price & u64::MAX as u128)) >> 64;
+    to_u64(whole.checked_add(fraction).ok_or(MathError::Overflow)?)
+}
+
+/// Flash loan fee on `amount` at `fee_bps`. Rounds up so small loans cannot
+/// avoid the fee
+pub fn flash_loan_fee(amount: u64, fee_bps: u64) -> Result<u64> {
+    mul_div_ceil(amount, fee_bps, BPS_DENOMINATOR)
+}
+
+/// Staking rewards for `amount_staked` over `duration` seconds at
+/// `reward_rate` basis points per second. Non-positive durations earn nothing.
+/// Rounds down
+pub fn staking_rewards(amount_staked: u64, reward_rate: u64, duration: i64) -> Result<u64> {
+    accrued_rewards(amount_staked, reward_per_token_growth(reward_rate, duration)?)
+}
+
+/// Growth of a reward-per-token accumulator over `duration` seconds at
+/// `reward_rate` basis points per second. Non-positive durations add nothing
+pub fn reward_per_token_growth(reward_rate: u64, duration: i64) -> Result<u128> {
+    if duration <= 0 {
+        return Ok(0);
+    }
+
+    (reward_rate as u128)
+        .checked_mul(duration as u128)
+        .ok_or(MathError::Overflow)
+}
+
+/// Rewards for `amount_staked` while the accumulator from
+/// [`reward_per_token_growth`] grew by `reward_per_token_delta`. Rounds down
+pub fn accrued_rewards(amount_staked: u64, reward_per_token_delta: u128) -> Result<u64> {
+    let reward = (amount_staked as u128)
+        .checked_mul(reward_per_token_delta)
+        .ok_or(MathError::Overflow)?
+        / BPS_DENOMINATOR as u128;
+    to_u64(reward)
+}
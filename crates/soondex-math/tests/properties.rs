@@ -0,0 +1,246 @@
+//! Randomized property tests. Each property runs `CASES` seeded cases so
+//! failures reproduce exactly; the failing inputs are printed on panic.
+
+use soondex_math::*;
+
+const CASES: usize = 20_000;
+const SWAP_FEE_BPS: u64 = 25;
+
+/// SplitMix64: small, dependency-free and good enough to spread test inputs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `low..=high`, biased towards small magnitudes so edge cases show up
+    fn range(&mut self, low: u64, high: u64) -> u64 {
+        let span = high - low;
+        let bits = self.next() % 64;
+        let mask = if bits == 63 { u64::MAX } else { (1u64 << (bits + 1)) - 1 };
+        low + (self.next() & mask) % (span.saturating_add(1)).max(1)
+    }
+
+    fn reserve(&mut self) -> u64 {
+        self.range(1_000, 1_000_000_000_000)
+    }
+}
+
+#[test]
+fn swap_never_decreases_k() {
+    let mut rng = Rng(1);
+    for _ in 0..CASES {
+        let (reserve_in, reserve_out) = (rng.reserve(), rng.reserve());
+        let amount_in = rng.range(1, 1_000_000_000_000);
+
+        let fee = swap_fee(amount_in, SWAP_FEE_BPS).unwrap();
+        let Ok(amount_out) = swap_output(amount_in - fee, reserve_in, reserve_out) else {
+            continue;
+        };
+
+        // The fee stays in the pool alongside the input
+        let k_before = reserve_in as u128 * reserve_out as u128;
+        let k_after = (reserve_in + amount_in) as u128 * (reserve_out - amount_out) as u128;
+        assert!(
+            k_after >= k_before,
+            "k decreased: reserves ({reserve_in}, {reserve_out}), amount_in {amount_in}"
+        );
+    }
+}
+
+#[test]
+fn exact_out_input_delivers_requested_output() {
+    let mut rng = Rng(2);
+    for _ in 0..CASES {
+        let (reserve_in, reserve_out) = (rng.reserve(), rng.reserve());
+        let amount_out = rng.range(1, reserve_out - 1);
+
+        let amount_in = swap_input(amount_out, reserve_in, reserve_out, SWAP_FEE_BPS).unwrap();
+        let fee = swap_fee(amount_in, SWAP_FEE_BPS).unwrap();
+        let delivered = swap_output(amount_in - fee, reserve_in, reserve_out).unwrap();
+        assert!(
+            delivered >= amount_out,
+            "short output: reserves ({reserve_in}, {reserve_out}), wanted {amount_out}, got {delivered}"
+        );
+    }
+}
+
+#[test]
+fn deposit_then_withdraw_never_profits() {
+    let mut rng = Rng(3);
+    for _ in 0..CASES {
+        let (reserve_x, reserve_y) = (rng.reserve(), rng.reserve());
+        let lp_supply = rng.range(1_000, 1_000_000_000_000);
+        let amount_x = rng.range(1, 1_000_000_000);
+        let amount_y = rng.range(1, 1_000_000_000);
+
+        let minted = lp_tokens_to_mint(amount_x, amount_y, reserve_x, reserve_y, lp_supply).unwrap();
+        let reserve_x = reserve_x + amount_x;
+        let reserve_y = reserve_y + amount_y;
+        let lp_supply = lp_supply + minted;
+
+        // Withdrawals around the depositor's pro-rata share, on both sides of it
+        let share_x = (minted as u128 * reserve_x as u128 / lp_supply as u128) as u64;
+        let share_y = (minted as u128 * reserve_y as u128 / lp_supply as u128) as u64;
+        let withdraw_x = share_x.saturating_add(rng.range(0, 2)).saturating_sub(rng.range(0, 2));
+        let withdraw_y = share_y.saturating_add(rng.range(0, 2)).saturating_sub(rng.range(0, 2));
+
+        if let Ok(burned) = lp_tokens_to_burn(withdraw_x, withdraw_y, reserve_x, reserve_y, lp_supply) {
+            if burned <= minted {
+                assert!(
+                    withdraw_x <= amount_x && withdraw_y <= amount_y,
+                    "profit: deposited ({amount_x}, {amount_y}) for {minted} LP, \
+                     withdrew ({withdraw_x}, {withdraw_y}) for {burned} LP"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn first_deposit_round_trip_never_profits() {
+    let mut rng = Rng(4);
+    for _ in 0..CASES {
+        let amount_x = rng.range(1, 1_000_000_000_000);
+        let amount_y = rng.range(1, 1_000_000_000_000);
+        let minted = lp_tokens_to_mint(amount_x, amount_y, 0, 0, 0).unwrap();
+        if minted == 0 {
+            continue;
+        }
+
+        let withdraw_x = rng.range(0, amount_x);
+        let withdraw_y = rng.range(0, amount_y);
+        let burned = lp_tokens_to_burn(withdraw_x, withdraw_y, amount_x, amount_y, minted).unwrap();
+        assert!(burned <= minted);
+        if withdraw_x == amount_x || withdraw_y == amount_y {
+            assert_eq!(burned, minted, "full withdrawal must burn every LP token");
+        }
+    }
+}
+
+#[test]
+fn price_impact_matches_constant_product_slippage() {
+    let mut rng = Rng(6);
+    for _ in 0..CASES {
+        let (reserve_in, reserve_out) = (rng.reserve(), rng.reserve());
+        let amount_in = rng.range(1, 1_000_000_000_000);
+        let Ok(amount_out) = swap_output(amount_in, reserve_in, reserve_out) else { continue };
+
+        // A constant-product swap of `amount_in` moves the price by amount_in / (reserve_in + amount_in)
+        let impact = price_impact_bps(amount_in, amount_out, reserve_in, reserve_out).unwrap();
+        let expected = (amount_in as u128 * BPS_DENOMINATOR as u128 / (reserve_in as u128 + amount_in as u128)) as u64;
+        assert!(
+            impact >= expected && impact <= BPS_DENOMINATOR,
+            "impact {impact} bps for {amount_in} into {reserve_in}/{reserve_out}, expected at least {expected}"
+        );
+    }
+}
+
+#[test]
+fn withdrawing_more_than_reserves_fails() {
+    assert_eq!(
+        lp_tokens_to_burn(101, 0, 100, 100, 100),
+        Err(MathError::InsufficientLiquidity)
+    );
+}
+
+#[test]
+fn flash_loan_fee_is_never_zero_for_nonzero_loans() {
+    let mut rng = Rng(5);
+    for _ in 0..CASES {
+        let amount = rng.range(1, u64::MAX / BPS_DENOMINATOR);
+        assert!(flash_loan_fee(amount, 9).unwrap() > 0, "free loan of {amount}");
+    }
+}
+
+#[test]
+fn staking_rewards_ignore_non_positive_durations() {
+    assert_eq!(staking_rewards(1_000, 10, 0), Ok(0));
+    assert_eq!(staking_rewards(1_000, 10, -5), Ok(0));
+    assert_eq!(staking_rewards(1_000, 10, 3), Ok(3));
+}
+
+#[test]
+fn accrued_rewards_pay_each_rate_for_its_own_period() {
+    let mut rng = Rng(8);
+    for _ in 0..CASES {
+        let amount = rng.range(0, 1_000_000_000_000);
+        let (first_rate, second_rate) = (rng.range(0, 1_000), rng.range(0, 1_000));
+        let (first, second) = (rng.range(0, 1_000_000) as i64, rng.range(0, 1_000_000) as i64);
+        let growth = reward_per_token_growth(first_rate, first).unwrap()
+            + reward_per_token_growth(second_rate, second).unwrap();
+        let accrued = accrued_rewards(amount, growth).unwrap();
+        let separate =
+            staking_rewards(amount, first_rate, first).unwrap() + staking_rewards(amount, second_rate, second).unwrap();
+        // Settling once rounds down once instead of twice
+        assert!(
+            accrued >= separate && accrued <= separate + 1,
+            "{amount} at {first_rate} for {first}s then {second_rate} for {second}s: {accrued} vs {separate}"
+        );
+    }
+}
+
+#[test]
+fn twamm_settlement_conserves_tokens_and_never_decreases_k() {
+    let mut rng = Rng(7);
+    for _ in 0..CASES {
+        let (reserve_x, reserve_y) = (rng.reserve(), rng.reserve());
+        let (sold_x, sold_y) = (rng.range(0, 1_000_000_000_000), rng.range(0, 1_000_000_000_000));
+        let settlement = twamm_settle(sold_x, sold_y, reserve_x, reserve_y, SWAP_FEE_BPS).unwrap();
+
+        // Whatever the orders sold either reached the other side or the pool
+        assert_eq!(settlement.reserve_x as u128 + settlement.proceeds_x as u128, reserve_x as u128 + sold_x as u128);
+        assert_eq!(settlement.reserve_y as u128 + settlement.proceeds_y as u128, reserve_y as u128 + sold_y as u128);
+        let k_before = reserve_x as u128 * reserve_y as u128;
+        let k_after = settlement.reserve_x as u128 * settlement.reserve_y as u128;
+        assert!(
+            k_after >= k_before,
+            "k decreased: reserves ({reserve_x}, {reserve_y}), sold ({sold_x}, {sold_y})"
+        );
+    }
+}
+
+#[test]
+fn q64_prices_round_down_by_at_most_one_unit() {
+    let mut rng = Rng(8);
+    for _ in 0..CASES {
+        let (reserve_base, reserve_quote) = (rng.reserve(), rng.reserve());
+        let amount = rng.range(0, 1_000_000_000_000);
+        let price = price_q64(reserve_base, reserve_quote).unwrap();
+        let Ok(value) = amount_at_price_q64(amount, price) else {
+            continue;
+        };
+
+        let exact = amount as u128 * reserve_quote as u128 / reserve_base as u128;
+        assert!(
+            value as u128 <= exact && exact - (value as u128) <= 1,
+            "{amount} at reserves ({reserve_base}, {reserve_quote}) valued {value}, exactly {exact}"
+        );
+    }
+}
+
+#[test]
+fn vault_deposit_then_redeem_never_profits() {
+    let mut rng = Rng(9);
+    for _ in 0..CASES {
+        let total_shares = rng.range(0, 1_000_000_000_000);
+        // Harvests only ever grow the position, so a share is worth at least one LP token
+        let vault_lp = total_shares + rng.range(0, total_shares);
+        let lp_amount = rng.range(1, 1_000_000_000_000);
+        let shares = vault_shares_to_mint(lp_amount, vault_lp, total_shares).unwrap();
+        if shares == 0 {
+            continue;
+        }
+
+        let redeemed = vault_shares_to_lp(shares, vault_lp + lp_amount, total_shares + shares).unwrap();
+        assert!(
+            redeemed <= lp_amount,
+            "{lp_amount} LP into ({vault_lp}, {total_shares}) minted {shares} shares worth {redeemed}"
+        );
+    }
+}
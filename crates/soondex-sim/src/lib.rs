@@ -0,0 +1,9 @@
+//! Off-chain simulator for Soondex pools: replays trade streams through the
+//! program's pool math to compare fee tiers and reward schedules before a pool
+//! is deployed.
+
+pub mod simulate;
+pub mod trades;
+
+pub use simulate::{simulate, Report, Scenario};
+pub use trades::{Side, Trade};
@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{ArgEnum, Parser};
+use soondex_math::TOTAL_FEE_RATE;
+use soondex_sim::{simulate, trades, Report, Scenario};
+
+/// Replays a CSV or JSON trade stream through Soondex pool math
+#[derive(Parser)]
+#[clap(name = "soondex-sim", version, about)]
+struct Cli {
+    /// Trades to replay; `.json` files are read as JSON, anything else as CSV
+    trades: PathBuf,
+    /// X deposited when the pool is seeded
+    #[clap(long)]
+    reserve_x: u64,
+    /// Y deposited when the pool is seeded
+    #[clap(long)]
+    reserve_y: u64,
+    /// Swap fees to compare, in basis points; defaults to the standard fee tier
+    #[clap(long, value_delimiter = ',', default_values_t = vec![TOTAL_FEE_RATE])]
+    fee_bps: Vec<u64>,
+    /// Reward rates to compare, in basis points of the LP supply per second
+    #[clap(long, value_delimiter = ',', default_values_t = vec![0])]
+    reward_rate: Vec<u64>,
+    #[clap(long, arg_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, ArgEnum)]
+enum Format {
+    /// One summary row per scenario
+    Table,
+    /// Every block of every scenario
+    Csv,
+    /// Blocks and summaries of every scenario
+    Json,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let trades = trades::load(&cli.trades)?;
+
+    let mut reports = Vec::new();
+    for &fee_bps in &cli.fee_bps {
+        for &reward_rate in &cli.reward_rate {
+            let scenario = Scenario { fee_bps, reward_rate };
+            reports.push(simulate(cli.reserve_x, cli.reserve_y, scenario, &trades)?);
+        }
+    }
+
+    match cli.format {
+        Format::Table => print_table(&reports),
+        Format::Csv => print_csv(&reports),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&reports)?),
+    }
+    Ok(())
+}
+
+fn print_table(reports: &[Report]) {
+    println!(
+        "{:>8} {:>12} {:>8} {:>7} {:>20} {:>20} {:>16} {:>16} {:>14} {:>14}",
+        "fee_bps", "reward_rate", "swaps", "failed", "reserve_x", "reserve_y", "fee_value", "rewards", "lp_vs_hodl_bps",
+        "il_bps",
+    );
+    for Report { scenario, summary, .. } in reports {
+        println!(
+            "{:>8} {:>12} {:>8} {:>7} {:>20} {:>20} {:>16.0} {:>16} {:>14.2} {:>14.2}",
+            scenario.fee_bps,
+            scenario.reward_rate,
+            summary.swaps,
+            summary.failed_swaps,
+            summary.reserve_x,
+            summary.reserve_y,
+            summary.fee_value,
+            summary.total_rewards,
+            summary.lp_vs_hodl_bps,
+            summary.impermanent_loss_bps,
+        );
+    }
+}
+
+fn print_csv(reports: &[Report]) {
+    println!(
+        "fee_bps,reward_rate,block,timestamp,swaps,failed_swaps,reserve_x,reserve_y,price,volume_x,volume_y,\
+         fees_x,fees_y,fee_value,rewards_emitted,total_rewards,lp_value,hodl_value,lp_vs_hodl_bps,impermanent_loss_bps"
+    );
+    for Report { scenario, blocks, .. } in reports {
+        for row in blocks {
+            println!(
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                scenario.fee_bps,
+                scenario.reward_rate,
+                row.block,
+                row.timestamp,
+                row.swaps,
+                row.failed_swaps,
+                row.reserve_x,
+                row.reserve_y,
+                row.price,
+                row.volume_x,
+                row.volume_y,
+                row.fees_x,
+                row.fees_y,
+                row.fee_value,
+                row.rewards_emitted,
+                row.total_rewards,
+                row.lp_value,
+                row.hodl_value,
+                row.lp_vs_hodl_bps,
+                row.impermanent_loss_bps,
+            );
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Replays trades through the program's constant-product math, one scenario at
+//! a time, and records the pool after every block.
+//!
+//! Like `swap_tokens`, the whole input (fee included) joins the input reserve,
+//! so fees accrue to LPs through the reserves. Rewards are emitted between
+//! blocks at `reward_rate` basis points of the LP supply per second, the rate
+//! `claim_rewards` pays.
+
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use soondex_math::{MathError, BPS_DENOMINATOR};
+
+use crate::trades::{Side, Trade};
+
+/// Pool parameters to compare
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Scenario {
+    pub fee_bps: u64,
+    pub reward_rate: u64,
+}
+
+/// Constant-product reserves after the initial deposit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pool {
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub lp_supply: u64,
+}
+
+/// A swap the pool executed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fill {
+    pub fee: u64,
+    pub amount_out: u64,
+}
+
+impl Pool {
+    /// Pool seeded by a first deposit of `reserve_x` and `reserve_y`
+    pub fn new(reserve_x: u64, reserve_y: u64) -> Result<Self> {
+        let lp_supply = soondex_math::lp_tokens_to_mint(reserve_x, reserve_y, 0, 0, 0).map_err(math_error)?;
+        if lp_supply == 0 {
+            bail!("initial reserves mint no LP tokens");
+        }
+        Ok(Self { reserve_x, reserve_y, lp_supply })
+    }
+
+    /// Executes an exact-in swap, leaving the pool untouched when it fails
+    pub fn swap(&mut self, side: Side, amount_in: u64, fee_bps: u64) -> Result<Fill, MathError> {
+        let (reserve_in, reserve_out) = match side {
+            Side::XToY => (self.reserve_x, self.reserve_y),
+            Side::YToX => (self.reserve_y, self.reserve_x),
+        };
+        let fee = soondex_math::swap_fee(amount_in, fee_bps)?;
+        let amount_out = soondex_math::swap_output(amount_in - fee, reserve_in, reserve_out)?;
+        let reserve_in = reserve_in.checked_add(amount_in).ok_or(MathError::Overflow)?;
+        let reserve_out = reserve_out - amount_out;
+
+        (self.reserve_x, self.reserve_y) = match side {
+            Side::XToY => (reserve_in, reserve_out),
+            Side::YToX => (reserve_out, reserve_in),
+        };
+        Ok(Fill { fee, amount_out })
+    }
+
+    /// Y per X at the margin
+    pub fn price(&self) -> f64 {
+        self.reserve_y as f64 / self.reserve_x as f64
+    }
+}
+
+/// Pool state at the end of a block. Values are in Y at the block's closing price
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BlockRow {
+    pub block: u64,
+    pub timestamp: i64,
+    pub swaps: u64,
+    /// Swaps the math rejected, such as outputs rounding to zero
+    pub failed_swaps: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub price: f64,
+    pub volume_x: u64,
+    pub volume_y: u64,
+    /// Cumulative swap fees in each input token
+    pub fees_x: u64,
+    pub fees_y: u64,
+    pub fee_value: f64,
+    pub rewards_emitted: u64,
+    pub total_rewards: u64,
+    pub lp_value: f64,
+    pub hodl_value: f64,
+    /// LP position against holding the initial deposit, fees included
+    pub lp_vs_hodl_bps: f64,
+    /// Loss from the price move alone, `2 * sqrt(r) / (1 + r) - 1`
+    pub impermanent_loss_bps: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub swaps: u64,
+    pub failed_swaps: u64,
+    pub reserve_x: u64,
+    pub reserve_y: u64,
+    pub fees_x: u64,
+    pub fees_y: u64,
+    pub fee_value: f64,
+    pub total_rewards: u64,
+    pub lp_vs_hodl_bps: f64,
+    pub impermanent_loss_bps: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Report {
+    pub scenario: Scenario,
+    pub blocks: Vec<BlockRow>,
+    pub summary: Summary,
+}
+
+/// Replays `trades` against a pool seeded with `reserve_x` and `reserve_y`
+pub fn simulate(reserve_x: u64, reserve_y: u64, scenario: Scenario, trades: &[Trade]) -> Result<Report> {
+    if scenario.fee_bps >= BPS_DENOMINATOR {
+        bail!("fee of {} bps must be below {BPS_DENOMINATOR}", scenario.fee_bps);
+    }
+    let mut pool = Pool::new(reserve_x, reserve_y)?;
+    let initial_price = pool.price();
+
+    let mut blocks: Vec<BlockRow> = Vec::new();
+    let (mut fees_x, mut fees_y, mut total_rewards) = (0u64, 0u64, 0u64);
+    let mut last_timestamp = trades.first().map_or(0, |trade| trade.timestamp);
+
+    for block_trades in trades.chunk_by(|a, b| a.block == b.block) {
+        let first = block_trades[0];
+        let rewards_emitted =
+            soondex_math::staking_rewards(pool.lp_supply, scenario.reward_rate, first.timestamp - last_timestamp)
+                .map_err(math_error)?;
+        total_rewards = total_rewards.checked_add(rewards_emitted).ok_or_else(|| math_error(MathError::Overflow))?;
+
+        let (mut swaps, mut failed_swaps, mut volume_x, mut volume_y) = (0, 0, 0u64, 0u64);
+        for trade in block_trades {
+            match pool.swap(trade.side, trade.amount_in, scenario.fee_bps) {
+                Ok(fill) => {
+                    swaps += 1;
+                    let (volume, fees) = match trade.side {
+                        Side::XToY => (&mut volume_x, &mut fees_x),
+                        Side::YToX => (&mut volume_y, &mut fees_y),
+                    };
+                    *volume = volume.saturating_add(trade.amount_in);
+                    *fees = fees.saturating_add(fill.fee);
+                }
+                Err(_) => failed_swaps += 1,
+            }
+        }
+
+        let last = block_trades[block_trades.len() - 1];
+        last_timestamp = last.timestamp;
+        let price = pool.price();
+        let lp_value = pool.reserve_x as f64 * price + pool.reserve_y as f64;
+        let hodl_value = reserve_x as f64 * price + reserve_y as f64;
+        let ratio = price / initial_price;
+        blocks.push(BlockRow {
+            block: last.block,
+            timestamp: last.timestamp,
+            swaps,
+            failed_swaps,
+            reserve_x: pool.reserve_x,
+            reserve_y: pool.reserve_y,
+            price,
+            volume_x,
+            volume_y,
+            fees_x,
+            fees_y,
+            fee_value: fees_x as f64 * price + fees_y as f64,
+            rewards_emitted,
+            total_rewards,
+            lp_value,
+            hodl_value,
+            lp_vs_hodl_bps: (lp_value / hodl_value - 1.0) * BPS_DENOMINATOR as f64,
+            impermanent_loss_bps: (2.0 * ratio.sqrt() / (1.0 + ratio) - 1.0) * BPS_DENOMINATOR as f64,
+        });
+    }
+
+    let summary = Summary {
+        swaps: blocks.iter().map(|row| row.swaps).sum(),
+        failed_swaps: blocks.iter().map(|row| row.failed_swaps).sum(),
+        reserve_x: pool.reserve_x,
+        reserve_y: pool.reserve_y,
+        fees_x,
+        fees_y,
+        fee_value: blocks.last().map_or(0.0, |row| row.fee_value),
+        total_rewards,
+        lp_vs_hodl_bps: blocks.last().map_or(0.0, |row| row.lp_vs_hodl_bps),
+        impermanent_loss_bps: blocks.last().map_or(0.0, |row| row.impermanent_loss_bps),
+    };
+    Ok(Report { scenario, blocks, summary })
+}
+
+fn math_error(error: MathError) -> anyhow::Error {
+    anyhow!("{error}")
+}
@@ -0,0 +1,103 @@
+//! Trade streams read from CSV or JSON.
+//!
+//! CSV files need a header naming the `block`, `timestamp`, `side` and
+//! `amount_in` columns, in any order. JSON files hold an array of objects with
+//! the same fields. `side` is `x_to_y` or `y_to_x`.
+
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    XToY,
+    YToX,
+}
+
+impl std::str::FromStr for Side {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "x_to_y" => Ok(Side::XToY),
+            "y_to_x" => Ok(Side::YToX),
+            _ => bail!("unknown side `{value}`, expected `x_to_y` or `y_to_x`"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Trade {
+    pub block: u64,
+    /// Unix timestamp of the block, used for reward emissions
+    pub timestamp: i64,
+    pub side: Side,
+    pub amount_in: u64,
+}
+
+/// Loads trades from `path`, reading JSON for `.json` files and CSV otherwise
+pub fn load(path: &Path) -> Result<Vec<Trade>> {
+    let contents = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let trades = if path.extension().is_some_and(|extension| extension == "json") {
+        parse_json(&contents)
+    } else {
+        parse_csv(&contents)
+    }
+    .with_context(|| format!("parsing {}", path.display()))?;
+    validate_order(&trades)?;
+    Ok(trades)
+}
+
+pub fn parse_json(contents: &str) -> Result<Vec<Trade>> {
+    Ok(serde_json::from_str(contents)?)
+}
+
+pub fn parse_csv(contents: &str) -> Result<Vec<Trade>> {
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or_else(|| anyhow!("missing header row"))?;
+    let columns: Vec<&str> = header.split(',').map(str::trim).collect();
+    let column = |name: &str| {
+        columns
+            .iter()
+            .position(|column| *column == name)
+            .ok_or_else(|| anyhow!("missing `{name}` column"))
+    };
+    let (block, timestamp, side, amount_in) =
+        (column("block")?, column("timestamp")?, column("side")?, column("amount_in")?);
+
+    lines
+        .map(|(index, line)| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let field = |position: usize| {
+                fields
+                    .get(position)
+                    .copied()
+                    .ok_or_else(|| anyhow!("expected {} fields", columns.len()))
+            };
+            let parse = || -> Result<Trade> {
+                Ok(Trade {
+                    block: field(block)?.parse()?,
+                    timestamp: field(timestamp)?.parse()?,
+                    side: field(side)?.parse()?,
+                    amount_in: field(amount_in)?.parse()?,
+                })
+            };
+            parse().with_context(|| format!("line {}", index + 1))
+        })
+        .collect::<Result<_>>()
+}
+
+/// Blocks and timestamps must not go backwards
+pub fn validate_order(trades: &[Trade]) -> Result<()> {
+    for (index, pair) in trades.windows(2).enumerate() {
+        if pair[1].block < pair[0].block || pair[1].timestamp < pair[0].timestamp {
+            bail!("trade {} goes back in time: blocks and timestamps must not decrease", index + 1);
+        }
+    }
+    Ok(())
+}
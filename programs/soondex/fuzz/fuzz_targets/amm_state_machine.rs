@@ -0,0 +1,339 @@
+//! Drives random sequences of liquidity, swap, staking and reward instructions
+//! through the program and checks the pool's invariants after every step:
+//!
+//! - vault balances equal the recorded reserves, and tokens are conserved
+//! - swaps never decrease k
+//! - deposits and withdrawals never decrease the reserves backing each LP token,
+//!   and a withdrawal never pays more than the burned LP tokens' pro-rata share
+//! - the LP ledger and staking totals match their per-user entries, and the
+//!   first deposit's `MINIMUM_LIQUIDITY` stays locked
+//! - stake and reward payouts match `soondex-math` for the elapsed time
+//! - failed instructions leave the pool untouched
+//!
+//! Run with `cargo +nightly fuzz run amm_state_machine` from `programs/soondex`.
+
+#![no_main]
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+use anchor_lang::AccountDeserialize;
+use anchor_spl::token::spl_token;
+use arbitrary::Arbitrary;
+use common::*;
+use libfuzzer_sys::fuzz_target;
+use soondex::{LiquidityPool, UserState, MINIMUM_LIQUIDITY};
+use soondex_client::instructions::{self, RewardAccounts, StakeAccounts};
+
+const USERS: usize = 3;
+const MAX_ACTIONS: usize = 32;
+// sqrt(10_000 * 10_000) meets the pool's default first deposit minimum
+const MIN_INITIAL_RESERVE: u64 = 10_000;
+
+#[derive(Arbitrary, Debug)]
+struct Scenario {
+    initial_x: u32,
+    initial_y: u32,
+    reward_rate: u8,
+    actions: Vec<Action>,
+}
+
+#[derive(Arbitrary, Debug, Clone, Copy)]
+enum Action {
+    /// Deposits `amount_y` and, unless `at_pool_ratio` is false, the matching X
+    AddLiquidity { user: u8, amount_x: u32, amount_y: u32, at_pool_ratio: bool },
+    /// Withdraws `share_bps` of the user's first position, nudged by the skews to probe rounding
+    RemoveLiquidity { user: u8, share_bps: u16, skew_x: i8, skew_y: i8 },
+    Swap { user: u8, x_to_y: bool, amount_in: u32 },
+    Stake { user: u8, amount: u32 },
+    Unstake { user: u8, amount: u32 },
+    ClaimRewards { user: u8 },
+    Warp { seconds: u16 },
+}
+
+struct Actor {
+    user: User,
+    stake: StakeAccounts,
+    rewards: RewardAccounts,
+}
+
+struct ActorSnapshot {
+    token_x: u64,
+    token_y: u64,
+    stake_tokens: u64,
+    reward_tokens: u64,
+    user_state: Option<UserState>,
+}
+
+struct Snapshot {
+    pool_data: Vec<u8>,
+    pool: LiquidityPool,
+    vault_x: u64,
+    vault_y: u64,
+    actors: Vec<ActorSnapshot>,
+}
+
+struct Fuzzer {
+    harness: Harness,
+    actors: Vec<Actor>,
+    reward_rate: u64,
+}
+
+impl Fuzzer {
+    async fn new(scenario: &Scenario) -> Self {
+        let mut harness = Harness::with_pool().await;
+        let stake_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+        let reward_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+
+        let mut actors = Vec::with_capacity(USERS);
+        for _ in 0..USERS {
+            let user = harness.create_user().await;
+            let stake = harness.stake_accounts(&user, &stake_mint).await;
+            harness.mint_to(&stake_mint, &stake.user_token_account, INITIAL_USER_TOKENS).await;
+            let rewards = harness.reward_accounts(&user, &reward_mint, INITIAL_USER_TOKENS).await;
+            actors.push(Actor { user, stake, rewards });
+        }
+
+        // Unstaking pays rewards out of the stake vault, so it holds a reward budget too
+        let stake_vault = actors[0].stake.pool_token_account;
+        harness.mint_to(&stake_mint, &stake_vault, INITIAL_USER_TOKENS).await;
+
+        let reward_rate = scenario.reward_rate as u64;
+        let ix = instructions::set_reward_rate(&harness.keys, harness.payer(), reward_rate);
+        harness.send(&[ix], &[]).await.unwrap();
+
+        let initial_x = (scenario.initial_x as u64).max(MIN_INITIAL_RESERVE);
+        let initial_y = (scenario.initial_y as u64).max(MIN_INITIAL_RESERVE);
+        harness.add_liquidity(&actors[0].user, initial_x, initial_y).await.unwrap();
+
+        Self { harness, actors, reward_rate }
+    }
+
+    async fn snapshot(&mut self) -> Snapshot {
+        let harness = &mut self.harness;
+        let pool_address = harness.pool();
+        let pool_data = harness.account(&pool_address).await.unwrap().data;
+        let pool = LiquidityPool::try_deserialize(&mut pool_data.as_slice()).unwrap();
+        let (vault_x, vault_y) = (harness.keys.vault_x(), harness.keys.vault_y());
+
+        let mut actors = Vec::with_capacity(self.actors.len());
+        for actor in &self.actors {
+            let user_state_address = harness.keys.user_state(&actor.user.pubkey());
+            let user_state = harness
+                .account(&user_state_address)
+                .await
+                .map(|account| UserState::try_deserialize(&mut account.data.as_slice()).unwrap());
+            actors.push(ActorSnapshot {
+                token_x: harness.token_balance(&actor.user.token_x).await,
+                token_y: harness.token_balance(&actor.user.token_y).await,
+                stake_tokens: harness.token_balance(&actor.stake.user_token_account).await,
+                reward_tokens: harness.token_balance(&actor.rewards.user_reward_account).await,
+                user_state,
+            });
+        }
+
+        Snapshot {
+            pool_data,
+            pool,
+            vault_x: harness.token_balance(&vault_x).await,
+            vault_y: harness.token_balance(&vault_y).await,
+            actors,
+        }
+    }
+
+    /// Sends the action's instruction; `false` when the program rejected it
+    async fn apply(&mut self, action: Action, before: &Snapshot) -> bool {
+        let harness = &mut self.harness;
+        let actor = &self.actors[user_index(action)];
+        let pool = &before.pool;
+
+        let result = match action {
+            Action::AddLiquidity { amount_x, amount_y, at_pool_ratio, .. } => {
+                let amount_y = amount_y as u64;
+                let amount_x = if at_pool_ratio && pool.token_y_reserve > 0 {
+                    mul_div(amount_y, pool.token_x_reserve, pool.token_y_reserve)
+                } else {
+                    amount_x as u64
+                };
+                harness.add_liquidity(&actor.user, amount_x, amount_y).await
+            }
+            Action::RemoveLiquidity { share_bps, skew_x, skew_y, .. } => {
+                let position = first_position(pool, &actor.user).unwrap_or(0);
+                let share = mul_div(position, share_bps.min(10_000) as u64, 10_000);
+                let amount_x = mul_div(share, pool.token_x_reserve, pool.lp_token_supply.max(1));
+                let amount_y = mul_div(share, pool.token_y_reserve, pool.lp_token_supply.max(1));
+                harness
+                    .remove_liquidity(
+                        &actor.user,
+                        amount_x.saturating_add_signed(skew_x as i64),
+                        amount_y.saturating_add_signed(skew_y as i64),
+                    )
+                    .await
+            }
+            Action::Swap { x_to_y, amount_in, .. } => harness.swap(&actor.user, x_to_y, amount_in as u64, 1).await,
+            Action::Stake { amount, .. } => {
+                let ix = instructions::stake(&harness.keys, actor.user.pubkey(), actor.stake, amount as u64);
+                harness.send(&[ix], &[&actor.user.keypair]).await
+            }
+            Action::Unstake { amount, .. } => {
+                let ix = instructions::unstake(&harness.keys, actor.user.pubkey(), actor.stake, amount as u64);
+                harness.send(&[ix], &[&actor.user.keypair]).await
+            }
+            Action::ClaimRewards { .. } => {
+                let ix = instructions::claim_rewards(&harness.keys, actor.user.pubkey(), actor.rewards);
+                harness.send(&[ix], &[&actor.user.keypair]).await
+            }
+            Action::Warp { seconds } => {
+                harness.warp_seconds(seconds as i64).await;
+                Ok(())
+            }
+        };
+        result.is_ok()
+    }
+
+    fn check(&self, action: Action, succeeded: bool, before: &Snapshot, after: &Snapshot) {
+        let (pool_before, pool) = (&before.pool, &after.pool);
+
+        assert_eq!(after.vault_x, pool.token_x_reserve, "vault X diverged from reserve after {action:?}");
+        assert_eq!(after.vault_y, pool.token_y_reserve, "vault Y diverged from reserve after {action:?}");
+
+        let total_x: u64 = after.actors.iter().map(|actor| actor.token_x).sum::<u64>() + after.vault_x;
+        let total_y: u64 = after.actors.iter().map(|actor| actor.token_y).sum::<u64>() + after.vault_y;
+        assert_eq!(total_x, USERS as u64 * INITIAL_USER_TOKENS, "X not conserved after {action:?}");
+        assert_eq!(total_y, USERS as u64 * INITIAL_USER_TOKENS, "Y not conserved after {action:?}");
+
+        let ledger: u64 = pool.lp_tokens.iter().map(|balance| balance.amount).sum();
+        assert_eq!(ledger, pool.lp_token_supply, "LP ledger does not sum to supply after {action:?}");
+        let locked = pool.lp_tokens.iter().find(|balance| balance.owner == self.harness.keys.locked_liquidity());
+        assert_eq!(locked.map(|balance| balance.amount), Some(MINIMUM_LIQUIDITY), "locked liquidity moved after {action:?}");
+
+        let staked: u64 = after
+            .actors
+            .iter()
+            .filter_map(|actor| actor.user_state.as_ref())
+            .map(|state| state.amount_staked)
+            .sum();
+        assert_eq!(staked, pool.total_staked, "staking total diverged after {action:?}");
+
+        if !succeeded {
+            assert_eq!(before.pool_data, after.pool_data, "failed {action:?} changed the pool");
+            return;
+        }
+
+        let index = user_index(action);
+        let (actor_before, actor_after) = (&before.actors[index], &after.actors[index]);
+        match action {
+            Action::Swap { .. } => {
+                assert!(k(pool) >= k(pool_before), "swap decreased k: {action:?}");
+            }
+            Action::AddLiquidity { .. } => {
+                assert_backing_not_diluted(pool_before, pool, action);
+            }
+            Action::RemoveLiquidity { .. } => {
+                let burned = (pool_before.lp_token_supply - pool.lp_token_supply) as u128;
+                let paid_x = (actor_after.token_x - actor_before.token_x) as u128;
+                let paid_y = (actor_after.token_y - actor_before.token_y) as u128;
+                let supply = pool_before.lp_token_supply as u128;
+                assert!(
+                    paid_x * supply <= burned * pool_before.token_x_reserve as u128
+                        && paid_y * supply <= burned * pool_before.token_y_reserve as u128,
+                    "withdrawal exceeded pro-rata share: {action:?}"
+                );
+                assert_backing_not_diluted(pool_before, pool, action);
+            }
+            Action::Stake { amount, .. } => {
+                assert_eq!(actor_before.stake_tokens - actor_after.stake_tokens, amount as u64);
+            }
+            Action::Unstake { amount, .. } => {
+                let staked_at = actor_before.user_state.as_ref().unwrap().last_stake_timestamp;
+                let unstaked_at = actor_after.user_state.as_ref().unwrap().last_stake_timestamp;
+                let rewards = soondex_math::staking_rewards(amount as u64, self.reward_rate, unstaked_at - staked_at)
+                    .unwrap();
+                assert_eq!(
+                    actor_after.stake_tokens - actor_before.stake_tokens,
+                    amount as u64 + rewards,
+                    "unstake paid the wrong amount: {action:?}"
+                );
+            }
+            Action::ClaimRewards { .. } => {
+                let user = self.actors[index].user.pubkey();
+                let position = pool_before.lp_tokens.iter().position(|balance| balance.owner == user).unwrap();
+                let (earning, claimed) = (&pool_before.lp_tokens[position], &pool.lp_tokens[position]);
+                let rewards = soondex_math::staking_rewards(
+                    earning.amount,
+                    self.reward_rate,
+                    claimed.last_reward_claim - earning.last_reward_claim,
+                )
+                .unwrap();
+                assert_eq!(
+                    actor_after.reward_tokens - actor_before.reward_tokens,
+                    rewards,
+                    "claim paid the wrong amount: {action:?}"
+                );
+            }
+            Action::Warp { .. } => {}
+        }
+    }
+}
+
+fn user_index(action: Action) -> usize {
+    let user = match action {
+        Action::AddLiquidity { user, .. }
+        | Action::RemoveLiquidity { user, .. }
+        | Action::Swap { user, .. }
+        | Action::Stake { user, .. }
+        | Action::Unstake { user, .. }
+        | Action::ClaimRewards { user } => user,
+        Action::Warp { .. } => 0,
+    };
+    user as usize % USERS
+}
+
+fn first_position(pool: &LiquidityPool, user: &User) -> Option<u64> {
+    pool.lp_tokens
+        .iter()
+        .find(|balance| balance.owner == user.pubkey())
+        .map(|balance| balance.amount)
+}
+
+fn mul_div(value: u64, numerator: u64, denominator: u64) -> u64 {
+    (value as u128 * numerator as u128 / denominator as u128).min(u64::MAX as u128) as u64
+}
+
+fn k(pool: &LiquidityPool) -> u128 {
+    pool.token_x_reserve as u128 * pool.token_y_reserve as u128
+}
+
+/// Deposits and withdrawals round in the pool's favour, so the reserves behind
+/// each LP token never shrink
+fn assert_backing_not_diluted(before: &LiquidityPool, after: &LiquidityPool, action: Action) {
+    if before.lp_token_supply == 0 || after.lp_token_supply == 0 {
+        return;
+    }
+    let (supply_before, supply_after) = (before.lp_token_supply as u128, after.lp_token_supply as u128);
+    assert!(
+        after.token_x_reserve as u128 * supply_before >= before.token_x_reserve as u128 * supply_after
+            && after.token_y_reserve as u128 * supply_before >= before.token_y_reserve as u128 * supply_after,
+        "LP backing diluted by {action:?}"
+    );
+}
+
+async fn run(scenario: Scenario) {
+    let mut fuzzer = Fuzzer::new(&scenario).await;
+    let mut before = fuzzer.snapshot().await;
+
+    for &action in scenario.actions.iter().take(MAX_ACTIONS) {
+        let succeeded = fuzzer.apply(action, &before).await;
+        let after = fuzzer.snapshot().await;
+        fuzzer.check(action, succeeded, &before, &after);
+        before = after;
+    }
+}
+
+fuzz_target!(|scenario: Scenario| {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(run(scenario));
+});
@@ -1,22 +1,50 @@
 use anchor_lang::{
     prelude::*,
+    solana_program::{
+        instruction::Instruction,
+        program::invoke,
+    },
+    solana_program::sysvar::instructions::{
+        self as instructions_sysvar,
+        load_current_index_checked,
+        load_instruction_at_checked,
+    },
     system_program,
+    Discriminator,
 };
 
 use anchor_spl::{
     associated_token,
-    token::{self, SyncNative},
+    token,
+    token_2022::{
+        self,
+        spl_token_2022::{
+            self,
+            extension::{
+                transfer_fee::{self, TransferFeeAmount, TransferFeeConfig},
+                BaseStateWithExtensions,
+                ExtensionType,
+                StateWithExtensions,
+            },
+        },
+    },
     token_interface::{
         Mint, 
         TokenAccount, 
         TokenInterface,
+        close_account,
+        initialize_account3,
+        sync_native,
         transfer_checked,
+        CloseAccount,
+        InitializeAccount3,
+        SyncNative,
         TransferChecked,
     },
 };
 
 use crate::associated_token::AssociatedToken;
-use integer_sqrt::IntegerSquareRoot;
+use soondex_math::{MathError, BPS_DENOMINATOR};
 use spl_token::native_mint::ID as NATIVE_MINT_ID;
 
 
@@ -24,9 +52,50 @@ use spl_token::native_mint::ID as NATIVE_MINT_ID;
 
 // Pool constants
 pub const POOL_SEED: &[u8] = b"pool";
+pub const POOL_REGISTRY_SEED: &[u8] = b"pool_registry";
+pub const USER_STATE_SEED: &[u8] = b"user_state";
+pub const POOLS_PER_REGISTRY_PAGE: u64 = 100;
+pub const TEMP_WSOL_SEED: &[u8] = b"temp_wsol";
+pub const LOCKED_LIQUIDITY_SEED: &[u8] = b"locked_liquidity";
+pub const LIMIT_ORDER_SEED: &[u8] = b"limit_order";
+pub const TWAMM_SEED: &[u8] = b"twamm";
+pub const LONG_TERM_ORDER_SEED: &[u8] = b"long_term_order";
+pub const DCA_VAULT_SEED: &[u8] = b"dca_vault";
+pub const LP_VAULT_SEED: &[u8] = b"lp_vault";
+pub const VAULT_POSITION_SEED: &[u8] = b"vault_position";
 pub const MAX_FEE_RATE: u64 = 5000; // 50% in basis points
-pub const TOTAL_FEE_RATE: u64 = 25; // 0.25% in basis points
+pub const FEE_TIERS: &[u64] = &[1, 5, 25, 100]; // basis points; each pair has at most one pool per tier
+pub const TOTAL_FEE_RATE: u64 = soondex_math::TOTAL_FEE_RATE; // 0.25% in basis points, the standard fee tier
 pub const PROTOCOL_FEE_LAMPORTS: u64 = 150_000_000; // 0.15 SOL in lamports
+pub const FLASH_LOAN_FEE_RATE: u64 = 9; // 0.09% in basis points
+pub const MAX_REFERRAL_FEE_RATE: u64 = 500; // 5% in basis points, the highest referral cap admins can set
+pub const LIMIT_ORDER_KEEPER_FEE_RATE: u64 = 10; // 0.1% of a limit order's input, paid to whoever fills it
+pub const TWAMM_ORDER_INTERVAL: i64 = 14_400; // 4 hours; long-term orders expire on multiples of it
+pub const TWAMM_MAX_INTERVALS: usize = 42; // one week, the longest long-term order
+pub const TWAMM_RATE_PRECISION: u128 = 1_000_000_000; // sell rates are tokens per second scaled by this
+pub const TWAMM_EARNINGS_PRECISION: u128 = 1_000_000_000_000_000_000; // scale of proceeds per unit of sell rate
+pub const DCA_KEEPER_FEE_RATE: u64 = 10; // 0.1% of each DCA execution's input, paid to whoever triggers it
+pub const MIN_DCA_INTERVAL: i64 = 60; // seconds; shorter TWAP windows are cheap to manipulate
+pub const MAX_PERFORMANCE_FEE_RATE: u64 = 2_000; // 20% in basis points, the most an LP vault keeps of harvested rewards
+pub const MIN_HARVEST_INTERVAL: i64 = 60; // seconds; harvest swaps are checked against the TWAP since the last harvest
+pub const MINIMUM_LIQUIDITY: u64 = 1_000; // LP tokens the first deposit locks forever
+pub const DEFAULT_MIN_INITIAL_LIQUIDITY: u64 = 10_000; // LP tokens, the first deposit floor new pools start with
+// Token-2022 mint extensions that keep vault balances and pool reserves consistent
+pub const ALLOWED_MINT_EXTENSIONS: &[ExtensionType] = &[
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::TransferHook,
+    ExtensionType::MintCloseAuthority,
+    ExtensionType::InterestBearingConfig,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+    ExtensionType::GroupPointer,
+    ExtensionType::TokenGroup,
+    ExtensionType::GroupMemberPointer,
+    ExtensionType::TokenGroupMember,
+];
+// sha256("global:soondex_flash_swap_callback")[..8], so Anchor receivers can expose
+// `soondex_flash_swap_callback(sender, input_token, amount_in, amount_out, data)`
+pub const FLASH_SWAP_CALLBACK_DISCRIMINATOR: [u8; 8] = [26, 53, 54, 227, 228, 226, 235, 201];
 declare_id!("8BPmoTqwHpFGECrtmWbDSz8wDXj5X7e32qdkLrNhQVqy");
 
 #[program]
@@ -41,7 +110,9 @@ pub mod soondex {
         fee_rate: u64,
     ) -> Result<()> {
         // CHECKS
-        require!(fee_rate <= MAX_FEE_RATE, ErrorCode::InvalidFeeRate);
+        require!(FEE_TIERS.contains(&fee_rate), ErrorCode::InvalidFeeRate);
+        // One pool per unordered pair and fee tier
+        require!(token_x_mint < token_y_mint, ErrorCode::InvalidMintOrder);
         require!(
             ctx.accounts.token_x_mint.key() == token_x_mint,
             ErrorCode::InvalidToken
@@ -54,6 +125,8 @@ pub mod soondex {
             ctx.accounts.payer.lamports() >= PROTOCOL_FEE_LAMPORTS,
             ErrorCode::InsufficientFunds
         );
+        validate_mint_extensions(&ctx.accounts.token_x_mint.to_account_info())?;
+        validate_mint_extensions(&ctx.accounts.token_y_mint.to_account_info())?;
         
         let liquidity_pool = &mut ctx.accounts.liquidity_pool;
         liquidity_pool.bump = ctx.bumps.liquidity_pool;
@@ -71,6 +144,25 @@ pub mod soondex {
         liquidity_pool.lp_token_supply = 0;
         liquidity_pool.token_x_mint = token_x_mint;
         liquidity_pool.token_y_mint = token_y_mint;
+        liquidity_pool.swap_fee_rate = fee_rate;
+        liquidity_pool.paused = false;
+        liquidity_pool.min_initial_liquidity = DEFAULT_MIN_INITIAL_LIQUIDITY;
+        liquidity_pool.token_x_vault = ctx.accounts.pool_token_x_account.key();
+        liquidity_pool.token_y_vault = ctx.accounts.pool_token_y_account.key();
+        liquidity_pool.event_sequence = 0;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // Register the pool so indexers and routers can enumerate it
+        let pool_registry = &mut ctx.accounts.pool_registry;
+        let pool_registry_page = &mut ctx.accounts.pool_registry_page;
+        pool_registry.bump = ctx.bumps.pool_registry;
+        pool_registry_page.page = pool_registry.pool_count / POOLS_PER_REGISTRY_PAGE;
+        pool_registry_page.bump = ctx.bumps.pool_registry_page;
+        pool_registry_page.pools.push(liquidity_pool.key());
+        liquidity_pool.registry_page = pool_registry_page.page;
+        pool_registry.pool_count = pool_registry.pool_count
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
 
         // INTERACTIONS
         anchor_lang::system_program::transfer(
@@ -84,83 +176,27 @@ pub mod soondex {
             PROTOCOL_FEE_LAMPORTS,
         )?;
 
-        // Create associated token accounts
-        let token_x_key = token_x_mint.key();
-        let token_y_key = token_y_mint.key();
-        
-        let bump = Pubkey::find_program_address(
-            &[
-                POOL_SEED,
-                token_x_key.as_ref(),
-                token_y_key.as_ref()
-            ],
-            ctx.program_id
-        ).1;
-        
-        let seeds = &[
-            POOL_SEED,
-            token_x_key.as_ref(),
-            token_y_key.as_ref(),
-            &[bump]
-        ];
-
-        // Create token X account
-        let pool_token_x_address = associated_token::get_associated_token_address(
-            &liquidity_pool.key(),
-            &ctx.accounts.token_x_mint.key(),
-        );
-
-        if !ctx.accounts.pool_token_x_account.to_account_info().key.eq(&pool_token_x_address) {
-            associated_token::create(CpiContext::new_with_signer(
-                ctx.accounts.associated_token_program.to_account_info(),
-                associated_token::Create {
-                    payer: ctx.accounts.payer.to_account_info(),
-                    authority: liquidity_pool.to_account_info(),
-                    associated_token: ctx.accounts.pool_token_x_account.to_account_info(),
-                    mint: ctx.accounts.token_x_mint.to_account_info(),
-                    system_program: ctx.accounts.system_program.to_account_info(),
-                    token_program: ctx.accounts.token_program.to_account_info(),
-                },
-                &[seeds],
-            ))?;
-        }
-
-        // Create token Y account 
-        let pool_token_y_address = associated_token::get_associated_token_address(
-            &liquidity_pool.key(),
-            &ctx.accounts.token_y_mint.key(),
-        );
-
-        if !ctx.accounts.pool_token_y_account.to_account_info().key.eq(&pool_token_y_address) {
-            associated_token::create(CpiContext::new_with_signer(
-                ctx.accounts.associated_token_program.to_account_info(),
-                associated_token::Create {
-                    payer: ctx.accounts.payer.to_account_info(),
-                    authority: liquidity_pool.to_account_info(),
-                    associated_token: ctx.accounts.pool_token_y_account.to_account_info(),
-                    mint: ctx.accounts.token_y_mint.to_account_info(),
-                    system_program: ctx.accounts.system_program.to_account_info(),
-                    token_program: ctx.accounts.token_program.to_account_info(),
-                },
-                &[seeds],
-            ))?;
-        }
-
         emit!(PoolInitialized {
+            pool: liquidity_pool.key(),
             authority: liquidity_pool.authority,
+            token_x_mint,
+            token_y_mint,
             fee_rate,
+            sequence,
         });
 
         Ok(())
     }
 
+    /// Closes a pool that never received liquidity. The first deposit locks `MINIMUM_LIQUIDITY`
+    /// LP tokens that can never be burned, so a pool that was ever funded is permanent.
     pub fn remove_pool(
         ctx: Context<RemovePool>,
         _token_x_mint: Pubkey,
         _token_y_mint: Pubkey,
     ) -> Result<()> {
         // CHECKS: Validate all conditions before making any state changes
-        let liquidity_pool = &ctx.accounts.liquidity_pool;
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
         
         // Verify token mints match the pool configuration
         require!(
@@ -188,6 +224,15 @@ pub mod soondex {
         );
     
         // EFFECTS: Modify program state before any external interactions
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // Unlist the pool so the registry only holds live pools
+        let pool_key = liquidity_pool.key();
+        let pools = &mut ctx.accounts.pool_registry_page.pools;
+        if let Some(index) = pools.iter().position(|pool| *pool == pool_key) {
+            pools.swap_remove(index);
+        }
+
         // Prepare to close the account and return lamports
         let current_balance = liquidity_pool.to_account_info().lamports();
         
@@ -201,7 +246,10 @@ pub mod soondex {
         emit!(PoolRemovedEvent {
             pool: liquidity_pool.key(),
             authority: ctx.accounts.authority.key(),
-            timestamp: Clock::get()?.unix_timestamp
+            token_x_mint: liquidity_pool.token_x_mint,
+            token_y_mint: liquidity_pool.token_y_mint,
+            timestamp: Clock::get()?.unix_timestamp,
+            sequence,
         });
         
         Ok(())
@@ -240,28 +288,120 @@ pub mod soondex {
             liquidity_pool.admins.retain(|&x| x != admin_address);
         }
     
+        let sequence = liquidity_pool.next_event_sequence()?;
+
         // Emit event for admin changes
         emit!(AdminUpdated {
+            pool: liquidity_pool.key(),
             admin: admin_address,
             is_added: is_add,
             super_admin: ctx.accounts.authority.key(),
+            token_x_mint: liquidity_pool.token_x_mint,
+            token_y_mint: liquidity_pool.token_y_mint,
+            sequence,
         });
     
         Ok(())
     }
 
-    pub fn add_liquidity(
-        ctx: Context<ProvideLiquidity>,
+    pub fn set_swap_fee(ctx: Context<UpdatePoolConfig>, swap_fee_rate: u64) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(liquidity_pool.is_admin(&ctx.accounts.authority.key()), ErrorCode::Unauthorized);
+        // The fee tier is part of the pool's address, so the fee may be lowered but never raised past it
+        require!(swap_fee_rate <= liquidity_pool.fee_rate, ErrorCode::InvalidFeeRate);
+
+        // EFFECTS
+        liquidity_pool.swap_fee_rate = swap_fee_rate;
+        emit_pool_config_updated(liquidity_pool, ctx.accounts.authority.key())
+    }
+
+    /// Rewards accrue at `reward_rate` basis points of the position per second
+    pub fn set_reward_rate(ctx: Context<UpdatePoolConfig>, reward_rate: u64) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(liquidity_pool.is_admin(&ctx.accounts.authority.key()), ErrorCode::Unauthorized);
+
+        // EFFECTS
+        // Rewards up to now accrue at the old rate
+        liquidity_pool.update_reward_cumulative(Clock::get()?.unix_timestamp)?;
+        liquidity_pool.reward_rate = reward_rate;
+        emit_pool_config_updated(liquidity_pool, ctx.accounts.authority.key())
+    }
+
+    pub fn set_paused(ctx: Context<UpdatePoolConfig>, paused: bool) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(liquidity_pool.is_admin(&ctx.accounts.authority.key()), ErrorCode::Unauthorized);
+
+        // EFFECTS
+        liquidity_pool.paused = paused;
+        emit_pool_config_updated(liquidity_pool, ctx.accounts.authority.key())
+    }
+
+    /// Limits on a single swap's input as a share of the input reserve and on its
+    /// price impact, plus a cap on swap input per slot summed as shares of the input
+    /// reserve. All in basis points; zero disables a limit
+    pub fn set_swap_guardrails(
+        ctx: Context<UpdatePoolConfig>,
+        max_swap_input_bps: u64,
+        max_price_impact_bps: u64,
+        max_slot_volume_bps: u64,
+    ) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(liquidity_pool.is_admin(&ctx.accounts.authority.key()), ErrorCode::Unauthorized);
+        require!(
+            max_swap_input_bps <= BPS_DENOMINATOR && max_price_impact_bps <= BPS_DENOMINATOR,
+            ErrorCode::InvalidGuardrail
+        );
+
+        // EFFECTS
+        liquidity_pool.max_swap_input_bps = max_swap_input_bps;
+        liquidity_pool.max_price_impact_bps = max_price_impact_bps;
+        liquidity_pool.max_slot_volume_bps = max_slot_volume_bps;
+        emit_pool_config_updated(liquidity_pool, ctx.accounts.authority.key())
+    }
+
+    /// Highest referral fee, in basis points of the input, that swaps may pay their referrer
+    pub fn set_max_referral_fee(ctx: Context<UpdatePoolConfig>, max_referral_fee_bps: u64) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(liquidity_pool.is_admin(&ctx.accounts.authority.key()), ErrorCode::Unauthorized);
+        require!(max_referral_fee_bps <= MAX_REFERRAL_FEE_RATE, ErrorCode::InvalidFeeRate);
+
+        // EFFECTS
+        liquidity_pool.max_referral_fee_bps = max_referral_fee_bps;
+        emit_pool_config_updated(liquidity_pool, ctx.accounts.authority.key())
+    }
+
+    /// LP tokens the first deposit must mint, counting the `MINIMUM_LIQUIDITY` it locks
+    pub fn set_min_initial_liquidity(ctx: Context<UpdatePoolConfig>, min_initial_liquidity: u64) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(liquidity_pool.is_admin(&ctx.accounts.authority.key()), ErrorCode::Unauthorized);
+
+        // EFFECTS
+        liquidity_pool.min_initial_liquidity = min_initial_liquidity;
+        emit_pool_config_updated(liquidity_pool, ctx.accounts.authority.key())
+    }
+
+    pub fn add_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ProvideLiquidity<'info>>,
         token_x_mint: Pubkey,
         token_y_mint: Pubkey,
         amount_x: u64,
         amount_y: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
         // CHECKS
         let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        check_deadline(deadline)?;
         
         // Validate input amounts
         require!(amount_x > 0 && amount_y > 0, ErrorCode::InvalidLiquidityAmount);
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
         
         // Verify token mints
         require!(
@@ -273,16 +413,31 @@ pub mod soondex {
             ErrorCode::InvalidToken
         );
         
-        // Verify balances
-        require!(
-            ctx.accounts.user_token_x_account.amount >= amount_x,
-            ErrorCode::InsufficientFunds
-        );
+        // Native SOL sides are paid as lamports when the user omits the token account
+        let is_sol_x = token_x_mint == NATIVE_MINT_ID && ctx.accounts.user_token_x_account.is_none();
+        let is_sol_y = token_y_mint == NATIVE_MINT_ID && ctx.accounts.user_token_y_account.is_none();
         require!(
-            ctx.accounts.user_token_y_account.amount >= amount_y,
-            ErrorCode::InsufficientFunds
+            !(is_sol_x || is_sol_y) || ctx.accounts.temp_wsol_account.is_some(),
+            ErrorCode::MissingWsolAccount
         );
-        
+
+        // Verify balances
+        let user_balance_x = match &ctx.accounts.user_token_x_account {
+            Some(account) => account.amount,
+            None if is_sol_x => ctx.accounts.user.lamports(),
+            None => return err!(ErrorCode::MissingTokenAccount),
+        };
+        let user_balance_y = match &ctx.accounts.user_token_y_account {
+            Some(account) => account.amount,
+            None if is_sol_y => ctx.accounts.user.lamports(),
+            None => return err!(ErrorCode::MissingTokenAccount),
+        };
+        require!(user_balance_x >= amount_x, ErrorCode::InsufficientFunds);
+        require!(user_balance_y >= amount_y, ErrorCode::InsufficientFunds);
+
+        // Long-term orders trade first, at the prices they saw
+        update_pool(liquidity_pool, ctx.accounts.twamm.as_deref_mut().map(|twamm| &mut **twamm))?;
+
         // Verify ratio
         if liquidity_pool.token_x_reserve > 0 {
             let expected_ratio = (amount_y as u128)
@@ -296,277 +451,456 @@ pub mod soondex {
             );
         }
     
-        // Calculate LP tokens
-        let lp_tokens = liquidity_pool.calculate_lp_tokens(amount_x, amount_y)?;
+        // Transfer-fee mints deliver less than the nominal amount to the vaults
+        let received_x = amount_x
+            .checked_sub(calculate_transfer_fee(&ctx.accounts.token_x_mint, amount_x)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let received_y = amount_y
+            .checked_sub(calculate_transfer_fee(&ctx.accounts.token_y_mint, amount_y)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(received_x > 0 && received_y > 0, ErrorCode::InvalidLiquidityAmount);
+    
+        // Calculate LP tokens; the first deposit also locks MINIMUM_LIQUIDITY
+        let (lp_tokens, locked_lp_tokens) = liquidity_pool.lp_tokens_for_deposit(received_x, received_y)?;
     
         // EFFECTS
         // Update reserves
         liquidity_pool.token_x_reserve = liquidity_pool.token_x_reserve
-            .checked_add(amount_x)
+            .checked_add(received_x)
             .ok_or(ErrorCode::MathOverflow)?;
         liquidity_pool.token_y_reserve = liquidity_pool.token_y_reserve
-            .checked_add(amount_y)
+            .checked_add(received_y)
             .ok_or(ErrorCode::MathOverflow)?;
         liquidity_pool.lp_token_supply = liquidity_pool.lp_token_supply
             .checked_add(lp_tokens)
+            .and_then(|supply| supply.checked_add(locked_lp_tokens))
             .ok_or(ErrorCode::MathOverflow)?;
     
         // Record LP tokens
-        liquidity_pool.lp_tokens.push(LpTokenBalance {
-            owner: ctx.accounts.user.key(),
-            amount: lp_tokens,
-            last_reward_claim: Clock::get()?.unix_timestamp,
-        });
+        let now = Clock::get()?.unix_timestamp;
+        let reward_checkpoint = liquidity_pool.update_reward_cumulative(now)?;
+        // Each provider keeps a single position, so repeat deposits don't use up `lp_tokens`
+        let user_key = ctx.accounts.user.key();
+        match liquidity_pool.lp_tokens.iter_mut().find(|x| x.owner == user_key) {
+            Some(user_lp_balance) => {
+                // Carry what the position has earned so far, so the new tokens earn from now on
+                let pending_rewards = calculate_rewards(
+                    user_lp_balance.amount,
+                    reward_checkpoint,
+                    user_lp_balance.reward_checkpoint,
+                )?;
+                user_lp_balance.rewards_earned = user_lp_balance.rewards_earned
+                    .checked_add(pending_rewards)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                user_lp_balance.amount = user_lp_balance.amount
+                    .checked_add(lp_tokens)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                user_lp_balance.reward_checkpoint = reward_checkpoint;
+            }
+            None => liquidity_pool.lp_tokens.push(LpTokenBalance {
+                owner: user_key,
+                amount: lp_tokens,
+                last_reward_claim: now,
+                reward_checkpoint,
+                rewards_earned: 0,
+            }),
+        }
+        if locked_lp_tokens > 0 {
+            // No instruction signs for this address, so the position can never be withdrawn
+            let owner = locked_liquidity_address(&liquidity_pool.key());
+            liquidity_pool.lp_tokens.push(LpTokenBalance {
+                owner,
+                amount: locked_lp_tokens,
+                last_reward_claim: now,
+                reward_checkpoint,
+                rewards_earned: 0,
+            });
+        }
+        let sequence = liquidity_pool.next_event_sequence()?;
     
         // INTERACTIONS
+        let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+        let pool_seeds: &[&[u8]] = &[
+            POOL_SEED,
+            token_x_mint.as_ref(),
+            token_y_mint.as_ref(),
+            fee_rate_bytes.as_ref(),
+            &[liquidity_pool.bump],
+        ];
+
         // Transfer token X
-        transfer_checked(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.user_token_x_account.to_account_info(),
-                    to: ctx.accounts.pool_token_x_account.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                    mint: ctx.accounts.token_x_mint.to_account_info(),
-                },
-            ),
-            amount_x,
-            ctx.accounts.token_x_mint.decimals,
-        )?;
+        match &ctx.accounts.user_token_x_account {
+            Some(user_token_x_account) => transfer_tokens(
+                &ctx.accounts.token_x_program,
+                user_token_x_account.to_account_info(),
+                ctx.accounts.pool_token_x_account.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.token_x_mint,
+                ctx.remaining_accounts,
+                amount_x,
+                &[],
+            )?,
+            None => NativeSolTransfer {
+                user: &ctx.accounts.user,
+                temp_wsol_account: ctx.accounts.temp_wsol_account.as_ref().ok_or(ErrorCode::MissingWsolAccount)?,
+                temp_wsol_bump: ctx.bumps.temp_wsol_account.ok_or(ErrorCode::MissingWsolAccount)?,
+                native_mint: &ctx.accounts.token_x_mint,
+                pool_vault: ctx.accounts.pool_token_x_account.to_account_info(),
+                liquidity_pool: liquidity_pool.to_account_info(),
+                token_program: &ctx.accounts.token_x_program,
+                system_program: &ctx.accounts.system_program,
+                pool_seeds,
+            }.wrap(amount_x)?,
+        }
     
         // Transfer token Y
-        transfer_checked(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.user_token_y_account.to_account_info(),
-                    to: ctx.accounts.pool_token_y_account.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                    mint: ctx.accounts.token_y_mint.to_account_info(),
-                },
-            ),
-            amount_y,
-            ctx.accounts.token_y_mint.decimals,
-        )?;
+        match &ctx.accounts.user_token_y_account {
+            Some(user_token_y_account) => transfer_tokens(
+                &ctx.accounts.token_y_program,
+                user_token_y_account.to_account_info(),
+                ctx.accounts.pool_token_y_account.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.token_y_mint,
+                ctx.remaining_accounts,
+                amount_y,
+                &[],
+            )?,
+            None => NativeSolTransfer {
+                user: &ctx.accounts.user,
+                temp_wsol_account: ctx.accounts.temp_wsol_account.as_ref().ok_or(ErrorCode::MissingWsolAccount)?,
+                temp_wsol_bump: ctx.bumps.temp_wsol_account.ok_or(ErrorCode::MissingWsolAccount)?,
+                native_mint: &ctx.accounts.token_y_mint,
+                pool_vault: ctx.accounts.pool_token_y_account.to_account_info(),
+                liquidity_pool: liquidity_pool.to_account_info(),
+                token_program: &ctx.accounts.token_y_program,
+                system_program: &ctx.accounts.system_program,
+                pool_seeds,
+            }.wrap(amount_y)?,
+        }
     
         // Emit event
         emit!(LiquidityProvided {
+            pool: liquidity_pool.key(),
             user: ctx.accounts.user.key(),
-            token_x_amount: amount_x,
-            token_y_amount: amount_y,
+            token_x_mint,
+            token_y_mint,
+            token_x_amount: received_x,
+            token_y_amount: received_y,
             lp_tokens_minted: lp_tokens,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
         });
     
         Ok(())
     }
 
 
-    pub fn swap_tokens(
-        ctx: Context<SwapTokens>,
+    /// `remaining_accounts` holds `hook_account_count` transfer-hook extra accounts
+    /// followed by the accounts forwarded to a flash swap callback
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap_tokens<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapTokens<'info>>,
         input_token: Pubkey, 
         output_token: Pubkey,
         amount_in: u64,
         minimum_amount_out: u64,
+        callback_data: Option<Vec<u8>>,
+        deadline: Option<i64>,
+        referral_fee_bps: u64,
+        hook_account_count: u8,
     ) -> Result<()> {
         // CHECKS
         let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        check_deadline(deadline)?;
+        require!(
+            usize::from(hook_account_count) <= ctx.remaining_accounts.len(),
+            ErrorCode::InvalidHookAccountCount
+        );
+        let (hook_accounts, callback_accounts) = ctx.remaining_accounts.split_at(hook_account_count.into());
         
         // Input validation
         require!(amount_in > 0, ErrorCode::InvalidSwapInput);
         require!(minimum_amount_out > 0, ErrorCode::InvalidSwapInput);
         require!(input_token != output_token, ErrorCode::InvalidTokenPair);
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
         
-        // Handle SOL validation
-        let is_sol_input = input_token == NATIVE_MINT_ID;
-        let is_sol_output = output_token == NATIVE_MINT_ID;
-        
-        // Validate tokens/SOL against pool
-        if !is_sol_input {
+        // Native SOL sides are paid and received as lamports when the user omits the token account
+        let is_sol_input = input_token == NATIVE_MINT_ID && ctx.accounts.user_token_in.is_none();
+        let is_sol_output = output_token == NATIVE_MINT_ID && ctx.accounts.user_token_out.is_none();
+        require!(
+            !(is_sol_input || is_sol_output) || ctx.accounts.temp_wsol_account.is_some(),
+            ErrorCode::MissingWsolAccount
+        );
+        require!(
+            is_sol_output || ctx.accounts.user_token_out.is_some(),
+            ErrorCode::MissingTokenAccount
+        );
+
+        // Flash swaps send the output first and let the callback pay the input
+        let is_flash_swap = callback_data.is_some();
+        if is_flash_swap {
+            require!(!is_sol_input && !is_sol_output, ErrorCode::InvalidSwapInput);
             require!(
-                input_token == liquidity_pool.token_x_mint || input_token == liquidity_pool.token_y_mint,
-                ErrorCode::InvalidToken
+                ctx.accounts.callback_program.as_ref()
+                    .is_some_and(|program| program.key() != crate::ID),
+                ErrorCode::MissingCallbackProgram
             );
-        }
-        if !is_sol_output {
+        } else {
             require!(
-                output_token == liquidity_pool.token_x_mint || output_token == liquidity_pool.token_y_mint,
-                ErrorCode::InvalidToken
+                is_sol_input || ctx.accounts.user_token_in.is_some(),
+                ErrorCode::MissingTokenAccount
             );
         }
 
-        // Calculate k value
-        let k_before = liquidity_pool.token_x_reserve
-            .checked_mul(liquidity_pool.token_y_reserve)
+        // Referrers are paid in the input mint, out of the input and on top of the swap fee
+        require!(
+            referral_fee_bps <= liquidity_pool.max_referral_fee_bps,
+            ErrorCode::ReferralFeeTooHigh
+        );
+        if referral_fee_bps > 0 {
+            require!(!is_sol_input && !is_flash_swap, ErrorCode::InvalidSwapInput);
+            require!(ctx.accounts.referrer_token.is_some(), ErrorCode::MissingTokenAccount);
+        }
+        let referral_fee = calculate_swap_fee(amount_in, referral_fee_bps)?;
+        let swap_amount_in = amount_in
+            .checked_sub(referral_fee)
             .ok_or(ErrorCode::MathOverflow)?;
+        
+        // Validate tokens against pool
+        require!(
+            input_token == liquidity_pool.token_x_mint || input_token == liquidity_pool.token_y_mint,
+            ErrorCode::InvalidToken
+        );
+        require!(
+            output_token == liquidity_pool.token_x_mint || output_token == liquidity_pool.token_y_mint,
+            ErrorCode::InvalidToken
+        );
+
+        // Long-term orders trade first, at the prices they saw
+        update_pool(liquidity_pool, ctx.accounts.twamm.as_deref_mut().map(|twamm| &mut **twamm))?;
 
         // Determine swap direction and reserves
-        let is_input_token_x = input_token == ctx.accounts.token_x_mint.key() || is_sol_input;
-        let (input_reserve, output_reserve) = if is_input_token_x {
-            (liquidity_pool.token_x_reserve, liquidity_pool.token_y_reserve)
+        let is_input_token_x = input_token == ctx.accounts.token_x_mint.key();
+        let (input_mint, output_mint) = if is_input_token_x {
+            (&ctx.accounts.token_x_mint, &ctx.accounts.token_y_mint)
         } else {
-            (liquidity_pool.token_y_reserve, liquidity_pool.token_x_reserve)
+            (&ctx.accounts.token_y_mint, &ctx.accounts.token_x_mint)
+        };
+        let (input_token_program, output_token_program) = if is_input_token_x {
+            (&ctx.accounts.token_x_program, &ctx.accounts.token_y_program)
+        } else {
+            (&ctx.accounts.token_y_program, &ctx.accounts.token_x_program)
         };
 
-        // Calculate amounts
-        let total_fee_amount = (amount_in as u128)
-            .checked_mul(TOTAL_FEE_RATE as u128)
-            .ok_or(ErrorCode::MathOverflow)?
-            .checked_div(10000)
-            .ok_or(ErrorCode::MathOverflow)? as u64;
-
-        let amount_in_after_fees = amount_in
-            .checked_sub(total_fee_amount)
-            .ok_or(ErrorCode::MathOverflow)?;
-
-        let output_amount = calculate_swap_output(
-            amount_in_after_fees,
-            input_reserve,
-            output_reserve,
-        )?;
+        // Calculate amounts; wrapped SOL never carries a transfer fee
+        let SwapQuote {
+            amount_in_received,
+            fee_amount: total_fee_amount,
+            amount_out: output_amount,
+            amount_out_received: output_received,
+            ..
+        } = price_swap_exact_in(liquidity_pool, input_mint, output_mint, swap_amount_in)?;
 
+        // Slippage is checked against what the user actually receives
         require!(
-            output_amount >= minimum_amount_out,
+            output_received >= minimum_amount_out,
             ErrorCode::ExcessiveSlippage
         );
 
-        // EFFECTS
-        if is_input_token_x {
-            liquidity_pool.token_x_reserve = liquidity_pool.token_x_reserve
-                .checked_add(amount_in)
-                .ok_or(ErrorCode::MathOverflow)?;
-            liquidity_pool.token_y_reserve = liquidity_pool.token_y_reserve
-                .checked_sub(output_amount)
-                .ok_or(ErrorCode::MathOverflow)?;
+        // Admin-set limits against fat-finger and manipulation trades
+        let (reserve_in, reserve_out) = if is_input_token_x {
+            (liquidity_pool.token_x_reserve, liquidity_pool.token_y_reserve)
         } else {
-            liquidity_pool.token_y_reserve = liquidity_pool.token_y_reserve
-                .checked_add(amount_in)
-                .ok_or(ErrorCode::MathOverflow)?;
-            liquidity_pool.token_x_reserve = liquidity_pool.token_x_reserve
-                .checked_sub(output_amount)
-                .ok_or(ErrorCode::MathOverflow)?;
-        }
+            (liquidity_pool.token_y_reserve, liquidity_pool.token_x_reserve)
+        };
+        let slot = Clock::get()?.slot;
+        let slot_volume_bps = liquidity_pool.check_swap_guardrails(
+            amount_in_received,
+            total_fee_amount,
+            output_amount,
+            reserve_in,
+            reserve_out,
+            slot,
+        )?;
 
-        // Verify k value
-        let k_after = liquidity_pool.token_x_reserve
-            .checked_mul(liquidity_pool.token_y_reserve)
-            .ok_or(ErrorCode::MathOverflow)?;
-        require!(k_after >= k_before, ErrorCode::InvalidK);
+        // EFFECTS
+        liquidity_pool.volume_slot = slot;
+        liquidity_pool.slot_volume_bps = slot_volume_bps;
+        liquidity_pool.apply_swap(is_input_token_x, amount_in_received, output_amount)?;
+        let sequence = liquidity_pool.next_event_sequence()?;
 
         // INTERACTIONS
-        if is_sol_input {
-            // Transfer SOL from user
-            system_program::transfer(
-                CpiContext::new(
-                    ctx.accounts.system_program.to_account_info(),
-                    system_program::Transfer {
-                        from: ctx.accounts.user.to_account_info(),
-                        to: ctx.accounts.wsol_account.as_ref().unwrap().to_account_info(),
-                    }
-                ),
-                amount_in
-            )?;
-            
-            // Sync wrapped SOL account
-            token::sync_native(CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                SyncNative {
-                    account: ctx.accounts.wsol_account.as_ref().unwrap().to_account_info(),
-                }
-            ))?;
+        let token_x_key = ctx.accounts.token_x_mint.key();
+        let token_y_key = ctx.accounts.token_y_mint.key();
+        let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+        let pool_seeds: &[&[u8]] = &[
+            POOL_SEED,
+            token_x_key.as_ref(),
+            token_y_key.as_ref(),
+            fee_rate_bytes.as_ref(),
+            &[liquidity_pool.bump],
+        ];
+        let (pool_token_in, pool_token_out) = if is_input_token_x {
+            (ctx.accounts.pool_token_x.to_account_info(), ctx.accounts.pool_token_y.to_account_info())
         } else {
+            (ctx.accounts.pool_token_y.to_account_info(), ctx.accounts.pool_token_x.to_account_info())
+        };
+
+        if is_sol_input {
+            // Wrap the user's lamports into the pool's WSOL vault
+            NativeSolTransfer {
+                user: &ctx.accounts.user,
+                temp_wsol_account: ctx.accounts.temp_wsol_account.as_ref().ok_or(ErrorCode::MissingWsolAccount)?,
+                temp_wsol_bump: ctx.bumps.temp_wsol_account.ok_or(ErrorCode::MissingWsolAccount)?,
+                native_mint: input_mint,
+                pool_vault: pool_token_in.clone(),
+                liquidity_pool: liquidity_pool.to_account_info(),
+                token_program: input_token_program,
+                system_program: &ctx.accounts.system_program,
+                pool_seeds,
+            }.wrap(amount_in)?;
+        } else if !is_flash_swap {
             // Regular token transfer
-            transfer_checked(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    TransferChecked {
-                        from: ctx.accounts.user_token_in.to_account_info(),
-                        to: if is_input_token_x {
-                            ctx.accounts.pool_token_x.to_account_info()
-                        } else {
-                            ctx.accounts.pool_token_y.to_account_info()
-                        },
-                        authority: ctx.accounts.user.to_account_info(),
-                        mint: if is_input_token_x {
-                            ctx.accounts.token_x_mint.to_account_info()
-                        } else {
-                            ctx.accounts.token_y_mint.to_account_info()
-                        },
-                    },
-                ),
-                amount_in,
-                if is_input_token_x {
-                    ctx.accounts.token_x_mint.decimals
-                } else {
-                    ctx.accounts.token_y_mint.decimals
-                },
+            transfer_tokens(
+                input_token_program,
+                ctx.accounts.user_token_in.as_ref().ok_or(ErrorCode::MissingTokenAccount)?.to_account_info(),
+                pool_token_in,
+                ctx.accounts.user.to_account_info(),
+                input_mint,
+                hook_accounts,
+                swap_amount_in,
+                &[],
             )?;
         }
 
-        if is_sol_output {
-            // Close WSOL account to unwrap SOL
-            token::close_account(
-                CpiContext::new(
-                    ctx.accounts.token_program.to_account_info(),
-                    token::CloseAccount {
-                        account: ctx.accounts.wsol_account.as_ref().unwrap().to_account_info(),
-                        destination: ctx.accounts.user.to_account_info(),
-                        authority: ctx.accounts.user.to_account_info(),
-                    }
-                )
+        if referral_fee > 0 {
+            transfer_tokens(
+                input_token_program,
+                ctx.accounts.user_token_in.as_ref().ok_or(ErrorCode::MissingTokenAccount)?.to_account_info(),
+                ctx.accounts.referrer_token.as_ref().ok_or(ErrorCode::MissingTokenAccount)?.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                input_mint,
+                hook_accounts,
+                referral_fee,
+                &[],
             )?;
+        }
+
+        if is_sol_output {
+            // Unwrap from the pool's WSOL vault and pay the user in lamports
+            NativeSolTransfer {
+                user: &ctx.accounts.user,
+                temp_wsol_account: ctx.accounts.temp_wsol_account.as_ref().ok_or(ErrorCode::MissingWsolAccount)?,
+                temp_wsol_bump: ctx.bumps.temp_wsol_account.ok_or(ErrorCode::MissingWsolAccount)?,
+                native_mint: output_mint,
+                pool_vault: pool_token_out,
+                liquidity_pool: liquidity_pool.to_account_info(),
+                token_program: output_token_program,
+                system_program: &ctx.accounts.system_program,
+                pool_seeds,
+            }.unwrap(output_amount)?;
         } else {
             // Regular token transfer
-            transfer_checked(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    TransferChecked {
-                        from: if is_input_token_x {
-                            ctx.accounts.pool_token_y.to_account_info()
-                        } else {
-                            ctx.accounts.pool_token_x.to_account_info()
-                        },
-                        to: ctx.accounts.user_token_out.to_account_info(),
-                        authority: liquidity_pool.to_account_info(),
-                        mint: if is_input_token_x {
-                            ctx.accounts.token_y_mint.to_account_info()
-                        } else {
-                            ctx.accounts.token_x_mint.to_account_info()
-                        },
-                    },
-                    &[&[
-                        b"pool",
-                        ctx.accounts.token_x_mint.key().as_ref(),
-                        ctx.accounts.token_y_mint.key().as_ref(),
-                        &[liquidity_pool.bump]
-                    ]]
-                ),
+            transfer_tokens(
+                output_token_program,
+                pool_token_out,
+                ctx.accounts.user_token_out.as_ref().ok_or(ErrorCode::MissingTokenAccount)?.to_account_info(),
+                liquidity_pool.to_account_info(),
+                output_mint,
+                hook_accounts,
                 output_amount,
-                if is_input_token_x {
-                    ctx.accounts.token_y_mint.decimals
-                } else {
-                    ctx.accounts.token_x_mint.decimals
-                },
+                &[pool_seeds],
             )?;
-            
+        }
+
+        if let Some(data) = callback_data {
+            // The input vault has not been touched yet, so its loaded balance is the pre-swap balance
+            let balance_before = if is_input_token_x {
+                ctx.accounts.pool_token_x.amount
+            } else {
+                ctx.accounts.pool_token_y.amount
+            };
+
+            let callback_program = ctx.accounts.callback_program
+                .as_ref()
+                .ok_or(ErrorCode::MissingCallbackProgram)?;
+
+            let mut callback_ix_data = FLASH_SWAP_CALLBACK_DISCRIMINATOR.to_vec();
+            FlashSwapCallback {
+                sender: ctx.accounts.user.key(),
+                input_token,
+                amount_in,
+                amount_out: output_amount,
+                data,
+            }.serialize(&mut callback_ix_data)?;
+
+            let callback_ix = Instruction {
+                program_id: callback_program.key(),
+                accounts: callback_accounts
+                    .iter()
+                    .map(|account| AccountMeta {
+                        pubkey: account.key(),
+                        is_signer: account.is_signer,
+                        is_writable: account.is_writable,
+                    })
+                    .collect(),
+                data: callback_ix_data,
+            };
+            let mut callback_accounts = callback_accounts.to_vec();
+            callback_accounts.push(callback_program.to_account_info());
+            invoke(&callback_ix, &callback_accounts)?;
+
+            // Verify the callback paid the input into the pool vault
+            let balance_after = if is_input_token_x {
+                ctx.accounts.pool_token_x.reload()?;
+                ctx.accounts.pool_token_x.amount
+            } else {
+                ctx.accounts.pool_token_y.reload()?;
+                ctx.accounts.pool_token_y.amount
+            };
+            require!(
+                balance_after >= balance_before
+                    .checked_add(amount_in_received)
+                    .ok_or(ErrorCode::MathOverflow)?,
+                ErrorCode::FlashSwapNotRepaid
+            );
         }
 
         emit!(TokensSwapped {
-            input_token: input_token.to_string(),
-            input_amount: amount_in,
+            pool: liquidity_pool.key(),
+            user: ctx.accounts.user.key(),
+            input_mint: input_token,
+            output_mint: output_token,
+            input_amount: swap_amount_in,
             output_amount,
+            fee_amount: total_fee_amount,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            referrer: (referral_fee > 0)
+                .then(|| ctx.accounts.referrer_token.as_ref().map(|referrer_token| referrer_token.key()))
+                .flatten(),
+            referral_fee,
+            sequence,
         });
 
         Ok(())
     }
 
-    pub fn remove_liquidity(
-        ctx: Context<RemoveLiquidity>,
+    pub fn remove_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RemoveLiquidity<'info>>,
         token_x_mint: Pubkey,
         token_y_mint: Pubkey,
         amount_x: u64,
         amount_y: u64,
+        deadline: Option<i64>,
     ) -> Result<()> {
         // CHECKS
+        check_deadline(deadline)?;
+
         // Verify token mints
         require!(
             token_x_mint == ctx.accounts.token_x_mint.key(),
@@ -580,9 +914,26 @@ pub mod soondex {
         let token_x_key = ctx.accounts.token_x_mint.key();
         let token_y_key = ctx.accounts.token_y_mint.key();
         let bump = ctx.accounts.liquidity_pool.bump;
+        let fee_rate_bytes = ctx.accounts.liquidity_pool.fee_rate.to_le_bytes();
+    
+        // Native SOL sides are received as lamports when the user omits the token account
+        let is_sol_x = token_x_mint == NATIVE_MINT_ID && ctx.accounts.user_token_x_account.is_none();
+        let is_sol_y = token_y_mint == NATIVE_MINT_ID && ctx.accounts.user_token_y_account.is_none();
+        require!(
+            !(is_sol_x || is_sol_y) || ctx.accounts.temp_wsol_account.is_some(),
+            ErrorCode::MissingWsolAccount
+        );
+        require!(
+            (is_sol_x || ctx.accounts.user_token_x_account.is_some()) &&
+            (is_sol_y || ctx.accounts.user_token_y_account.is_some()),
+            ErrorCode::MissingTokenAccount
+        );
     
         let liquidity_pool = &mut ctx.accounts.liquidity_pool;
-        let lp_tokens = liquidity_pool.calculate_lp_tokens(amount_x, amount_y)?;
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        // Long-term orders trade first, at the prices they saw
+        update_pool(liquidity_pool, ctx.accounts.twamm.as_deref_mut().map(|twamm| &mut **twamm))?;
+        let lp_tokens = liquidity_pool.lp_tokens_to_burn(amount_x, amount_y)?;
     
         // Find and verify user LP balance
         let user_lp_index = liquidity_pool.lp_tokens
@@ -620,54 +971,86 @@ pub mod soondex {
                 liquidity_pool.lp_tokens.remove(user_lp_index);
             }
         }
+        let sequence = liquidity_pool.next_event_sequence()?;
     
         // INTERACTIONS
-        let pool_seeds = &[POOL_SEED, token_x_key.as_ref(), token_y_key.as_ref(), &[bump]];
+        let pool_seeds: &[&[u8]] = &[
+            POOL_SEED,
+            token_x_key.as_ref(),
+            token_y_key.as_ref(),
+            fee_rate_bytes.as_ref(),
+            &[bump],
+        ];
         let liquidity_pool_info = liquidity_pool.to_account_info();
     
         // Return token X to user
-        transfer_checked(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.pool_token_x_account.to_account_info(),
-                    to: ctx.accounts.user_token_x_account.to_account_info(),
-                    authority: liquidity_pool_info.clone(),
-                    mint: ctx.accounts.token_x_mint.to_account_info(),
-                },
+        match &ctx.accounts.user_token_x_account {
+            Some(user_token_x_account) => transfer_tokens(
+                &ctx.accounts.token_x_program,
+                ctx.accounts.pool_token_x_account.to_account_info(),
+                user_token_x_account.to_account_info(),
+                liquidity_pool_info.clone(),
+                &ctx.accounts.token_x_mint,
+                ctx.remaining_accounts,
+                amount_x,
                 &[pool_seeds],
-            ),
-            amount_x,
-            ctx.accounts.token_x_mint.decimals,
-        )?;
+            )?,
+            None => NativeSolTransfer {
+                user: &ctx.accounts.user,
+                temp_wsol_account: ctx.accounts.temp_wsol_account.as_ref().ok_or(ErrorCode::MissingWsolAccount)?,
+                temp_wsol_bump: ctx.bumps.temp_wsol_account.ok_or(ErrorCode::MissingWsolAccount)?,
+                native_mint: &ctx.accounts.token_x_mint,
+                pool_vault: ctx.accounts.pool_token_x_account.to_account_info(),
+                liquidity_pool: liquidity_pool_info.clone(),
+                token_program: &ctx.accounts.token_x_program,
+                system_program: &ctx.accounts.system_program,
+                pool_seeds,
+            }.unwrap(amount_x)?,
+        }
     
         // Return token Y to user
-        transfer_checked(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.pool_token_y_account.to_account_info(),
-                    to: ctx.accounts.user_token_y_account.to_account_info(),
-                    authority: liquidity_pool_info,
-                    mint: ctx.accounts.token_y_mint.to_account_info(),
-                },
+        match &ctx.accounts.user_token_y_account {
+            Some(user_token_y_account) => transfer_tokens(
+                &ctx.accounts.token_y_program,
+                ctx.accounts.pool_token_y_account.to_account_info(),
+                user_token_y_account.to_account_info(),
+                liquidity_pool_info,
+                &ctx.accounts.token_y_mint,
+                ctx.remaining_accounts,
+                amount_y,
                 &[pool_seeds],
-            ),
-            amount_y,
-            ctx.accounts.token_y_mint.decimals,
-        )?;
+            )?,
+            None => NativeSolTransfer {
+                user: &ctx.accounts.user,
+                temp_wsol_account: ctx.accounts.temp_wsol_account.as_ref().ok_or(ErrorCode::MissingWsolAccount)?,
+                temp_wsol_bump: ctx.bumps.temp_wsol_account.ok_or(ErrorCode::MissingWsolAccount)?,
+                native_mint: &ctx.accounts.token_y_mint,
+                pool_vault: ctx.accounts.pool_token_y_account.to_account_info(),
+                liquidity_pool: liquidity_pool_info,
+                token_program: &ctx.accounts.token_y_program,
+                system_program: &ctx.accounts.system_program,
+                pool_seeds,
+            }.unwrap(amount_y)?,
+        }
     
         emit!(LiquidityRemoved {
+            pool: liquidity_pool.key(),
             user: ctx.accounts.user.key(),
+            token_x_mint,
+            token_y_mint,
             token_x_amount: amount_x,
             token_y_amount: amount_y,
             lp_tokens_burned: lp_tokens,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
         });
     
         Ok(())
     }
 
-    pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+    pub fn claim_rewards<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimRewards<'info>>) -> Result<()> {
     // CHECKS
     let token_x_key = ctx.accounts.token_x_mint.key();
     let token_y_key = ctx.accounts.token_y_mint.key();
@@ -679,48 +1062,56 @@ pub mod soondex {
         .position(|x| x.owner == ctx.accounts.user.key())
         .ok_or(ErrorCode::NoLiquidity)?;
 
-    let user_lp_balance = &liquidity_pool.lp_tokens[user_lp_index];
-    
     // Calculate and verify rewards
     let current_timestamp = Clock::get()?.unix_timestamp;
+    let reward_per_token_cumulative = liquidity_pool.update_reward_cumulative(current_timestamp)?;
+    let user_lp_balance = &mut liquidity_pool.lp_tokens[user_lp_index];
     let rewards = calculate_rewards(
         user_lp_balance.amount,
-        liquidity_pool.reward_rate,
-        current_timestamp - user_lp_balance.last_reward_claim
-    )?;
+        reward_per_token_cumulative,
+        user_lp_balance.reward_checkpoint,
+    )?
+        .checked_add(user_lp_balance.rewards_earned)
+        .ok_or(ErrorCode::MathOverflow)?;
     require!(rewards > 0, ErrorCode::NoRewardsAvailable);
 
     // EFFECTS
-    liquidity_pool.lp_tokens[user_lp_index].last_reward_claim = current_timestamp;
+    user_lp_balance.last_reward_claim = current_timestamp;
+    user_lp_balance.reward_checkpoint = reward_per_token_cumulative;
+    user_lp_balance.rewards_earned = 0;
+    let sequence = liquidity_pool.next_event_sequence()?;
 
     // INTERACTIONS
+    let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
     let pool_seeds = &[
         POOL_SEED,
         token_x_key.as_ref(),
         token_y_key.as_ref(),
+        fee_rate_bytes.as_ref(),
         &[liquidity_pool.bump],
     ];
 
     // Transfer rewards
-    transfer_checked(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            TransferChecked {
-                from: ctx.accounts.pool_reward_account.to_account_info(),
-                to: ctx.accounts.user_reward_account.to_account_info(),
-                authority: liquidity_pool.to_account_info(),
-                mint: ctx.accounts.reward_mint.to_account_info(),
-            },
-            &[pool_seeds],
-        ),
+    transfer_tokens(
+        &ctx.accounts.token_program,
+        ctx.accounts.pool_reward_account.to_account_info(),
+        ctx.accounts.user_reward_account.to_account_info(),
+        liquidity_pool.to_account_info(),
+        &ctx.accounts.reward_mint,
+        ctx.remaining_accounts,
         rewards,
-        ctx.accounts.reward_mint.decimals,
+        &[pool_seeds],
     )?;
 
     emit!(RewardsClaimed {
+        pool: liquidity_pool.key(),
         user: ctx.accounts.user.key(),
+        token_x_mint: token_x_key,
+        token_y_mint: token_y_key,
+        reward_mint: ctx.accounts.reward_mint.key(),
         amount: rewards,
         timestamp: current_timestamp,
+        sequence,
     });
 
     Ok(())
@@ -728,8 +1119,8 @@ pub mod soondex {
     
     
 
-    pub fn stake(
-        ctx: Context<Stake>,
+    pub fn stake<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Stake<'info>>,
         amount: u64,
     ) -> Result<()> {
         // CHECKS
@@ -737,56 +1128,67 @@ pub mod soondex {
         
         let liquidity_pool = &mut ctx.accounts.liquidity_pool;
         let user_state = &mut ctx.accounts.user_state;
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
     
         // Calculate pending rewards before updating stake
         let current_timestamp = Clock::get()?.unix_timestamp;
+        let reward_per_token_cumulative = liquidity_pool.update_reward_cumulative(current_timestamp)?;
         if user_state.amount_staked > 0 {
             let pending_rewards = calculate_rewards(
                 user_state.amount_staked,
-                liquidity_pool.reward_rate,
-                current_timestamp - user_state.last_stake_timestamp
+                reward_per_token_cumulative,
+                user_state.reward_checkpoint,
             )?;
             user_state.rewards_earned = user_state.rewards_earned
                 .checked_add(pending_rewards)
                 .ok_or(ErrorCode::MathOverflow)?;
         }
     
+        // Only what reaches the pool after any transfer fee is staked
+        let amount_received = amount
+            .checked_sub(calculate_transfer_fee(&ctx.accounts.token_mint, amount)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+    
         // EFFECTS
         user_state.amount_staked = user_state.amount_staked
-            .checked_add(amount)
+            .checked_add(amount_received)
             .ok_or(ErrorCode::MathOverflow)?;
         user_state.last_stake_timestamp = current_timestamp;
+        user_state.reward_checkpoint = reward_per_token_cumulative;
         
         liquidity_pool.total_staked = liquidity_pool.total_staked
-            .checked_add(amount)
+            .checked_add(amount_received)
             .ok_or(ErrorCode::MathOverflow)?;
+        let sequence = liquidity_pool.next_event_sequence()?;
     
         // INTERACTIONS
-        transfer_checked(
-            CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                TransferChecked {
-                    from: ctx.accounts.user_token_account.to_account_info(),
-                    to: ctx.accounts.pool_token_account.to_account_info(),
-                    authority: ctx.accounts.user.to_account_info(),
-                    mint: ctx.accounts.token_mint.to_account_info(),
-                }
-            ),
+        transfer_tokens(
+            &ctx.accounts.token_program,
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.pool_token_account.to_account_info(),
+            ctx.accounts.user.to_account_info(),
+            &ctx.accounts.token_mint,
+            ctx.remaining_accounts,
             amount,
-            ctx.accounts.token_mint.decimals,
+            &[],
         )?;
     
         emit!(TokensStaked {
+            pool: liquidity_pool.key(),
             user: ctx.accounts.user.key(),
-            amount,
+            token_x_mint: liquidity_pool.token_x_mint,
+            token_y_mint: liquidity_pool.token_y_mint,
+            amount: amount_received,
+            total_staked: liquidity_pool.total_staked,
             timestamp: current_timestamp,
+            sequence,
         });
     
         Ok(())
     }
     
-    pub fn unstake(
-    ctx: Context<Unstake>,
+    pub fn unstake<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Unstake<'info>>,
     amount: u64,
 ) -> Result<()> {
     // CHECKS
@@ -800,10 +1202,16 @@ pub mod soondex {
     require!(amount > 0, ErrorCode::InvalidStakeAmount);
     require!(user_state.amount_staked >= amount, ErrorCode::InsufficientStake);
 
-    // Calculate rewards
+    // Settle the whole stake, which moves its checkpoint, and pay what earlier stakes carried forward
     let current_timestamp = Clock::get()?.unix_timestamp;
-    let stake_duration = current_timestamp - user_state.last_stake_timestamp;
-    let rewards = calculate_rewards(amount, liquidity_pool.reward_rate, stake_duration)?;
+    let reward_per_token_cumulative = liquidity_pool.update_reward_cumulative(current_timestamp)?;
+    let rewards = calculate_rewards(
+        user_state.amount_staked,
+        reward_per_token_cumulative,
+        user_state.reward_checkpoint,
+    )?
+        .checked_add(user_state.rewards_earned)
+        .ok_or(ErrorCode::MathOverflow)?;
     let total_withdrawal = amount
         .checked_add(rewards)
         .ok_or(ErrorCode::MathOverflow)?;
@@ -813,581 +1221,4823 @@ pub mod soondex {
         .checked_sub(amount)
         .ok_or(ErrorCode::MathOverflow)?;
     user_state.last_stake_timestamp = current_timestamp;
+    user_state.reward_checkpoint = reward_per_token_cumulative;
+    user_state.rewards_earned = 0;
     
     liquidity_pool.total_staked = liquidity_pool.total_staked
         .checked_sub(amount)
         .ok_or(ErrorCode::MathOverflow)?;
+    let sequence = liquidity_pool.next_event_sequence()?;
 
     // INTERACTIONS
+    let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
     let pool_seeds = &[
         POOL_SEED,
         token_x_key.as_ref(),
         token_y_key.as_ref(),
+        fee_rate_bytes.as_ref(),
         &[liquidity_pool.bump],
     ];
 
-    transfer_checked(
-        CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            TransferChecked {
-                from: ctx.accounts.pool_token_account.to_account_info(),
-                to: ctx.accounts.user_token_account.to_account_info(),
-                authority: liquidity_pool.to_account_info(),
-                mint: ctx.accounts.token_mint.to_account_info(),
-            },
-            &[pool_seeds],
-        ),
+    transfer_tokens(
+        &ctx.accounts.token_program,
+        ctx.accounts.pool_token_account.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        liquidity_pool.to_account_info(),
+        &ctx.accounts.token_mint,
+        ctx.remaining_accounts,
         total_withdrawal,
-        ctx.accounts.token_mint.decimals,
+        &[pool_seeds],
     )?;
 
     emit!(TokensUnstaked {
+        pool: liquidity_pool.key(),
         user: ctx.accounts.user.key(),
+        token_x_mint: token_x_key,
+        token_y_mint: token_y_key,
         amount,
         rewards,
+        total_staked: liquidity_pool.total_staked,
         timestamp: current_timestamp,
+        sequence,
     });
 
     Ok(())
     }
-    
-}
 
+    pub fn flash_borrow<'info>(
+        ctx: Context<'_, '_, 'info, 'info, FlashBorrow<'info>>,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
 
+        require!(amount_x > 0 || amount_y > 0, ErrorCode::InvalidFlashLoanAmount);
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
+        require!(
+            amount_x <= liquidity_pool.token_x_reserve &&
+            amount_y <= liquidity_pool.token_y_reserve,
+            ErrorCode::InsufficientFunds
+        );
 
-#[derive(Accounts)]
-#[instruction(token_x_mint: Pubkey, token_y_mint: Pubkey, fee_rate: u64)]
-pub struct InitializePool<'info> {
-    #[account(
-        init,
-        payer = payer,
-        space = 8 + LiquidityPool::INIT_SPACE,
-        seeds = [
+        // A matching flash_repay for this pool must appear later in the transaction
+        let instructions = ctx.accounts.instructions_sysvar.to_account_info();
+        let current_index = load_current_index_checked(&instructions)? as usize;
+        let mut repay_found = false;
+        let mut index = current_index + 1;
+        while let Ok(ix) = load_instruction_at_checked(index, &instructions) {
+            if ix.program_id == crate::ID
+                && ix.data.len() >= 8
+                && ix.data[..8] == crate::instruction::FlashRepay::DISCRIMINATOR
+                && ix.accounts.first().map(|meta| meta.pubkey) == Some(liquidity_pool.key())
+            {
+                repay_found = true;
+                break;
+            }
+            index += 1;
+        }
+        require!(repay_found, ErrorCode::MissingFlashRepay);
+
+        // EFFECTS
+        liquidity_pool.flash_loan_active = true;
+        liquidity_pool.flash_loan_amount_x = amount_x;
+        liquidity_pool.flash_loan_amount_y = amount_y;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        let token_x_key = ctx.accounts.token_x_mint.key();
+        let token_y_key = ctx.accounts.token_y_mint.key();
+        let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+        let pool_seeds = &[
             POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-        ],
-        bump    )]
-    pub liquidity_pool: Account<'info, LiquidityPool>,
+            token_x_key.as_ref(),
+            token_y_key.as_ref(),
+            fee_rate_bytes.as_ref(),
+            &[liquidity_pool.bump],
+        ];
 
-    #[account(mut)]
-    pub payer: Signer<'info>,
+        if amount_x > 0 {
+            transfer_tokens(
+                &ctx.accounts.token_x_program,
+                ctx.accounts.pool_token_x_account.to_account_info(),
+                ctx.accounts.user_token_x_account.to_account_info(),
+                liquidity_pool.to_account_info(),
+                &ctx.accounts.token_x_mint,
+                ctx.remaining_accounts,
+                amount_x,
+                &[pool_seeds],
+            )?;
+        }
 
-    pub system_program: Program<'info, System>,
-    pub token_program: Interface<'info, TokenInterface>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub token_x_mint: InterfaceAccount<'info, Mint>,
-    pub token_y_mint: InterfaceAccount<'info, Mint>,
+        if amount_y > 0 {
+            transfer_tokens(
+                &ctx.accounts.token_y_program,
+                ctx.accounts.pool_token_y_account.to_account_info(),
+                ctx.accounts.user_token_y_account.to_account_info(),
+                liquidity_pool.to_account_info(),
+                &ctx.accounts.token_y_mint,
+                ctx.remaining_accounts,
+                amount_y,
+                &[pool_seeds],
+            )?;
+        }
 
-    #[account(mut)]
-    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)] 
-    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
+        emit!(FlashLoanBorrowed {
+            pool: liquidity_pool.key(),
+            user: ctx.accounts.user.key(),
+            token_x_mint: token_x_key,
+            token_y_mint: token_y_key,
+            amount_x,
+            amount_y,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
 
-    /// CHECK: This is safe because we only use it to transfer SOL as protocol fee
-    #[account(mut)]
-    pub protocol_wallet: AccountInfo<'info>,
-}
-#[derive(Accounts)]
-pub struct RemovePool<'info> {
-    #[account(
-        mut,
-        seeds = [
+        Ok(())
+    }
+
+    pub fn flash_repay<'info>(ctx: Context<'_, '_, 'info, 'info, FlashRepay<'info>>) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(liquidity_pool.flash_loan_active, ErrorCode::FlashLoanNotActive);
+
+        let amount_x = liquidity_pool.flash_loan_amount_x;
+        let amount_y = liquidity_pool.flash_loan_amount_y;
+        let fee_x = calculate_flash_loan_fee(amount_x)?;
+        let fee_y = calculate_flash_loan_fee(amount_y)?;
+        // The borrower also covers any transfer fee, so the vaults receive the loan and fee in full
+        let repay_x = gross_up_transfer_fee(&ctx.accounts.token_x_mint, amount_x, fee_x)?;
+        let repay_y = gross_up_transfer_fee(&ctx.accounts.token_y_mint, amount_y, fee_y)?;
+
+        // Nothing that moves the reserves runs while the loan is out, so they still give the pre-borrow k
+        let k_before = (liquidity_pool.token_x_reserve as u128)
+            .checked_mul(liquidity_pool.token_y_reserve as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        // EFFECTS
+        liquidity_pool.update_price_cumulative(Clock::get()?.unix_timestamp)?;
+        // Fees stay in the pool and accrue to LPs
+        liquidity_pool.flash_loan_active = false;
+        liquidity_pool.flash_loan_amount_x = 0;
+        liquidity_pool.flash_loan_amount_y = 0;
+        liquidity_pool.token_x_reserve = liquidity_pool.token_x_reserve
+            .checked_add(fee_x)
+            .ok_or(ErrorCode::MathOverflow)?;
+        liquidity_pool.token_y_reserve = liquidity_pool.token_y_reserve
+            .checked_add(fee_y)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        if amount_x > 0 {
+            transfer_tokens(
+                &ctx.accounts.token_x_program,
+                ctx.accounts.user_token_x_account.to_account_info(),
+                ctx.accounts.pool_token_x_account.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.token_x_mint,
+                ctx.remaining_accounts,
+                repay_x,
+                &[],
+            )?;
+        }
+
+        if amount_y > 0 {
+            transfer_tokens(
+                &ctx.accounts.token_y_program,
+                ctx.accounts.user_token_y_account.to_account_info(),
+                ctx.accounts.pool_token_y_account.to_account_info(),
+                ctx.accounts.user.to_account_info(),
+                &ctx.accounts.token_y_mint,
+                ctx.remaining_accounts,
+                repay_y,
+                &[],
+            )?;
+        }
+
+        // Verify k over what the vaults hold did not decrease and the vaults cover the reserves.
+        // Vault balances owed to long-term orders are not reserves
+        ctx.accounts.pool_token_x_account.reload()?;
+        ctx.accounts.pool_token_y_account.reload()?;
+        let token_x_balance = ctx.accounts.pool_token_x_account.amount
+            .saturating_sub(liquidity_pool.twamm_token_x_balance);
+        let token_y_balance = ctx.accounts.pool_token_y_account.amount
+            .saturating_sub(liquidity_pool.twamm_token_y_balance);
+        let k_after = (token_x_balance as u128)
+            .checked_mul(token_y_balance as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(k_after >= k_before, ErrorCode::InvalidK);
+        require!(
+            token_x_balance >= liquidity_pool.token_x_reserve &&
+            token_y_balance >= liquidity_pool.token_y_reserve,
+            ErrorCode::InsufficientFunds
+        );
+
+        emit!(FlashLoanRepaid {
+            pool: liquidity_pool.key(),
+            user: ctx.accounts.user.key(),
+            token_x_mint: liquidity_pool.token_x_mint,
+            token_y_mint: liquidity_pool.token_y_mint,
+            amount_x,
+            amount_y,
+            fee_x,
+            fee_y,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless. Sets the reserves to the vault balances, absorbing donations
+    /// and correcting drift from transfer fees or rounding. Staked tokens and
+    /// rewards never sit in the reserve vaults, so the balances are all reserves
+    pub fn sync(ctx: Context<SyncReserves>) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        // Without LP supply the balances would back no one's shares
+        require!(liquidity_pool.lp_token_supply > 0, ErrorCode::NoLiquidity);
+        // Vault balances owed to long-term orders are not reserves
+        let token_x_balance = ctx.accounts.pool_token_x_account.amount
+            .saturating_sub(liquidity_pool.twamm_token_x_balance);
+        let token_y_balance = ctx.accounts.pool_token_y_account.amount
+            .saturating_sub(liquidity_pool.twamm_token_y_balance);
+        require!(token_x_balance > 0 && token_y_balance > 0, ErrorCode::NoLiquidity);
+
+        // EFFECTS
+        liquidity_pool.update_price_cumulative(Clock::get()?.unix_timestamp)?;
+        let previous_token_x_reserve = liquidity_pool.token_x_reserve;
+        let previous_token_y_reserve = liquidity_pool.token_y_reserve;
+        liquidity_pool.token_x_reserve = token_x_balance;
+        liquidity_pool.token_y_reserve = token_y_balance;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        emit!(ReservesSynced {
+            pool: liquidity_pool.key(),
+            user: ctx.accounts.user.key(),
+            token_x_mint: liquidity_pool.token_x_mint,
+            token_y_mint: liquidity_pool.token_y_mint,
+            previous_token_x_reserve,
+            previous_token_y_reserve,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless. Sends vault balances above the reserves to the recipient's
+    /// token accounts; the reserves are unchanged
+    pub fn skim<'info>(ctx: Context<'_, '_, 'info, 'info, Skim<'info>>) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        let amount_x = ctx.accounts.pool_token_x_account.amount
+            .saturating_sub(liquidity_pool.token_x_reserve)
+            .saturating_sub(liquidity_pool.twamm_token_x_balance);
+        let amount_y = ctx.accounts.pool_token_y_account.amount
+            .saturating_sub(liquidity_pool.token_y_reserve)
+            .saturating_sub(liquidity_pool.twamm_token_y_balance);
+        require!(amount_x > 0 || amount_y > 0, ErrorCode::NothingToSkim);
+
+        // EFFECTS
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        let token_x_key = ctx.accounts.token_x_mint.key();
+        let token_y_key = ctx.accounts.token_y_mint.key();
+        let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+        let pool_seeds: &[&[u8]] = &[
             POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-            ],
-            bump = liquidity_pool.bump,
-            close = authority
-        )]
-        pub liquidity_pool: Account<'info, LiquidityPool>,
-        
-        #[account(mut)]
-        pub authority: Signer<'info>,
-        
-        pub token_x_mint: InterfaceAccount<'info, Mint>,
-        pub token_y_mint: InterfaceAccount<'info, Mint>,
- }
+            token_x_key.as_ref(),
+            token_y_key.as_ref(),
+            fee_rate_bytes.as_ref(),
+            &[liquidity_pool.bump],
+        ];
+
+        if amount_x > 0 {
+            transfer_tokens(
+                &ctx.accounts.token_x_program,
+                ctx.accounts.pool_token_x_account.to_account_info(),
+                ctx.accounts.recipient_token_x_account.to_account_info(),
+                liquidity_pool.to_account_info(),
+                &ctx.accounts.token_x_mint,
+                ctx.remaining_accounts,
+                amount_x,
+                &[pool_seeds],
+            )?;
+        }
+        if amount_y > 0 {
+            transfer_tokens(
+                &ctx.accounts.token_y_program,
+                ctx.accounts.pool_token_y_account.to_account_info(),
+                ctx.accounts.recipient_token_y_account.to_account_info(),
+                liquidity_pool.to_account_info(),
+                &ctx.accounts.token_y_mint,
+                ctx.remaining_accounts,
+                amount_y,
+                &[pool_seeds],
+            )?;
+        }
+
+        emit!(ExcessSkimmed {
+            pool: liquidity_pool.key(),
+            user: ctx.accounts.user.key(),
+            recipient: ctx.accounts.recipient_token_x_account.owner,
+            token_x_mint: token_x_key,
+            token_y_mint: token_y_key,
+            amount_x,
+            amount_y,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Escrows `amount_in` of one pool token until the pool pays at least `min_amount_out`
+    /// of the other for it; `expiry` is a unix timestamp after which the order can't fill
+    pub fn place_limit_order<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PlaceLimitOrder<'info>>,
+        order_id: u64,
+        amount_in: u64,
+        min_amount_out: u64,
+        expiry: Option<i64>,
+    ) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
+        require!(amount_in > 0 && min_amount_out > 0, ErrorCode::InvalidLimitOrder);
+        if let Some(expiry) = expiry {
+            require!(Clock::get()?.unix_timestamp < expiry, ErrorCode::InvalidLimitOrder);
+        }
+
+        let input_mint = ctx.accounts.input_mint.key();
+        let output_mint = if input_mint == liquidity_pool.token_x_mint {
+            liquidity_pool.token_y_mint
+        } else {
+            liquidity_pool.token_x_mint
+        };
+        // Transfer-fee mints escrow less than the nominal amount
+        let amount_escrowed = amount_in
+            .checked_sub(calculate_transfer_fee(&ctx.accounts.input_mint, amount_in)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(amount_escrowed > 0, ErrorCode::InvalidLimitOrder);
+
+        // EFFECTS
+        let limit_order = &mut ctx.accounts.limit_order;
+        limit_order.pool = liquidity_pool.key();
+        limit_order.owner = ctx.accounts.owner.key();
+        limit_order.order_id = order_id;
+        limit_order.input_mint = input_mint;
+        limit_order.output_mint = output_mint;
+        limit_order.amount_in = amount_escrowed;
+        limit_order.min_amount_out = min_amount_out;
+        limit_order.expiry = expiry.unwrap_or(0);
+        limit_order.escrow = ctx.accounts.escrow.key();
+        limit_order.bump = ctx.bumps.limit_order;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        transfer_tokens(
+            &ctx.accounts.input_token_program,
+            ctx.accounts.owner_token_in.to_account_info(),
+            ctx.accounts.escrow.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.input_mint,
+            ctx.remaining_accounts,
+            amount_in,
+            &[],
+        )?;
+
+        emit!(LimitOrderPlaced {
+            pool: liquidity_pool.key(),
+            owner: limit_order.owner,
+            order: limit_order.key(),
+            order_id,
+            input_mint,
+            output_mint,
+            amount_in: amount_escrowed,
+            min_amount_out,
+            expiry: limit_order.expiry,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Fills a limit order once the pool price reaches its limit. Anyone may call it;
+    /// the keeper earns `LIMIT_ORDER_KEEPER_FEE_RATE` of the order's input
+    pub fn fill_limit_order<'info>(ctx: Context<'_, '_, 'info, 'info, FillLimitOrder<'info>>) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        let limit_order = &ctx.accounts.limit_order;
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
+        if limit_order.expiry != 0 {
+            require!(Clock::get()?.unix_timestamp <= limit_order.expiry, ErrorCode::Expired);
+        }
+
+        update_pool(liquidity_pool, ctx.accounts.twamm.as_deref_mut().map(|twamm| &mut **twamm))?;
+
+        let is_input_token_x = limit_order.input_mint == ctx.accounts.token_x_mint.key();
+        let (input_mint, output_mint) = if is_input_token_x {
+            (&ctx.accounts.token_x_mint, &ctx.accounts.token_y_mint)
+        } else {
+            (&ctx.accounts.token_y_mint, &ctx.accounts.token_x_mint)
+        };
+        let (input_token_program, output_token_program) = if is_input_token_x {
+            (&ctx.accounts.token_x_program, &ctx.accounts.token_y_program)
+        } else {
+            (&ctx.accounts.token_y_program, &ctx.accounts.token_x_program)
+        };
+
+        // Tokens sent to the escrow after placement go into the swap too
+        let keeper_fee = calculate_swap_fee(limit_order.amount_in, LIMIT_ORDER_KEEPER_FEE_RATE)?;
+        let swap_amount = ctx.accounts.escrow.amount
+            .checked_sub(keeper_fee)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let SwapQuote {
+            amount_in_received,
+            fee_amount,
+            amount_out,
+            amount_out_received,
+            ..
+        } = price_swap_exact_in(liquidity_pool, input_mint, output_mint, swap_amount)?;
+        require!(amount_out_received >= limit_order.min_amount_out, ErrorCode::LimitPriceNotReached);
+
+        let (reserve_in, reserve_out) = if is_input_token_x {
+            (liquidity_pool.token_x_reserve, liquidity_pool.token_y_reserve)
+        } else {
+            (liquidity_pool.token_y_reserve, liquidity_pool.token_x_reserve)
+        };
+        let slot = Clock::get()?.slot;
+        let slot_volume_bps = liquidity_pool.check_swap_guardrails(
+            amount_in_received,
+            fee_amount,
+            amount_out,
+            reserve_in,
+            reserve_out,
+            slot,
+        )?;
+
+        // EFFECTS
+        liquidity_pool.volume_slot = slot;
+        liquidity_pool.slot_volume_bps = slot_volume_bps;
+        liquidity_pool.apply_swap(is_input_token_x, amount_in_received, amount_out)?;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        let token_x_key = ctx.accounts.token_x_mint.key();
+        let token_y_key = ctx.accounts.token_y_mint.key();
+        let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+        let pool_seeds: &[&[u8]] = &[
+            POOL_SEED,
+            token_x_key.as_ref(),
+            token_y_key.as_ref(),
+            fee_rate_bytes.as_ref(),
+            &[liquidity_pool.bump],
+        ];
+        let pool_key = liquidity_pool.key();
+        let owner_key = limit_order.owner;
+        let order_id_bytes = limit_order.order_id.to_le_bytes();
+        let order_seeds: &[&[u8]] = &[
+            LIMIT_ORDER_SEED,
+            pool_key.as_ref(),
+            owner_key.as_ref(),
+            order_id_bytes.as_ref(),
+            &[limit_order.bump],
+        ];
+        let (pool_token_in, pool_token_out) = if is_input_token_x {
+            (ctx.accounts.pool_token_x.to_account_info(), ctx.accounts.pool_token_y.to_account_info())
+        } else {
+            (ctx.accounts.pool_token_y.to_account_info(), ctx.accounts.pool_token_x.to_account_info())
+        };
+
+        if keeper_fee > 0 {
+            transfer_tokens(
+                input_token_program,
+                ctx.accounts.escrow.to_account_info(),
+                ctx.accounts.keeper_token_in.to_account_info(),
+                limit_order.to_account_info(),
+                input_mint,
+                ctx.remaining_accounts,
+                keeper_fee,
+                &[order_seeds],
+            )?;
+        }
+        transfer_tokens(
+            input_token_program,
+            ctx.accounts.escrow.to_account_info(),
+            pool_token_in,
+            limit_order.to_account_info(),
+            input_mint,
+            ctx.remaining_accounts,
+            swap_amount,
+            &[order_seeds],
+        )?;
+        transfer_tokens(
+            output_token_program,
+            pool_token_out,
+            ctx.accounts.owner_token_out.to_account_info(),
+            liquidity_pool.to_account_info(),
+            output_mint,
+            ctx.remaining_accounts,
+            amount_out,
+            &[pool_seeds],
+        )?;
+        close_token_account(
+            input_token_program,
+            ctx.accounts.escrow.to_account_info(),
+            input_mint,
+            ctx.accounts.owner.to_account_info(),
+            limit_order.to_account_info(),
+            &[order_seeds],
+        )?;
+
+        emit!(LimitOrderFilled {
+            pool: pool_key,
+            owner: owner_key,
+            keeper: ctx.accounts.keeper.key(),
+            order: limit_order.key(),
+            input_mint: input_mint.key(),
+            output_mint: output_mint.key(),
+            input_amount: amount_in_received,
+            output_amount: amount_out,
+            fee_amount,
+            keeper_fee,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Returns a limit order's escrow to its owner; allowed while the pool is paused
+    pub fn cancel_limit_order<'info>(ctx: Context<'_, '_, 'info, 'info, CancelLimitOrder<'info>>) -> Result<()> {
+        // CHECKS
+        let limit_order = &ctx.accounts.limit_order;
+        let amount = ctx.accounts.escrow.amount;
+
+        // EFFECTS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        let pool_key = liquidity_pool.key();
+        let owner_key = limit_order.owner;
+        let order_id_bytes = limit_order.order_id.to_le_bytes();
+        let order_seeds: &[&[u8]] = &[
+            LIMIT_ORDER_SEED,
+            pool_key.as_ref(),
+            owner_key.as_ref(),
+            order_id_bytes.as_ref(),
+            &[limit_order.bump],
+        ];
+
+        if amount > 0 {
+            transfer_tokens(
+                &ctx.accounts.input_token_program,
+                ctx.accounts.escrow.to_account_info(),
+                ctx.accounts.owner_token_in.to_account_info(),
+                limit_order.to_account_info(),
+                &ctx.accounts.input_mint,
+                ctx.remaining_accounts,
+                amount,
+                &[order_seeds],
+            )?;
+        }
+        close_token_account(
+            &ctx.accounts.input_token_program,
+            ctx.accounts.escrow.to_account_info(),
+            &ctx.accounts.input_mint,
+            ctx.accounts.owner.to_account_info(),
+            limit_order.to_account_info(),
+            &[order_seeds],
+        )?;
+
+        emit!(LimitOrderCancelled {
+            pool: pool_key,
+            owner: owner_key,
+            order: limit_order.key(),
+            amount_in: amount,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Enables long-term orders on a pool. Afterwards every instruction that trades
+    /// against the reserves must pass the pool's `TwammState`
+    pub fn initialize_twamm(ctx: Context<InitializeTwamm>) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(liquidity_pool.is_admin(&ctx.accounts.authority.key()), ErrorCode::Unauthorized);
+
+        // EFFECTS
+        let twamm = &mut ctx.accounts.twamm;
+        twamm.pool = liquidity_pool.key();
+        twamm.last_virtual_order_time = Clock::get()?.unix_timestamp;
+        twamm.expiries = vec![TwammExpiry::default(); TWAMM_MAX_INTERVALS];
+        twamm.bump = ctx.bumps.twamm;
+        liquidity_pool.twamm_enabled = true;
+        emit_pool_config_updated(liquidity_pool, ctx.accounts.authority.key())
+    }
+
+    /// Settles the pool's long-term orders up to now. Permissionless; every trade does
+    /// this first, so calling it only brings the reserves up to date for quoting
+    pub fn execute_twamm(ctx: Context<ExecuteTwamm>) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+
+        // EFFECTS
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.twamm.execute_virtual_orders(liquidity_pool, now)?;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        emit!(VirtualOrdersExecuted {
+            pool: liquidity_pool.key(),
+            user: ctx.accounts.user.key(),
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Sells `amount_in` of one pool token at a constant rate from now until the end of
+    /// the `intervals`-th `TWAMM_ORDER_INTERVAL` boundary, so the first interval is partial
+    pub fn place_long_term_order<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PlaceLongTermOrder<'info>>,
+        order_id: u64,
+        amount_in: u64,
+        intervals: u64,
+    ) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        let twamm = &mut ctx.accounts.twamm;
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
+        require!(amount_in > 0, ErrorCode::InvalidSwapInput);
+        require!(
+            intervals > 0 && intervals <= TWAMM_MAX_INTERVALS as u64,
+            ErrorCode::InvalidOrderDuration
+        );
+        require!(liquidity_pool.lp_token_supply > 0, ErrorCode::NoLiquidity);
+
+        let now = Clock::get()?.unix_timestamp;
+        twamm.execute_virtual_orders(liquidity_pool, now)?;
+
+        let sells_x = ctx.accounts.input_mint.key() == liquidity_pool.token_x_mint;
+        let expiry = (now / TWAMM_ORDER_INTERVAL + intervals as i64)
+            .checked_mul(TWAMM_ORDER_INTERVAL)
+            .ok_or(ErrorCode::MathOverflow)?;
+        // Transfer-fee mints deliver less than the nominal amount to the vault
+        let amount_received = amount_in
+            .checked_sub(calculate_transfer_fee(&ctx.accounts.input_mint, amount_in)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let sell_rate = (amount_received as u128)
+            .checked_mul(TWAMM_RATE_PRECISION)
+            .ok_or(ErrorCode::MathOverflow)?
+            / (expiry - now) as u128;
+        require!(sell_rate > 0, ErrorCode::InvalidSwapInput);
+
+        // EFFECTS
+        twamm.add_sell_rate(sells_x, sell_rate, expiry)?;
+        if sells_x {
+            liquidity_pool.twamm_token_x_balance = liquidity_pool.twamm_token_x_balance
+                .checked_add(amount_received)
+                .ok_or(ErrorCode::MathOverflow)?;
+        } else {
+            liquidity_pool.twamm_token_y_balance = liquidity_pool.twamm_token_y_balance
+                .checked_add(amount_received)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+
+        let order = &mut ctx.accounts.long_term_order;
+        order.pool = liquidity_pool.key();
+        order.owner = ctx.accounts.owner.key();
+        order.order_id = order_id;
+        order.input_mint = ctx.accounts.input_mint.key();
+        order.output_mint = if sells_x { liquidity_pool.token_y_mint } else { liquidity_pool.token_x_mint };
+        order.sell_rate = sell_rate;
+        order.earnings_factor_last = twamm.earnings_factor(sells_x);
+        order.start_time = now;
+        order.expiry = expiry;
+        order.bump = ctx.bumps.long_term_order;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        transfer_tokens(
+            &ctx.accounts.input_token_program,
+            ctx.accounts.owner_token_in.to_account_info(),
+            ctx.accounts.pool_token_in.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.input_mint,
+            ctx.remaining_accounts,
+            amount_in,
+            &[],
+        )?;
+
+        emit!(LongTermOrderPlaced {
+            pool: liquidity_pool.key(),
+            owner: order.owner,
+            order: order.key(),
+            order_id,
+            input_mint: order.input_mint,
+            output_mint: order.output_mint,
+            amount_in: amount_received,
+            sell_rate,
+            expiry,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Pays a long-term order's proceeds so far to its owner. Once the order has expired
+    /// anyone may call it, which pays out the rest and closes the order
+    pub fn withdraw_long_term_order<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ManageLongTermOrder<'info>>,
+    ) -> Result<()> {
+        // CHECKS
+        let now = Clock::get()?.unix_timestamp;
+        let expired = now >= ctx.accounts.long_term_order.expiry;
+        require!(
+            expired || ctx.accounts.user.key() == ctx.accounts.long_term_order.owner,
+            ErrorCode::Unauthorized
+        );
+        settle_long_term_order(ctx, now, false)
+    }
+
+    /// Pays out a long-term order's proceeds, refunds its unsold input and closes it
+    pub fn cancel_long_term_order<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ManageLongTermOrder<'info>>,
+    ) -> Result<()> {
+        // CHECKS
+        require!(
+            ctx.accounts.user.key() == ctx.accounts.long_term_order.owner,
+            ErrorCode::Unauthorized
+        );
+        settle_long_term_order(ctx, Clock::get()?.unix_timestamp, true)
+    }
+
+    /// Opens a DCA vault that buys the other pool token with `amount_per_execution` of
+    /// `amount` every `interval` seconds. Executions accept at worst `max_slippage_bps`
+    /// below the pool's TWAP since the previous one, which also has to cover the swap fee
+    pub fn open_dca_vault<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenDcaVault<'info>>,
+        vault_id: u64,
+        amount: u64,
+        amount_per_execution: u64,
+        interval: i64,
+        max_slippage_bps: u64,
+    ) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
+        require!(amount > 0 && amount_per_execution > 0, ErrorCode::InvalidSwapInput);
+        require!(
+            interval >= MIN_DCA_INTERVAL && max_slippage_bps < BPS_DENOMINATOR,
+            ErrorCode::InvalidDcaSchedule
+        );
+        require!(liquidity_pool.lp_token_supply > 0, ErrorCode::NoLiquidity);
+
+        let now = Clock::get()?.unix_timestamp;
+        let input_mint = ctx.accounts.input_mint.key();
+        let (price_x_cumulative, price_y_cumulative) = liquidity_pool.price_cumulative_at(now)?;
+        let amount_received = amount
+            .checked_sub(calculate_transfer_fee(&ctx.accounts.input_mint, amount)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        // EFFECTS
+        let dca_vault = &mut ctx.accounts.dca_vault;
+        dca_vault.pool = liquidity_pool.key();
+        dca_vault.owner = ctx.accounts.owner.key();
+        dca_vault.vault_id = vault_id;
+        dca_vault.input_mint = input_mint;
+        dca_vault.output_mint = ctx.accounts.output_mint.key();
+        dca_vault.input_vault = ctx.accounts.input_vault.key();
+        dca_vault.output_vault = ctx.accounts.output_vault.key();
+        dca_vault.amount_per_execution = amount_per_execution;
+        dca_vault.interval = interval;
+        dca_vault.max_slippage_bps = max_slippage_bps;
+        // The first execution waits a full interval so it has a TWAP to check against
+        dca_vault.last_execution = now;
+        dca_vault.price_cumulative_last = if input_mint == liquidity_pool.token_x_mint {
+            price_x_cumulative
+        } else {
+            price_y_cumulative
+        };
+        dca_vault.executions = 0;
+        dca_vault.bump = ctx.bumps.dca_vault;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        transfer_tokens(
+            &ctx.accounts.input_token_program,
+            ctx.accounts.owner_token_in.to_account_info(),
+            ctx.accounts.input_vault.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.input_mint,
+            ctx.remaining_accounts,
+            amount,
+            &[],
+        )?;
+
+        emit!(DcaVaultOpened {
+            pool: liquidity_pool.key(),
+            owner: dca_vault.owner,
+            vault: dca_vault.key(),
+            vault_id,
+            input_mint,
+            output_mint: dca_vault.output_mint,
+            amount: amount_received,
+            amount_per_execution,
+            interval,
+            max_slippage_bps,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Runs a DCA vault's next purchase once its interval has elapsed. Anyone may call
+    /// it; the keeper earns `DCA_KEEPER_FEE_RATE` of the execution's input
+    pub fn execute_dca<'info>(ctx: Context<'_, '_, 'info, 'info, ExecuteDca<'info>>) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        let dca_vault = &mut ctx.accounts.dca_vault;
+        require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
+        let now = Clock::get()?.unix_timestamp;
+        let elapsed = now - dca_vault.last_execution;
+        require!(elapsed >= dca_vault.interval, ErrorCode::DcaExecutionNotDue);
+
+        update_pool(liquidity_pool, ctx.accounts.twamm.as_deref_mut().map(|twamm| &mut **twamm))?;
+
+        let is_input_token_x = dca_vault.input_mint == ctx.accounts.token_x_mint.key();
+        let (input_mint, output_mint) = if is_input_token_x {
+            (&ctx.accounts.token_x_mint, &ctx.accounts.token_y_mint)
+        } else {
+            (&ctx.accounts.token_y_mint, &ctx.accounts.token_x_mint)
+        };
+        let (input_token_program, output_token_program) = if is_input_token_x {
+            (&ctx.accounts.token_x_program, &ctx.accounts.token_y_program)
+        } else {
+            (&ctx.accounts.token_y_program, &ctx.accounts.token_x_program)
+        };
+
+        // The last execution spends whatever is left
+        let amount = dca_vault.amount_per_execution.min(ctx.accounts.input_vault.amount);
+        let keeper_fee = calculate_swap_fee(amount, DCA_KEEPER_FEE_RATE)?;
+        let swap_amount = amount.checked_sub(keeper_fee).ok_or(ErrorCode::MathOverflow)?;
+        require!(swap_amount > 0, ErrorCode::InsufficientFunds);
+
+        // The TWAP since the previous execution sets the worst price this one accepts
+        let price_cumulative = if is_input_token_x {
+            liquidity_pool.price_x_cumulative
+        } else {
+            liquidity_pool.price_y_cumulative
+        };
+        let min_amount_out = twap_min_amount_out(
+            price_cumulative,
+            dca_vault.price_cumulative_last,
+            elapsed,
+            swap_amount,
+            dca_vault.max_slippage_bps,
+        )?;
+
+        let SwapQuote {
+            amount_in_received,
+            fee_amount,
+            amount_out,
+            amount_out_received,
+            ..
+        } = price_swap_exact_in(liquidity_pool, input_mint, output_mint, swap_amount)?;
+        require!(amount_out_received >= min_amount_out, ErrorCode::DcaSlippageExceeded);
+
+        let (reserve_in, reserve_out) = if is_input_token_x {
+            (liquidity_pool.token_x_reserve, liquidity_pool.token_y_reserve)
+        } else {
+            (liquidity_pool.token_y_reserve, liquidity_pool.token_x_reserve)
+        };
+        let slot = Clock::get()?.slot;
+        let slot_volume_bps = liquidity_pool.check_swap_guardrails(
+            amount_in_received,
+            fee_amount,
+            amount_out,
+            reserve_in,
+            reserve_out,
+            slot,
+        )?;
+
+        // EFFECTS
+        liquidity_pool.volume_slot = slot;
+        liquidity_pool.slot_volume_bps = slot_volume_bps;
+        liquidity_pool.apply_swap(is_input_token_x, amount_in_received, amount_out)?;
+        dca_vault.last_execution = now;
+        dca_vault.price_cumulative_last = price_cumulative;
+        dca_vault.executions = dca_vault.executions.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        let token_x_key = ctx.accounts.token_x_mint.key();
+        let token_y_key = ctx.accounts.token_y_mint.key();
+        let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+        let pool_seeds: &[&[u8]] = &[
+            POOL_SEED,
+            token_x_key.as_ref(),
+            token_y_key.as_ref(),
+            fee_rate_bytes.as_ref(),
+            &[liquidity_pool.bump],
+        ];
+        let pool_key = liquidity_pool.key();
+        let owner_key = dca_vault.owner;
+        let vault_id_bytes = dca_vault.vault_id.to_le_bytes();
+        let vault_seeds: &[&[u8]] = &[
+            DCA_VAULT_SEED,
+            pool_key.as_ref(),
+            owner_key.as_ref(),
+            vault_id_bytes.as_ref(),
+            &[dca_vault.bump],
+        ];
+        let (pool_token_in, pool_token_out) = if is_input_token_x {
+            (ctx.accounts.pool_token_x.to_account_info(), ctx.accounts.pool_token_y.to_account_info())
+        } else {
+            (ctx.accounts.pool_token_y.to_account_info(), ctx.accounts.pool_token_x.to_account_info())
+        };
+
+        if keeper_fee > 0 {
+            transfer_tokens(
+                input_token_program,
+                ctx.accounts.input_vault.to_account_info(),
+                ctx.accounts.keeper_token_in.to_account_info(),
+                dca_vault.to_account_info(),
+                input_mint,
+                ctx.remaining_accounts,
+                keeper_fee,
+                &[vault_seeds],
+            )?;
+        }
+        transfer_tokens(
+            input_token_program,
+            ctx.accounts.input_vault.to_account_info(),
+            pool_token_in,
+            dca_vault.to_account_info(),
+            input_mint,
+            ctx.remaining_accounts,
+            swap_amount,
+            &[vault_seeds],
+        )?;
+        transfer_tokens(
+            output_token_program,
+            pool_token_out,
+            ctx.accounts.output_vault.to_account_info(),
+            liquidity_pool.to_account_info(),
+            output_mint,
+            ctx.remaining_accounts,
+            amount_out,
+            &[pool_seeds],
+        )?;
+
+        emit!(DcaExecuted {
+            pool: pool_key,
+            owner: owner_key,
+            keeper: ctx.accounts.keeper.key(),
+            vault: dca_vault.key(),
+            input_mint: input_mint.key(),
+            output_mint: output_mint.key(),
+            input_amount: amount_in_received,
+            output_amount: amount_out,
+            fee_amount,
+            keeper_fee,
+            min_amount_out,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Sends everything a DCA vault has bought and `input_amount` of its unspent input to
+    /// the owner; allowed while the pool is paused
+    pub fn withdraw_dca_vault<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ManageDcaVault<'info>>,
+        input_amount: u64,
+    ) -> Result<()> {
+        withdraw_from_dca_vault(ctx, Some(input_amount))
+    }
+
+    /// Sends a DCA vault's whole balance to the owner and closes it
+    pub fn close_dca_vault<'info>(ctx: Context<'_, '_, 'info, 'info, ManageDcaVault<'info>>) -> Result<()> {
+        withdraw_from_dca_vault(ctx, None)
+    }
+
+    /// Creates the pool's auto-compounding LP vault. Harvests sell the pool's rewards
+    /// through `reward_pool`, which must pair `reward_mint` with one of the pool's tokens
+    pub fn initialize_lp_vault(
+        ctx: Context<InitializeLpVault>,
+        fee_recipient: Pubkey,
+        performance_fee_bps: u64,
+        max_slippage_bps: u64,
+    ) -> Result<()> {
+        // CHECKS
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        let reward_pool = &ctx.accounts.reward_pool;
+        require!(liquidity_pool.is_admin(&ctx.accounts.authority.key()), ErrorCode::Unauthorized);
+        require!(
+            performance_fee_bps <= MAX_PERFORMANCE_FEE_RATE && max_slippage_bps < BPS_DENOMINATOR,
+            ErrorCode::InvalidLpVault
+        );
+
+        let reward_mint = ctx.accounts.reward_mint.key();
+        require!(
+            reward_mint != liquidity_pool.token_x_mint && reward_mint != liquidity_pool.token_y_mint,
+            ErrorCode::InvalidLpVault
+        );
+        let bridge_mint = if reward_pool.token_x_mint == reward_mint {
+            reward_pool.token_y_mint
+        } else if reward_pool.token_y_mint == reward_mint {
+            reward_pool.token_x_mint
+        } else {
+            return err!(ErrorCode::InvalidLpVault);
+        };
+        require!(
+            bridge_mint == liquidity_pool.token_x_mint || bridge_mint == liquidity_pool.token_y_mint,
+            ErrorCode::InvalidLpVault
+        );
+        require!(
+            liquidity_pool.lp_token_supply > 0 && reward_pool.lp_token_supply > 0,
+            ErrorCode::NoLiquidity
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        let (reward_x_cumulative, reward_y_cumulative) = reward_pool.price_cumulative_at(now)?;
+        let (price_x_cumulative, price_y_cumulative) = liquidity_pool.price_cumulative_at(now)?;
+
+        // EFFECTS
+        let lp_vault = &mut ctx.accounts.lp_vault;
+        lp_vault.pool = liquidity_pool.key();
+        lp_vault.reward_pool = reward_pool.key();
+        lp_vault.reward_mint = reward_mint;
+        lp_vault.bridge_mint = bridge_mint;
+        lp_vault.fee_recipient = fee_recipient;
+        lp_vault.performance_fee_bps = performance_fee_bps;
+        lp_vault.max_slippage_bps = max_slippage_bps;
+        lp_vault.total_shares = 0;
+        lp_vault.pending_rewards = 0;
+        // The first harvest waits MIN_HARVEST_INTERVAL so it has a TWAP to check against
+        lp_vault.last_harvest = now;
+        lp_vault.reward_price_cumulative_last = if reward_pool.token_x_mint == reward_mint {
+            reward_x_cumulative
+        } else {
+            reward_y_cumulative
+        };
+        lp_vault.price_cumulative_last = if bridge_mint == liquidity_pool.token_x_mint {
+            price_x_cumulative
+        } else {
+            price_y_cumulative
+        };
+        lp_vault.bump = ctx.bumps.lp_vault;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        emit!(LpVaultInitialized {
+            pool: liquidity_pool.key(),
+            vault: lp_vault.key(),
+            reward_pool: lp_vault.reward_pool,
+            reward_mint,
+            bridge_mint,
+            fee_recipient,
+            performance_fee_bps,
+            max_slippage_bps,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Moves `lp_amount` of the owner's LP position into the pool's LP vault for shares.
+    /// Rewards the moved LP tokens earned since the owner's last claim are forfeited, so
+    /// claim first
+    pub fn deposit_lp_vault(ctx: Context<ManageLpVault>, lp_amount: u64) -> Result<()> {
+        // CHECKS
+        let vault_key = ctx.accounts.lp_vault.key();
+        let owner = ctx.accounts.owner.key();
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        let lp_vault = &mut ctx.accounts.lp_vault;
+        require!(lp_amount > 0, ErrorCode::InvalidLiquidityAmount);
+        require!(!liquidity_pool.paused, ErrorCode::PoolPaused);
+
+        let owner_index = liquidity_pool.lp_tokens
+            .iter()
+            .position(|x| x.owner == owner)
+            .ok_or(ErrorCode::NoLiquidity)?;
+        require!(liquidity_pool.lp_tokens[owner_index].amount >= lp_amount, ErrorCode::InsufficientFunds);
+
+        // Rewards earned before the deposit belong to the existing shares
+        let now = Clock::get()?.unix_timestamp;
+        let vault_lp = match lp_vault.accrue_rewards(vault_key, liquidity_pool, now)? {
+            Some(index) => liquidity_pool.lp_tokens[index].amount,
+            None => 0,
+        };
+        let shares = soondex_math::vault_shares_to_mint(lp_amount, vault_lp, lp_vault.total_shares)
+            .map_err(ErrorCode::from)?;
+        require!(shares > 0, ErrorCode::InvalidLiquidityAmount);
+
+        // EFFECTS
+        let owner_balance = &mut liquidity_pool.lp_tokens[owner_index];
+        owner_balance.amount = owner_balance.amount
+            .checked_sub(lp_amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+        if owner_balance.amount == 0 {
+            liquidity_pool.lp_tokens.remove(owner_index);
+        }
+
+        let vault_lp = vault_lp.checked_add(lp_amount).ok_or(ErrorCode::MathOverflow)?;
+        let reward_checkpoint = liquidity_pool.reward_per_token_cumulative;
+        match liquidity_pool.lp_tokens.iter_mut().find(|x| x.owner == vault_key) {
+            Some(vault_balance) => vault_balance.amount = vault_lp,
+            None => liquidity_pool.lp_tokens.push(LpTokenBalance {
+                owner: vault_key,
+                amount: vault_lp,
+                last_reward_claim: now,
+                reward_checkpoint,
+                rewards_earned: 0,
+            }),
+        }
+
+        let vault_position = &mut ctx.accounts.vault_position;
+        vault_position.vault = vault_key;
+        vault_position.owner = owner;
+        vault_position.shares = vault_position.shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+        vault_position.bump = ctx.bumps.vault_position;
+        lp_vault.total_shares = lp_vault.total_shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        emit!(LpVaultDeposited {
+            pool: liquidity_pool.key(),
+            vault: vault_key,
+            owner,
+            lp_amount,
+            shares,
+            vault_lp_tokens: vault_lp,
+            total_shares: lp_vault.total_shares,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Redeems `shares` of the pool's LP vault into the owner's LP position, paying out
+    /// the rewards that position earned first; allowed while the pool is paused
+    pub fn withdraw_lp_vault<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawLpVault<'info>>,
+        shares: u64,
+    ) -> Result<()> {
+        // CHECKS
+        let vault_key = ctx.accounts.lp_vault.key();
+        let owner = ctx.accounts.owner.key();
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        let lp_vault = &mut ctx.accounts.lp_vault;
+        let vault_position = &mut ctx.accounts.vault_position;
+        require!(shares > 0, ErrorCode::InvalidLiquidityAmount);
+        require!(vault_position.shares >= shares, ErrorCode::InsufficientFunds);
+
+        let now = Clock::get()?.unix_timestamp;
+        let vault_index = lp_vault.accrue_rewards(vault_key, liquidity_pool, now)?
+            .ok_or(ErrorCode::NoLiquidity)?;
+        let vault_lp = liquidity_pool.lp_tokens[vault_index].amount;
+        let lp_amount = soondex_math::vault_shares_to_lp(shares, vault_lp, lp_vault.total_shares)
+            .map_err(ErrorCode::from)?;
+        require!(lp_amount > 0, ErrorCode::InvalidLiquidityAmount);
+
+        // EFFECTS
+        let vault_lp = vault_lp.checked_sub(lp_amount).ok_or(ErrorCode::MathOverflow)?;
+        if vault_lp == 0 {
+            liquidity_pool.lp_tokens.remove(vault_index);
+        } else {
+            liquidity_pool.lp_tokens[vault_index].amount = vault_lp;
+        }
+        // Settling the owner's rewards restarts its reward clock, so the redeemed LP tokens
+        // can join the position without claiming rewards they never earned
+        let reward_per_token_cumulative = liquidity_pool.reward_per_token_cumulative;
+        let rewards = match liquidity_pool.lp_tokens.iter_mut().find(|x| x.owner == owner) {
+            Some(owner_balance) => {
+                let rewards = calculate_rewards(
+                    owner_balance.amount,
+                    reward_per_token_cumulative,
+                    owner_balance.reward_checkpoint,
+                )?
+                    .checked_add(owner_balance.rewards_earned)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                owner_balance.amount = owner_balance.amount
+                    .checked_add(lp_amount)
+                    .ok_or(ErrorCode::MathOverflow)?;
+                owner_balance.last_reward_claim = now;
+                owner_balance.reward_checkpoint = reward_per_token_cumulative;
+                owner_balance.rewards_earned = 0;
+                rewards
+            }
+            None => {
+                liquidity_pool.lp_tokens.push(LpTokenBalance {
+                    owner,
+                    amount: lp_amount,
+                    last_reward_claim: now,
+                    reward_checkpoint: reward_per_token_cumulative,
+                    rewards_earned: 0,
+                });
+                0
+            }
+        };
+        let rewards_sequence = if rewards > 0 {
+            Some(liquidity_pool.next_event_sequence()?)
+        } else {
+            None
+        };
+
+        vault_position.shares = vault_position.shares
+            .checked_sub(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+        lp_vault.total_shares = lp_vault.total_shares
+            .checked_sub(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        if let Some(rewards_sequence) = rewards_sequence {
+            let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+            let pool_seeds: &[&[u8]] = &[
+                POOL_SEED,
+                liquidity_pool.token_x_mint.as_ref(),
+                liquidity_pool.token_y_mint.as_ref(),
+                fee_rate_bytes.as_ref(),
+                &[liquidity_pool.bump],
+            ];
+            transfer_tokens(
+                &ctx.accounts.token_program,
+                ctx.accounts.pool_reward_account.to_account_info(),
+                ctx.accounts.owner_reward_account.to_account_info(),
+                liquidity_pool.to_account_info(),
+                &ctx.accounts.reward_mint,
+                ctx.remaining_accounts,
+                rewards,
+                &[pool_seeds],
+            )?;
+
+            emit!(RewardsClaimed {
+                pool: liquidity_pool.key(),
+                user: owner,
+                token_x_mint: liquidity_pool.token_x_mint,
+                token_y_mint: liquidity_pool.token_y_mint,
+                reward_mint: ctx.accounts.reward_mint.key(),
+                amount: rewards,
+                timestamp: now,
+                sequence: rewards_sequence,
+            });
+        }
+
+        emit!(LpVaultWithdrawn {
+            pool: liquidity_pool.key(),
+            vault: vault_key,
+            owner,
+            lp_amount,
+            shares,
+            vault_lp_tokens: vault_lp,
+            total_shares: lp_vault.total_shares,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Reinvests the LP vault's rewards. Anyone may call it once `MIN_HARVEST_INTERVAL`
+    /// has passed: the performance fee goes to the vault's fee recipient, the rest is sold
+    /// for the bridge token through the reward pool, half of that buys the pool's other
+    /// token, and both halves are added to the vault's LP position. Both swaps must pay
+    /// within `max_slippage_bps` of their pool's TWAP since the last harvest
+    pub fn harvest_lp_vault<'info>(ctx: Context<'_, '_, 'info, 'info, HarvestLpVault<'info>>) -> Result<()> {
+        // CHECKS
+        let vault_key = ctx.accounts.lp_vault.key();
+        let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+        let reward_pool = &mut ctx.accounts.reward_pool;
+        let lp_vault = &mut ctx.accounts.lp_vault;
+        require!(
+            !liquidity_pool.flash_loan_active && !reward_pool.flash_loan_active,
+            ErrorCode::FlashLoanActive
+        );
+        require!(!liquidity_pool.paused && !reward_pool.paused, ErrorCode::PoolPaused);
+        require!(lp_vault.total_shares > 0, ErrorCode::NoLiquidity);
+        let now = Clock::get()?.unix_timestamp;
+        let elapsed = now - lp_vault.last_harvest;
+        require!(elapsed >= MIN_HARVEST_INTERVAL, ErrorCode::HarvestNotDue);
+
+        update_pool(liquidity_pool, ctx.accounts.twamm.as_deref_mut().map(|twamm| &mut **twamm))?;
+        update_pool(reward_pool, ctx.accounts.reward_twamm.as_deref_mut().map(|twamm| &mut **twamm))?;
+
+        let vault_index = lp_vault.accrue_rewards(vault_key, liquidity_pool, now)?
+            .ok_or(ErrorCode::NoLiquidity)?;
+        let rewards = lp_vault.pending_rewards;
+        require!(rewards > 0, ErrorCode::NoRewardsAvailable);
+        let performance_fee = calculate_swap_fee(rewards, lp_vault.performance_fee_bps)?;
+        let swap_amount = rewards.checked_sub(performance_fee).ok_or(ErrorCode::MathOverflow)?;
+
+        // Sell the rewards for the bridge token through the reward pool
+        let reward_is_x = reward_pool.token_x_mint == lp_vault.reward_mint;
+        let reward_quote = price_swap_exact_in(
+            reward_pool,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.bridge_mint,
+            swap_amount,
+        )?;
+        let reward_price_cumulative = if reward_is_x {
+            reward_pool.price_x_cumulative
+        } else {
+            reward_pool.price_y_cumulative
+        };
+        let min_bridge_amount = twap_min_amount_out(
+            reward_price_cumulative,
+            lp_vault.reward_price_cumulative_last,
+            elapsed,
+            swap_amount,
+            lp_vault.max_slippage_bps,
+        )?;
+        require!(reward_quote.amount_out_received >= min_bridge_amount, ErrorCode::HarvestSlippageExceeded);
+
+        let (reward_reserve_in, reward_reserve_out) = if reward_is_x {
+            (reward_pool.token_x_reserve, reward_pool.token_y_reserve)
+        } else {
+            (reward_pool.token_y_reserve, reward_pool.token_x_reserve)
+        };
+        let slot = Clock::get()?.slot;
+        let reward_slot_volume_bps = reward_pool.check_swap_guardrails(
+            reward_quote.amount_in_received,
+            reward_quote.fee_amount,
+            reward_quote.amount_out,
+            reward_reserve_in,
+            reward_reserve_out,
+            slot,
+        )?;
+
+        // Half of the bridge tokens buy the pool's other token. They never leave the
+        // pool's vaults, so no transfer fee applies
+        let bridge_is_x = lp_vault.bridge_mint == liquidity_pool.token_x_mint;
+        let bridge_amount = reward_quote.amount_out_received;
+        let zap_amount = bridge_amount / 2;
+        let (reserve_in, reserve_out) = if bridge_is_x {
+            (liquidity_pool.token_x_reserve, liquidity_pool.token_y_reserve)
+        } else {
+            (liquidity_pool.token_y_reserve, liquidity_pool.token_x_reserve)
+        };
+        let zap_fee = calculate_swap_fee(zap_amount, liquidity_pool.swap_fee_rate)?;
+        let zap_amount_out = calculate_swap_output(
+            zap_amount.checked_sub(zap_fee).ok_or(ErrorCode::MathOverflow)?,
+            reserve_in,
+            reserve_out,
+        )?;
+        let price_cumulative = if bridge_is_x {
+            liquidity_pool.price_x_cumulative
+        } else {
+            liquidity_pool.price_y_cumulative
+        };
+        let min_zap_amount_out = twap_min_amount_out(
+            price_cumulative,
+            lp_vault.price_cumulative_last,
+            elapsed,
+            zap_amount,
+            lp_vault.max_slippage_bps,
+        )?;
+        require!(zap_amount_out >= min_zap_amount_out, ErrorCode::HarvestSlippageExceeded);
+        let slot_volume_bps = liquidity_pool.check_swap_guardrails(
+            zap_amount,
+            zap_fee,
+            zap_amount_out,
+            reserve_in,
+            reserve_out,
+            slot,
+        )?;
+
+        // EFFECTS
+        reward_pool.volume_slot = slot;
+        reward_pool.slot_volume_bps = reward_slot_volume_bps;
+        reward_pool.apply_swap(reward_is_x, reward_quote.amount_in_received, reward_quote.amount_out)?;
+        let reward_sequence = reward_pool.next_event_sequence()?;
+
+        liquidity_pool.volume_slot = slot;
+        liquidity_pool.slot_volume_bps = slot_volume_bps;
+        liquidity_pool.apply_swap(bridge_is_x, zap_amount, zap_amount_out)?;
+        let zap_reserves = (
+            liquidity_pool.token_x_reserve,
+            liquidity_pool.token_y_reserve,
+            liquidity_pool.lp_token_supply,
+        );
+        let zap_sequence = liquidity_pool.next_event_sequence()?;
+
+        let bridge_remaining = bridge_amount.checked_sub(zap_amount).ok_or(ErrorCode::MathOverflow)?;
+        let (amount_x, amount_y) = if bridge_is_x {
+            (bridge_remaining, zap_amount_out)
+        } else {
+            (zap_amount_out, bridge_remaining)
+        };
+        let lp_tokens = liquidity_pool.lp_tokens_to_mint(amount_x, amount_y)?;
+        require!(lp_tokens > 0, ErrorCode::InvalidLiquidityAmount);
+        liquidity_pool.token_x_reserve = liquidity_pool.token_x_reserve
+            .checked_add(amount_x)
+            .ok_or(ErrorCode::MathOverflow)?;
+        liquidity_pool.token_y_reserve = liquidity_pool.token_y_reserve
+            .checked_add(amount_y)
+            .ok_or(ErrorCode::MathOverflow)?;
+        liquidity_pool.lp_token_supply = liquidity_pool.lp_token_supply
+            .checked_add(lp_tokens)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let vault_balance = &mut liquidity_pool.lp_tokens[vault_index];
+        vault_balance.amount = vault_balance.amount
+            .checked_add(lp_tokens)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let vault_lp = vault_balance.amount;
+
+        lp_vault.pending_rewards = 0;
+        lp_vault.last_harvest = now;
+        lp_vault.reward_price_cumulative_last = reward_price_cumulative;
+        lp_vault.price_cumulative_last = price_cumulative;
+        let sequence = liquidity_pool.next_event_sequence()?;
+
+        // INTERACTIONS
+        let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+        let pool_seeds: &[&[u8]] = &[
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            fee_rate_bytes.as_ref(),
+            &[liquidity_pool.bump],
+        ];
+        let reward_fee_rate_bytes = reward_pool.fee_rate.to_le_bytes();
+        let reward_pool_seeds: &[&[u8]] = &[
+            POOL_SEED,
+            reward_pool.token_x_mint.as_ref(),
+            reward_pool.token_y_mint.as_ref(),
+            reward_fee_rate_bytes.as_ref(),
+            &[reward_pool.bump],
+        ];
+
+        if performance_fee > 0 {
+            transfer_tokens(
+                &ctx.accounts.reward_token_program,
+                ctx.accounts.pool_reward_account.to_account_info(),
+                ctx.accounts.fee_recipient_token.to_account_info(),
+                liquidity_pool.to_account_info(),
+                &ctx.accounts.reward_mint,
+                ctx.remaining_accounts,
+                performance_fee,
+                &[pool_seeds],
+            )?;
+        }
+        transfer_tokens(
+            &ctx.accounts.reward_token_program,
+            ctx.accounts.pool_reward_account.to_account_info(),
+            ctx.accounts.reward_pool_token_in.to_account_info(),
+            liquidity_pool.to_account_info(),
+            &ctx.accounts.reward_mint,
+            ctx.remaining_accounts,
+            swap_amount,
+            &[pool_seeds],
+        )?;
+        transfer_tokens(
+            &ctx.accounts.bridge_token_program,
+            ctx.accounts.reward_pool_token_out.to_account_info(),
+            ctx.accounts.pool_token_bridge.to_account_info(),
+            reward_pool.to_account_info(),
+            &ctx.accounts.bridge_mint,
+            ctx.remaining_accounts,
+            reward_quote.amount_out,
+            &[reward_pool_seeds],
+        )?;
+
+        let pool_key = liquidity_pool.key();
+        let (other_mint, bridge_mint) = if bridge_is_x {
+            (liquidity_pool.token_y_mint, liquidity_pool.token_x_mint)
+        } else {
+            (liquidity_pool.token_x_mint, liquidity_pool.token_y_mint)
+        };
+        emit!(TokensSwapped {
+            pool: reward_pool.key(),
+            user: vault_key,
+            input_mint: lp_vault.reward_mint,
+            output_mint: bridge_mint,
+            input_amount: reward_quote.amount_in_received,
+            output_amount: reward_quote.amount_out,
+            fee_amount: reward_quote.fee_amount,
+            token_x_reserve: reward_pool.token_x_reserve,
+            token_y_reserve: reward_pool.token_y_reserve,
+            lp_token_supply: reward_pool.lp_token_supply,
+            referrer: None,
+            referral_fee: 0,
+            sequence: reward_sequence,
+        });
+        emit!(TokensSwapped {
+            pool: pool_key,
+            user: vault_key,
+            input_mint: bridge_mint,
+            output_mint: other_mint,
+            input_amount: zap_amount,
+            output_amount: zap_amount_out,
+            fee_amount: zap_fee,
+            token_x_reserve: zap_reserves.0,
+            token_y_reserve: zap_reserves.1,
+            lp_token_supply: zap_reserves.2,
+            referrer: None,
+            referral_fee: 0,
+            sequence: zap_sequence,
+        });
+        emit!(LpVaultHarvested {
+            pool: pool_key,
+            vault: vault_key,
+            keeper: ctx.accounts.keeper.key(),
+            rewards,
+            performance_fee,
+            token_x_amount: amount_x,
+            token_y_amount: amount_y,
+            lp_tokens_minted: lp_tokens,
+            vault_lp_tokens: vault_lp,
+            total_shares: lp_vault.total_shares,
+            token_x_reserve: liquidity_pool.token_x_reserve,
+            token_y_reserve: liquidity_pool.token_y_reserve,
+            lp_token_supply: liquidity_pool.lp_token_supply,
+            sequence,
+        });
+
+        Ok(())
+    }
+
+    /// Prices a swap of `amount_in` against the current reserves without executing it.
+    /// A referral fee of `referral_fee_bps` comes out of `amount_in` before the swap, as
+    /// in `swap_tokens`; the quote's `amount_in` is what the user pays, including it
+    pub fn quote_swap(
+        ctx: Context<Quote>,
+        input_token: Pubkey,
+        amount_in: u64,
+        referral_fee_bps: u64,
+    ) -> Result<SwapQuote> {
+        require!(
+            referral_fee_bps <= ctx.accounts.liquidity_pool.max_referral_fee_bps,
+            ErrorCode::ReferralFeeTooHigh
+        );
+        let swap_amount_in = amount_in
+            .checked_sub(calculate_swap_fee(amount_in, referral_fee_bps)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        ctx.accounts.settle()?;
+        let (input_mint, output_mint) = ctx.accounts.swap_mints(input_token)?;
+        let quote = price_swap_exact_in(&ctx.accounts.liquidity_pool, input_mint, output_mint, swap_amount_in)?;
+        ctx.accounts.check_swap_guardrails(input_token, &quote)?;
+        Ok(SwapQuote { amount_in, ..quote })
+    }
+
+    /// Prices the smallest input that delivers at least `amount_out` to the user
+    pub fn quote_swap_exact_out(
+        ctx: Context<Quote>,
+        input_token: Pubkey,
+        amount_out: u64,
+    ) -> Result<SwapQuote> {
+        ctx.accounts.settle()?;
+        let (input_mint, output_mint) = ctx.accounts.swap_mints(input_token)?;
+        let quote = price_swap_exact_out(&ctx.accounts.liquidity_pool, input_mint, output_mint, amount_out)?;
+        ctx.accounts.check_swap_guardrails(input_token, &quote)?;
+        Ok(quote)
+    }
+
+    pub fn quote_add_liquidity(
+        ctx: Context<Quote>,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Result<AddLiquidityQuote> {
+        require!(amount_x > 0 && amount_y > 0, ErrorCode::InvalidLiquidityAmount);
+        ctx.accounts.settle()?;
+
+        let amount_x_received = amount_x
+            .checked_sub(calculate_transfer_fee(&ctx.accounts.token_x_mint, amount_x)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        let amount_y_received = amount_y
+            .checked_sub(calculate_transfer_fee(&ctx.accounts.token_y_mint, amount_y)?)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            amount_x_received > 0 && amount_y_received > 0,
+            ErrorCode::InvalidLiquidityAmount
+        );
+
+        Ok(AddLiquidityQuote {
+            amount_x_received,
+            amount_y_received,
+            lp_tokens: ctx.accounts.liquidity_pool.lp_tokens_for_deposit(amount_x_received, amount_y_received)?.0,
+        })
+    }
+
+    pub fn quote_remove_liquidity(
+        ctx: Context<Quote>,
+        amount_x: u64,
+        amount_y: u64,
+    ) -> Result<RemoveLiquidityQuote> {
+        ctx.accounts.settle()?;
+        let liquidity_pool = &ctx.accounts.liquidity_pool;
+        require!(
+            amount_x <= liquidity_pool.token_x_reserve &&
+            amount_y <= liquidity_pool.token_y_reserve,
+            ErrorCode::InsufficientFunds
+        );
+
+        let lp_tokens = liquidity_pool.lp_tokens_to_burn(amount_x, amount_y)?;
+
+        Ok(RemoveLiquidityQuote {
+            lp_tokens,
+            amount_x_received: amount_x
+                .checked_sub(calculate_transfer_fee(&ctx.accounts.token_x_mint, amount_x)?)
+                .ok_or(ErrorCode::MathOverflow)?,
+            amount_y_received: amount_y
+                .checked_sub(calculate_transfer_fee(&ctx.accounts.token_y_mint, amount_y)?)
+                .ok_or(ErrorCode::MathOverflow)?,
+        })
+    }
+}
+
+
+
+#[derive(Accounts)]
+#[instruction(token_x_mint: Pubkey, token_y_mint: Pubkey, fee_rate: u64)]
+pub struct InitializePool<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + LiquidityPool::INIT_SPACE,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            fee_rate.to_le_bytes().as_ref()
+        ],
+        bump    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + PoolRegistry::INIT_SPACE,
+        seeds = [POOL_REGISTRY_SEED],
+        bump
+    )]
+    pub pool_registry: Account<'info, PoolRegistry>,
+
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + PoolRegistryPage::INIT_SPACE,
+        seeds = [
+            POOL_REGISTRY_SEED,
+            (pool_registry.pool_count / POOLS_PER_REGISTRY_PAGE).to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub pool_registry_page: Account<'info, PoolRegistryPage>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+    #[account(mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+
+    /// The pool's associated token accounts, which clients create before this instruction
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            &liquidity_pool.key(),
+            &token_x_mint.key(),
+            &token_x_program.key(),
+        ) @ ErrorCode::InvalidPoolVault,
+    )]
+    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            &liquidity_pool.key(),
+            &token_y_mint.key(),
+            &token_y_program.key(),
+        ) @ ErrorCode::InvalidPoolVault,
+    )]
+    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: This is safe because we only use it to transfer SOL as protocol fee
+    #[account(mut)]
+    pub protocol_wallet: AccountInfo<'info>,
+}
+#[derive(Accounts)]
+pub struct RemovePool<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+            ],
+            bump = liquidity_pool.bump,
+            close = authority
+        )]
+        pub liquidity_pool: Account<'info, LiquidityPool>,
+
+        #[account(
+            mut,
+            seeds = [POOL_REGISTRY_SEED, liquidity_pool.registry_page.to_le_bytes().as_ref()],
+            bump = pool_registry_page.bump,
+        )]
+        pub pool_registry_page: Account<'info, PoolRegistryPage>,
+        
+        #[account(mut)]
+        pub authority: Signer<'info>,
+        
+        pub token_x_mint: InterfaceAccount<'info, Mint>,
+        pub token_y_mint: InterfaceAccount<'info, Mint>,
+ }
+
+#[derive(Accounts)]
+pub struct ManageAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePoolConfig<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(token_x_mint: Pubkey, token_y_mint: Pubkey, amount_x: u64, amount_y: u64)]
+pub struct ProvideLiquidity<'info> {
+    #[account(mut, mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump,
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_token_x_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_x_account.mint == token_x_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_x_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = user_token_y_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_y_account.mint == token_y_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_y_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+    /// CHECK: Ephemeral WSOL token account created and closed within the instruction
+    #[account(
+        mut,
+        seeds = [
+            TEMP_WSOL_SEED,
+            liquidity_pool.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
+    )]
+    pub temp_wsol_account: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+    /// Required once `initialize_twamm` has run for the pool
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Option<Box<Account<'info, TwammState>>>,
+}
+
+#[derive(Accounts)]
+#[instruction(token_x_mint: Pubkey, token_y_mint: Pubkey, amount_x: u64, amount_y: u64)]
+pub struct RemoveLiquidity<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_token_x_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_x_account.mint == token_x_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_x_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = user_token_y_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_y_account.mint == token_y_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_y_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+    #[account(mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: Ephemeral WSOL token account created and closed within the instruction
+    #[account(
+        mut,
+        seeds = [
+            TEMP_WSOL_SEED,
+            liquidity_pool.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
+    )]
+    pub temp_wsol_account: Option<UncheckedAccount<'info>>,
+    pub system_program: Program<'info, System>,
+    /// Required once `initialize_twamm` has run for the pool
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Option<Box<Account<'info, TwammState>>>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(input_token: Pubkey, output_token: Pubkey, amount_in: u64, minimum_amount_out: u64)]
+pub struct SwapTokens<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        constraint = user_token_in.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_in.mint == input_token @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_in: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(
+        mut,
+        constraint = user_token_out.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_out.mint == output_token @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_out: Option<InterfaceAccount<'info, TokenAccount>>,
+    
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x: InterfaceAccount<'info, TokenAccount>,
+    
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y: InterfaceAccount<'info, TokenAccount>,
+    
+    pub token_x_program: Interface<'info, TokenInterface>,
+    
+    pub token_y_program: Interface<'info, TokenInterface>,
+    
+    #[account(mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    
+    #[account(mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    
+    /// CHECK: Ephemeral WSOL token account created and closed within the instruction
+    #[account(
+        mut,
+        seeds = [
+            TEMP_WSOL_SEED,
+            liquidity_pool.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
+    )]
+    pub temp_wsol_account: Option<UncheckedAccount<'info>>,
+    
+    pub system_program: Program<'info, System>,
+    
+    /// CHECK: Native mint is a static known address
+    #[account(address = NATIVE_MINT_ID @ ErrorCode::InvalidNativeMint)]
+    pub native_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Only invoked for flash swaps; the paid input is verified after the callback returns
+    pub callback_program: Option<UncheckedAccount<'info>>,
+    /// Required once `initialize_twamm` has run for the pool
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Option<Box<Account<'info, TwammState>>>,
+    /// Integrator credited with the swap, paid any referral fee in the input mint
+    #[account(
+        mut,
+        constraint = referrer_token.mint == input_token @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub referrer_token: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64)]
+pub struct Stake<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserState::INIT_SPACE,
+        seeds = [
+            USER_STATE_SEED,
+            liquidity_pool.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_account.mint == token_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            &liquidity_pool.key(),
+            &token_mint.key(),
+            &token_program.key(),
+        ) @ ErrorCode::InvalidPoolVault,
+        constraint = pool_token_account.key() != liquidity_pool.token_x_vault
+            && pool_token_account.key() != liquidity_pool.token_y_vault @ ErrorCode::ReserveVaultNotAllowed,
+    )]
+    pub pool_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_program)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+#[instruction(amount: u64)]
+pub struct Unstake<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + UserState::INIT_SPACE,
+        seeds = [
+            USER_STATE_SEED,
+            liquidity_pool.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_account.mint == token_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            &liquidity_pool.key(),
+            &token_mint.key(),
+            &token_program.key(),
+        ) @ ErrorCode::InvalidPoolVault,
+        constraint = pool_token_account.key() != liquidity_pool.token_x_vault
+            && pool_token_account.key() != liquidity_pool.token_y_vault @ ErrorCode::ReserveVaultNotAllowed,
+    )]
+    pub pool_token_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_program)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    
+    #[account(
+        mut,
+        seeds = [
+            USER_STATE_SEED,
+            liquidity_pool.key().as_ref(),
+            user.key().as_ref()
+        ],
+        bump
+    )]
+    pub user_state: Account<'info, UserState>,
+    
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_reward_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_reward_account.mint == reward_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_reward_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            &liquidity_pool.key(),
+            &reward_mint.key(),
+            &token_program.key(),
+        ) @ ErrorCode::InvalidPoolVault,
+        constraint = pool_reward_account.key() != liquidity_pool.token_x_vault
+            && pool_reward_account.key() != liquidity_pool.token_y_vault @ ErrorCode::ReserveVaultNotAllowed,
+    )]
+    pub pool_reward_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_program)]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct FlashBorrow<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_token_x_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_x_account.mint == token_x_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_token_y_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_y_account.mint == token_y_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_y_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+    /// CHECK: Address is constrained to the instructions sysvar
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FlashRepay<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        mut,
+        constraint = user_token_x_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_x_account.mint == token_x_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_token_y_account.owner == user.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = user_token_y_account.mint == token_y_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub user_token_y_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct SyncReserves<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    pub user: Signer<'info>,
+    #[account(address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct Skim<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    pub user: Signer<'info>,
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = recipient_token_x_account.mint == token_x_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub recipient_token_x_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = recipient_token_y_account.mint == token_y_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+        constraint = recipient_token_y_account.owner == recipient_token_x_account.owner
+            @ ErrorCode::InvalidTokenAccountOwner,
+    )]
+    pub recipient_token_y_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: u64)]
+pub struct PlaceLimitOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + LimitOrder::INIT_SPACE,
+        seeds = [
+            LIMIT_ORDER_SEED,
+            liquidity_pool.key().as_ref(),
+            owner.key().as_ref(),
+            order_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub limit_order: Account<'info, LimitOrder>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = owner_token_in.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_in.mint == input_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = input_mint,
+        associated_token::authority = limit_order,
+        associated_token::token_program = input_token_program,
+    )]
+    pub escrow: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mint::token_program = input_token_program,
+        constraint = input_mint.key() == liquidity_pool.token_x_mint
+            || input_mint.key() == liquidity_pool.token_y_mint @ ErrorCode::InvalidLimitOrder,
+    )]
+    pub input_mint: InterfaceAccount<'info, Mint>,
+    pub input_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FillLimitOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ ErrorCode::InvalidLimitOrder,
+        has_one = escrow @ ErrorCode::InvalidLimitOrder,
+        constraint = limit_order.pool == liquidity_pool.key() @ ErrorCode::InvalidLimitOrder,
+    )]
+    pub limit_order: Account<'info, LimitOrder>,
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+    #[account(
+        mut,
+        constraint = owner_token_out.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_out.mint == limit_order.output_mint @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_out: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub escrow: InterfaceAccount<'info, TokenAccount>,
+    pub keeper: Signer<'info>,
+    #[account(
+        mut,
+        constraint = keeper_token_in.mint == limit_order.input_mint @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub keeper_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y: InterfaceAccount<'info, TokenAccount>,
+    /// Writable so withheld transfer fees can be harvested from the escrow before it closes
+    #[account(mut, mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut, mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+    /// Required once `initialize_twamm` has run for the pool
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Option<Box<Account<'info, TwammState>>>,
+}
+
+#[derive(Accounts)]
+pub struct CancelLimitOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        mut,
+        close = owner,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = escrow @ ErrorCode::InvalidLimitOrder,
+        constraint = limit_order.pool == liquidity_pool.key() @ ErrorCode::InvalidLimitOrder,
+    )]
+    pub limit_order: Account<'info, LimitOrder>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = owner_token_in.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_in.mint == limit_order.input_mint @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub escrow: InterfaceAccount<'info, TokenAccount>,
+    /// Writable so withheld transfer fees can be harvested from the escrow before it closes
+    #[account(
+        mut,
+        address = limit_order.input_mint @ ErrorCode::InvalidToken,
+        mint::token_program = input_token_program,
+    )]
+    pub input_mint: InterfaceAccount<'info, Mint>,
+    pub input_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTwamm<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + TwammState::INIT_SPACE,
+        seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()],
+        bump
+    )]
+    pub twamm: Box<Account<'info, TwammState>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteTwamm<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Box<Account<'info, TwammState>>,
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: u64)]
+pub struct PlaceLongTermOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Box<Account<'info, TwammState>>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + LongTermOrder::INIT_SPACE,
+        seeds = [
+            LONG_TERM_ORDER_SEED,
+            liquidity_pool.key().as_ref(),
+            owner.key().as_ref(),
+            order_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub long_term_order: Account<'info, LongTermOrder>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = owner_token_in.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_in.mint == input_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = (pool_token_in.key() == liquidity_pool.token_x_vault
+            || pool_token_in.key() == liquidity_pool.token_y_vault)
+            && pool_token_in.mint == input_mint.key() @ ErrorCode::InvalidPoolVault,
+    )]
+    pub pool_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mint::token_program = input_token_program,
+        constraint = input_mint.key() == liquidity_pool.token_x_mint
+            || input_mint.key() == liquidity_pool.token_y_mint @ ErrorCode::InvalidToken,
+    )]
+    pub input_mint: InterfaceAccount<'info, Mint>,
+    pub input_token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Shared by `withdraw_long_term_order` and `cancel_long_term_order`
+#[derive(Accounts)]
+pub struct ManageLongTermOrder<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Box<Account<'info, TwammState>>,
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::InvalidLongTermOrder,
+        constraint = long_term_order.pool == liquidity_pool.key() @ ErrorCode::InvalidLongTermOrder,
+    )]
+    pub long_term_order: Account<'info, LongTermOrder>,
+    pub user: Signer<'info>,
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+    #[account(
+        mut,
+        constraint = owner_token_in.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_in.mint == long_term_order.input_mint @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = owner_token_out.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_out.mint == long_term_order.output_mint @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_out: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(vault_id: u64)]
+pub struct OpenDcaVault<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + DcaVault::INIT_SPACE,
+        seeds = [
+            DCA_VAULT_SEED,
+            liquidity_pool.key().as_ref(),
+            owner.key().as_ref(),
+            vault_id.to_le_bytes().as_ref()
+        ],
+        bump
+    )]
+    pub dca_vault: Account<'info, DcaVault>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = owner_token_in.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_in.mint == input_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = input_mint,
+        associated_token::authority = dca_vault,
+        associated_token::token_program = input_token_program,
+    )]
+    pub input_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = owner,
+        associated_token::mint = output_mint,
+        associated_token::authority = dca_vault,
+        associated_token::token_program = output_token_program,
+    )]
+    pub output_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mint::token_program = input_token_program,
+        constraint = input_mint.key() == liquidity_pool.token_x_mint
+            || input_mint.key() == liquidity_pool.token_y_mint @ ErrorCode::InvalidDcaVault,
+    )]
+    pub input_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mint::token_program = output_token_program,
+        constraint = output_mint.key() != input_mint.key() && (output_mint.key() == liquidity_pool.token_x_mint
+            || output_mint.key() == liquidity_pool.token_y_mint) @ ErrorCode::InvalidDcaVault,
+    )]
+    pub output_mint: InterfaceAccount<'info, Mint>,
+    pub input_token_program: Interface<'info, TokenInterface>,
+    pub output_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteDca<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        mut,
+        has_one = input_vault @ ErrorCode::InvalidDcaVault,
+        has_one = output_vault @ ErrorCode::InvalidDcaVault,
+        constraint = dca_vault.pool == liquidity_pool.key() @ ErrorCode::InvalidDcaVault,
+    )]
+    pub dca_vault: Account<'info, DcaVault>,
+    #[account(mut)]
+    pub input_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub output_vault: InterfaceAccount<'info, TokenAccount>,
+    pub keeper: Signer<'info>,
+    #[account(
+        mut,
+        constraint = keeper_token_in.mint == dca_vault.input_mint @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub keeper_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_x_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_x: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut, address = liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault)]
+    pub pool_token_y: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_x_program)]
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    #[account(mint::token_program = token_y_program)]
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    pub token_x_program: Interface<'info, TokenInterface>,
+    pub token_y_program: Interface<'info, TokenInterface>,
+    /// Required once `initialize_twamm` has run for the pool
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Option<Box<Account<'info, TwammState>>>,
+}
+
+/// Shared by `withdraw_dca_vault` and `close_dca_vault`
+#[derive(Accounts)]
+pub struct ManageDcaVault<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::Unauthorized,
+        has_one = input_vault @ ErrorCode::InvalidDcaVault,
+        has_one = output_vault @ ErrorCode::InvalidDcaVault,
+        constraint = dca_vault.pool == liquidity_pool.key() @ ErrorCode::InvalidDcaVault,
+    )]
+    pub dca_vault: Account<'info, DcaVault>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        constraint = owner_token_in.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_in.mint == dca_vault.input_mint @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = owner_token_out.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_token_out.mint == dca_vault.output_mint @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_token_out: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub input_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(mut)]
+    pub output_vault: InterfaceAccount<'info, TokenAccount>,
+    /// Writable so withheld transfer fees can be harvested from the vaults before they close
+    #[account(
+        mut,
+        address = dca_vault.input_mint @ ErrorCode::InvalidToken,
+        mint::token_program = input_token_program,
+    )]
+    pub input_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        address = dca_vault.output_mint @ ErrorCode::InvalidToken,
+        mint::token_program = output_token_program,
+    )]
+    pub output_mint: InterfaceAccount<'info, Mint>,
+    pub input_token_program: Interface<'info, TokenInterface>,
+    pub output_token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLpVault<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + LpVault::INIT_SPACE,
+        seeds = [LP_VAULT_SEED, liquidity_pool.key().as_ref()],
+        bump
+    )]
+    pub lp_vault: Account<'info, LpVault>,
+    #[account(
+        seeds = [
+            POOL_SEED,
+            reward_pool.token_x_mint.as_ref(),
+            reward_pool.token_y_mint.as_ref(),
+            reward_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = reward_pool.bump
+    )]
+    pub reward_pool: Box<Account<'info, LiquidityPool>>,
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageLpVault<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(mut, seeds = [LP_VAULT_SEED, liquidity_pool.key().as_ref()], bump = lp_vault.bump)]
+    pub lp_vault: Account<'info, LpVault>,
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + VaultPosition::INIT_SPACE,
+        seeds = [VAULT_POSITION_SEED, lp_vault.key().as_ref(), owner.key().as_ref()],
+        bump
+    )]
+    pub vault_position: Account<'info, VaultPosition>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawLpVault<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    #[account(
+        mut,
+        seeds = [LP_VAULT_SEED, liquidity_pool.key().as_ref()],
+        bump = lp_vault.bump,
+        has_one = reward_mint @ ErrorCode::InvalidLpVault,
+    )]
+    pub lp_vault: Account<'info, LpVault>,
+    #[account(
+        mut,
+        seeds = [VAULT_POSITION_SEED, lp_vault.key().as_ref(), owner.key().as_ref()],
+        bump = vault_position.bump
+    )]
+    pub vault_position: Account<'info, VaultPosition>,
+    pub owner: Signer<'info>,
+    /// Receives the rewards the owner's LP position earned before the redeemed LP tokens join it
+    #[account(
+        mut,
+        constraint = owner_reward_account.owner == owner.key() @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = owner_reward_account.mint == reward_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub owner_reward_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            &liquidity_pool.key(),
+            &reward_mint.key(),
+            &token_program.key(),
+        ) @ ErrorCode::InvalidPoolVault,
+    )]
+    pub pool_reward_account: InterfaceAccount<'info, TokenAccount>,
+    #[account(mint::token_program = token_program)]
+    pub reward_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct HarvestLpVault<'info> {
+    #[account(
+        mut,
+        seeds = [
+            POOL_SEED,
+            liquidity_pool.token_x_mint.as_ref(),
+            liquidity_pool.token_y_mint.as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Box<Account<'info, LiquidityPool>>,
+    #[account(
+        mut,
+        seeds = [LP_VAULT_SEED, liquidity_pool.key().as_ref()],
+        bump = lp_vault.bump,
+        has_one = reward_pool @ ErrorCode::InvalidLpVault,
+        has_one = reward_mint @ ErrorCode::InvalidLpVault,
+        has_one = bridge_mint @ ErrorCode::InvalidLpVault,
+    )]
+    pub lp_vault: Box<Account<'info, LpVault>>,
+    #[account(mut)]
+    pub reward_pool: Box<Account<'info, LiquidityPool>>,
+    pub keeper: Signer<'info>,
+    #[account(
+        mut,
+        address = associated_token::get_associated_token_address_with_program_id(
+            &liquidity_pool.key(),
+            &reward_mint.key(),
+            &reward_token_program.key(),
+        ) @ ErrorCode::InvalidPoolVault,
+    )]
+    pub pool_reward_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = fee_recipient_token.owner == lp_vault.fee_recipient @ ErrorCode::InvalidTokenAccountOwner,
+        constraint = fee_recipient_token.mint == reward_mint.key() @ ErrorCode::InvalidTokenAccountMint,
+    )]
+    pub fee_recipient_token: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// The reward pool's vault for `reward_mint`
+    #[account(
+        mut,
+        constraint = reward_pool_token_in.key() == reward_pool.token_x_vault
+            || reward_pool_token_in.key() == reward_pool.token_y_vault @ ErrorCode::InvalidPoolVault,
+        constraint = reward_pool_token_in.mint == reward_mint.key() @ ErrorCode::InvalidPoolVault,
+    )]
+    pub reward_pool_token_in: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// The reward pool's vault for `bridge_mint`
+    #[account(
+        mut,
+        constraint = reward_pool_token_out.key() == reward_pool.token_x_vault
+            || reward_pool_token_out.key() == reward_pool.token_y_vault @ ErrorCode::InvalidPoolVault,
+        constraint = reward_pool_token_out.mint == bridge_mint.key() @ ErrorCode::InvalidPoolVault,
+    )]
+    pub reward_pool_token_out: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// The pool's vault for `bridge_mint`
+    #[account(
+        mut,
+        constraint = pool_token_bridge.key() == liquidity_pool.token_x_vault
+            || pool_token_bridge.key() == liquidity_pool.token_y_vault @ ErrorCode::InvalidPoolVault,
+        constraint = pool_token_bridge.mint == bridge_mint.key() @ ErrorCode::InvalidPoolVault,
+    )]
+    pub pool_token_bridge: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mint::token_program = reward_token_program)]
+    pub reward_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = bridge_token_program)]
+    pub bridge_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub reward_token_program: Interface<'info, TokenInterface>,
+    pub bridge_token_program: Interface<'info, TokenInterface>,
+    /// Required once `initialize_twamm` has run for the pool
+    #[account(mut, seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Option<Box<Account<'info, TwammState>>>,
+    /// Required once `initialize_twamm` has run for the reward pool
+    #[account(mut, seeds = [TWAMM_SEED, reward_pool.key().as_ref()], bump = reward_twamm.bump)]
+    pub reward_twamm: Option<Box<Account<'info, TwammState>>>,
+}
+
+/// Read-only view of a pool for the quote instructions
+#[derive(Accounts)]
+pub struct Quote<'info> {
+    #[account(
+        seeds = [
+            POOL_SEED,
+            token_x_mint.key().as_ref(),
+            token_y_mint.key().as_ref(),
+            liquidity_pool.fee_rate.to_le_bytes().as_ref()
+        ],
+        bump = liquidity_pool.bump
+    )]
+    pub liquidity_pool: Account<'info, LiquidityPool>,
+    pub token_x_mint: InterfaceAccount<'info, Mint>,
+    pub token_y_mint: InterfaceAccount<'info, Mint>,
+    /// Required once `initialize_twamm` has run for the pool
+    #[account(seeds = [TWAMM_SEED, liquidity_pool.key().as_ref()], bump = twamm.bump)]
+    pub twamm: Option<Box<Account<'info, TwammState>>>,
+}
+
+impl<'info> Quote<'info> {
+    /// Settles pending long-term orders the way a trade would before pricing it. The
+    /// accounts are read-only, so the settled pool is never written back
+    fn settle(&mut self) -> Result<()> {
+        update_pool(&mut self.liquidity_pool, self.twamm.as_deref_mut().map(|twamm| &mut **twamm))
+    }
+
+    /// Rejects swaps `swap_tokens` would refuse under the pool's guardrails, counting the
+    /// volume already swapped this slot
+    fn check_swap_guardrails(&self, input_token: Pubkey, quote: &SwapQuote) -> Result<()> {
+        let liquidity_pool = &self.liquidity_pool;
+        let (reserve_in, reserve_out) = if input_token == liquidity_pool.token_x_mint {
+            (liquidity_pool.token_x_reserve, liquidity_pool.token_y_reserve)
+        } else {
+            (liquidity_pool.token_y_reserve, liquidity_pool.token_x_reserve)
+        };
+        liquidity_pool.check_swap_guardrails(
+            quote.amount_in_received,
+            quote.fee_amount,
+            quote.amount_out,
+            reserve_in,
+            reserve_out,
+            Clock::get()?.slot,
+        )?;
+        Ok(())
+    }
+
+    /// Orders the pool mints as (input, output) for `input_token`
+    fn swap_mints(
+        &self,
+        input_token: Pubkey,
+    ) -> Result<(&InterfaceAccount<'info, Mint>, &InterfaceAccount<'info, Mint>)> {
+        if input_token == self.token_x_mint.key() {
+            Ok((&self.token_x_mint, &self.token_y_mint))
+        } else if input_token == self.token_y_mint.key() {
+            Ok((&self.token_y_mint, &self.token_x_mint))
+        } else {
+            err!(ErrorCode::InvalidToken)
+        }
+    }
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct LiquidityPool {
+    pub authority: Pubkey,
+    pub super_admin: Pubkey,
+    #[max_len(3)]
+    pub admins: Vec<Pubkey>,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    #[max_len(100)]
+    pub lp_tokens: Vec<LpTokenBalance>,
+    pub fee_rate: u64,
+    pub bump: u8,
+    pub reward_rate: u64,
+    pub total_staked: u64,
+    pub flash_loan_active: bool,
+    pub flash_loan_amount_x: u64,
+    pub flash_loan_amount_y: u64,
+    /// Sequence number of the last event emitted for this pool
+    pub event_sequence: u64,
+    /// Swap fee in basis points; starts at the fee tier and admins may lower it through `set_swap_fee`
+    pub swap_fee_rate: u64,
+    /// Blocks swaps, deposits, staking and flash loans; withdrawals stay open
+    pub paused: bool,
+    /// Token accounts holding the reserves, fixed at `initialize_pool`
+    pub token_x_vault: Pubkey,
+    pub token_y_vault: Pubkey,
+    /// LP tokens the first deposit must mint, changed by admins through `set_min_initial_liquidity`
+    pub min_initial_liquidity: u64,
+    /// Swap limits in basis points, changed by admins through `set_swap_guardrails`; zero disables each
+    pub max_swap_input_bps: u64,
+    pub max_price_impact_bps: u64,
+    pub max_slot_volume_bps: u64,
+    /// Swap input so far in `volume_slot`, summed as basis points of the input reserve
+    pub volume_slot: u64,
+    pub slot_volume_bps: u64,
+    /// Long-term orders settle against this pool through its `TwammState`, set by `initialize_twamm`
+    pub twamm_enabled: bool,
+    /// Vault balances owed to long-term orders, unsold input plus proceeds; not part of the reserves
+    pub twamm_token_x_balance: u64,
+    pub twamm_token_y_balance: u64,
+    /// Sums of the Q64.64 spot prices of X in Y and Y in X times the seconds each held. They
+    /// wrap on overflow, so a TWAP is the difference of two readings over the time between them
+    pub price_x_cumulative: u128,
+    pub price_y_cumulative: u128,
+    pub price_cumulative_last_update: i64,
+    /// Referral fee cap in basis points of the input, changed by admins through `set_max_referral_fee`
+    pub max_referral_fee_bps: u64,
+    /// Sum of `reward_rate` times the seconds each rate applied, so one LP token or staked token
+    /// has earned `reward_per_token_cumulative / BPS_DENOMINATOR` since the pool opened. Positions
+    /// checkpoint it when they settle, which keeps a rate change from reaching back in time
+    pub reward_per_token_cumulative: u128,
+    pub reward_cumulative_last_update: i64,
+    /// Registry page listing this pool, which `remove_pool` unlists it from
+    pub registry_page: u64,
+}
+
+/// Number of pools ever registered, which picks the page new pools are appended to;
+/// pages are `[POOL_REGISTRY_SEED, page]` for `page` in `0..=pool_count / POOLS_PER_REGISTRY_PAGE`
+#[account]
+#[derive(InitSpace)]
+pub struct PoolRegistry {
+    pub pool_count: u64,
+    pub bump: u8,
+}
+
+/// Live pools in creation order, except that `remove_pool` swap-removes its pool's
+/// entry and moves the page's last entry into its place
+#[account]
+#[derive(InitSpace)]
+pub struct PoolRegistryPage {
+    pub page: u64,
+    #[max_len(100)] // POOLS_PER_REGISTRY_PAGE
+    pub pools: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct UserState {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub amount_staked: u64,
+    pub last_stake_timestamp: i64,
+    pub rewards_earned: u64,
+    pub bump: u8,
+    /// The pool's `reward_per_token_cumulative` when the stake last settled its rewards
+    pub reward_checkpoint: u128,
+}
+
+/// Escrowed swap that fills once the pool pays at least `min_amount_out` for `amount_in`
+#[account]
+#[derive(InitSpace)]
+pub struct LimitOrder {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+    pub expiry: i64, // unix timestamp, 0 for none
+    pub escrow: Pubkey,
+    pub bump: u8,
+}
+
+/// Virtual order pools of a TWAMM-enabled pool. Long-term orders sell into the pool
+/// at a constant rate; their sales are settled lazily, one order interval at a time,
+/// whenever anything touches the pool
+#[account]
+#[derive(InitSpace)]
+pub struct TwammState {
+    pub pool: Pubkey,
+    /// Time up to which the orders' sales have been executed
+    pub last_virtual_order_time: i64,
+    /// Total X and Y sold per second by active orders, scaled by `TWAMM_RATE_PRECISION`
+    pub sell_rate_x: u128,
+    pub sell_rate_y: u128,
+    /// Cumulative proceeds per unit of sell rate, scaled by `TWAMM_EARNINGS_PRECISION`:
+    /// Y earned by X sellers and X earned by Y sellers
+    pub earnings_factor_x: u128,
+    pub earnings_factor_y: u128,
+    /// Ring of order expiries indexed by interval, `TWAMM_MAX_INTERVALS` long
+    #[max_len(42)] // TWAMM_MAX_INTERVALS
+    pub expiries: Vec<TwammExpiry>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, InitSpace)]
+pub struct TwammExpiry {
+    pub expiry: i64,
+    /// Orders expiring here that have not been closed; the slot can't be reused until zero
+    pub open_orders: u32,
+    pub ending_sell_rate_x: u128,
+    pub ending_sell_rate_y: u128,
+    /// Earnings factors when this expiry passed
+    pub earnings_factor_x: u128,
+    pub earnings_factor_y: u128,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct LongTermOrder {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    /// Input sold per second, scaled by `TWAMM_RATE_PRECISION`
+    pub sell_rate: u128,
+    /// Earnings factor at the last withdrawal
+    pub earnings_factor_last: u128,
+    pub start_time: i64,
+    pub expiry: i64,
+    pub bump: u8,
+}
+
+/// Buys `output_mint` with `amount_per_execution` of `input_mint` every `interval`
+/// seconds. Both balances sit in the vault's associated token accounts; sending more
+/// input to `input_vault` extends the schedule
+#[account]
+#[derive(InitSpace)]
+pub struct DcaVault {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub vault_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub input_vault: Pubkey,
+    pub output_vault: Pubkey,
+    pub amount_per_execution: u64,
+    pub interval: i64,
+    /// Worst execution price accepted, in basis points below the TWAP since the last execution
+    pub max_slippage_bps: u64,
+    pub last_execution: i64,
+    /// The pool's price accumulator for `input_mint` at `last_execution`
+    pub price_cumulative_last: u128,
+    pub executions: u64,
+    pub bump: u8,
+}
+
+/// Pools depositors' LP tokens into one LP position owned by the vault, whose rewards
+/// `harvest_lp_vault` reinvests. Depositors own the position pro rata to their shares.
+/// The vault holds an `lp_tokens` entry rather than a staked `UserState`: LP tokens are
+/// balances recorded on the pool, not a mint `stake` could transfer, and LP positions
+/// already earn the pool's rewards
+#[account]
+#[derive(InitSpace)]
+pub struct LpVault {
+    pub pool: Pubkey,
+    /// Pool the rewards are sold through; it pairs `reward_mint` with `bridge_mint`
+    pub reward_pool: Pubkey,
+    pub reward_mint: Pubkey,
+    /// The pool token the rewards are sold for
+    pub bridge_mint: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub performance_fee_bps: u64,
+    /// Worst price either harvest swap accepts, in basis points below its pool's TWAP since the last harvest
+    pub max_slippage_bps: u64,
+    pub total_shares: u64,
+    /// Rewards the vault's position has earned that the next harvest reinvests
+    pub pending_rewards: u64,
+    pub last_harvest: i64,
+    /// The reward pool's price accumulator for `reward_mint` at `last_harvest`
+    pub reward_price_cumulative_last: u128,
+    /// The pool's price accumulator for `bridge_mint` at `last_harvest`
+    pub price_cumulative_last: u128,
+    pub bump: u8,
+}
+
+/// A depositor's shares of an `LpVault`
+#[account]
+#[derive(InitSpace)]
+pub struct VaultPosition {
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub shares: u64,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, InitSpace)]
+pub struct LpTokenBalance {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub last_reward_claim: i64,
+    /// The pool's `reward_per_token_cumulative` when the position last settled its rewards
+    pub reward_checkpoint: u128,
+    /// Rewards settled by a later deposit into the position and not yet claimed
+    pub rewards_earned: u64,
+}
+
+/// Arguments passed to the flash swap receiver after `FLASH_SWAP_CALLBACK_DISCRIMINATOR`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FlashSwapCallback {
+    pub sender: Pubkey,
+    pub input_token: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub data: Vec<u8>,
+}
+
+/// Swap pricing returned by `quote_swap` and `quote_swap_exact_out`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SwapQuote {
+    pub amount_in: u64,
+    /// Input reaching the vault after any Token-2022 transfer fee
+    pub amount_in_received: u64,
+    pub fee_amount: u64,
+    /// Output leaving the vault
+    pub amount_out: u64,
+    /// Output reaching the user after any Token-2022 transfer fee
+    pub amount_out_received: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AddLiquidityQuote {
+    pub amount_x_received: u64,
+    pub amount_y_received: u64,
+    pub lp_tokens: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RemoveLiquidityQuote {
+    pub lp_tokens: u64,
+    pub amount_x_received: u64,
+    pub amount_y_received: u64,
+}
+
+// Every pool event carries the pool's `sequence`, which increases by one per
+// event, so indexers can detect missed logs
+#[event]
+pub struct PoolInitialized {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub fee_rate: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct PoolRemovedEvent {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub timestamp: i64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct AdminUpdated {
+    pub pool: Pubkey,
+    pub admin: Pubkey,
+    pub is_added: bool,
+    pub super_admin: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub sequence: u64,
+}
+
+/// Emitted by `set_swap_fee`, `set_reward_rate`, `set_paused`, `set_swap_guardrails`,
+/// `set_max_referral_fee`, `set_min_initial_liquidity` and `initialize_twamm` with the
+/// pool's full settings
+#[event]
+pub struct PoolConfigUpdated {
+    pub pool: Pubkey,
+    pub admin: Pubkey,
+    pub swap_fee_rate: u64,
+    pub reward_rate: u64,
+    pub paused: bool,
+    pub min_initial_liquidity: u64,
+    pub max_swap_input_bps: u64,
+    pub max_price_impact_bps: u64,
+    pub max_slot_volume_bps: u64,
+    pub twamm_enabled: bool,
+    pub max_referral_fee_bps: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LiquidityProvided {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub token_x_amount: u64,
+    pub token_y_amount: u64,
+    pub lp_tokens_minted: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LiquidityRemoved {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub token_x_amount: u64,
+    pub token_y_amount: u64,
+    pub lp_tokens_burned: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct TokensSwapped {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub fee_amount: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    /// Referrer token account paid `referral_fee` on top of `input_amount`; none when no fee was paid
+    pub referrer: Option<Pubkey>,
+    pub referral_fee: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct TokensStaked {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub amount: u64,
+    pub total_staked: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct TokensUnstaked {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub amount: u64,
+    pub rewards: u64,
+    pub total_staked: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct FlashLoanBorrowed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct FlashLoanRepaid {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub fee_x: u64,
+    pub fee_y: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+/// Emitted by `sync` with the reserves before and after
+#[event]
+pub struct ReservesSynced {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub previous_token_x_reserve: u64,
+    pub previous_token_y_reserve: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+/// Emitted by `skim`; `recipient` owns the token accounts that received the excess
+#[event]
+pub struct ExcessSkimmed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub recipient: Pubkey,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub amount_x: u64,
+    pub amount_y: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LimitOrderPlaced {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order: Pubkey,
+    pub order_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+    pub expiry: i64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LimitOrderFilled {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub keeper: Pubkey,
+    pub order: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub fee_amount: u64,
+    pub keeper_fee: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LimitOrderCancelled {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order: Pubkey,
+    pub amount_in: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct VirtualOrdersExecuted {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LongTermOrderPlaced {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order: Pubkey,
+    pub order_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount_in: u64,
+    pub sell_rate: u128,
+    pub expiry: i64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LongTermOrderWithdrawn {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order: Pubkey,
+    pub output_mint: Pubkey,
+    pub proceeds: u64,
+    /// Input returned by a cancellation
+    pub refunded: u64,
+    pub closed: bool,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct DcaVaultOpened {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub vault: Pubkey,
+    pub vault_id: u64,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub amount: u64,
+    pub amount_per_execution: u64,
+    pub interval: i64,
+    pub max_slippage_bps: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct DcaExecuted {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub keeper: Pubkey,
+    pub vault: Pubkey,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub fee_amount: u64,
+    pub keeper_fee: u64,
+    /// Floor the TWAP and the vault's slippage allowed
+    pub min_amount_out: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct DcaVaultWithdrawn {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub vault: Pubkey,
+    pub input_amount: u64,
+    pub output_amount: u64,
+    pub closed: bool,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LpVaultInitialized {
+    pub pool: Pubkey,
+    pub vault: Pubkey,
+    pub reward_pool: Pubkey,
+    pub reward_mint: Pubkey,
+    pub bridge_mint: Pubkey,
+    pub fee_recipient: Pubkey,
+    pub performance_fee_bps: u64,
+    pub max_slippage_bps: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LpVaultDeposited {
+    pub pool: Pubkey,
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub lp_amount: u64,
+    pub shares: u64,
+    pub vault_lp_tokens: u64,
+    pub total_shares: u64,
+    pub sequence: u64,
+}
+
+#[event]
+pub struct LpVaultWithdrawn {
+    pub pool: Pubkey,
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub lp_amount: u64,
+    pub shares: u64,
+    pub vault_lp_tokens: u64,
+    pub total_shares: u64,
+    pub sequence: u64,
+}
+
+/// The reward pool's sale and the pool's half swap are each emitted as `TokensSwapped` first
+#[event]
+pub struct LpVaultHarvested {
+    pub pool: Pubkey,
+    pub vault: Pubkey,
+    pub keeper: Pubkey,
+    pub rewards: u64,
+    pub performance_fee: u64,
+    pub token_x_amount: u64,
+    pub token_y_amount: u64,
+    pub lp_tokens_minted: u64,
+    pub vault_lp_tokens: u64,
+    pub total_shares: u64,
+    pub token_x_reserve: u64,
+    pub token_y_reserve: u64,
+    pub lp_token_supply: u64,
+    pub sequence: u64,
+}
+
+#[error_code]
+pub enum ErrorCode {
+
+    #[msg("Unauthorized access")]
+    Unauthorized,
+    
+    #[msg("Admin already exists")]
+    AdminAlreadyExists,
+
+    #[msg("Admin doesn't exists")]
+    AdminDoesntExist,
+    
+    #[msg("Maximum admin limit reached")]
+    MaxAdminLimitReached,
+
+    #[msg("Invalid token input")]
+    InvalidToken,
+    
+    #[msg("Invalid Token Pair")]
+    InvalidTokenPair,
+    
+    #[msg("Mathematical operation overflow")]
+    MathOverflow,
+    
+    #[msg("Invalid liquidity amount")]
+    InvalidLiquidityAmount,
+    
+    #[msg("Invalid swap input")]
+    InvalidSwapInput,
+    
+    #[msg("Slippage tolerance exceeded")]
+    ExcessiveSlippage,
+    
+    #[msg("Insufficient funds")]
+    InsufficientFunds,
+    
+    #[msg("Invalid fee rate")]
+    InvalidFeeRate,
+    
+    #[msg("Invalid token ratio")]
+    InvalidTokenRatio,
+
+    #[msg("Invalid stake amount")]
+    InvalidStakeAmount,
+    
+    #[msg("Insufficient stake balance")]
+    InsufficientStake,
+    
+    #[msg("Invalid LP token amount")]
+    InvalidLPTokenAmount,
+    
+    #[msg("No liquidity provided")]
+    NoLiquidity,
+
+    #[msg("No rewards available to claim")]
+    NoRewardsAvailable,
+
+    #[msg("Pool is not empty")]
+    PoolNotEmpty,
+
+    #[msg("Invalid K value after swap")]
+    InvalidK,
+
+    #[msg("Invalid flash loan amount")]
+    InvalidFlashLoanAmount,
+
+    #[msg("Flash loan already in progress")]
+    FlashLoanActive,
+
+    #[msg("No flash loan in progress")]
+    FlashLoanNotActive,
+
+    #[msg("Flash repay instruction not found")]
+    MissingFlashRepay,
+
+    #[msg("Flash swap callback program not provided")]
+    MissingCallbackProgram,
+
+    #[msg("Flash swap input was not paid")]
+    FlashSwapNotRepaid,
+
+    #[msg("Mint has an unsupported Token-2022 extension")]
+    UnsupportedMintExtension,
+
+    #[msg("Token mints must be in canonical order")]
+    InvalidMintOrder,
+
+    #[msg("User token account not provided")]
+    MissingTokenAccount,
+
+    #[msg("Temporary WSOL account not provided")]
+    MissingWsolAccount,
+
+    #[msg("Pool is paused")]
+    PoolPaused,
+
+    #[msg("Pool vault does not match the pool's vault address")]
+    InvalidPoolVault,
+
+    #[msg("Token account is not owned by the signer")]
+    InvalidTokenAccountOwner,
+
+    #[msg("Token account mint does not match the expected mint")]
+    InvalidTokenAccountMint,
+
+    #[msg("Native mint account is not the wrapped SOL mint")]
+    InvalidNativeMint,
+
+    #[msg("Vault balances do not exceed the reserves")]
+    NothingToSkim,
+
+    #[msg("Staking and reward vaults cannot be the pool's reserve vaults")]
+    ReserveVaultNotAllowed,
+
+    #[msg("First deposit mints fewer LP tokens than the pool's minimum")]
+    InitialLiquidityTooLow,
+
+    #[msg("Transaction executed after its deadline")]
+    Expired,
+
+    #[msg("Guardrail limits cannot exceed 10000 basis points")]
+    InvalidGuardrail,
+
+    #[msg("Swap input exceeds the pool's maximum share of reserves")]
+    SwapTooLarge,
+
+    #[msg("Swap price impact exceeds the pool's maximum")]
+    PriceImpactTooHigh,
+
+    #[msg("Swap volume in this slot exceeds the pool's cap")]
+    SlotVolumeExceeded,
+
+    #[msg("Limit order is empty, already expired, or not for this pool, owner or escrow")]
+    InvalidLimitOrder,
+
+    #[msg("Pool price has not reached the order's limit")]
+    LimitPriceNotReached,
+
+    #[msg("Pool has long-term orders enabled; pass its TWAMM state")]
+    MissingTwammState,
+
+    #[msg("Long-term orders must last between 1 and TWAMM_MAX_INTERVALS intervals")]
+    InvalidOrderDuration,
+
+    #[msg("An expired long-term order still holds this expiry; withdraw it first")]
+    TwammExpiryUnavailable,
+
+    #[msg("Long-term order does not belong to this pool or owner")]
+    InvalidLongTermOrder,
+
+    #[msg("DCA vaults need an interval of at least MIN_DCA_INTERVAL and slippage below 10000 basis points")]
+    InvalidDcaSchedule,
+
+    #[msg("DCA vault does not trade this pool's mints or belong to these token accounts")]
+    InvalidDcaVault,
+
+    #[msg("DCA vault's interval has not elapsed since its last execution")]
+    DcaExecutionNotDue,
+
+    #[msg("Execution price is further below the TWAP than the vault allows")]
+    DcaSlippageExceeded,
+
+    #[msg("LP vault needs a reward pool pairing another mint with a pool token and fees within their limits")]
+    InvalidLpVault,
+
+    #[msg("LP vault was harvested less than MIN_HARVEST_INTERVAL ago")]
+    HarvestNotDue,
+
+    #[msg("Harvest swap price is further below the TWAP than the vault allows")]
+    HarvestSlippageExceeded,
+
+    #[msg("Referral fee exceeds the pool's cap")]
+    ReferralFeeTooHigh,
+
+    #[msg("hook_account_count is more than the remaining accounts passed")]
+    InvalidHookAccountCount,
+}
+
+impl LiquidityPool {
+    /// The super admin and every listed admin may change pool settings
+    pub fn is_admin(&self, key: &Pubkey) -> bool {
+        *key == self.super_admin || self.admins.contains(key)
+    }
+
+    /// Advances and returns the pool's event sequence number so indexers can detect gaps
+    pub fn next_event_sequence(&mut self) -> Result<u64> {
+        self.event_sequence = self.event_sequence
+            .checked_add(1)
+            .ok_or(ErrorCode::MathOverflow)?;
+        Ok(self.event_sequence)
+    }
+
+    /// LP tokens minted for a deposit, rounded down
+    pub fn lp_tokens_to_mint(&self, token_x_amount: u64, token_y_amount: u64) -> Result<u64> {
+        Ok(soondex_math::lp_tokens_to_mint(
+            token_x_amount,
+            token_y_amount,
+            self.token_x_reserve,
+            self.token_y_reserve,
+            self.lp_token_supply,
+        ).map_err(ErrorCode::from)?)
+    }
+
+    /// LP tokens credited to the depositor and LP tokens locked forever. The first
+    /// deposit locks `MINIMUM_LIQUIDITY` and must mint at least `min_initial_liquidity`
+    /// in total, so the share price cannot be inflated from a dust position
+    pub fn lp_tokens_for_deposit(&self, token_x_amount: u64, token_y_amount: u64) -> Result<(u64, u64)> {
+        let lp_tokens = self.lp_tokens_to_mint(token_x_amount, token_y_amount)?;
+        if self.lp_token_supply > 0 {
+            return Ok((lp_tokens, 0));
+        }
+        require!(
+            lp_tokens > MINIMUM_LIQUIDITY && lp_tokens >= self.min_initial_liquidity,
+            ErrorCode::InitialLiquidityTooLow
+        );
+        Ok((lp_tokens - MINIMUM_LIQUIDITY, MINIMUM_LIQUIDITY))
+    }
+
+    /// Checks a swap of `amount_in_received` (including `fee_amount`) paying
+    /// `amount_out` against the pool's guardrails and returns the slot's swap
+    /// volume including it
+    pub fn check_swap_guardrails(
+        &self,
+        amount_in_received: u64,
+        fee_amount: u64,
+        amount_out: u64,
+        reserve_in: u64,
+        reserve_out: u64,
+        slot: u64,
+    ) -> Result<u64> {
+        let input_bps = soondex_math::share_of_reserve_bps(amount_in_received, reserve_in)
+            .map_err(ErrorCode::from)?;
+        if self.max_swap_input_bps > 0 {
+            require!(input_bps <= self.max_swap_input_bps, ErrorCode::SwapTooLarge);
+        }
+
+        if self.max_price_impact_bps > 0 {
+            let amount_in_after_fees = amount_in_received
+                .checked_sub(fee_amount)
+                .ok_or(ErrorCode::MathOverflow)?;
+            let impact_bps = soondex_math::price_impact_bps(amount_in_after_fees, amount_out, reserve_in, reserve_out)
+                .map_err(ErrorCode::from)?;
+            require!(impact_bps <= self.max_price_impact_bps, ErrorCode::PriceImpactTooHigh);
+        }
+
+        // The running total restarts with each new slot
+        let previous_bps = if slot == self.volume_slot { self.slot_volume_bps } else { 0 };
+        let slot_volume_bps = previous_bps.checked_add(input_bps).ok_or(ErrorCode::MathOverflow)?;
+        if self.max_slot_volume_bps > 0 {
+            require!(slot_volume_bps <= self.max_slot_volume_bps, ErrorCode::SlotVolumeExceeded);
+        }
+        Ok(slot_volume_bps)
+    }
+
+    /// The price accumulators as they would read at `now`
+    pub fn price_cumulative_at(&self, now: i64) -> Result<(u128, u128)> {
+        let elapsed = now - self.price_cumulative_last_update;
+        if elapsed <= 0 || self.token_x_reserve == 0 || self.token_y_reserve == 0 {
+            return Ok((self.price_x_cumulative, self.price_y_cumulative));
+        }
+
+        let price_x = soondex_math::price_q64(self.token_x_reserve, self.token_y_reserve).map_err(ErrorCode::from)?;
+        let price_y = soondex_math::price_q64(self.token_y_reserve, self.token_x_reserve).map_err(ErrorCode::from)?;
+        Ok((
+            self.price_x_cumulative.wrapping_add(price_x.wrapping_mul(elapsed as u128)),
+            self.price_y_cumulative.wrapping_add(price_y.wrapping_mul(elapsed as u128)),
+        ))
+    }
+
+    /// Accrues the prices held since the last update; call before the reserves change
+    pub fn update_price_cumulative(&mut self, now: i64) -> Result<()> {
+        (self.price_x_cumulative, self.price_y_cumulative) = self.price_cumulative_at(now)?;
+        self.price_cumulative_last_update = self.price_cumulative_last_update.max(now);
+        Ok(())
+    }
+
+    /// The reward accumulator as it would read at `now`
+    pub fn reward_per_token_cumulative_at(&self, now: i64) -> Result<u128> {
+        let growth = soondex_math::reward_per_token_growth(self.reward_rate, now - self.reward_cumulative_last_update)
+            .map_err(ErrorCode::from)?;
+        Ok(self.reward_per_token_cumulative.checked_add(growth).ok_or(ErrorCode::MathOverflow)?)
+    }
+
+    /// Accrues rewards at the current rate since the last update; call before the rate
+    /// changes and before a position settles
+    pub fn update_reward_cumulative(&mut self, now: i64) -> Result<u128> {
+        self.reward_per_token_cumulative = self.reward_per_token_cumulative_at(now)?;
+        self.reward_cumulative_last_update = self.reward_cumulative_last_update.max(now);
+        Ok(self.reward_per_token_cumulative)
+    }
+
+    /// Adds a swap's input to and removes its output from the reserves; k must not decrease
+    pub fn apply_swap(&mut self, is_input_token_x: bool, amount_in_received: u64, amount_out: u64) -> Result<()> {
+        let k_before = self.token_x_reserve
+            .checked_mul(self.token_y_reserve)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        let (reserve_in, reserve_out) = if is_input_token_x {
+            (&mut self.token_x_reserve, &mut self.token_y_reserve)
+        } else {
+            (&mut self.token_y_reserve, &mut self.token_x_reserve)
+        };
+        *reserve_in = reserve_in.checked_add(amount_in_received).ok_or(ErrorCode::MathOverflow)?;
+        *reserve_out = reserve_out.checked_sub(amount_out).ok_or(ErrorCode::MathOverflow)?;
+
+        let k_after = self.token_x_reserve
+            .checked_mul(self.token_y_reserve)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(k_after >= k_before, ErrorCode::InvalidK);
+        Ok(())
+    }
+
+    /// LP tokens burned for a withdrawal, rounded up
+    pub fn lp_tokens_to_burn(&self, token_x_amount: u64, token_y_amount: u64) -> Result<u64> {
+        Ok(soondex_math::lp_tokens_to_burn(
+            token_x_amount,
+            token_y_amount,
+            self.token_x_reserve,
+            self.token_y_reserve,
+            self.lp_token_supply,
+        ).map_err(ErrorCode::from)?)
+    }
+}
+
+impl LpVault {
+    /// Moves the rewards the vault's LP position has earned into `pending_rewards` and
+    /// returns the position's index, if the vault holds one
+    pub fn accrue_rewards(
+        &mut self,
+        vault: Pubkey,
+        liquidity_pool: &mut LiquidityPool,
+        now: i64,
+    ) -> Result<Option<usize>> {
+        let reward_per_token_cumulative = liquidity_pool.update_reward_cumulative(now)?;
+        let Some(index) = liquidity_pool.lp_tokens.iter().position(|x| x.owner == vault) else {
+            return Ok(None);
+        };
+        let position = &mut liquidity_pool.lp_tokens[index];
+        let rewards = calculate_rewards(position.amount, reward_per_token_cumulative, position.reward_checkpoint)?;
+        self.pending_rewards = self.pending_rewards
+            .checked_add(rewards)
+            .ok_or(ErrorCode::MathOverflow)?;
+        position.last_reward_claim = now;
+        position.reward_checkpoint = reward_per_token_cumulative;
+        Ok(Some(index))
+    }
+}
+
+impl TwammState {
+    fn expiry_index(expiry: i64) -> usize {
+        (expiry / TWAMM_ORDER_INTERVAL) as usize % TWAMM_MAX_INTERVALS
+    }
+
+    pub fn earnings_factor(&self, sells_x: bool) -> u128 {
+        if sells_x { self.earnings_factor_x } else { self.earnings_factor_y }
+    }
+
+    /// Executes the orders' sales up to `now`, stopping at every interval boundary so
+    /// expiring orders stop selling on time
+    pub fn execute_virtual_orders(&mut self, liquidity_pool: &mut LiquidityPool, now: i64) -> Result<()> {
+        while self.last_virtual_order_time < now {
+            // With nothing selling, no order can expire in between
+            if self.sell_rate_x == 0 && self.sell_rate_y == 0 {
+                self.last_virtual_order_time = now;
+                break;
+            }
+
+            let next_expiry = (self.last_virtual_order_time / TWAMM_ORDER_INTERVAL + 1) * TWAMM_ORDER_INTERVAL;
+            let until = next_expiry.min(now);
+            // The period's prices held until its orders traded at its end
+            liquidity_pool.update_price_cumulative(until)?;
+            self.execute_period(liquidity_pool, until - self.last_virtual_order_time)?;
+            self.last_virtual_order_time = until;
+
+            if until == next_expiry {
+                let (earnings_factor_x, earnings_factor_y) = (self.earnings_factor_x, self.earnings_factor_y);
+                let expiry = &mut self.expiries[Self::expiry_index(next_expiry)];
+                if expiry.expiry == next_expiry {
+                    expiry.earnings_factor_x = earnings_factor_x;
+                    expiry.earnings_factor_y = earnings_factor_y;
+                    let (ending_x, ending_y) = (expiry.ending_sell_rate_x, expiry.ending_sell_rate_y);
+                    self.sell_rate_x = self.sell_rate_x.checked_sub(ending_x).ok_or(ErrorCode::MathOverflow)?;
+                    self.sell_rate_y = self.sell_rate_y.checked_sub(ending_y).ok_or(ErrorCode::MathOverflow)?;
+                }
+            }
+        }
+        liquidity_pool.update_price_cumulative(now)
+    }
+
+    fn execute_period(&mut self, liquidity_pool: &mut LiquidityPool, seconds: i64) -> Result<()> {
+        let sold = |sell_rate: u128| -> Result<u64> {
+            let amount = sell_rate
+                .checked_mul(seconds as u128)
+                .ok_or(ErrorCode::MathOverflow)?
+                / TWAMM_RATE_PRECISION;
+            Ok(u64::try_from(amount).map_err(|_| ErrorCode::MathOverflow)?)
+        };
+        let (sold_x, sold_y) = (sold(self.sell_rate_x)?, sold(self.sell_rate_y)?);
+        if sold_x == 0 && sold_y == 0 {
+            return Ok(());
+        }
+
+        let settlement = soondex_math::twamm_settle(
+            sold_x,
+            sold_y,
+            liquidity_pool.token_x_reserve,
+            liquidity_pool.token_y_reserve,
+            liquidity_pool.swap_fee_rate,
+        ).map_err(ErrorCode::from)?;
+        liquidity_pool.token_x_reserve = settlement.reserve_x;
+        liquidity_pool.token_y_reserve = settlement.reserve_y;
+        // Sold input leaves the orders' balance and proceeds join it
+        liquidity_pool.twamm_token_x_balance = liquidity_pool.twamm_token_x_balance
+            .checked_add(settlement.proceeds_x)
+            .and_then(|balance| balance.checked_sub(sold_x))
+            .ok_or(ErrorCode::MathOverflow)?;
+        liquidity_pool.twamm_token_y_balance = liquidity_pool.twamm_token_y_balance
+            .checked_add(settlement.proceeds_y)
+            .and_then(|balance| balance.checked_sub(sold_y))
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        if self.sell_rate_x > 0 {
+            self.earnings_factor_x = self.earnings_factor_x
+                .checked_add(earnings_per_rate(settlement.proceeds_y, self.sell_rate_x)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        if self.sell_rate_y > 0 {
+            self.earnings_factor_y = self.earnings_factor_y
+                .checked_add(earnings_per_rate(settlement.proceeds_x, self.sell_rate_y)?)
+                .ok_or(ErrorCode::MathOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Starts selling `sell_rate` until `expiry`, reserving the expiry's slot in the ring
+    pub fn add_sell_rate(&mut self, sells_x: bool, sell_rate: u128, expiry: i64) -> Result<()> {
+        let slot = &mut self.expiries[Self::expiry_index(expiry)];
+        if slot.expiry != expiry {
+            // The slot still belongs to an expired order nobody has withdrawn
+            require!(slot.open_orders == 0, ErrorCode::TwammExpiryUnavailable);
+            *slot = TwammExpiry { expiry, ..TwammExpiry::default() };
+        }
+        slot.open_orders = slot.open_orders.checked_add(1).ok_or(ErrorCode::MathOverflow)?;
+
+        let (ending_rate, total_rate) = if sells_x {
+            (&mut slot.ending_sell_rate_x, &mut self.sell_rate_x)
+        } else {
+            (&mut slot.ending_sell_rate_y, &mut self.sell_rate_y)
+        };
+        *ending_rate = ending_rate.checked_add(sell_rate).ok_or(ErrorCode::MathOverflow)?;
+        *total_rate = total_rate.checked_add(sell_rate).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Output `order` has earned since its last withdrawal and the earnings factor to
+    /// record; an expired order earns up to its expiry
+    pub fn order_proceeds(&self, order: &LongTermOrder, sells_x: bool, now: i64) -> Result<(u64, u128)> {
+        let earnings_factor = if now >= order.expiry {
+            let expiry = &self.expiries[Self::expiry_index(order.expiry)];
+            require!(expiry.expiry == order.expiry, ErrorCode::InvalidLongTermOrder);
+            if sells_x { expiry.earnings_factor_x } else { expiry.earnings_factor_y }
+        } else {
+            self.earnings_factor(sells_x)
+        };
 
-#[derive(Accounts)]
-pub struct ManageAdmin<'info> {
-    #[account(
-        mut,
-        seeds = [
-            POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-        ],
-        bump,
-    )]
-    pub liquidity_pool: Account<'info, LiquidityPool>,
-    pub authority: Signer<'info>,
-    pub token_x_mint: InterfaceAccount<'info, Mint>,
-    pub token_y_mint: InterfaceAccount<'info, Mint>,
+        let proceeds = order.sell_rate
+            .checked_mul(earnings_factor.checked_sub(order.earnings_factor_last).ok_or(ErrorCode::MathOverflow)?)
+            .ok_or(ErrorCode::MathOverflow)?
+            / TWAMM_EARNINGS_PRECISION;
+        Ok((u64::try_from(proceeds).map_err(|_| ErrorCode::MathOverflow)?, earnings_factor))
+    }
+
+    /// Stops `order` selling before its expiry and returns its unsold input
+    pub fn remove_sell_rate(&mut self, order: &LongTermOrder, sells_x: bool, now: i64) -> Result<u64> {
+        let slot = &mut self.expiries[Self::expiry_index(order.expiry)];
+        let (ending_rate, total_rate) = if sells_x {
+            (&mut slot.ending_sell_rate_x, &mut self.sell_rate_x)
+        } else {
+            (&mut slot.ending_sell_rate_y, &mut self.sell_rate_y)
+        };
+        *ending_rate = ending_rate.checked_sub(order.sell_rate).ok_or(ErrorCode::MathOverflow)?;
+        *total_rate = total_rate.checked_sub(order.sell_rate).ok_or(ErrorCode::MathOverflow)?;
+
+        let unsold = order.sell_rate
+            .checked_mul((order.expiry - now) as u128)
+            .ok_or(ErrorCode::MathOverflow)?
+            / TWAMM_RATE_PRECISION;
+        Ok(u64::try_from(unsold).map_err(|_| ErrorCode::MathOverflow)?)
+    }
+
+    /// Releases a closed order's hold on its expiry slot
+    pub fn close_order(&mut self, order: &LongTermOrder) -> Result<()> {
+        let slot = &mut self.expiries[Self::expiry_index(order.expiry)];
+        slot.open_orders = slot.open_orders.checked_sub(1).ok_or(ErrorCode::MathOverflow)?;
+        Ok(())
+    }
 }
 
-#[derive(Accounts)]
-#[instruction(token_x_mint: Pubkey, token_y_mint: Pubkey, amount_x: u64, amount_y: u64)]
-pub struct ProvideLiquidity<'info> {
-    #[account(mut)]
-    pub token_x_mint: InterfaceAccount<'info, Mint>,
-    #[account(mut)]
-    pub token_y_mint: InterfaceAccount<'info, Mint>,
-    #[account(mut,
-        seeds = [
-            POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-        ],
-        bump,
-    )]
-    pub liquidity_pool: Account<'info, LiquidityPool>,
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut)]
-    pub user_token_x_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_token_y_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
-    pub token_program: Interface<'info, TokenInterface>,
+fn earnings_per_rate(proceeds: u64, sell_rate: u128) -> Result<u128> {
+    Ok((proceeds as u128)
+        .checked_mul(TWAMM_EARNINGS_PRECISION)
+        .ok_or(ErrorCode::MathOverflow)?
+        / sell_rate)
 }
 
-#[derive(Accounts)]
-#[instruction(token_x_mint: Pubkey, token_y_mint: Pubkey, amount_x: u64, amount_y: u64)]
-pub struct RemoveLiquidity<'info> {
-    #[account(
-        mut,
-        seeds = [
-            POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-        ],
-        bump = liquidity_pool.bump
-    )]
-    pub liquidity_pool: Account<'info, LiquidityPool>,
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut)]
-    pub user_token_x_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)] 
-    pub user_token_y_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_x_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_y_account: InterfaceAccount<'info, TokenAccount>,
-    pub token_program: Interface<'info, TokenInterface>,
-    pub token_x_mint: InterfaceAccount<'info, Mint>,
-    pub token_y_mint: InterfaceAccount<'info, Mint>,
+/// Brings the pool up to now before anything trades against it: runs its long-term
+/// orders and accrues its price accumulators. Pools with TWAMM enabled must pass
+/// their `TwammState`
+fn update_pool(liquidity_pool: &mut LiquidityPool, twamm: Option<&mut TwammState>) -> Result<()> {
+    let now = Clock::get()?.unix_timestamp;
+    match twamm {
+        Some(twamm) => twamm.execute_virtual_orders(liquidity_pool, now),
+        None => {
+            require!(!liquidity_pool.twamm_enabled, ErrorCode::MissingTwammState);
+            liquidity_pool.update_price_cumulative(now)
+        }
+    }
 }
 
+/// Pays out a long-term order's proceeds and, when `cancel` is set or the order has
+/// expired, refunds any unsold input and closes it
+fn settle_long_term_order<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ManageLongTermOrder<'info>>,
+    now: i64,
+    cancel: bool,
+) -> Result<()> {
+    // CHECKS
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    let twamm = &mut ctx.accounts.twamm;
+    let order = &mut ctx.accounts.long_term_order;
+    require!(!liquidity_pool.flash_loan_active, ErrorCode::FlashLoanActive);
+    twamm.execute_virtual_orders(liquidity_pool, now)?;
 
-#[derive(Accounts)]
-#[instruction(input_token: Pubkey, output_token: Pubkey, amount_in: u64, minimum_amount_out: u64)]
-pub struct SwapTokens<'info> {
-    #[account(
-        mut,
-        seeds = [
-            POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-        ],
-        bump = liquidity_pool.bump
-    )]
-    pub liquidity_pool: Account<'info, LiquidityPool>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
-    
-    #[account(mut)]
-    pub user_token_in: InterfaceAccount<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub user_token_out: InterfaceAccount<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub pool_token_x: InterfaceAccount<'info, TokenAccount>,
-    
-    #[account(mut)]
-    pub pool_token_y: InterfaceAccount<'info, TokenAccount>,
-    
-    pub token_program: Interface<'info, TokenInterface>,
-    
-    pub token_x_mint: InterfaceAccount<'info, Mint>,
-    
-    pub token_y_mint: InterfaceAccount<'info, Mint>,
-    
-    #[account(mut)]
-    pub wsol_account: Option<InterfaceAccount<'info, TokenAccount>>,
-    
-    pub system_program: Program<'info, System>,
-    
-    /// CHECK: Native mint is a static known address
-    pub native_mint: UncheckedAccount<'info>,
-}
+    let sells_x = order.input_mint == liquidity_pool.token_x_mint;
+    let (proceeds, earnings_factor) = twamm.order_proceeds(order, sells_x, now)?;
+    let expired = now >= order.expiry;
+    let close = cancel || expired;
 
-#[derive(Accounts)]
-#[instruction(amount: u64)]
-pub struct Stake<'info> {
-    #[account(
-        mut,
-        seeds = [
-            POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-        ],
-        bump = liquidity_pool.bump
-    )]
-    pub liquidity_pool: Account<'info, LiquidityPool>,
-    
-    #[account(
-        init_if_needed,
-        payer = user,
-        space = 8 + UserState::INIT_SPACE,
-        seeds = [
-            b"user_state",
-            liquidity_pool.key().as_ref(),
-            user.key().as_ref()
-        ],
-        bump
-    )]
-    pub user_state: Account<'info, UserState>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut)]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_account: InterfaceAccount<'info, TokenAccount>,
-    pub token_mint: InterfaceAccount<'info, Mint>,
-    pub token_x_mint: InterfaceAccount<'info, Mint>,
-    pub token_y_mint: InterfaceAccount<'info, Mint>,
-    pub token_program: Interface<'info, TokenInterface>,
-    pub system_program: Program<'info, System>,
-}
+    // EFFECTS
+    let refunded = if cancel && !expired { twamm.remove_sell_rate(order, sells_x, now)? } else { 0 };
+    if close {
+        twamm.close_order(order)?;
+    }
+    order.earnings_factor_last = earnings_factor;
+    let (refunded_x, refunded_y, proceeds_x, proceeds_y) = if sells_x {
+        (refunded, 0, 0, proceeds)
+    } else {
+        (0, refunded, proceeds, 0)
+    };
+    liquidity_pool.twamm_token_x_balance = liquidity_pool.twamm_token_x_balance
+        .checked_sub(refunded_x + proceeds_x)
+        .ok_or(ErrorCode::MathOverflow)?;
+    liquidity_pool.twamm_token_y_balance = liquidity_pool.twamm_token_y_balance
+        .checked_sub(refunded_y + proceeds_y)
+        .ok_or(ErrorCode::MathOverflow)?;
+    let sequence = liquidity_pool.next_event_sequence()?;
 
+    // INTERACTIONS
+    let token_x_key = ctx.accounts.token_x_mint.key();
+    let token_y_key = ctx.accounts.token_y_mint.key();
+    let fee_rate_bytes = liquidity_pool.fee_rate.to_le_bytes();
+    let pool_seeds: &[&[u8]] = &[
+        POOL_SEED,
+        token_x_key.as_ref(),
+        token_y_key.as_ref(),
+        fee_rate_bytes.as_ref(),
+        &[liquidity_pool.bump],
+    ];
+    let (input_side, output_side) = if sells_x {
+        (
+            (&ctx.accounts.pool_token_x, &ctx.accounts.token_x_mint, &ctx.accounts.token_x_program),
+            (&ctx.accounts.pool_token_y, &ctx.accounts.token_y_mint, &ctx.accounts.token_y_program),
+        )
+    } else {
+        (
+            (&ctx.accounts.pool_token_y, &ctx.accounts.token_y_mint, &ctx.accounts.token_y_program),
+            (&ctx.accounts.pool_token_x, &ctx.accounts.token_x_mint, &ctx.accounts.token_x_program),
+        )
+    };
 
-#[derive(Accounts)]
-#[instruction(amount: u64)]
-pub struct Unstake<'info> {
-    #[account(
-        mut,
-        seeds = [
-            POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-        ],
-        bump = liquidity_pool.bump
-    )]
-    pub liquidity_pool: Account<'info, LiquidityPool>,
-    
-    #[account(
-        init_if_needed,
-        payer = user,
-        space = 8 + UserState::INIT_SPACE,
-        seeds = [
-            b"user_state",
-            liquidity_pool.key().as_ref(),
-            user.key().as_ref()
-        ],
-        bump
-    )]
-    pub user_state: Account<'info, UserState>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut)]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_token_account: InterfaceAccount<'info, TokenAccount>,
-    pub token_mint: InterfaceAccount<'info, Mint>,
-    pub token_x_mint: InterfaceAccount<'info, Mint>,
-    pub token_y_mint: InterfaceAccount<'info, Mint>,
-    pub token_program: Interface<'info, TokenInterface>,
-    pub system_program: Program<'info, System>,
-}
+    if proceeds > 0 {
+        let (pool_token_out, output_mint, output_token_program) = output_side;
+        transfer_tokens(
+            output_token_program,
+            pool_token_out.to_account_info(),
+            ctx.accounts.owner_token_out.to_account_info(),
+            liquidity_pool.to_account_info(),
+            output_mint,
+            ctx.remaining_accounts,
+            proceeds,
+            &[pool_seeds],
+        )?;
+    }
+    if refunded > 0 {
+        let (pool_token_in, input_mint, input_token_program) = input_side;
+        transfer_tokens(
+            input_token_program,
+            pool_token_in.to_account_info(),
+            ctx.accounts.owner_token_in.to_account_info(),
+            liquidity_pool.to_account_info(),
+            input_mint,
+            ctx.remaining_accounts,
+            refunded,
+            &[pool_seeds],
+        )?;
+    }
+    if close {
+        order.close(ctx.accounts.owner.to_account_info())?;
+    }
 
+    emit!(LongTermOrderWithdrawn {
+        pool: liquidity_pool.key(),
+        owner: order.owner,
+        order: order.key(),
+        output_mint: order.output_mint,
+        proceeds,
+        refunded,
+        closed: close,
+        token_x_reserve: liquidity_pool.token_x_reserve,
+        token_y_reserve: liquidity_pool.token_y_reserve,
+        lp_token_supply: liquidity_pool.lp_token_supply,
+        sequence,
+    });
 
-#[derive(Accounts)]
-pub struct ClaimRewards<'info> {
-    #[account(
-        mut,
-        seeds = [
-            POOL_SEED,
-            token_x_mint.key().as_ref(),
-            token_y_mint.key().as_ref()
-        ],
-        bump = liquidity_pool.bump
-    )]
-    pub liquidity_pool: Account<'info, LiquidityPool>,
-    
-    #[account(
-        mut,
-        seeds = [
-            b"user_state",
-            liquidity_pool.key().as_ref(),
-            user.key().as_ref()
-        ],
-        bump
-    )]
-    pub user_state: Account<'info, UserState>,
-    
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut)]
-    pub user_reward_account: InterfaceAccount<'info, TokenAccount>,
-    #[account(mut)]
-    pub pool_reward_account: InterfaceAccount<'info, TokenAccount>,
-    pub reward_mint: InterfaceAccount<'info, Mint>,
-    pub token_x_mint: InterfaceAccount<'info, Mint>,
-    pub token_y_mint: InterfaceAccount<'info, Mint>,
-    pub token_program: Interface<'info, TokenInterface>,
-    pub system_program: Program<'info, System>,
+    Ok(())
 }
 
+/// Pays a DCA vault's output and `input_amount` of its input to the owner. `None`
+/// empties the vault and closes it along with its token accounts
+fn withdraw_from_dca_vault<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ManageDcaVault<'info>>,
+    input_amount: Option<u64>,
+) -> Result<()> {
+    // CHECKS
+    let dca_vault = &ctx.accounts.dca_vault;
+    let close = input_amount.is_none();
+    let input_amount = input_amount.unwrap_or(ctx.accounts.input_vault.amount);
+    require!(input_amount <= ctx.accounts.input_vault.amount, ErrorCode::InsufficientFunds);
+    let output_amount = ctx.accounts.output_vault.amount;
+
+    // EFFECTS
+    let liquidity_pool = &mut ctx.accounts.liquidity_pool;
+    let sequence = liquidity_pool.next_event_sequence()?;
+
+    // INTERACTIONS
+    let pool_key = liquidity_pool.key();
+    let owner_key = dca_vault.owner;
+    let vault_id_bytes = dca_vault.vault_id.to_le_bytes();
+    let vault_seeds: &[&[u8]] = &[
+        DCA_VAULT_SEED,
+        pool_key.as_ref(),
+        owner_key.as_ref(),
+        vault_id_bytes.as_ref(),
+        &[dca_vault.bump],
+    ];
+
+    if input_amount > 0 {
+        transfer_tokens(
+            &ctx.accounts.input_token_program,
+            ctx.accounts.input_vault.to_account_info(),
+            ctx.accounts.owner_token_in.to_account_info(),
+            dca_vault.to_account_info(),
+            &ctx.accounts.input_mint,
+            ctx.remaining_accounts,
+            input_amount,
+            &[vault_seeds],
+        )?;
+    }
+    if output_amount > 0 {
+        transfer_tokens(
+            &ctx.accounts.output_token_program,
+            ctx.accounts.output_vault.to_account_info(),
+            ctx.accounts.owner_token_out.to_account_info(),
+            dca_vault.to_account_info(),
+            &ctx.accounts.output_mint,
+            ctx.remaining_accounts,
+            output_amount,
+            &[vault_seeds],
+        )?;
+    }
+    if close {
+        for (account, mint, token_program) in [
+            (&ctx.accounts.input_vault, &ctx.accounts.input_mint, &ctx.accounts.input_token_program),
+            (&ctx.accounts.output_vault, &ctx.accounts.output_mint, &ctx.accounts.output_token_program),
+        ] {
+            close_token_account(
+                token_program,
+                account.to_account_info(),
+                mint,
+                ctx.accounts.owner.to_account_info(),
+                dca_vault.to_account_info(),
+                &[vault_seeds],
+            )?;
+        }
+        dca_vault.close(ctx.accounts.owner.to_account_info())?;
+    }
 
-#[account]
-#[derive(InitSpace)]
-pub struct LiquidityPool {
-    pub authority: Pubkey,
-    pub super_admin: Pubkey,
-    #[max_len(3)]
-    pub admins: Vec<Pubkey>,
-    pub token_x_reserve: u64,
-    pub token_y_reserve: u64,
-    pub lp_token_supply: u64,
-    pub token_x_mint: Pubkey,
-    pub token_y_mint: Pubkey,
-    #[max_len(100)]
-    pub lp_tokens: Vec<LpTokenBalance>,
-    pub fee_rate: u64,
-    pub bump: u8,
-    pub reward_rate: u64,
-    pub total_staked: u64,
+    emit!(DcaVaultWithdrawn {
+        pool: pool_key,
+        owner: owner_key,
+        vault: dca_vault.key(),
+        input_amount,
+        output_amount,
+        closed: close,
+        sequence,
+    });
+
+    Ok(())
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct UserState {
-    pub pool: Pubkey,
-    pub owner: Pubkey,
-    pub amount_staked: u64,
-    pub last_stake_timestamp: i64,
-    pub rewards_earned: u64,
-    pub bump: u8,
+/// Rejects trades a validator held past the user's `deadline`, a unix timestamp
+fn check_deadline(deadline: Option<i64>) -> Result<()> {
+    if let Some(deadline) = deadline {
+        require!(Clock::get()?.unix_timestamp <= deadline, ErrorCode::Expired);
+    }
+    Ok(())
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, InitSpace)]
-pub struct LpTokenBalance {
-    pub owner: Pubkey,
-    pub amount: u64,
-    pub last_reward_claim: i64,
+/// Owner of the LP position locked by a pool's first deposit
+pub fn locked_liquidity_address(pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[LOCKED_LIQUIDITY_SEED, pool.as_ref()], &crate::ID).0
 }
 
+fn emit_pool_config_updated(liquidity_pool: &mut Account<LiquidityPool>, admin: Pubkey) -> Result<()> {
+    let sequence = liquidity_pool.next_event_sequence()?;
+    emit!(PoolConfigUpdated {
+        pool: liquidity_pool.key(),
+        admin,
+        swap_fee_rate: liquidity_pool.swap_fee_rate,
+        reward_rate: liquidity_pool.reward_rate,
+        paused: liquidity_pool.paused,
+        min_initial_liquidity: liquidity_pool.min_initial_liquidity,
+        max_swap_input_bps: liquidity_pool.max_swap_input_bps,
+        max_price_impact_bps: liquidity_pool.max_price_impact_bps,
+        max_slot_volume_bps: liquidity_pool.max_slot_volume_bps,
+        twamm_enabled: liquidity_pool.twamm_enabled,
+        max_referral_fee_bps: liquidity_pool.max_referral_fee_bps,
+        sequence,
+    });
+    Ok(())
+}
 
-#[event]
-pub struct PoolInitialized {
-    pub authority: Pubkey,
-    pub fee_rate: u64,
+fn calculate_swap_fee(amount_in: u64, fee_rate: u64) -> Result<u64> {
+    Ok(soondex_math::swap_fee(amount_in, fee_rate).map_err(ErrorCode::from)?)
 }
 
-#[event]
-pub struct PoolRemovedEvent {
-    pool: Pubkey,
-    authority: Pubkey,
-    timestamp: i64,
+fn calculate_swap_input(amount_out: u64, reserve_in: u64, reserve_out: u64, fee_rate: u64) -> Result<u64> {
+    Ok(soondex_math::swap_input(amount_out, reserve_in, reserve_out, fee_rate)
+        .map_err(ErrorCode::from)?)
 }
 
-#[event]
-pub struct AdminUpdated {
-    pub admin: Pubkey,
-    pub is_added: bool,
-    pub super_admin: Pubkey,
+fn calculate_swap_output(amount_in: u64, reserve_in: u64, reserve_out: u64) -> Result<u64> {
+    Ok(soondex_math::swap_output(amount_in, reserve_in, reserve_out).map_err(ErrorCode::from)?)
 }
 
-#[event]
-pub struct LiquidityProvided {
-    pub user: Pubkey,
-    pub token_x_amount: u64,
-    pub token_y_amount: u64,
-    pub lp_tokens_minted: u64,
+/// Least output accepted for selling `amount` at the TWAP between two readings of the
+/// input's price accumulator taken `elapsed` seconds apart, less `max_slippage_bps`
+fn twap_min_amount_out(
+    price_cumulative: u128,
+    price_cumulative_last: u128,
+    elapsed: i64,
+    amount: u64,
+    max_slippage_bps: u64,
+) -> Result<u64> {
+    let twap = price_cumulative.wrapping_sub(price_cumulative_last) / elapsed as u128;
+    let twap_amount_out = soondex_math::amount_at_price_q64(amount, twap).map_err(ErrorCode::from)?;
+    Ok(twap_amount_out
+        .checked_sub(calculate_swap_fee(twap_amount_out, max_slippage_bps)?)
+        .ok_or(ErrorCode::MathOverflow)?)
 }
 
-#[event]
-pub struct LiquidityRemoved {
-    pub user: Pubkey,
-    pub token_x_amount: u64,
-    pub token_y_amount: u64,
-    pub lp_tokens_burned: u64,
+/// Prices a swap exactly as `swap_tokens` executes it
+fn price_swap_exact_in(
+    liquidity_pool: &LiquidityPool,
+    input_mint: &InterfaceAccount<Mint>,
+    output_mint: &InterfaceAccount<Mint>,
+    amount_in: u64,
+) -> Result<SwapQuote> {
+    let (reserve_in, reserve_out) = if input_mint.key() == liquidity_pool.token_x_mint {
+        (liquidity_pool.token_x_reserve, liquidity_pool.token_y_reserve)
+    } else {
+        (liquidity_pool.token_y_reserve, liquidity_pool.token_x_reserve)
+    };
+
+    // Transfer-fee mints deliver less than the nominal amount to the vault
+    let amount_in_received = amount_in
+        .checked_sub(calculate_transfer_fee(input_mint, amount_in)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    let fee_amount = calculate_swap_fee(amount_in_received, liquidity_pool.swap_fee_rate)?;
+    let amount_in_after_fees = amount_in_received
+        .checked_sub(fee_amount)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    let amount_out = calculate_swap_output(amount_in_after_fees, reserve_in, reserve_out)?;
+    let amount_out_received = amount_out
+        .checked_sub(calculate_transfer_fee(output_mint, amount_out)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    Ok(SwapQuote {
+        amount_in,
+        amount_in_received,
+        fee_amount,
+        amount_out,
+        amount_out_received,
+    })
 }
 
-#[event]
-pub struct TokensSwapped {
-    pub input_token: String,
-    pub input_amount: u64,
-    pub output_amount: u64,
+/// Inverts `price_swap_exact_in`, rounding the required input up
+fn price_swap_exact_out(
+    liquidity_pool: &LiquidityPool,
+    input_mint: &InterfaceAccount<Mint>,
+    output_mint: &InterfaceAccount<Mint>,
+    amount_out_received: u64,
+) -> Result<SwapQuote> {
+    require!(amount_out_received > 0, ErrorCode::InvalidSwapInput);
+    let (reserve_in, reserve_out) = if input_mint.key() == liquidity_pool.token_x_mint {
+        (liquidity_pool.token_x_reserve, liquidity_pool.token_y_reserve)
+    } else {
+        (liquidity_pool.token_y_reserve, liquidity_pool.token_x_reserve)
+    };
+
+    let amount_out = amount_out_received
+        .checked_add(calculate_inverse_transfer_fee(output_mint, amount_out_received)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    let amount_in_received = calculate_swap_input(amount_out, reserve_in, reserve_out, liquidity_pool.swap_fee_rate)?;
+
+    let amount_in = amount_in_received
+        .checked_add(calculate_inverse_transfer_fee(input_mint, amount_in_received)?)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    price_swap_exact_in(liquidity_pool, input_mint, output_mint, amount_in)
 }
 
-#[event]
-pub struct TokensStaked {
-    pub user: Pubkey,
-    pub amount: u64,
-    pub timestamp: i64,
+/// Token-2022 transfer fee withheld from `amount`; zero for legacy mints
+fn calculate_transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != token_2022::ID {
+        return Ok(0);
+    }
+
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(transfer_fee_config) => Ok(transfer_fee_config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(ErrorCode::MathOverflow)?),
+        Err(_) => Ok(0),
+    }
 }
 
-#[event]
-pub struct TokensUnstaked {
-    pub user: Pubkey,
-    pub amount: u64,
-    pub rewards: u64,
-    pub timestamp: i64,
+/// Transfer fee to add to `post_fee_amount` so the recipient receives it in full
+fn calculate_inverse_transfer_fee(mint: &InterfaceAccount<Mint>, post_fee_amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != token_2022::ID {
+        return Ok(0);
+    }
+
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    match mint_state.get_extension::<TransferFeeConfig>() {
+        Ok(transfer_fee_config) => Ok(transfer_fee_config
+            .calculate_inverse_epoch_fee(Clock::get()?.epoch, post_fee_amount)
+            .ok_or(ErrorCode::MathOverflow)?),
+        Err(_) => Ok(0),
+    }
 }
 
-#[event]
-pub struct RewardsClaimed {
-    pub user: Pubkey,
-    pub amount: u64,
-    pub timestamp: i64,
+/// `amount` plus `fee`, grossed up by the transfer fee so the recipient receives both in full
+fn gross_up_transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64, fee: u64) -> Result<u64> {
+    let post_fee_amount = amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    post_fee_amount
+        .checked_add(calculate_inverse_transfer_fee(mint, post_fee_amount)?)
+        .ok_or(error!(ErrorCode::MathOverflow))
 }
 
-#[error_code]
-pub enum ErrorCode {
+fn validate_mint_extensions(mint_info: &AccountInfo) -> Result<()> {
+    if *mint_info.owner != token_2022::ID {
+        return Ok(());
+    }
 
-    #[msg("Unauthorized access")]
-    Unauthorized,
-    
-    #[msg("Admin already exists")]
-    AdminAlreadyExists,
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    for extension in mint_state.get_extension_types()? {
+        require!(
+            ALLOWED_MINT_EXTENSIONS.contains(&extension),
+            ErrorCode::UnsupportedMintExtension
+        );
+    }
 
-    #[msg("Admin doesn't exists")]
-    AdminDoesntExist,
-    
-    #[msg("Maximum admin limit reached")]
-    MaxAdminLimitReached,
+    Ok(())
+}
 
-    #[msg("Invalid token input")]
-    InvalidToken,
-    
-    #[msg("Invalid Token Pair")]
-    InvalidTokenPair,
-    
-    #[msg("Mathematical operation overflow")]
-    MathOverflow,
-    
-    #[msg("Invalid liquidity amount")]
-    InvalidLiquidityAmount,
-    
-    #[msg("Invalid swap input")]
-    InvalidSwapInput,
-    
-    #[msg("Slippage tolerance exceeded")]
-    ExcessiveSlippage,
-    
-    #[msg("Insufficient funds")]
-    InsufficientFunds,
-    
-    #[msg("Invalid fee rate")]
-    InvalidFeeRate,
-    
-    #[msg("Invalid token ratio")]
-    InvalidTokenRatio,
+/// Closes a token account to `destination`. Token-2022 refuses to close accounts holding
+/// withheld transfer fees, so those are first harvested to the mint, which anyone may do
+fn close_token_account<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    account: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    destination: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    if withheld_transfer_fees(&account)? > 0 {
+        let harvest_ix = transfer_fee::instruction::harvest_withheld_tokens_to_mint(
+            &token_program.key(),
+            &mint.key(),
+            &[&account.key()],
+        )?;
+        invoke(
+            &harvest_ix,
+            &[mint.to_account_info(), account.clone(), token_program.to_account_info()],
+        )?;
+    }
 
-    #[msg("Invalid stake amount")]
-    InvalidStakeAmount,
-    
-    #[msg("Insufficient stake balance")]
-    InsufficientStake,
-    
-    #[msg("Invalid LP token amount")]
-    InvalidLPTokenAmount,
-    
-    #[msg("No liquidity provided")]
-    NoLiquidity,
+    close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account,
+            destination,
+            authority,
+        },
+        signer_seeds,
+    ))
+}
 
-    #[msg("No rewards available to claim")]
-    NoRewardsAvailable,
+fn withheld_transfer_fees(account: &AccountInfo) -> Result<u64> {
+    if *account.owner != token_2022::ID {
+        return Ok(0);
+    }
 
-    #[msg("Pool is not empty")]
-    PoolNotEmpty,
+    let account_data = account.try_borrow_data()?;
+    let account_state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account_data)?;
+    Ok(account_state
+        .get_extension::<TransferFeeAmount>()
+        .map_or(0, |transfer_fee_amount| u64::from(transfer_fee_amount.withheld_amount)))
+}
 
-    #[msg("Invalid K value after swap")]
-    InvalidK,
+/// `transfer_checked` that forwards any transfer-hook extra accounts from `remaining_accounts`
+#[allow(clippy::too_many_arguments)]
+fn transfer_tokens<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    mint: &InterfaceAccount<'info, Mint>,
+    remaining_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    spl_token_2022::onchain::invoke_transfer_checked(
+        &token_program.key(),
+        from,
+        mint.to_account_info(),
+        to,
+        authority,
+        remaining_accounts,
+        amount,
+        mint.decimals,
+        signer_seeds,
+    )?;
+
+    Ok(())
 }
 
-impl LiquidityPool {
-    pub fn calculate_lp_tokens(&self, token_x_amount: u64, token_y_amount: u64) -> Result<u64> {
-        if self.lp_token_supply == 0 {
-            Ok(((token_x_amount as u128)
-                .checked_mul(token_y_amount as u128)
-                .ok_or(ErrorCode::MathOverflow)?)
-            .integer_sqrt() as u64)
-        } else {
-            let x_ratio = (token_x_amount as u128)
-                .checked_mul(self.lp_token_supply as u128)
-                .ok_or(ErrorCode::MathOverflow)?
-                .checked_div(self.token_x_reserve as u128)
-                .ok_or(ErrorCode::MathOverflow)? as u64;
+/// Moves native SOL between a user and a pool's WSOL vault through an ephemeral
+/// PDA token account, so the user pays and receives plain lamports
+struct NativeSolTransfer<'a, 'info> {
+    user: &'a Signer<'info>,
+    temp_wsol_account: &'a UncheckedAccount<'info>,
+    temp_wsol_bump: u8,
+    native_mint: &'a InterfaceAccount<'info, Mint>,
+    pool_vault: AccountInfo<'info>,
+    liquidity_pool: AccountInfo<'info>,
+    token_program: &'a Interface<'info, TokenInterface>,
+    system_program: &'a Program<'info, System>,
+    pool_seeds: &'a [&'a [u8]],
+}
 
-            let y_ratio = (token_y_amount as u128)
-                .checked_mul(self.lp_token_supply as u128)
-                .ok_or(ErrorCode::MathOverflow)?
-                .checked_div(self.token_y_reserve as u128)
-                .ok_or(ErrorCode::MathOverflow)? as u64;
+impl<'info> NativeSolTransfer<'_, 'info> {
+    fn wrap(&self, lamports: u64) -> Result<()> {
+        self.open_temp_account()?;
 
-            Ok(std::cmp::min(x_ratio, y_ratio))
-        }
+        system_program::transfer(
+            CpiContext::new(
+                self.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: self.user.to_account_info(),
+                    to: self.temp_wsol_account.to_account_info(),
+                },
+            ),
+            lamports,
+        )?;
+        sync_native(CpiContext::new(
+            self.token_program.to_account_info(),
+            SyncNative {
+                account: self.temp_wsol_account.to_account_info(),
+            },
+        ))?;
+
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.temp_wsol_account.to_account_info(),
+                    to: self.pool_vault.clone(),
+                    authority: self.liquidity_pool.clone(),
+                    mint: self.native_mint.to_account_info(),
+                },
+                &[self.pool_seeds],
+            ),
+            lamports,
+            self.native_mint.decimals,
+        )?;
+
+        self.close_temp_account()
     }
-}
 
-fn calculate_swap_output(amount_in: u64, reserve_in: u64, reserve_out: u64) -> Result<u64> {
-    require!(amount_in > 0, ErrorCode::InvalidSwapInput);
-    require!(
-        reserve_in > 0 && reserve_out > 0,
-        ErrorCode::InvalidSwapInput
-    );
-
-    let amount_in = amount_in as u128;
-    let reserve_in = reserve_in as u128;
-    let reserve_out = reserve_out as u128;
-
-    let k = reserve_in
-        .checked_mul(reserve_out)
-        .ok_or(ErrorCode::MathOverflow)?;
+    fn unwrap(&self, amount: u64) -> Result<()> {
+        self.open_temp_account()?;
 
-    let new_reserve_in = reserve_in
-        .checked_add(amount_in)
-        .ok_or(ErrorCode::MathOverflow)?;
+        transfer_checked(
+            CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                TransferChecked {
+                    from: self.pool_vault.clone(),
+                    to: self.temp_wsol_account.to_account_info(),
+                    authority: self.liquidity_pool.clone(),
+                    mint: self.native_mint.to_account_info(),
+                },
+                &[self.pool_seeds],
+            ),
+            amount,
+            self.native_mint.decimals,
+        )?;
 
-    let new_reserve_out = k
-        .checked_div(new_reserve_in)
-        .ok_or(ErrorCode::MathOverflow)?;
+        // Closing sends the unwrapped lamports and the rent back to the user
+        self.close_temp_account()
+    }
 
-    let amount_out = reserve_out
-        .checked_sub(new_reserve_out)
-        .ok_or(ErrorCode::MathOverflow)?;
+    fn open_temp_account(&self) -> Result<()> {
+        require_keys_eq!(self.native_mint.key(), NATIVE_MINT_ID, ErrorCode::InvalidToken);
 
-    require!(amount_out > 0, ErrorCode::InvalidSwapInput);
-    require!(amount_out <= reserve_out, ErrorCode::InsufficientFunds);
+        let liquidity_pool_key = self.liquidity_pool.key();
+        let user_key = self.user.key();
+        let temp_wsol_seeds: &[&[u8]] = &[
+            TEMP_WSOL_SEED,
+            liquidity_pool_key.as_ref(),
+            user_key.as_ref(),
+            &[self.temp_wsol_bump],
+        ];
+        let space = token::TokenAccount::LEN;
+        let rent = Rent::get()?.minimum_balance(space);
+        let current_lamports = self.temp_wsol_account.lamports();
 
-    Ok(amount_out.try_into().map_err(|_| ErrorCode::MathOverflow)?)
-}
+        if current_lamports == 0 {
+            system_program::create_account(
+                CpiContext::new_with_signer(
+                    self.system_program.to_account_info(),
+                    system_program::CreateAccount {
+                        from: self.user.to_account_info(),
+                        to: self.temp_wsol_account.to_account_info(),
+                    },
+                    &[temp_wsol_seeds],
+                ),
+                rent,
+                space as u64,
+                &self.token_program.key(),
+            )?;
+        } else {
+            // Someone pre-funded the address; top it up and take ownership instead
+            let top_up = rent.saturating_sub(current_lamports);
+            if top_up > 0 {
+                system_program::transfer(
+                    CpiContext::new(
+                        self.system_program.to_account_info(),
+                        system_program::Transfer {
+                            from: self.user.to_account_info(),
+                            to: self.temp_wsol_account.to_account_info(),
+                        },
+                    ),
+                    top_up,
+                )?;
+            }
+            system_program::allocate(
+                CpiContext::new_with_signer(
+                    self.system_program.to_account_info(),
+                    system_program::Allocate {
+                        account_to_allocate: self.temp_wsol_account.to_account_info(),
+                    },
+                    &[temp_wsol_seeds],
+                ),
+                space as u64,
+            )?;
+            system_program::assign(
+                CpiContext::new_with_signer(
+                    self.system_program.to_account_info(),
+                    system_program::Assign {
+                        account_to_assign: self.temp_wsol_account.to_account_info(),
+                    },
+                    &[temp_wsol_seeds],
+                ),
+                &self.token_program.key(),
+            )?;
+        }
 
-fn calculate_rewards(amount_staked: u64, reward_rate: u64, duration: i64) -> Result<u64> {
-    if duration <= 0 {
-        return Ok(0);
+        initialize_account3(CpiContext::new(
+            self.token_program.to_account_info(),
+            InitializeAccount3 {
+                account: self.temp_wsol_account.to_account_info(),
+                mint: self.native_mint.to_account_info(),
+                authority: self.liquidity_pool.clone(),
+            },
+        ))
     }
 
-    let reward = (amount_staked as u128)
-        .checked_mul(reward_rate as u128)
-        .ok_or(ErrorCode::MathOverflow)?
-        .checked_mul(duration as u128)
-        .ok_or(ErrorCode::MathOverflow)?
-        .checked_div(10000)
-        .ok_or(ErrorCode::MathOverflow)? as u64;
+    fn close_temp_account(&self) -> Result<()> {
+        close_account(CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            CloseAccount {
+                account: self.temp_wsol_account.to_account_info(),
+                destination: self.user.to_account_info(),
+                authority: self.liquidity_pool.clone(),
+            },
+            &[self.pool_seeds],
+        ))
+    }
+}
+
+fn calculate_flash_loan_fee(amount: u64) -> Result<u64> {
+    Ok(soondex_math::flash_loan_fee(amount, FLASH_LOAN_FEE_RATE).map_err(ErrorCode::from)?)
+}
+
+/// Rewards `amount_staked` earned since its position checkpointed the pool's reward accumulator
+fn calculate_rewards(amount_staked: u64, reward_per_token_cumulative: u128, reward_checkpoint: u128) -> Result<u64> {
+    let reward_per_token_delta = reward_per_token_cumulative
+        .checked_sub(reward_checkpoint)
+        .ok_or(ErrorCode::MathOverflow)?;
+    Ok(soondex_math::accrued_rewards(amount_staked, reward_per_token_delta).map_err(ErrorCode::from)?)
+}
 
-    Ok(reward)
+impl From<MathError> for ErrorCode {
+    fn from(error: MathError) -> Self {
+        match error {
+            MathError::Overflow | MathError::DivisionByZero => ErrorCode::MathOverflow,
+            MathError::ZeroAmount | MathError::ZeroOutput => ErrorCode::InvalidSwapInput,
+            MathError::InsufficientLiquidity => ErrorCode::InsufficientFunds,
+        }
+    }
 }
 
 
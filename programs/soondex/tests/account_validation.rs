@@ -0,0 +1,163 @@
+mod common;
+
+use anchor_spl::token::spl_token::{self, native_mint};
+use common::*;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use soondex::ErrorCode;
+use soondex_client::instructions::{self, SwapParams};
+
+/// Points the account meta currently holding `from` at `to`
+fn substitute(mut ix: Instruction, from: Pubkey, to: Pubkey) -> Instruction {
+    let meta = ix.accounts.iter_mut().find(|meta| meta.pubkey == from).expect("account is in the instruction");
+    meta.pubkey = to;
+    ix
+}
+
+fn swap_x_to_y(harness: &Harness, trader: &User) -> Instruction {
+    instructions::swap_tokens(
+        &harness.keys,
+        trader.pubkey(),
+        SwapParams {
+            input_mint: harness.keys.token_x_mint,
+            user_token_in: Some(trader.token_x),
+            user_token_out: Some(trader.token_y),
+            amount_in: 1_000_000,
+            minimum_amount_out: 1,
+            deadline: None,
+            referral: None,
+            transfer_hook_accounts: vec![],
+        },
+        None,
+    )
+}
+
+#[tokio::test]
+async fn pool_records_its_vaults() {
+    let mut harness = Harness::with_pool().await;
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.token_x_vault, pool.token_y_vault), (harness.keys.vault_x(), harness.keys.vault_y()));
+}
+
+#[tokio::test]
+async fn initialize_pool_requires_the_pool_vaults() {
+    let mut harness = Harness::new().await;
+    let keys = harness.keys;
+    harness.create_token_account(&keys.address(), &keys.token_x_mint, &keys.token_x_program).await;
+    harness.create_token_account(&keys.address(), &keys.token_y_mint, &keys.token_y_program).await;
+    let payer = harness.payer();
+    let decoy = harness.create_token_account(&payer, &keys.token_x_mint, &keys.token_x_program).await;
+
+    let ix = instructions::initialize_pool(&keys, payer, harness.protocol_wallet, 0);
+    let ix = substitute(ix, keys.vault_x(), decoy);
+    assert_error(harness.send(&[ix], &[]).await, ErrorCode::InvalidPoolVault);
+}
+
+#[tokio::test]
+async fn swap_rejects_foreign_vaults() {
+    let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let ix = substitute(swap_x_to_y(&harness, &trader), harness.keys.vault_x(), provider.token_x);
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::InvalidPoolVault);
+
+    let ix = substitute(swap_x_to_y(&harness, &trader), harness.keys.vault_y(), provider.token_y);
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::InvalidPoolVault);
+}
+
+#[tokio::test]
+async fn swap_rejects_user_accounts_the_signer_does_not_own() {
+    let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let ix = substitute(swap_x_to_y(&harness, &trader), trader.token_x, harness.keys.vault_x());
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::InvalidTokenAccountOwner);
+
+    let ix = substitute(swap_x_to_y(&harness, &trader), trader.token_y, provider.token_y);
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::InvalidTokenAccountOwner);
+}
+
+#[tokio::test]
+async fn swap_rejects_user_accounts_of_the_wrong_mint() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let ix = substitute(swap_x_to_y(&harness, &trader), trader.token_y, trader.token_x);
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::InvalidTokenAccountMint);
+}
+
+#[tokio::test]
+async fn swap_rejects_a_fake_native_mint() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let ix = substitute(swap_x_to_y(&harness, &trader), native_mint::ID, Pubkey::new_unique());
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::InvalidNativeMint);
+}
+
+#[tokio::test]
+async fn liquidity_accounts_are_bound_to_the_pool_and_signer() {
+    let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let keys = harness.keys;
+
+    let ix = instructions::add_liquidity(&keys, provider.pubkey(), provider.liquidity_accounts(), 1_000, 4_000, None);
+    let ix = substitute(ix, provider.token_x, provider.token_y);
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::InvalidTokenAccountMint);
+
+    let other = harness.create_user().await;
+    let ix = instructions::remove_liquidity(&keys, provider.pubkey(), provider.liquidity_accounts(), 1_000, 4_000, None);
+    let ix = substitute(ix, provider.token_y, other.token_y);
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::InvalidTokenAccountOwner);
+
+    let ix = instructions::remove_liquidity(&keys, provider.pubkey(), provider.liquidity_accounts(), 1_000, 4_000, None);
+    let ix = substitute(ix, keys.vault_x(), other.token_x);
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::InvalidPoolVault);
+}
+
+#[tokio::test]
+async fn flash_loans_pay_out_of_the_pool_vaults_only() {
+    let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let borrower = harness.create_user().await;
+
+    let ix = instructions::flash_borrow(&harness.keys, borrower.pubkey(), borrower.token_x, borrower.token_y, 1_000, 0);
+    let ix = substitute(ix, harness.keys.vault_x(), provider.token_x);
+    assert_error(harness.send(&[ix], &[&borrower.keypair]).await, ErrorCode::InvalidPoolVault);
+}
+
+#[tokio::test]
+async fn staking_uses_the_pool_vault_of_the_stake_mint() {
+    let mut harness = Harness::with_pool().await;
+    let staker = harness.create_user().await;
+    let stake_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+    let accounts = harness.stake_accounts(&staker, &stake_mint).await;
+    harness.mint_to(&stake_mint, &accounts.user_token_account, INITIAL_USER_TOKENS).await;
+
+    // A pool-owned account of another mint is still not the stake vault
+    let ix = instructions::stake(&harness.keys, staker.pubkey(), accounts, 1_000);
+    let ix = substitute(ix, accounts.pool_token_account, harness.keys.vault_x());
+    assert_error(harness.send(&[ix], &[&staker.keypair]).await, ErrorCode::InvalidPoolVault);
+
+    let ix = instructions::stake(&harness.keys, staker.pubkey(), accounts, 1_000);
+    let ix = substitute(ix, accounts.user_token_account, staker.token_x);
+    assert_error(harness.send(&[ix], &[&staker.keypair]).await, ErrorCode::InvalidTokenAccountMint);
+}
+
+#[tokio::test]
+async fn rewards_are_paid_to_the_signer_only() {
+    let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let other = harness.create_user().await;
+
+    // Claiming needs the provider's staking state
+    let stake_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+    let stake_accounts = harness.stake_accounts(&provider, &stake_mint).await;
+    harness.mint_to(&stake_mint, &stake_accounts.user_token_account, INITIAL_USER_TOKENS).await;
+    let ix = instructions::stake(&harness.keys, provider.pubkey(), stake_accounts, 1_000);
+    harness.send(&[ix], &[&provider.keypair]).await.unwrap();
+
+    let reward_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+    let accounts = harness.reward_accounts(&provider, &reward_mint, INITIAL_USER_TOKENS).await;
+    let other_account = harness.create_token_account(&other.pubkey(), &reward_mint, &spl_token::ID).await;
+
+    let ix = instructions::claim_rewards(&harness.keys, provider.pubkey(), accounts);
+    let ix = substitute(ix, accounts.user_reward_account, other_account);
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::InvalidTokenAccountOwner);
+}
@@ -0,0 +1,452 @@
+//! In-process harness for the Soondex program: loads the program natively into
+//! `solana-program-test` and provides mint, pool and user fixtures.
+
+#![allow(dead_code)]
+
+use anchor_lang::{AccountDeserialize, AccountSerialize, AnchorDeserialize};
+use anchor_spl::associated_token::{get_associated_token_address_with_program_id, spl_associated_token_account};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::instruction::initialize_transfer_fee_config, ExtensionType},
+};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    account_info::AccountInfo,
+    clock::Clock,
+    compute_budget::ComputeBudgetInstruction,
+    entrypoint::ProgramResult,
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+use soondex::{ErrorCode, LiquidityPool, UserState};
+use soondex_client::{
+    instructions::{self, LiquidityAccounts, RewardAccounts, StakeAccounts},
+    pda, PoolKeys,
+};
+
+pub const FEE_RATE: u64 = 25;
+/// Reserves the tests seed pools with, a price of 4 Y per X
+pub const RESERVE_X: u64 = 1_000_000_000;
+pub const RESERVE_Y: u64 = 4_000_000_000;
+pub const INITIAL_USER_TOKENS: u64 = 1_000_000_000_000;
+pub const INITIAL_USER_LAMPORTS: u64 = 10_000_000_000;
+pub const DECIMALS: u8 = 6;
+/// Transfer fee of the fixture's Token-2022 `TransferFeeConfig` mints
+pub const TRANSFER_FEE_BPS: u16 = 100;
+
+/// Program id of the flash swap receiver registered by [`program_test`]
+pub const FLASH_RECEIVER_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+
+// Anchor's entrypoint ties the accounts slice to the account lifetimes, which
+// the native processor signature cannot express
+fn soondex_entry(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    soondex::entry(program_id, accounts, data)
+}
+
+/// Flash swap receiver. With a non-empty payload it repays by transferring
+/// `amount_in` from `accounts[0]` to `accounts[1]` (mint `accounts[2]`,
+/// authority `accounts[3]`, token program `accounts[4]`); otherwise it does nothing.
+fn flash_receiver_entry(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let callback = soondex::FlashSwapCallback::try_from_slice(&data[8..])?;
+    if callback.data.is_empty() {
+        return Ok(());
+    }
+
+    let ix = spl_token::instruction::transfer_checked(
+        accounts[4].key,
+        accounts[0].key,
+        accounts[2].key,
+        accounts[1].key,
+        accounts[3].key,
+        &[],
+        callback.amount_in,
+        DECIMALS,
+    )?;
+    solana_sdk::program::invoke(&ix, accounts)
+}
+
+pub fn program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::new("soondex", soondex::ID, processor!(soondex_entry));
+    program_test.prefer_bpf(false);
+    program_test.add_program("flash_receiver", FLASH_RECEIVER_ID, processor!(flash_receiver_entry));
+    program_test
+}
+
+/// Custom program error code raised for `error`
+pub fn error_code(error: ErrorCode) -> u32 {
+    error.into()
+}
+
+/// Asserts that `result` failed with `error` in any instruction
+#[track_caller]
+pub fn assert_error(result: Result<(), BanksClientError>, error: ErrorCode) {
+    let expected = error_code(error);
+    match result {
+        Err(BanksClientError::TransactionError(TransactionError::InstructionError(
+            _,
+            InstructionError::Custom(code),
+        ))) if code == expected => {}
+        Err(BanksClientError::SimulationError {
+            err: TransactionError::InstructionError(_, InstructionError::Custom(code)),
+            ..
+        }) if code == expected => {}
+        other => panic!("expected {error:?} ({expected}), got {other:?}"),
+    }
+}
+
+pub struct User {
+    pub keypair: Keypair,
+    pub token_x: Pubkey,
+    pub token_y: Pubkey,
+}
+
+impl User {
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    pub fn liquidity_accounts(&self) -> LiquidityAccounts {
+        LiquidityAccounts {
+            user_token_x_account: Some(self.token_x),
+            user_token_y_account: Some(self.token_y),
+        }
+    }
+}
+
+pub struct Harness {
+    pub context: ProgramTestContext,
+    pub keys: PoolKeys,
+    pub protocol_wallet: Pubkey,
+    /// Makes otherwise identical transactions unique within a blockhash
+    nonce: u64,
+}
+
+impl Harness {
+    /// Starts the bank and creates two legacy mints; the pool is not initialized
+    pub async fn new() -> Self {
+        let mut context = program_test().start_with_context().await;
+        let mint_a = create_mint(&mut context, &spl_token::ID, &[]).await;
+        let mint_b = create_mint(&mut context, &spl_token::ID, &[]).await;
+        Self::from_context(context, PoolKeys::new(mint_a, mint_b, FEE_RATE))
+    }
+
+    /// Like `new`, but one side is a Token-2022 mint with `extensions`
+    pub async fn with_token_2022_mint(extensions: &[ExtensionType]) -> Self {
+        let mut context = program_test().start_with_context().await;
+        let mint_a = create_mint(&mut context, &spl_token_2022::ID, extensions).await;
+        let mint_b = create_mint(&mut context, &spl_token::ID, &[]).await;
+        let keys = PoolKeys::new(mint_a, mint_b, FEE_RATE).with_token_program(mint_a, spl_token_2022::ID);
+        Self::from_context(context, keys)
+    }
+
+    /// Wraps a started bank whose mints already exist; the pool is not initialized
+    pub fn from_context(context: ProgramTestContext, keys: PoolKeys) -> Self {
+        Self {
+            context,
+            keys,
+            protocol_wallet: Pubkey::new_unique(),
+            nonce: 0,
+        }
+    }
+
+    /// Starts the bank with an initialized pool
+    pub async fn with_pool() -> Self {
+        let mut harness = Self::new().await;
+        harness.initialize_pool().await.unwrap();
+        harness
+    }
+
+    /// Starts the bank with an initialized pool seeded by `provider`
+    pub async fn with_liquidity(amount_x: u64, amount_y: u64) -> (Self, User) {
+        let mut harness = Self::with_pool().await;
+        let provider = harness.create_user().await;
+        harness.add_liquidity(&provider, amount_x, amount_y).await.unwrap();
+        (harness, provider)
+    }
+
+    pub fn payer(&self) -> Pubkey {
+        self.context.payer.pubkey()
+    }
+
+    pub fn pool(&self) -> Pubkey {
+        self.keys.address()
+    }
+
+    /// Signs `instructions` with the payer and `signers`. A leading priority fee
+    /// instruction carries a nonce, so repeated instructions never collide as
+    /// duplicate transactions and no send waits for a new blockhash
+    async fn transaction(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Transaction {
+        self.nonce += 1;
+        let mut all_instructions = vec![ComputeBudgetInstruction::set_compute_unit_price(self.nonce)];
+        all_instructions.extend_from_slice(instructions);
+
+        let blockhash = self.context.banks_client.get_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        Transaction::new_signed_with_payer(
+            &all_instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        )
+    }
+
+    pub async fn send(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), BanksClientError> {
+        let tx = self.transaction(instructions, signers).await;
+        self.context.banks_client.process_transaction(tx).await
+    }
+
+    /// Simulates a read-only instruction and decodes its return data
+    pub async fn simulate<T: AnchorDeserialize>(&mut self, instruction: Instruction) -> Result<T, BanksClientError> {
+        let tx = self.transaction(&[instruction], &[]).await;
+        let simulation = self.context.banks_client.simulate_transaction(tx).await?;
+        if let Some(Err(err)) = simulation.result {
+            return Err(BanksClientError::TransactionError(err));
+        }
+        let return_data = simulation
+            .simulation_details
+            .and_then(|details| details.return_data)
+            .expect("quote instructions set return data");
+        Ok(soondex_client::state::return_data(&return_data.data).unwrap())
+    }
+
+    /// Creates the pool's vaults, which `initialize_pool` expects to exist, then the pool
+    pub async fn initialize_pool(&mut self) -> Result<(), BanksClientError> {
+        let keys = self.keys;
+        self.create_token_account(&keys.address(), &keys.token_x_mint, &keys.token_x_program).await;
+        self.create_token_account(&keys.address(), &keys.token_y_mint, &keys.token_y_program).await;
+
+        let pool_count = self.pool_count().await;
+        let ix = instructions::initialize_pool(&keys, self.payer(), self.protocol_wallet, pool_count);
+        self.send(&[ix], &[]).await
+    }
+
+    pub async fn pool_count(&mut self) -> u64 {
+        let registry = pda::pool_registry_address().0;
+        match self.context.banks_client.get_account(registry).await.unwrap() {
+            Some(account) => soondex_client::state::pool_registry(&account.data).unwrap().pool_count,
+            None => 0,
+        }
+    }
+
+    /// Creates a user with SOL, X/Y token accounts and `INITIAL_USER_TOKENS` of each
+    pub async fn create_user(&mut self) -> User {
+        let keypair = Keypair::new();
+        let fund = system_instruction::transfer(&self.payer(), &keypair.pubkey(), INITIAL_USER_LAMPORTS);
+        self.send(&[fund], &[]).await.unwrap();
+
+        let keys = self.keys;
+        let token_x = self.create_token_account(&keypair.pubkey(), &keys.token_x_mint, &keys.token_x_program).await;
+        let token_y = self.create_token_account(&keypair.pubkey(), &keys.token_y_mint, &keys.token_y_program).await;
+        self.mint_to(&keys.token_x_mint, &token_x, INITIAL_USER_TOKENS).await;
+        self.mint_to(&keys.token_y_mint, &token_y, INITIAL_USER_TOKENS).await;
+
+        User { keypair, token_x, token_y }
+    }
+
+    /// Creates the associated token account of `owner` for `mint`
+    pub async fn create_token_account(&mut self, owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+        let ix = spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &self.payer(),
+            owner,
+            mint,
+            token_program,
+        );
+        self.send(&[ix], &[]).await.unwrap();
+        get_associated_token_address_with_program_id(owner, mint, token_program)
+    }
+
+    /// Mints with the payer, which is the authority of every fixture mint
+    pub async fn mint_to(&mut self, mint: &Pubkey, account: &Pubkey, amount: u64) {
+        let token_program = self.account(mint).await.unwrap().owner;
+        let ix = spl_token_2022::instruction::mint_to(&token_program, mint, account, &self.payer(), &[], amount).unwrap();
+        self.send(&[ix], &[]).await.unwrap();
+    }
+
+    pub async fn add_liquidity(&mut self, user: &User, amount_x: u64, amount_y: u64) -> Result<(), BanksClientError> {
+        let ix = instructions::add_liquidity(&self.keys, user.pubkey(), user.liquidity_accounts(), amount_x, amount_y, None);
+        self.send(&[ix], &[&user.keypair]).await
+    }
+
+    pub async fn remove_liquidity(&mut self, user: &User, amount_x: u64, amount_y: u64) -> Result<(), BanksClientError> {
+        let ix = instructions::remove_liquidity(&self.keys, user.pubkey(), user.liquidity_accounts(), amount_x, amount_y, None);
+        self.send(&[ix], &[&user.keypair]).await
+    }
+
+    /// Swaps X for Y when `x_to_y`, otherwise Y for X
+    pub async fn swap(
+        &mut self,
+        user: &User,
+        x_to_y: bool,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<(), BanksClientError> {
+        let (input_mint, user_token_in, user_token_out) = if x_to_y {
+            (self.keys.token_x_mint, user.token_x, user.token_y)
+        } else {
+            (self.keys.token_y_mint, user.token_y, user.token_x)
+        };
+        let ix = instructions::swap_tokens(
+            &self.keys,
+            user.pubkey(),
+            instructions::SwapParams {
+                input_mint,
+                user_token_in: Some(user_token_in),
+                user_token_out: Some(user_token_out),
+                amount_in,
+                minimum_amount_out,
+                deadline: None,
+                referral: None,
+                transfer_hook_accounts: vec![],
+            },
+            None,
+        );
+        self.send(&[ix], &[&user.keypair]).await
+    }
+
+    /// Staking fixture: a separate stake mint, the user's funded account and the pool's vault
+    pub async fn stake_accounts(&mut self, user: &User, stake_mint: &Pubkey) -> StakeAccounts {
+        let pool = self.pool();
+        let user_token_account = self.create_token_account(&user.pubkey(), stake_mint, &spl_token::ID).await;
+        let pool_token_account = self.create_token_account(&pool, stake_mint, &spl_token::ID).await;
+        StakeAccounts {
+            token_mint: *stake_mint,
+            user_token_account,
+            pool_token_account,
+            token_program: spl_token::ID,
+        }
+    }
+
+    /// Reward fixture: the pool's reward vault funded with `funding` tokens
+    pub async fn reward_accounts(&mut self, user: &User, reward_mint: &Pubkey, funding: u64) -> RewardAccounts {
+        let pool = self.pool();
+        let user_reward_account = self.create_token_account(&user.pubkey(), reward_mint, &spl_token::ID).await;
+        let pool_reward_account = self.create_token_account(&pool, reward_mint, &spl_token::ID).await;
+        self.mint_to(reward_mint, &pool_reward_account, funding).await;
+        RewardAccounts {
+            reward_mint: *reward_mint,
+            user_reward_account,
+            pool_reward_account,
+            token_program: spl_token::ID,
+        }
+    }
+
+    pub async fn liquidity_pool(&mut self) -> LiquidityPool {
+        self.anchor_account(&self.pool()).await
+    }
+
+    pub async fn user_state(&mut self, user: &User) -> UserState {
+        let address = self.keys.user_state(&user.pubkey());
+        self.anchor_account(&address).await
+    }
+
+    pub async fn anchor_account<T: AccountDeserialize>(&mut self, address: &Pubkey) -> T {
+        let account = self.context.banks_client.get_account(*address).await.unwrap().unwrap();
+        T::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    pub async fn account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.context.banks_client.get_account(*address).await.unwrap()
+    }
+
+    pub async fn token_balance(&mut self, address: &Pubkey) -> u64 {
+        let account = self.account(address).await.unwrap();
+        spl_token::state::Account::unpack_from_slice(&account.data[..spl_token::state::Account::LEN])
+            .unwrap()
+            .amount
+    }
+
+    /// Overwrites the pool account, e.g. to set a reward rate no instruction exposes yet
+    pub async fn update_pool(&mut self, update: impl FnOnce(&mut LiquidityPool)) {
+        let address = self.pool();
+        let mut account = self.account(&address).await.unwrap();
+        let mut pool = LiquidityPool::try_deserialize(&mut account.data.as_slice()).unwrap();
+        update(&mut pool);
+
+        let mut data = Vec::with_capacity(account.data.len());
+        pool.try_serialize(&mut data).unwrap();
+        data.resize(account.data.len(), 0);
+        account.data = data;
+        self.context.set_account(&address, &AccountSharedData::from(account));
+    }
+
+    pub async fn clock(&mut self) -> Clock {
+        self.context.banks_client.get_sysvar().await.unwrap()
+    }
+
+    /// Moves to a later slot and sets the clock `seconds` ahead
+    pub async fn warp_seconds(&mut self, seconds: i64) {
+        let clock = self.clock().await;
+        self.context.warp_to_slot(clock.slot + 2).unwrap();
+
+        let mut warped = self.clock().await;
+        warped.unix_timestamp = clock.unix_timestamp + seconds;
+        self.context.set_sysvar(&warped);
+    }
+
+    /// Asserts the vaults hold at least the recorded reserves plus what long-term orders own
+    pub async fn assert_vaults_cover_reserves(&mut self) {
+        let pool = self.liquidity_pool().await;
+        let (vault_x, vault_y) = (self.keys.vault_x(), self.keys.vault_y());
+        assert!(self.token_balance(&vault_x).await >= pool.token_x_reserve + pool.twamm_token_x_balance);
+        assert!(self.token_balance(&vault_y).await >= pool.token_y_reserve + pool.twamm_token_y_balance);
+    }
+}
+
+/// Creates a mint with `DECIMALS` whose authority is the payer
+pub async fn create_mint(context: &mut ProgramTestContext, token_program: &Pubkey, extensions: &[ExtensionType]) -> Pubkey {
+    let mint = Keypair::new();
+    let space = if extensions.is_empty() {
+        spl_token::state::Mint::LEN
+    } else {
+        ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(extensions).unwrap()
+    };
+    let rent = context.banks_client.get_rent().await.unwrap();
+
+    let mut ixs = vec![system_instruction::create_account(
+        &context.payer.pubkey(),
+        &mint.pubkey(),
+        rent.minimum_balance(space),
+        space as u64,
+        token_program,
+    )];
+    for extension in extensions {
+        ixs.push(match extension {
+            ExtensionType::NonTransferable => {
+                spl_token_2022::instruction::initialize_non_transferable_mint(token_program, &mint.pubkey()).unwrap()
+            }
+            ExtensionType::TransferFeeConfig => initialize_transfer_fee_config(
+                token_program,
+                &mint.pubkey(),
+                Some(&context.payer.pubkey()),
+                Some(&context.payer.pubkey()),
+                TRANSFER_FEE_BPS,
+                u64::MAX,
+            )
+            .unwrap(),
+            other => panic!("fixture does not initialize {other:?}"),
+        });
+    }
+    ixs.push(
+        spl_token_2022::instruction::initialize_mint2(
+            token_program,
+            &mint.pubkey(),
+            &context.payer.pubkey(),
+            None,
+            DECIMALS,
+        )
+        .unwrap(),
+    );
+
+    let blockhash = context.banks_client.get_latest_blockhash().await.unwrap();
+    let tx = Transaction::new_signed_with_payer(&ixs, Some(&context.payer.pubkey()), &[&context.payer, &mint], blockhash);
+    context.banks_client.process_transaction(tx).await.unwrap();
+    mint.pubkey()
+}
@@ -0,0 +1,247 @@
+mod common;
+
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeAmount, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+};
+use common::*;
+use solana_program_test::BanksClientError;
+use solana_sdk::instruction::AccountMeta;
+use soondex::{DcaVault, ErrorCode, SwapQuote};
+use soondex_client::instructions::{self, DcaSchedule};
+
+const VAULT_ID: u64 = 11;
+const DEPOSIT: u64 = 25_000_000;
+const AMOUNT_PER_EXECUTION: u64 = 10_000_000;
+// 0.1% of each execution, which leaves 9_990_000 X for the pool
+const KEEPER_FEE: u64 = 10_000;
+const INTERVAL: i64 = 3_600;
+// Covers the 0.25% swap fee and the ~1% price impact of one execution
+const SCHEDULE: DcaSchedule = DcaSchedule {
+    amount_per_execution: AMOUNT_PER_EXECUTION,
+    interval: INTERVAL,
+    max_slippage_bps: 300,
+};
+
+async fn open_vault(harness: &mut Harness, owner: &User, schedule: DcaSchedule) -> Result<(), BanksClientError> {
+    let ix = instructions::open_dca_vault(
+        &harness.keys,
+        owner.pubkey(),
+        harness.keys.token_x_mint,
+        VAULT_ID,
+        DEPOSIT,
+        schedule,
+    );
+    harness.send(&[ix], &[&owner.keypair]).await
+}
+
+async fn execute(harness: &mut Harness, keeper: &User, owner: &User) -> Result<(), BanksClientError> {
+    let ix = instructions::execute_dca(
+        &harness.keys,
+        keeper.pubkey(),
+        keeper.token_x,
+        owner.pubkey(),
+        harness.keys.token_x_mint,
+        VAULT_ID,
+    );
+    harness.send(&[ix], &[&keeper.keypair]).await
+}
+
+async fn vault_balances(harness: &mut Harness, owner: &User) -> (u64, u64) {
+    let keys = harness.keys;
+    let input_vault = keys.dca_vault_token_account(&owner.pubkey(), VAULT_ID, &keys.token_x_mint);
+    let output_vault = keys.dca_vault_token_account(&owner.pubkey(), VAULT_ID, &keys.token_y_mint);
+    (harness.token_balance(&input_vault).await, harness.token_balance(&output_vault).await)
+}
+
+#[tokio::test]
+async fn opening_a_vault_deposits_the_input() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    open_vault(&mut harness, &owner, SCHEDULE).await.unwrap();
+
+    let keys = harness.keys;
+    let vault: DcaVault = harness.anchor_account(&keys.dca_vault(&owner.pubkey(), VAULT_ID)).await;
+    assert_eq!((vault.owner, vault.vault_id, vault.pool), (owner.pubkey(), VAULT_ID, keys.address()));
+    assert_eq!((vault.input_mint, vault.output_mint), (keys.token_x_mint, keys.token_y_mint));
+    assert_eq!((vault.amount_per_execution, vault.interval), (AMOUNT_PER_EXECUTION, INTERVAL));
+    assert_eq!(vault.max_slippage_bps, SCHEDULE.max_slippage_bps);
+    assert_eq!(vault.last_execution, harness.clock().await.unix_timestamp);
+    assert_eq!(vault_balances(&mut harness, &owner).await, (DEPOSIT, 0));
+    assert_eq!(harness.token_balance(&owner.token_x).await, INITIAL_USER_TOKENS - DEPOSIT);
+}
+
+#[tokio::test]
+async fn schedules_are_validated() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let too_frequent = DcaSchedule { interval: soondex::MIN_DCA_INTERVAL - 1, ..SCHEDULE };
+    assert_error(open_vault(&mut harness, &owner, too_frequent).await, ErrorCode::InvalidDcaSchedule);
+    let no_floor = DcaSchedule { max_slippage_bps: 10_000, ..SCHEDULE };
+    assert_error(open_vault(&mut harness, &owner, no_floor).await, ErrorCode::InvalidDcaSchedule);
+    let nothing = DcaSchedule { amount_per_execution: 0, ..SCHEDULE };
+    assert_error(open_vault(&mut harness, &owner, nothing).await, ErrorCode::InvalidSwapInput);
+}
+
+#[tokio::test]
+async fn vaults_must_trade_the_pool_mints() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keys = harness.keys;
+
+    let token_program = keys.token_program(&keys.token_y_mint);
+    let other_mint = create_mint(&mut harness.context, &token_program, &[]).await;
+    let owner_token_in = harness.create_token_account(&owner.pubkey(), &other_mint, &token_program).await;
+    harness.mint_to(&other_mint, &owner_token_in, DEPOSIT).await;
+    let ix = instructions::open_dca_vault(&keys, owner.pubkey(), other_mint, VAULT_ID, DEPOSIT, SCHEDULE);
+    assert_error(harness.send(&[ix], &[&owner.keypair]).await, ErrorCode::InvalidDcaVault);
+}
+
+#[tokio::test]
+async fn vaults_only_move_through_their_own_token_accounts() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    let outsider = harness.create_user().await;
+    open_vault(&mut harness, &owner, SCHEDULE).await.unwrap();
+    open_vault(&mut harness, &outsider, SCHEDULE).await.unwrap();
+    harness.warp_seconds(INTERVAL).await;
+    let keys = harness.keys;
+    let input_vault = keys.dca_vault_token_account(&owner.pubkey(), VAULT_ID, &keys.token_x_mint);
+    let output_vault = keys.dca_vault_token_account(&owner.pubkey(), VAULT_ID, &keys.token_y_mint);
+
+    // The vault's own token accounts, swapped around
+    let mut ix =
+        instructions::execute_dca(&keys, keeper.pubkey(), keeper.token_x, owner.pubkey(), keys.token_x_mint, VAULT_ID);
+    ix.accounts.swap(2, 3);
+    assert_error(harness.send(&[ix], &[&keeper.keypair]).await, ErrorCode::InvalidDcaVault);
+
+    // The outsider's own vault, but another owner's token accounts
+    let mut ix = instructions::withdraw_dca_vault(&keys, outsider.pubkey(), keys.token_x_mint, VAULT_ID, DEPOSIT);
+    ix.accounts[5] = AccountMeta::new(input_vault, false);
+    ix.accounts[6] = AccountMeta::new(output_vault, false);
+    assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::InvalidDcaVault);
+
+    execute(&mut harness, &keeper, &owner).await.unwrap();
+}
+
+#[tokio::test]
+async fn keepers_execute_once_per_interval() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    open_vault(&mut harness, &owner, SCHEDULE).await.unwrap();
+    assert_error(execute(&mut harness, &keeper, &owner).await, ErrorCode::DcaExecutionNotDue);
+
+    harness.warp_seconds(INTERVAL).await;
+    let keys = harness.keys;
+    let quote: SwapQuote = harness
+        .simulate(instructions::quote_swap(&keys, keys.token_x_mint, AMOUNT_PER_EXECUTION - KEEPER_FEE, 0))
+        .await
+        .unwrap();
+    execute(&mut harness, &keeper, &owner).await.unwrap();
+
+    assert_eq!(vault_balances(&mut harness, &owner).await, (DEPOSIT - AMOUNT_PER_EXECUTION, quote.amount_out));
+    assert_eq!(harness.token_balance(&keeper.token_x).await, INITIAL_USER_TOKENS + KEEPER_FEE);
+    let vault: DcaVault = harness.anchor_account(&keys.dca_vault(&owner.pubkey(), VAULT_ID)).await;
+    assert_eq!((vault.executions, vault.last_execution), (1, harness.clock().await.unix_timestamp));
+    let pool = harness.liquidity_pool().await;
+    assert_eq!(pool.token_x_reserve, RESERVE_X + AMOUNT_PER_EXECUTION - KEEPER_FEE);
+    assert_eq!(pool.token_y_reserve, RESERVE_Y - quote.amount_out);
+
+    assert_error(execute(&mut harness, &keeper, &owner).await, ErrorCode::DcaExecutionNotDue);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn the_last_execution_spends_the_remainder() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    open_vault(&mut harness, &owner, SCHEDULE).await.unwrap();
+
+    for _ in 0..3 {
+        harness.warp_seconds(INTERVAL).await;
+        execute(&mut harness, &keeper, &owner).await.unwrap();
+    }
+    assert_eq!(vault_balances(&mut harness, &owner).await.0, 0);
+    // 0.1% of each of 10M, 10M and 5M
+    assert_eq!(harness.token_balance(&keeper.token_x).await, INITIAL_USER_TOKENS + 2 * KEEPER_FEE + KEEPER_FEE / 2);
+
+    harness.warp_seconds(INTERVAL).await;
+    assert_error(execute(&mut harness, &keeper, &owner).await, ErrorCode::InsufficientFunds);
+}
+
+#[tokio::test]
+async fn executions_below_the_twap_are_refused() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    let trader = harness.create_user().await;
+    open_vault(&mut harness, &owner, SCHEDULE).await.unwrap();
+    harness.warp_seconds(INTERVAL).await;
+
+    // Dumping X right before the execution moves the spot price but not the TWAP
+    harness.swap(&trader, true, RESERVE_X / 10, 1).await.unwrap();
+    assert_error(execute(&mut harness, &keeper, &owner).await, ErrorCode::DcaSlippageExceeded);
+
+    // Buying it back restores the price the TWAP expects
+    let bought = harness.token_balance(&trader.token_y).await - INITIAL_USER_TOKENS;
+    harness.swap(&trader, false, bought, 1).await.unwrap();
+    execute(&mut harness, &keeper, &owner).await.unwrap();
+}
+
+#[tokio::test]
+async fn owners_withdraw_at_any_time_and_close() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    let outsider = harness.create_user().await;
+    open_vault(&mut harness, &owner, SCHEDULE).await.unwrap();
+    harness.warp_seconds(INTERVAL).await;
+    execute(&mut harness, &keeper, &owner).await.unwrap();
+    let (_, bought) = vault_balances(&mut harness, &owner).await;
+
+    let keys = harness.keys;
+    let withdraw = instructions::withdraw_dca_vault(&keys, owner.pubkey(), keys.token_x_mint, VAULT_ID, 5_000_000);
+    let mut stolen = withdraw.clone();
+    stolen.accounts[2] = AccountMeta::new(outsider.pubkey(), true);
+    assert_error(harness.send(&[stolen], &[&outsider.keypair]).await, ErrorCode::Unauthorized);
+
+    harness.send(&[withdraw], &[&owner.keypair]).await.unwrap();
+    assert_eq!(vault_balances(&mut harness, &owner).await, (DEPOSIT - AMOUNT_PER_EXECUTION - 5_000_000, 0));
+    assert_eq!(harness.token_balance(&owner.token_y).await, INITIAL_USER_TOKENS + bought);
+
+    let close = instructions::close_dca_vault(&keys, owner.pubkey(), keys.token_x_mint, VAULT_ID);
+    harness.send(&[close], &[&owner.keypair]).await.unwrap();
+    assert_eq!(harness.token_balance(&owner.token_x).await, INITIAL_USER_TOKENS - AMOUNT_PER_EXECUTION);
+    assert!(harness.account(&keys.dca_vault(&owner.pubkey(), VAULT_ID)).await.is_none());
+    let input_vault = keys.dca_vault_token_account(&owner.pubkey(), VAULT_ID, &keys.token_x_mint);
+    assert!(harness.account(&input_vault).await.is_none());
+}
+
+#[tokio::test]
+async fn transfer_fee_vaults_close() {
+    let mut harness = Harness::with_token_2022_mint(&[ExtensionType::TransferFeeConfig]).await;
+    harness.initialize_pool().await.unwrap();
+    let provider = harness.create_user().await;
+    harness.add_liquidity(&provider, RESERVE_X, RESERVE_Y).await.unwrap();
+    let owner = harness.create_user().await;
+
+    let keys = harness.keys;
+    let fee_mint = if keys.token_x_program == spl_token_2022::ID { keys.token_x_mint } else { keys.token_y_mint };
+    let ix = instructions::open_dca_vault(&keys, owner.pubkey(), fee_mint, VAULT_ID, DEPOSIT, SCHEDULE);
+    harness.send(&[ix], &[&owner.keypair]).await.unwrap();
+
+    // The deposit withholds a fee in the input vault, which Token-2022 won't let an account close with
+    let input_vault = keys.dca_vault_token_account(&owner.pubkey(), VAULT_ID, &fee_mint);
+    let account = harness.account(&input_vault).await.unwrap();
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data).unwrap();
+    let withheld = u64::from(state.get_extension::<TransferFeeAmount>().unwrap().withheld_amount);
+    assert_eq!(withheld, DEPOSIT * TRANSFER_FEE_BPS as u64 / 10_000);
+
+    let ix = instructions::close_dca_vault(&keys, owner.pubkey(), fee_mint, VAULT_ID);
+    harness.send(&[ix], &[&owner.keypair]).await.unwrap();
+    assert!(harness.account(&keys.dca_vault(&owner.pubkey(), VAULT_ID)).await.is_none());
+    assert!(harness.account(&input_vault).await.is_none());
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use anchor_spl::token_2022::spl_token_2022::extension::ExtensionType;
+use common::*;
+use solana_sdk::instruction::Instruction;
+use soondex::{ErrorCode, FLASH_LOAN_FEE_RATE};
+use soondex_client::instructions::{self, SwapParams};
+
+fn borrow(harness: &Harness, borrower: &User, amount_x: u64, amount_y: u64) -> Instruction {
+    instructions::flash_borrow(&harness.keys, borrower.pubkey(), borrower.token_x, borrower.token_y, amount_x, amount_y)
+}
+
+fn repay(harness: &Harness, borrower: &User) -> Instruction {
+    instructions::flash_repay(&harness.keys, borrower.pubkey(), borrower.token_x, borrower.token_y)
+}
+
+#[tokio::test]
+async fn flash_loan_fee_accrues_to_reserves() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let borrower = harness.create_user().await;
+
+    let ixs = [borrow(&harness, &borrower, 100_000_000, 1), repay(&harness, &borrower)];
+    harness.send(&ixs, &[&borrower.keypair]).await.unwrap();
+
+    let fee_x = soondex_math::flash_loan_fee(100_000_000, FLASH_LOAN_FEE_RATE).unwrap();
+    let fee_y = soondex_math::flash_loan_fee(1, FLASH_LOAN_FEE_RATE).unwrap();
+    assert_eq!(fee_y, 1);
+
+    let pool = harness.liquidity_pool().await;
+    assert!(!pool.flash_loan_active);
+    assert_eq!(pool.token_x_reserve, RESERVE_X + fee_x);
+    assert_eq!(pool.token_y_reserve, RESERVE_Y + fee_y);
+    assert_eq!(harness.token_balance(&borrower.token_x).await, INITIAL_USER_TOKENS - fee_x);
+    assert_eq!(harness.token_balance(&borrower.token_y).await, INITIAL_USER_TOKENS - fee_y);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn borrowers_cover_transfer_fees_on_repay() {
+    let mut harness = Harness::with_token_2022_mint(&[ExtensionType::TransferFeeConfig]).await;
+    harness.initialize_pool().await.unwrap();
+    let provider = harness.create_user().await;
+    harness.add_liquidity(&provider, RESERVE_X, RESERVE_Y).await.unwrap();
+    let borrower = harness.create_user().await;
+    let before = harness.liquidity_pool().await;
+
+    let ixs = [borrow(&harness, &borrower, 100_000_000, 100_000_000), repay(&harness, &borrower)];
+    harness.send(&ixs, &[&borrower.keypair]).await.unwrap();
+
+    // Repaying only the loan and fee would leave the fee mint's vault short by the transfer fee
+    let fee = soondex_math::flash_loan_fee(100_000_000, FLASH_LOAN_FEE_RATE).unwrap();
+    let pool = harness.liquidity_pool().await;
+    assert_eq!(pool.token_x_reserve, before.token_x_reserve + fee);
+    assert_eq!(pool.token_y_reserve, before.token_y_reserve + fee);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn repay_checks_k_against_the_vault_balances() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let borrower = harness.create_user().await;
+    // Recorded reserves the vaults no longer hold, more than the loan fee makes up
+    harness.update_pool(|pool| pool.token_x_reserve += 1_000_000).await;
+
+    let ixs = [borrow(&harness, &borrower, 100_000_000, 0), repay(&harness, &borrower)];
+    assert_error(harness.send(&ixs, &[&borrower.keypair]).await, ErrorCode::InvalidK);
+}
+
+#[tokio::test]
+async fn borrow_requires_repay_in_same_transaction() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let borrower = harness.create_user().await;
+
+    let ix = borrow(&harness, &borrower, 1_000, 0);
+    assert_error(harness.send(&[ix], &[&borrower.keypair]).await, ErrorCode::MissingFlashRepay);
+}
+
+#[tokio::test]
+async fn repay_requires_active_loan() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let borrower = harness.create_user().await;
+
+    let ix = repay(&harness, &borrower);
+    assert_error(harness.send(&[ix], &[&borrower.keypair]).await, ErrorCode::FlashLoanNotActive);
+}
+
+#[tokio::test]
+async fn borrow_amounts_are_validated() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let borrower = harness.create_user().await;
+
+    let ixs = [borrow(&harness, &borrower, 0, 0), repay(&harness, &borrower)];
+    assert_error(harness.send(&ixs, &[&borrower.keypair]).await, ErrorCode::InvalidFlashLoanAmount);
+
+    let ixs = [borrow(&harness, &borrower, RESERVE_X + 1, 0), repay(&harness, &borrower)];
+    assert_error(harness.send(&ixs, &[&borrower.keypair]).await, ErrorCode::InsufficientFunds);
+}
+
+#[tokio::test]
+async fn pool_is_locked_while_loan_is_outstanding() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let borrower = harness.create_user().await;
+
+    let ixs = [
+        borrow(&harness, &borrower, 1_000, 0),
+        borrow(&harness, &borrower, 1_000, 0),
+        repay(&harness, &borrower),
+    ];
+    assert_error(harness.send(&ixs, &[&borrower.keypair]).await, ErrorCode::FlashLoanActive);
+
+    let swap = instructions::swap_tokens(
+        &harness.keys,
+        borrower.pubkey(),
+        SwapParams {
+            input_mint: harness.keys.token_x_mint,
+            user_token_in: Some(borrower.token_x),
+            user_token_out: Some(borrower.token_y),
+            amount_in: 1_000_000,
+            minimum_amount_out: 1,
+            deadline: None,
+            referral: None,
+            transfer_hook_accounts: vec![],
+        },
+        None,
+    );
+    let ixs = [borrow(&harness, &borrower, 1_000, 0), swap, repay(&harness, &borrower)];
+    assert_error(harness.send(&ixs, &[&borrower.keypair]).await, ErrorCode::FlashLoanActive);
+
+    let add = instructions::add_liquidity(&harness.keys, borrower.pubkey(), borrower.liquidity_accounts(), 1_000, 4_000, None);
+    let ixs = [borrow(&harness, &borrower, 1_000, 0), add, repay(&harness, &borrower)];
+    assert_error(harness.send(&ixs, &[&borrower.keypair]).await, ErrorCode::FlashLoanActive);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use common::*;
+use soondex::{ErrorCode, SwapQuote};
+use soondex_client::instructions;
+
+async fn set_guardrails(harness: &mut Harness, max_input_bps: u64, max_price_impact_bps: u64, max_slot_volume_bps: u64) {
+    let ix = instructions::set_swap_guardrails(
+        &harness.keys,
+        harness.payer(),
+        max_input_bps,
+        max_price_impact_bps,
+        max_slot_volume_bps,
+    );
+    harness.send(&[ix], &[]).await.unwrap();
+}
+
+#[tokio::test]
+async fn guardrails_start_disabled() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.max_swap_input_bps, pool.max_price_impact_bps, pool.max_slot_volume_bps), (0, 0, 0));
+    harness.swap(&trader, true, RESERVE_X, 1).await.unwrap();
+}
+
+#[tokio::test]
+async fn swap_input_is_limited_to_a_share_of_the_reserve() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    set_guardrails(&mut harness, 100, 0, 0).await;
+
+    assert_error(harness.swap(&trader, true, RESERVE_X / 100 + 1, 1).await, ErrorCode::SwapTooLarge);
+    harness.swap(&trader, true, RESERVE_X / 100, 1).await.unwrap();
+    assert_error(harness.swap(&trader, false, RESERVE_Y / 50, 1).await, ErrorCode::SwapTooLarge);
+}
+
+#[tokio::test]
+async fn swap_price_impact_is_limited() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    set_guardrails(&mut harness, 0, 50, 0).await;
+
+    // About 0.1% and 9% of the X reserve after fees
+    harness.swap(&trader, true, 1_000_000, 1).await.unwrap();
+    assert_error(harness.swap(&trader, true, 100_000_000, 1).await, ErrorCode::PriceImpactTooHigh);
+}
+
+#[tokio::test]
+async fn swap_volume_is_capped_per_slot() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    set_guardrails(&mut harness, 0, 0, 150).await;
+
+    harness.swap(&trader, true, RESERVE_X / 100, 1).await.unwrap();
+    assert_error(harness.swap(&trader, false, RESERVE_Y / 100, 1).await, ErrorCode::SlotVolumeExceeded);
+
+    let slot = harness.clock().await.slot;
+    assert_eq!(harness.liquidity_pool().await.volume_slot, slot);
+    harness.warp_seconds(1).await;
+    harness.swap(&trader, false, RESERVE_Y / 100, 1).await.unwrap();
+}
+
+#[tokio::test]
+async fn quotes_are_held_to_the_guardrails() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    let keys = harness.keys;
+
+    set_guardrails(&mut harness, 100, 0, 0).await;
+    let ix = instructions::quote_swap(&keys, keys.token_x_mint, RESERVE_X / 100 + 1, 0);
+    assert_error(harness.simulate::<SwapQuote>(ix).await.map(drop), ErrorCode::SwapTooLarge);
+    let ix = instructions::quote_swap_exact_out(&keys, keys.token_y_mint, RESERVE_X / 50);
+    assert_error(harness.simulate::<SwapQuote>(ix).await.map(drop), ErrorCode::SwapTooLarge);
+
+    set_guardrails(&mut harness, 0, 50, 0).await;
+    let ix = instructions::quote_swap(&keys, keys.token_x_mint, 100_000_000, 0);
+    assert_error(harness.simulate::<SwapQuote>(ix).await.map(drop), ErrorCode::PriceImpactTooHigh);
+
+    // Quotes count the volume already swapped this slot
+    set_guardrails(&mut harness, 0, 0, 150).await;
+    harness.swap(&trader, true, RESERVE_X / 100, 1).await.unwrap();
+    let ix = instructions::quote_swap(&keys, keys.token_y_mint, RESERVE_Y / 100, 0);
+    assert_error(harness.simulate::<SwapQuote>(ix).await.map(drop), ErrorCode::SlotVolumeExceeded);
+}
+
+#[tokio::test]
+async fn guardrails_are_set_by_admins_within_bounds() {
+    let mut harness = Harness::with_pool().await;
+    let outsider = harness.create_user().await;
+
+    let ix = instructions::set_swap_guardrails(&harness.keys, outsider.pubkey(), 100, 100, 100);
+    assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::Unauthorized);
+
+    let ix = instructions::set_swap_guardrails(&harness.keys, harness.payer(), 10_001, 0, 0);
+    assert_error(harness.send(&[ix], &[]).await, ErrorCode::InvalidGuardrail);
+
+    set_guardrails(&mut harness, 500, 300, 20_000).await;
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.max_swap_input_bps, pool.max_price_impact_bps, pool.max_slot_volume_bps), (500, 300, 20_000));
+}
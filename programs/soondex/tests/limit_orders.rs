@@ -0,0 +1,231 @@
+mod common;
+
+use anchor_spl::token_2022::spl_token_2022::{
+    self,
+    extension::{transfer_fee::TransferFeeAmount, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+};
+use common::*;
+use solana_program_test::BanksClientError;
+use solana_sdk::{instruction::AccountMeta, pubkey::Pubkey};
+use soondex::{ErrorCode, LimitOrder, SwapQuote};
+use soondex_client::instructions;
+
+const ORDER_ID: u64 = 7;
+const ORDER_AMOUNT: u64 = 10_000_000;
+// 0.1% of the order, which leaves 9_990_000 X for the pool
+const KEEPER_FEE: u64 = 10_000;
+// The spot price pays about 39.5M Y for the order; this needs X to appreciate by ~15%
+const LIMIT_AMOUNT_OUT: u64 = 45_000_000;
+
+async fn place_order(harness: &mut Harness, owner: &User, expiry: Option<i64>) -> Result<(), BanksClientError> {
+    let ix = instructions::place_limit_order(
+        &harness.keys,
+        owner.pubkey(),
+        harness.keys.token_x_mint,
+        ORDER_ID,
+        ORDER_AMOUNT,
+        LIMIT_AMOUNT_OUT,
+        expiry,
+    );
+    harness.send(&[ix], &[&owner.keypair]).await
+}
+
+async fn fill_order(harness: &mut Harness, keeper: &User, owner: &User) -> Result<(), BanksClientError> {
+    let ix = instructions::fill_limit_order(
+        &harness.keys,
+        keeper.pubkey(),
+        keeper.token_x,
+        owner.pubkey(),
+        harness.keys.token_x_mint,
+        ORDER_ID,
+    );
+    harness.send(&[ix], &[&keeper.keypair]).await
+}
+
+async fn cancel_order(harness: &mut Harness, owner: &User) -> Result<(), BanksClientError> {
+    let ix = instructions::cancel_limit_order(&harness.keys, owner.pubkey(), harness.keys.token_x_mint, ORDER_ID);
+    harness.send(&[ix], &[&owner.keypair]).await
+}
+
+#[tokio::test]
+async fn placing_an_order_escrows_the_input() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    place_order(&mut harness, &owner, Some(i64::MAX)).await.unwrap();
+
+    let keys = harness.keys;
+    let order: LimitOrder = harness.anchor_account(&keys.limit_order(&owner.pubkey(), ORDER_ID)).await;
+    assert_eq!((order.owner, order.order_id, order.pool), (owner.pubkey(), ORDER_ID, keys.address()));
+    assert_eq!((order.input_mint, order.output_mint), (keys.token_x_mint, keys.token_y_mint));
+    assert_eq!((order.amount_in, order.min_amount_out, order.expiry), (ORDER_AMOUNT, LIMIT_AMOUNT_OUT, i64::MAX));
+    assert_eq!(order.escrow, keys.limit_order_escrow(&owner.pubkey(), ORDER_ID, &keys.token_x_mint));
+    assert_eq!(harness.token_balance(&order.escrow).await, ORDER_AMOUNT);
+    assert_eq!(harness.token_balance(&owner.token_x).await, INITIAL_USER_TOKENS - ORDER_AMOUNT);
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.token_x_reserve, pool.token_y_reserve), (RESERVE_X, RESERVE_Y));
+}
+
+#[tokio::test]
+async fn orders_fill_once_the_pool_reaches_the_limit_price() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    let trader = harness.create_user().await;
+    place_order(&mut harness, &owner, None).await.unwrap();
+    assert_error(fill_order(&mut harness, &keeper, &owner).await, ErrorCode::LimitPriceNotReached);
+
+    // Buying X with Y moves the price through the limit
+    harness.swap(&trader, false, RESERVE_Y / 10, 1).await.unwrap();
+    let keys = harness.keys;
+    let quote: SwapQuote = harness
+        .simulate(instructions::quote_swap(&keys, keys.token_x_mint, ORDER_AMOUNT - KEEPER_FEE, 0))
+        .await
+        .unwrap();
+    assert!(quote.amount_out_received >= LIMIT_AMOUNT_OUT);
+    let reserves_before = harness.liquidity_pool().await;
+
+    fill_order(&mut harness, &keeper, &owner).await.unwrap();
+
+    assert_eq!(harness.token_balance(&owner.token_y).await, INITIAL_USER_TOKENS + quote.amount_out);
+    assert_eq!(harness.token_balance(&keeper.token_x).await, INITIAL_USER_TOKENS + KEEPER_FEE);
+    let pool = harness.liquidity_pool().await;
+    assert_eq!(pool.token_x_reserve, reserves_before.token_x_reserve + ORDER_AMOUNT - KEEPER_FEE);
+    assert_eq!(pool.token_y_reserve, reserves_before.token_y_reserve - quote.amount_out);
+    assert!(harness.account(&keys.limit_order(&owner.pubkey(), ORDER_ID)).await.is_none());
+    assert!(harness.account(&keys.limit_order_escrow(&owner.pubkey(), ORDER_ID, &keys.token_x_mint)).await.is_none());
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn expired_orders_cannot_fill_but_can_be_cancelled() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    let trader = harness.create_user().await;
+
+    let now = harness.clock().await.unix_timestamp;
+    assert_error(place_order(&mut harness, &owner, Some(now)).await, ErrorCode::InvalidLimitOrder);
+    place_order(&mut harness, &owner, Some(now + 60)).await.unwrap();
+    harness.swap(&trader, false, RESERVE_Y / 10, 1).await.unwrap();
+    harness.warp_seconds(120).await;
+
+    assert_error(fill_order(&mut harness, &keeper, &owner).await, ErrorCode::Expired);
+    cancel_order(&mut harness, &owner).await.unwrap();
+    assert_eq!(harness.token_balance(&owner.token_x).await, INITIAL_USER_TOKENS);
+    let keys = harness.keys;
+    assert!(harness.account(&keys.limit_order(&owner.pubkey(), ORDER_ID)).await.is_none());
+}
+
+#[tokio::test]
+async fn orders_must_be_fillable_when_placed() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keys = harness.keys;
+
+    for (amount_in, min_amount_out) in [(0, LIMIT_AMOUNT_OUT), (ORDER_AMOUNT, 0)] {
+        let ix = instructions::place_limit_order(
+            &keys,
+            owner.pubkey(),
+            keys.token_x_mint,
+            ORDER_ID,
+            amount_in,
+            min_amount_out,
+            None,
+        );
+        assert_error(harness.send(&[ix], &[&owner.keypair]).await, ErrorCode::InvalidLimitOrder);
+    }
+
+    let past = harness.clock().await.unix_timestamp - 1;
+    assert_error(place_order(&mut harness, &owner, Some(past)).await, ErrorCode::InvalidLimitOrder);
+
+    // A mint the pool doesn't trade
+    let token_program = keys.token_program(&keys.token_y_mint);
+    let other_mint = create_mint(&mut harness.context, &token_program, &[]).await;
+    let owner_token_in = harness.create_token_account(&owner.pubkey(), &other_mint, &token_program).await;
+    harness.mint_to(&other_mint, &owner_token_in, ORDER_AMOUNT).await;
+    let ix = instructions::place_limit_order(
+        &keys,
+        owner.pubkey(),
+        other_mint,
+        ORDER_ID,
+        ORDER_AMOUNT,
+        LIMIT_AMOUNT_OUT,
+        None,
+    );
+    assert_error(harness.send(&[ix], &[&owner.keypair]).await, ErrorCode::InvalidLimitOrder);
+}
+
+#[tokio::test]
+async fn only_the_owner_can_cancel() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let outsider = harness.create_user().await;
+    place_order(&mut harness, &owner, None).await.unwrap();
+
+    let mut ix = instructions::cancel_limit_order(&harness.keys, owner.pubkey(), harness.keys.token_x_mint, ORDER_ID);
+    ix.accounts[2] = AccountMeta::new(outsider.pubkey(), true);
+    assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::Unauthorized);
+
+    cancel_order(&mut harness, &owner).await.unwrap();
+    assert_eq!(harness.token_balance(&owner.token_x).await, INITIAL_USER_TOKENS);
+}
+
+#[tokio::test]
+async fn paused_pools_refuse_fills_but_allow_cancels() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    let trader = harness.create_user().await;
+    place_order(&mut harness, &owner, None).await.unwrap();
+    harness.swap(&trader, false, RESERVE_Y / 10, 1).await.unwrap();
+
+    let pause = instructions::set_paused(&harness.keys, harness.payer(), true);
+    harness.send(&[pause], &[]).await.unwrap();
+    assert_error(fill_order(&mut harness, &keeper, &owner).await, ErrorCode::PoolPaused);
+    cancel_order(&mut harness, &owner).await.unwrap();
+}
+
+async fn withheld_fees(harness: &mut Harness, address: &Pubkey) -> u64 {
+    let account = harness.account(address).await.unwrap();
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data).unwrap();
+    u64::from(state.get_extension::<TransferFeeAmount>().unwrap().withheld_amount)
+}
+
+#[tokio::test]
+async fn transfer_fee_escrows_close_after_a_fill_or_cancel() {
+    let mut harness = Harness::with_token_2022_mint(&[ExtensionType::TransferFeeConfig]).await;
+    harness.initialize_pool().await.unwrap();
+    let provider = harness.create_user().await;
+    harness.add_liquidity(&provider, RESERVE_X, RESERVE_Y).await.unwrap();
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+
+    let keys = harness.keys;
+    let (fee_mint, keeper_token_in) = if keys.token_x_program == spl_token_2022::ID {
+        (keys.token_x_mint, keeper.token_x)
+    } else {
+        (keys.token_y_mint, keeper.token_y)
+    };
+    for order_id in [1, 2] {
+        let ix = instructions::place_limit_order(&keys, owner.pubkey(), fee_mint, order_id, ORDER_AMOUNT, 1, None);
+        harness.send(&[ix], &[&owner.keypair]).await.unwrap();
+    }
+
+    // Placement withholds a fee in each escrow, which Token-2022 won't let an account close with
+    let escrows = [1, 2].map(|order_id| keys.limit_order_escrow(&owner.pubkey(), order_id, &fee_mint));
+    for escrow in &escrows {
+        assert_eq!(withheld_fees(&mut harness, escrow).await, ORDER_AMOUNT * TRANSFER_FEE_BPS as u64 / 10_000);
+    }
+
+    let ix = instructions::fill_limit_order(&keys, keeper.pubkey(), keeper_token_in, owner.pubkey(), fee_mint, 1);
+    harness.send(&[ix], &[&keeper.keypair]).await.unwrap();
+    let ix = instructions::cancel_limit_order(&keys, owner.pubkey(), fee_mint, 2);
+    harness.send(&[ix], &[&owner.keypair]).await.unwrap();
+
+    for (order_id, escrow) in [1, 2].into_iter().zip(escrows) {
+        assert!(harness.account(&keys.limit_order(&owner.pubkey(), order_id)).await.is_none());
+        assert!(harness.account(&escrow).await.is_none());
+    }
+    harness.assert_vaults_cover_reserves().await;
+}
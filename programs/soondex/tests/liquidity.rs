@@ -0,0 +1,213 @@
+mod common;
+
+use common::*;
+use soondex::{ErrorCode, MINIMUM_LIQUIDITY};
+use soondex_client::instructions::{self, LiquidityAccounts};
+
+#[tokio::test]
+async fn first_deposit_mints_geometric_mean_and_locks_minimum() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.token_x_reserve, pool.token_y_reserve), (1_000_000, 4_000_000));
+    assert_eq!(pool.lp_token_supply, 2_000_000);
+    assert_eq!(pool.lp_tokens.len(), 2);
+    assert_eq!(pool.lp_tokens[0].owner, provider.pubkey());
+    assert_eq!(pool.lp_tokens[0].amount, 2_000_000 - MINIMUM_LIQUIDITY);
+    assert_eq!(pool.lp_tokens[1].owner, harness.keys.locked_liquidity());
+    assert_eq!(pool.lp_tokens[1].amount, MINIMUM_LIQUIDITY);
+
+    assert_eq!(harness.token_balance(&provider.token_x).await, INITIAL_USER_TOKENS - 1_000_000);
+    assert_eq!(harness.token_balance(&provider.token_y).await, INITIAL_USER_TOKENS - 4_000_000);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn later_deposits_mint_proportional_shares() {
+    let (mut harness, _provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let second = harness.create_user().await;
+    harness.add_liquidity(&second, 500_000, 2_000_000).await.unwrap();
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!(pool.lp_token_supply, 3_000_000);
+    assert_eq!(pool.lp_tokens[2].owner, second.pubkey());
+    assert_eq!(pool.lp_tokens[2].amount, 1_000_000);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn repeat_deposits_grow_a_single_position() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    for _ in 0..3 {
+        harness.add_liquidity(&provider, 500_000, 2_000_000).await.unwrap();
+    }
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!(pool.lp_token_supply, 5_000_000);
+    assert_eq!(pool.lp_tokens.len(), 2);
+    assert_eq!(pool.lp_tokens[0].owner, provider.pubkey());
+    assert_eq!(pool.lp_tokens[0].amount, 5_000_000 - MINIMUM_LIQUIDITY);
+}
+
+#[tokio::test]
+async fn first_deposit_must_reach_the_pool_minimum() {
+    let mut harness = Harness::with_pool().await;
+    let provider = harness.create_user().await;
+    assert_eq!(harness.liquidity_pool().await.min_initial_liquidity, soondex::DEFAULT_MIN_INITIAL_LIQUIDITY);
+
+    // sqrt(1_000 * 4_000) = 2_000 LP tokens
+    assert_error(harness.add_liquidity(&provider, 1_000, 4_000).await, ErrorCode::InitialLiquidityTooLow);
+
+    let ix = instructions::set_min_initial_liquidity(&harness.keys, harness.payer(), 2_000);
+    harness.send(&[ix], &[]).await.unwrap();
+    harness.add_liquidity(&provider, 1_000, 4_000).await.unwrap();
+    assert_eq!(harness.liquidity_pool().await.lp_tokens[0].amount, 2_000 - MINIMUM_LIQUIDITY);
+}
+
+#[tokio::test]
+async fn first_deposit_must_exceed_the_locked_minimum() {
+    let mut harness = Harness::with_pool().await;
+    let provider = harness.create_user().await;
+    let ix = instructions::set_min_initial_liquidity(&harness.keys, harness.payer(), 0);
+    harness.send(&[ix], &[]).await.unwrap();
+
+    assert_error(
+        harness.add_liquidity(&provider, MINIMUM_LIQUIDITY, MINIMUM_LIQUIDITY).await,
+        ErrorCode::InitialLiquidityTooLow,
+    );
+    harness.add_liquidity(&provider, MINIMUM_LIQUIDITY + 1, MINIMUM_LIQUIDITY + 1).await.unwrap();
+    assert_eq!(harness.liquidity_pool().await.lp_tokens[0].amount, 1);
+}
+
+#[tokio::test]
+async fn liquidity_changes_fail_after_their_deadline() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let keys = harness.keys;
+    let expired = harness.clock().await.unix_timestamp - 1;
+
+    let ix = instructions::add_liquidity(&keys, provider.pubkey(), provider.liquidity_accounts(), 1_000, 4_000, Some(expired));
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::Expired);
+    let ix = instructions::remove_liquidity(&keys, provider.pubkey(), provider.liquidity_accounts(), 1_000, 4_000, Some(expired));
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::Expired);
+
+    let ix = instructions::add_liquidity(&keys, provider.pubkey(), provider.liquidity_accounts(), 1_000, 4_000, Some(expired + 60));
+    harness.send(&[ix], &[&provider.keypair]).await.unwrap();
+}
+
+#[tokio::test]
+async fn deposits_must_match_pool_ratio() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    assert_error(
+        harness.add_liquidity(&provider, 1_000_000, 1_000_000).await,
+        ErrorCode::InvalidTokenRatio,
+    );
+}
+
+#[tokio::test]
+async fn deposits_must_be_positive() {
+    let mut harness = Harness::with_pool().await;
+    let provider = harness.create_user().await;
+    assert_error(harness.add_liquidity(&provider, 0, 1_000).await, ErrorCode::InvalidLiquidityAmount);
+    assert_error(harness.add_liquidity(&provider, 1_000, 0).await, ErrorCode::InvalidLiquidityAmount);
+}
+
+#[tokio::test]
+async fn deposits_are_limited_by_user_balance() {
+    let mut harness = Harness::with_pool().await;
+    let provider = harness.create_user().await;
+    assert_error(
+        harness.add_liquidity(&provider, INITIAL_USER_TOKENS + 1, 1_000).await,
+        ErrorCode::InsufficientFunds,
+    );
+}
+
+#[tokio::test]
+async fn non_native_sides_need_token_accounts() {
+    let mut harness = Harness::with_pool().await;
+    let provider = harness.create_user().await;
+    let accounts = LiquidityAccounts {
+        user_token_x_account: None,
+        user_token_y_account: Some(provider.token_y),
+    };
+
+    let ix = instructions::add_liquidity(&harness.keys, provider.pubkey(), accounts, 1_000, 1_000, None);
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::MissingTokenAccount);
+    let ix = instructions::remove_liquidity(&harness.keys, provider.pubkey(), accounts, 1_000, 1_000, None);
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::MissingTokenAccount);
+}
+
+#[tokio::test]
+async fn withdrawals_burn_rounded_up_shares() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    harness.remove_liquidity(&provider, 250_000, 1_000_000).await.unwrap();
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.token_x_reserve, pool.token_y_reserve), (750_000, 3_000_000));
+    assert_eq!(pool.lp_token_supply, 1_500_000);
+    assert_eq!(pool.lp_tokens[0].amount, 1_500_000 - MINIMUM_LIQUIDITY);
+
+    // One unit of Y is worth half an LP token, so the burn rounds up to a whole one
+    harness.remove_liquidity(&provider, 0, 1).await.unwrap();
+    assert_eq!(harness.liquidity_pool().await.lp_token_supply, 1_499_999);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn full_withdrawal_leaves_the_locked_liquidity() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    assert_error(harness.remove_liquidity(&provider, 1_000_000, 4_000_000).await, ErrorCode::InsufficientFunds);
+
+    // The provider's 1_999_000 of 2_000_000 LP tokens
+    harness.remove_liquidity(&provider, 999_500, 3_998_000).await.unwrap();
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.token_x_reserve, pool.token_y_reserve, pool.lp_token_supply), (500, 2_000, MINIMUM_LIQUIDITY));
+    assert_eq!(pool.lp_tokens.len(), 1);
+    assert_eq!(pool.lp_tokens[0].owner, harness.keys.locked_liquidity());
+    assert_eq!(harness.token_balance(&provider.token_x).await, INITIAL_USER_TOKENS - 500);
+    assert_eq!(harness.token_balance(&provider.token_y).await, INITIAL_USER_TOKENS - 2_000);
+}
+
+#[tokio::test]
+async fn withdrawals_require_a_position() {
+    let (mut harness, _provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let outsider = harness.create_user().await;
+    assert_error(harness.remove_liquidity(&outsider, 1_000, 4_000).await, ErrorCode::NoLiquidity);
+}
+
+#[tokio::test]
+async fn withdrawals_are_limited_by_position() {
+    let (mut harness, _provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let second = harness.create_user().await;
+    harness.add_liquidity(&second, 100_000, 400_000).await.unwrap();
+
+    assert_error(harness.remove_liquidity(&second, 200_000, 800_000).await, ErrorCode::InsufficientFunds);
+}
+
+#[tokio::test]
+async fn withdrawals_are_limited_by_reserves() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    assert_error(harness.remove_liquidity(&provider, 1_000_001, 0).await, ErrorCode::InsufficientFunds);
+}
+
+#[tokio::test]
+async fn liquidity_quotes_match_execution() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let keys = harness.keys;
+
+    let add: soondex::AddLiquidityQuote = harness
+        .simulate(instructions::quote_add_liquidity(&keys, 300_000, 1_200_000))
+        .await
+        .unwrap();
+    let supply_before = harness.liquidity_pool().await.lp_token_supply;
+    harness.add_liquidity(&provider, 300_000, 1_200_000).await.unwrap();
+    assert_eq!(harness.liquidity_pool().await.lp_token_supply - supply_before, add.lp_tokens);
+
+    let remove: soondex::RemoveLiquidityQuote = harness
+        .simulate(instructions::quote_remove_liquidity(&keys, 333_333, 1_333_332))
+        .await
+        .unwrap();
+    let supply_before = harness.liquidity_pool().await.lp_token_supply;
+    harness.remove_liquidity(&provider, 333_333, 1_333_332).await.unwrap();
+    assert_eq!(supply_before - harness.liquidity_pool().await.lp_token_supply, remove.lp_tokens);
+}
@@ -0,0 +1,279 @@
+mod common;
+
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_program_test::BanksClientError;
+use solana_sdk::pubkey::Pubkey;
+use soondex::{ErrorCode, LiquidityPool, LpVault, VaultPosition};
+use soondex_client::{
+    instructions::{self, RewardAccounts},
+    PoolKeys,
+};
+use soondex_math::BPS_DENOMINATOR;
+
+// The first deposit mints sqrt(RESERVE_X * RESERVE_Y) and locks MINIMUM_LIQUIDITY of it
+const PROVIDER_LP: u64 = 2_000_000_000 - 1_000;
+const REWARD_RESERVE: u64 = 100_000_000_000;
+const REWARD_FUNDING: u64 = 1_000_000_000;
+const REWARD_RATE: u64 = 1;
+const DEPOSIT_LP: u64 = 100_000_000;
+const PERFORMANCE_FEE_BPS: u64 = 1_000;
+const MAX_SLIPPAGE_BPS: u64 = 100;
+const HARVEST_WAIT: i64 = 600;
+
+/// A funded X/Y pool paying rewards in a third mint, and a reward/X pool to sell them through
+struct VaultFixture {
+    harness: Harness,
+    provider: User,
+    reward_provider: User,
+    reward_keys: PoolKeys,
+    reward_mint: Pubkey,
+    rewards: RewardAccounts,
+    pool_reward_account: Pubkey,
+    fee_recipient: Pubkey,
+    fee_recipient_token: Pubkey,
+}
+
+impl VaultFixture {
+    async fn new() -> Self {
+        let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+        let keys = harness.keys;
+        let reward_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+        let reward_keys = PoolKeys::new(reward_mint, keys.token_x_mint, FEE_RATE);
+
+        // The harness fixtures act on `harness.keys`, so point them at the reward pool while seeding it
+        harness.keys = reward_keys;
+        harness.initialize_pool().await.unwrap();
+        let reward_provider = harness.create_user().await;
+        harness.add_liquidity(&reward_provider, REWARD_RESERVE, REWARD_RESERVE).await.unwrap();
+        harness.keys = keys;
+
+        let ix = instructions::set_reward_rate(&keys, harness.payer(), REWARD_RATE);
+        harness.send(&[ix], &[]).await.unwrap();
+        let rewards = harness.reward_accounts(&provider, &reward_mint, REWARD_FUNDING).await;
+        let fee_recipient = Pubkey::new_unique();
+        let fee_recipient_token = harness.create_token_account(&fee_recipient, &reward_mint, &spl_token::ID).await;
+
+        Self {
+            harness,
+            provider,
+            reward_provider,
+            reward_keys,
+            reward_mint,
+            rewards,
+            pool_reward_account: rewards.pool_reward_account,
+            fee_recipient,
+            fee_recipient_token,
+        }
+    }
+
+    /// Same as `new` with the vault created by the pool admin
+    async fn with_vault() -> Self {
+        let mut fixture = Self::new().await;
+        fixture.initialize(PERFORMANCE_FEE_BPS).await.unwrap();
+        fixture
+    }
+
+    async fn initialize(&mut self, performance_fee_bps: u64) -> Result<(), BanksClientError> {
+        let ix = instructions::initialize_lp_vault(
+            &self.harness.keys,
+            self.harness.payer(),
+            &self.reward_keys,
+            self.fee_recipient,
+            performance_fee_bps,
+            MAX_SLIPPAGE_BPS,
+        );
+        self.harness.send(&[ix], &[]).await
+    }
+
+    async fn deposit(&mut self, lp_amount: u64) -> Result<(), BanksClientError> {
+        let ix = instructions::deposit_lp_vault(&self.harness.keys, self.provider.pubkey(), lp_amount);
+        self.harness.send(&[ix], &[&self.provider.keypair]).await
+    }
+
+    async fn withdraw(&mut self, shares: u64) -> Result<(), BanksClientError> {
+        let ix = instructions::withdraw_lp_vault(&self.harness.keys, self.provider.pubkey(), self.rewards, shares);
+        self.harness.send(&[ix], &[&self.provider.keypair]).await
+    }
+
+    async fn harvest(&mut self) -> Result<(), BanksClientError> {
+        let keeper = self.harness.payer();
+        let ix =
+            instructions::harvest_lp_vault(&self.harness.keys, keeper, &self.reward_keys, self.fee_recipient_token);
+        self.harness.send(&[ix], &[]).await
+    }
+
+    async fn lp_vault(&mut self) -> LpVault {
+        let address = self.harness.keys.lp_vault();
+        self.harness.anchor_account(&address).await
+    }
+
+    async fn shares(&mut self) -> u64 {
+        let address = self.harness.keys.vault_position(&self.provider.pubkey());
+        self.harness.anchor_account::<VaultPosition>(&address).await.shares
+    }
+}
+
+/// Sum of `owner`'s LP positions in `pool`
+fn lp_balance(pool: &LiquidityPool, owner: &Pubkey) -> u64 {
+    pool.lp_tokens.iter().filter(|balance| balance.owner == *owner).map(|balance| balance.amount).sum()
+}
+
+#[tokio::test]
+async fn deposits_move_lp_positions_into_the_vault_for_shares() {
+    let mut fixture = VaultFixture::with_vault().await;
+    fixture.deposit(DEPOSIT_LP).await.unwrap();
+
+    let keys = fixture.harness.keys;
+    let lp_vault = fixture.lp_vault().await;
+    assert_eq!((lp_vault.pool, lp_vault.reward_pool), (keys.address(), fixture.reward_keys.address()));
+    assert_eq!((lp_vault.reward_mint, lp_vault.bridge_mint), (fixture.reward_mint, keys.token_x_mint));
+    assert_eq!(lp_vault.total_shares, DEPOSIT_LP);
+    assert_eq!(fixture.shares().await, DEPOSIT_LP);
+
+    let pool = fixture.harness.liquidity_pool().await;
+    assert_eq!(lp_balance(&pool, &fixture.provider.pubkey()), PROVIDER_LP - DEPOSIT_LP);
+    assert_eq!(lp_balance(&pool, &keys.lp_vault()), DEPOSIT_LP);
+    assert_eq!((pool.token_x_reserve, pool.token_y_reserve), (RESERVE_X, RESERVE_Y));
+
+    // A second deposit is priced against the vault's position
+    fixture.deposit(DEPOSIT_LP).await.unwrap();
+    assert_eq!(fixture.shares().await, 2 * DEPOSIT_LP);
+    assert_error(fixture.deposit(PROVIDER_LP).await, ErrorCode::InsufficientFunds);
+    assert_error(fixture.deposit(0).await, ErrorCode::InvalidLiquidityAmount);
+}
+
+#[tokio::test]
+async fn withdrawals_merge_into_the_owners_lp_position() {
+    let mut fixture = VaultFixture::with_vault().await;
+    fixture.deposit(DEPOSIT_LP).await.unwrap();
+    let keys = fixture.harness.keys;
+    let owner = fixture.provider.pubkey();
+    let pool = fixture.harness.liquidity_pool().await;
+    let claimed_at = pool.lp_tokens.iter().find(|balance| balance.owner == owner).unwrap().last_reward_claim;
+    fixture.harness.warp_seconds(HARVEST_WAIT).await;
+
+    fixture.withdraw(DEPOSIT_LP / 4).await.unwrap();
+    let pool = fixture.harness.liquidity_pool().await;
+    assert_eq!(lp_balance(&pool, &keys.lp_vault()), DEPOSIT_LP - DEPOSIT_LP / 4);
+    assert_eq!(fixture.shares().await, DEPOSIT_LP - DEPOSIT_LP / 4);
+
+    // The owner keeps a single position, and the rewards it earned were paid out before the merge
+    let positions: Vec<_> = pool.lp_tokens.iter().filter(|balance| balance.owner == owner).collect();
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].amount, PROVIDER_LP - DEPOSIT_LP + DEPOSIT_LP / 4);
+    let elapsed = positions[0].last_reward_claim - claimed_at;
+    assert!(elapsed >= HARVEST_WAIT);
+    let rewards = soondex_math::staking_rewards(PROVIDER_LP - DEPOSIT_LP, REWARD_RATE, elapsed).unwrap();
+    assert_eq!(fixture.harness.token_balance(&fixture.rewards.user_reward_account).await, rewards);
+
+    assert_error(fixture.withdraw(DEPOSIT_LP).await, ErrorCode::InsufficientFunds);
+    fixture.withdraw(DEPOSIT_LP - DEPOSIT_LP / 4).await.unwrap();
+    let pool = fixture.harness.liquidity_pool().await;
+    assert!(pool.lp_tokens.iter().all(|balance| balance.owner != keys.lp_vault()));
+    assert_eq!(pool.lp_tokens.iter().filter(|balance| balance.owner == owner).count(), 1);
+    assert_eq!(lp_balance(&pool, &owner), PROVIDER_LP);
+    assert_eq!(fixture.lp_vault().await.total_shares, 0);
+}
+
+#[tokio::test]
+async fn harvest_reinvests_rewards_after_the_performance_fee() {
+    let mut fixture = VaultFixture::with_vault().await;
+    fixture.deposit(DEPOSIT_LP).await.unwrap();
+    let keys = fixture.harness.keys;
+    let pool = fixture.harness.liquidity_pool().await;
+    let vault_balance = pool.lp_tokens.iter().find(|balance| balance.owner == keys.lp_vault()).unwrap();
+    let deposited_at = vault_balance.last_reward_claim;
+    let reward_pool_before: LiquidityPool = fixture.harness.anchor_account(&fixture.reward_keys.address()).await;
+
+    fixture.harness.warp_seconds(HARVEST_WAIT).await;
+    fixture.harvest().await.unwrap();
+
+    let lp_vault = fixture.lp_vault().await;
+    let elapsed = lp_vault.last_harvest - deposited_at;
+    assert!(elapsed >= HARVEST_WAIT);
+    let rewards = soondex_math::staking_rewards(DEPOSIT_LP, REWARD_RATE, elapsed).unwrap();
+    let performance_fee = rewards * PERFORMANCE_FEE_BPS / BPS_DENOMINATOR;
+    assert!(performance_fee > 0);
+    assert_eq!(lp_vault.pending_rewards, 0);
+    assert_eq!(fixture.harness.token_balance(&fixture.fee_recipient_token).await, performance_fee);
+    assert_eq!(fixture.harness.token_balance(&fixture.pool_reward_account).await, REWARD_FUNDING - rewards);
+
+    // The reward pool bought the rest of the rewards
+    let reward_pool: LiquidityPool = fixture.harness.anchor_account(&fixture.reward_keys.address()).await;
+    let (reward_reserve, reward_reserve_before) = if fixture.reward_keys.token_x_mint == fixture.reward_mint {
+        (reward_pool.token_x_reserve, reward_pool_before.token_x_reserve)
+    } else {
+        (reward_pool.token_y_reserve, reward_pool_before.token_y_reserve)
+    };
+    assert_eq!(reward_reserve, reward_reserve_before + rewards - performance_fee);
+
+    // X came in; the Y the half swap bought went straight back in as liquidity
+    let pool = fixture.harness.liquidity_pool().await;
+    assert!(pool.token_x_reserve > RESERVE_X);
+    assert_eq!(pool.token_y_reserve, RESERVE_Y);
+    let vault_lp = lp_balance(&pool, &keys.lp_vault());
+    assert!(vault_lp > DEPOSIT_LP);
+    assert_eq!(pool.lp_token_supply, PROVIDER_LP + 1_000 + vault_lp - DEPOSIT_LP);
+    assert_eq!(lp_vault.total_shares, DEPOSIT_LP);
+    fixture.harness.assert_vaults_cover_reserves().await;
+
+    // Shares now redeem for the compounded position
+    fixture.withdraw(DEPOSIT_LP).await.unwrap();
+    let pool = fixture.harness.liquidity_pool().await;
+    assert_eq!(lp_balance(&pool, &fixture.provider.pubkey()), PROVIDER_LP - DEPOSIT_LP + vault_lp);
+}
+
+#[tokio::test]
+async fn harvests_wait_for_the_interval_and_depositors() {
+    let mut fixture = VaultFixture::with_vault().await;
+    fixture.harness.warp_seconds(HARVEST_WAIT).await;
+    assert_error(fixture.harvest().await, ErrorCode::NoLiquidity);
+
+    fixture.deposit(DEPOSIT_LP).await.unwrap();
+    fixture.harness.warp_seconds(HARVEST_WAIT).await;
+    fixture.harvest().await.unwrap();
+    assert_error(fixture.harvest().await, ErrorCode::HarvestNotDue);
+
+    fixture.harness.warp_seconds(HARVEST_WAIT).await;
+    let pause = instructions::set_paused(&fixture.harness.keys, fixture.harness.payer(), true);
+    fixture.harness.send(&[pause], &[]).await.unwrap();
+    assert_error(fixture.harvest().await, ErrorCode::PoolPaused);
+    assert_error(fixture.deposit(DEPOSIT_LP).await, ErrorCode::PoolPaused);
+    fixture.withdraw(DEPOSIT_LP).await.unwrap();
+}
+
+#[tokio::test]
+async fn harvest_rejects_prices_far_from_the_twap() {
+    let mut fixture = VaultFixture::with_vault().await;
+    fixture.deposit(DEPOSIT_LP).await.unwrap();
+    fixture.harness.warp_seconds(HARVEST_WAIT).await;
+
+    // Dumping the reward token right before the harvest moves the spot price, not the TWAP
+    let keys = fixture.harness.keys;
+    fixture.harness.keys = fixture.reward_keys;
+    let sells_x = fixture.reward_keys.token_x_mint == fixture.reward_mint;
+    fixture.harness.swap(&fixture.reward_provider, sells_x, REWARD_RESERVE / 10, 1).await.unwrap();
+    fixture.harness.keys = keys;
+
+    assert_error(fixture.harvest().await, ErrorCode::HarvestSlippageExceeded);
+}
+
+#[tokio::test]
+async fn only_admins_create_vaults_with_a_valid_route() {
+    let mut fixture = VaultFixture::new().await;
+    assert_error(fixture.initialize(soondex::MAX_PERFORMANCE_FEE_RATE + 1).await, ErrorCode::InvalidLpVault);
+
+    // The pool itself cannot be the reward route: its reward mint would be a pool token
+    let keys = fixture.harness.keys;
+    let ix = instructions::initialize_lp_vault(&keys, fixture.harness.payer(), &keys, fixture.fee_recipient, 0, 0);
+    assert_error(fixture.harness.send(&[ix], &[]).await, ErrorCode::InvalidLpVault);
+
+    let outsider = fixture.provider.pubkey();
+    let ix = instructions::initialize_lp_vault(&keys, outsider, &fixture.reward_keys, outsider, 0, 0);
+    let result = fixture.harness.send(&[ix], &[&fixture.provider.keypair]).await;
+    assert_error(result, ErrorCode::Unauthorized);
+
+    fixture.initialize(PERFORMANCE_FEE_BPS).await.unwrap();
+    assert_eq!(fixture.lp_vault().await.fee_recipient, fixture.fee_recipient);
+}
@@ -0,0 +1,153 @@
+mod common;
+
+use anchor_spl::token::spl_token::{self, native_mint};
+use common::*;
+use solana_sdk::{
+    account::Account,
+    instruction::AccountMeta,
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+};
+use soondex::ErrorCode;
+use soondex_client::{
+    instructions::{self, LiquidityAccounts, SwapParams},
+    PoolKeys,
+};
+
+const SOL_RESERVE: u64 = 1_000_000_000;
+const TOKEN_RESERVE: u64 = 4_000_000_000;
+
+/// Pool pairing native SOL with a legacy mint, and a user who holds lamports and
+/// `INITIAL_USER_TOKENS` of the other mint but no WSOL account
+async fn native_pool() -> (Harness, Keypair, Pubkey) {
+    let mut context = program_test().start_with_context().await;
+    let rent = context.banks_client.get_rent().await.unwrap();
+    if context.banks_client.get_account(native_mint::ID).await.unwrap().is_none() {
+        let mut data = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 0,
+            decimals: native_mint::DECIMALS,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut data);
+        let account = Account {
+            lamports: rent.minimum_balance(data.len()),
+            data,
+            owner: spl_token::ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        context.set_account(&native_mint::ID, &account.into());
+    }
+
+    let token_mint = create_mint(&mut context, &spl_token::ID, &[]).await;
+    let mut harness = Harness::from_context(context, PoolKeys::new(native_mint::ID, token_mint, FEE_RATE));
+    harness.initialize_pool().await.unwrap();
+
+    let user = Keypair::new();
+    let fund = system_instruction::transfer(&harness.payer(), &user.pubkey(), INITIAL_USER_LAMPORTS);
+    harness.send(&[fund], &[]).await.unwrap();
+    let token_account = harness.create_token_account(&user.pubkey(), &token_mint, &spl_token::ID).await;
+    harness.mint_to(&token_mint, &token_account, INITIAL_USER_TOKENS).await;
+
+    (harness, user, token_account)
+}
+
+/// Liquidity accounts that pay the SOL side in lamports
+fn lamport_accounts(keys: &PoolKeys, token_account: Pubkey) -> LiquidityAccounts {
+    if keys.token_x_mint == native_mint::ID {
+        LiquidityAccounts { user_token_x_account: None, user_token_y_account: Some(token_account) }
+    } else {
+        LiquidityAccounts { user_token_x_account: Some(token_account), user_token_y_account: None }
+    }
+}
+
+/// (amount_x, amount_y) for `sol` lamports and `tokens` of the other mint
+fn ordered(keys: &PoolKeys, sol: u64, tokens: u64) -> (u64, u64) {
+    if keys.token_x_mint == native_mint::ID {
+        (sol, tokens)
+    } else {
+        (tokens, sol)
+    }
+}
+
+#[tokio::test]
+async fn liquidity_is_provided_and_withdrawn_in_lamports() {
+    let (mut harness, user, token_account) = native_pool().await;
+    let keys = harness.keys;
+    let accounts = lamport_accounts(&keys, token_account);
+    let (amount_x, amount_y) = ordered(&keys, SOL_RESERVE, TOKEN_RESERVE);
+
+    let lamports_before = harness.account(&user.pubkey()).await.unwrap().lamports;
+    let ix = instructions::add_liquidity(&keys, user.pubkey(), accounts, amount_x, amount_y, None);
+    harness.send(&[ix], &[&user]).await.unwrap();
+
+    let sol_vault = if keys.token_x_mint == native_mint::ID { keys.vault_x() } else { keys.vault_y() };
+    assert_eq!(harness.token_balance(&sol_vault).await, SOL_RESERVE);
+    // The payer covers transaction fees, and the temporary WSOL account's rent is refunded
+    assert_eq!(harness.account(&user.pubkey()).await.unwrap().lamports, lamports_before - SOL_RESERVE);
+    assert!(harness.account(&keys.temp_wsol_account(&user.pubkey())).await.is_none());
+
+    // The first deposit's locked minimum keeps a full withdrawal out of reach
+    let ix = instructions::remove_liquidity(&keys, user.pubkey(), accounts, amount_x / 2, amount_y / 2, None);
+    harness.send(&[ix], &[&user]).await.unwrap();
+    assert_eq!(harness.account(&user.pubkey()).await.unwrap().lamports, lamports_before - SOL_RESERVE / 2);
+    assert_eq!(harness.token_balance(&token_account).await, INITIAL_USER_TOKENS - TOKEN_RESERVE / 2);
+}
+
+#[tokio::test]
+async fn swap_pays_lamports_in() {
+    let (mut harness, user, token_account) = native_pool().await;
+    let keys = harness.keys;
+    let (amount_x, amount_y) = ordered(&keys, SOL_RESERVE, TOKEN_RESERVE);
+    let ix = instructions::add_liquidity(&keys, user.pubkey(), lamport_accounts(&keys, token_account), amount_x, amount_y, None);
+    harness.send(&[ix], &[&user]).await.unwrap();
+
+    let quote: soondex::SwapQuote = harness
+        .simulate(instructions::quote_swap(&keys, native_mint::ID, 10_000_000, 0))
+        .await
+        .unwrap();
+    let lamports_before = harness.account(&user.pubkey()).await.unwrap().lamports;
+    let tokens_before = harness.token_balance(&token_account).await;
+
+    let ix = instructions::swap_tokens(
+        &keys,
+        user.pubkey(),
+        SwapParams {
+            input_mint: native_mint::ID,
+            user_token_in: None,
+            user_token_out: Some(token_account),
+            amount_in: 10_000_000,
+            minimum_amount_out: quote.amount_out,
+            deadline: None,
+            referral: None,
+            transfer_hook_accounts: vec![],
+        },
+        None,
+    );
+    harness.send(&[ix], &[&user]).await.unwrap();
+
+    assert_eq!(harness.account(&user.pubkey()).await.unwrap().lamports, lamports_before - 10_000_000);
+    assert_eq!(harness.token_balance(&token_account).await, tokens_before + quote.amount_out);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn lamport_sides_need_temporary_wsol_account() {
+    let (mut harness, user, token_account) = native_pool().await;
+    let keys = harness.keys;
+    let (amount_x, amount_y) = ordered(&keys, SOL_RESERVE, TOKEN_RESERVE);
+
+    let mut ix = instructions::add_liquidity(&keys, user.pubkey(), lamport_accounts(&keys, token_account), amount_x, amount_y, None);
+    // Anchor reads the program id in an optional account slot as `None`
+    let temp_wsol = keys.temp_wsol_account(&user.pubkey());
+    let meta = ix.accounts.iter_mut().find(|meta| meta.pubkey == temp_wsol).unwrap();
+    *meta = AccountMeta::new_readonly(soondex::ID, false);
+
+    assert_error(harness.send(&[ix], &[&user]).await, ErrorCode::MissingWsolAccount);
+}
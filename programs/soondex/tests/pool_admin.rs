@@ -0,0 +1,236 @@
+mod common;
+
+use anchor_spl::token_2022::spl_token_2022::{self, extension::ExtensionType};
+use common::*;
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, signature::Keypair, signer::Signer, system_instruction};
+use soondex::{ErrorCode, PROTOCOL_FEE_LAMPORTS};
+use soondex_client::{instructions, pda, PoolKeys};
+
+#[tokio::test]
+async fn initialize_pool_registers_pool_and_charges_protocol_fee() {
+    let mut harness = Harness::new().await;
+    harness.initialize_pool().await.unwrap();
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!(pool.token_x_mint, harness.keys.token_x_mint);
+    assert_eq!(pool.token_y_mint, harness.keys.token_y_mint);
+    assert_eq!(pool.fee_rate, FEE_RATE);
+    assert_eq!(pool.super_admin, harness.payer());
+    assert_eq!(pool.admins, vec![harness.payer()]);
+    assert_eq!((pool.token_x_reserve, pool.token_y_reserve, pool.lp_token_supply), (0, 0, 0));
+    assert_eq!(pool.event_sequence, 1);
+
+    let protocol_wallet = harness.protocol_wallet;
+    assert_eq!(harness.account(&protocol_wallet).await.unwrap().lamports, PROTOCOL_FEE_LAMPORTS);
+
+    assert_eq!(harness.pool_count().await, 1);
+    let page_address = pda::pool_registry_page_address(0).0;
+    let page: soondex::PoolRegistryPage = harness.anchor_account(&page_address).await;
+    assert_eq!(page.pools, vec![harness.pool()]);
+}
+
+#[tokio::test]
+async fn initialize_pool_rejects_fee_above_maximum() {
+    let mut harness = Harness::new().await;
+    harness.keys.fee_rate = soondex::MAX_FEE_RATE + 1;
+    assert_error(harness.initialize_pool().await, ErrorCode::InvalidFeeRate);
+}
+
+#[tokio::test]
+async fn initialize_pool_rejects_fee_outside_the_tiers() {
+    let mut harness = Harness::new().await;
+    harness.keys.fee_rate = 26;
+    assert!(!soondex::FEE_TIERS.contains(&harness.keys.fee_rate));
+    assert_error(harness.initialize_pool().await, ErrorCode::InvalidFeeRate);
+}
+
+#[tokio::test]
+async fn initialize_pool_rejects_reversed_mints() {
+    let mut harness = Harness::new().await;
+    let keys = harness.keys;
+    harness.keys = PoolKeys {
+        token_x_mint: keys.token_y_mint,
+        token_y_mint: keys.token_x_mint,
+        ..keys
+    };
+    assert_error(harness.initialize_pool().await, ErrorCode::InvalidMintOrder);
+}
+
+#[tokio::test]
+async fn initialize_pool_requires_protocol_fee_balance() {
+    let mut harness = Harness::new().await;
+    let keys = harness.keys;
+    harness.create_token_account(&keys.address(), &keys.token_x_mint, &keys.token_x_program).await;
+    harness.create_token_account(&keys.address(), &keys.token_y_mint, &keys.token_y_program).await;
+
+    // Enough for the pool's rent but not for the protocol fee on top of it
+    let poor_payer = Keypair::new();
+    let fund = system_instruction::transfer(&harness.payer(), &poor_payer.pubkey(), LAMPORTS_PER_SOL / 10);
+    harness.send(&[fund], &[]).await.unwrap();
+
+    let protocol_wallet = harness.protocol_wallet;
+    let ix = instructions::initialize_pool(&keys, poor_payer.pubkey(), protocol_wallet, 0);
+    assert_error(harness.send(&[ix], &[&poor_payer]).await, ErrorCode::InsufficientFunds);
+}
+
+#[tokio::test]
+async fn initialize_pool_rejects_non_transferable_mint() {
+    let mut harness = Harness::new().await;
+    let bad_mint = create_mint(&mut harness.context, &spl_token_2022::ID, &[ExtensionType::NonTransferable]).await;
+    let keys = PoolKeys::new(bad_mint, harness.keys.token_x_mint, FEE_RATE)
+        .with_token_program(bad_mint, spl_token_2022::ID);
+    harness.keys = keys;
+
+    assert_error(harness.initialize_pool().await, ErrorCode::UnsupportedMintExtension);
+}
+
+#[tokio::test]
+async fn super_admin_manages_admin_list() {
+    let mut harness = Harness::with_pool().await;
+    let keys = harness.keys;
+    let super_admin = harness.payer();
+    let (first, second, third) = (Keypair::new().pubkey(), Keypair::new().pubkey(), Keypair::new().pubkey());
+
+    let add_first = instructions::manage_admin(&keys, super_admin, first, true);
+    harness.send(&[add_first.clone()], &[]).await.unwrap();
+    assert_error(harness.send(&[add_first], &[]).await, ErrorCode::AdminAlreadyExists);
+
+    harness.send(&[instructions::manage_admin(&keys, super_admin, second, true)], &[]).await.unwrap();
+    assert_eq!(harness.liquidity_pool().await.admins, vec![super_admin, first, second]);
+
+    assert_error(
+        harness.send(&[instructions::manage_admin(&keys, super_admin, third, true)], &[]).await,
+        ErrorCode::MaxAdminLimitReached,
+    );
+
+    harness.send(&[instructions::manage_admin(&keys, super_admin, first, false)], &[]).await.unwrap();
+    assert_eq!(harness.liquidity_pool().await.admins, vec![super_admin, second]);
+    assert_error(
+        harness.send(&[instructions::manage_admin(&keys, super_admin, first, false)], &[]).await,
+        ErrorCode::AdminDoesntExist,
+    );
+}
+
+#[tokio::test]
+async fn only_super_admin_manages_admins() {
+    let mut harness = Harness::with_pool().await;
+    let outsider = harness.create_user().await;
+    let ix = instructions::manage_admin(&harness.keys, outsider.pubkey(), outsider.pubkey(), true);
+    assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::Unauthorized);
+}
+
+#[tokio::test]
+async fn remove_pool_closes_empty_pool() {
+    let mut harness = Harness::with_pool().await;
+    let ix = instructions::remove_pool(&harness.keys, harness.payer(), 0);
+    harness.send(&[ix], &[]).await.unwrap();
+
+    let pool = harness.pool();
+    assert!(harness.account(&pool).await.is_none());
+}
+
+#[tokio::test]
+async fn reinitializing_a_removed_pool_lists_it_once() {
+    let mut harness = Harness::with_pool().await;
+    let ix = instructions::remove_pool(&harness.keys, harness.payer(), 0);
+    harness.send(&[ix], &[]).await.unwrap();
+
+    let page_address = pda::pool_registry_page_address(0).0;
+    let page: soondex::PoolRegistryPage = harness.anchor_account(&page_address).await;
+    assert!(page.pools.is_empty());
+
+    harness.initialize_pool().await.unwrap();
+    let page: soondex::PoolRegistryPage = harness.anchor_account(&page_address).await;
+    assert_eq!(page.pools, vec![harness.pool()]);
+    assert_eq!(harness.liquidity_pool().await.registry_page, 0);
+}
+
+#[tokio::test]
+async fn remove_pool_requires_authority() {
+    let mut harness = Harness::with_pool().await;
+    let outsider = harness.create_user().await;
+    let ix = instructions::remove_pool(&harness.keys, outsider.pubkey(), 0);
+    assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::Unauthorized);
+}
+
+#[tokio::test]
+async fn remove_pool_rejects_pool_with_liquidity() {
+    let (mut harness, _provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let ix = instructions::remove_pool(&harness.keys, harness.payer(), 0);
+    assert_error(harness.send(&[ix], &[]).await, ErrorCode::PoolNotEmpty);
+}
+
+#[tokio::test]
+async fn funded_pools_cannot_be_removed() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    // Everything but the locked MINIMUM_LIQUIDITY
+    harness.remove_liquidity(&provider, 999_500, 3_998_000).await.unwrap();
+    assert_eq!(harness.liquidity_pool().await.lp_token_supply, soondex::MINIMUM_LIQUIDITY);
+
+    let ix = instructions::remove_pool(&harness.keys, harness.payer(), 0);
+    assert_error(harness.send(&[ix], &[]).await, ErrorCode::PoolNotEmpty);
+    let pool = harness.pool();
+    assert!(harness.account(&pool).await.is_some());
+}
+
+#[tokio::test]
+async fn admins_update_pool_config() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000_000, 4_000_000_000).await;
+    let keys = harness.keys;
+    let admin = harness.create_user().await;
+    harness.send(&[instructions::manage_admin(&keys, harness.payer(), admin.pubkey(), true)], &[]).await.unwrap();
+    assert_eq!(harness.liquidity_pool().await.swap_fee_rate, FEE_RATE);
+
+    let ixs = [
+        instructions::set_swap_fee(&keys, admin.pubkey(), 10),
+        instructions::set_reward_rate(&keys, admin.pubkey(), 7),
+    ];
+    harness.send(&ixs, &[&admin.keypair]).await.unwrap();
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.swap_fee_rate, pool.reward_rate), (10, 7));
+
+    // Swaps are charged the updated fee
+    let quote: soondex::SwapQuote =
+        harness.simulate(instructions::quote_swap(&keys, keys.token_x_mint, 10_000_000, 0)).await.unwrap();
+    assert_eq!(quote.fee_amount, soondex_math::swap_fee(10_000_000, 10).unwrap());
+    harness.swap(&provider, true, 10_000_000, quote.amount_out).await.unwrap();
+}
+
+#[tokio::test]
+async fn pool_config_requires_admin() {
+    let mut harness = Harness::with_pool().await;
+    let keys = harness.keys;
+    let outsider = harness.create_user().await;
+
+    for ix in [
+        instructions::set_swap_fee(&keys, outsider.pubkey(), 10),
+        instructions::set_reward_rate(&keys, outsider.pubkey(), 10),
+        instructions::set_paused(&keys, outsider.pubkey(), true),
+        instructions::set_min_initial_liquidity(&keys, outsider.pubkey(), 0),
+    ] {
+        assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::Unauthorized);
+    }
+
+    // The fee tier caps the swap fee
+    let ix = instructions::set_swap_fee(&keys, harness.payer(), FEE_RATE + 1);
+    assert_error(harness.send(&[ix], &[]).await, ErrorCode::InvalidFeeRate);
+}
+
+#[tokio::test]
+async fn paused_pool_only_allows_withdrawals() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let keys = harness.keys;
+    harness.send(&[instructions::set_paused(&keys, harness.payer(), true)], &[]).await.unwrap();
+
+    assert_error(harness.swap(&provider, true, 10_000, 1).await, ErrorCode::PoolPaused);
+    assert_error(harness.add_liquidity(&provider, 1_000, 4_000).await, ErrorCode::PoolPaused);
+    let ixs = [
+        instructions::flash_borrow(&keys, provider.pubkey(), provider.token_x, provider.token_y, 1_000, 0),
+        instructions::flash_repay(&keys, provider.pubkey(), provider.token_x, provider.token_y),
+    ];
+    assert_error(harness.send(&ixs, &[&provider.keypair]).await, ErrorCode::PoolPaused);
+    harness.remove_liquidity(&provider, 1_000, 4_000).await.unwrap();
+
+    harness.send(&[instructions::set_paused(&keys, harness.payer(), false)], &[]).await.unwrap();
+    harness.swap(&provider, true, 10_000, 1).await.unwrap();
+}
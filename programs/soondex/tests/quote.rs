@@ -0,0 +1,75 @@
+mod common;
+
+use common::*;
+use soondex::{SwapQuote, TWAMM_ORDER_INTERVAL};
+use soondex_client::{instructions, quote};
+
+const AMOUNTS: [u64; 4] = [1_000, 123_457, 10_000_000, 500_000_000];
+
+#[tokio::test]
+async fn local_swap_quotes_match_the_program() {
+    let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    // Move off the fee tier and the initial price so neither can hide a mismatch
+    let ix = instructions::set_swap_fee(&harness.keys, harness.payer(), 10);
+    harness.send(&[ix], &[]).await.unwrap();
+    harness.swap(&provider, true, 37_000_000, 1).await.unwrap();
+
+    let keys = harness.keys;
+    let pool = harness.liquidity_pool().await;
+    for input_mint in [keys.token_x_mint, keys.token_y_mint] {
+        for amount in AMOUNTS {
+            let ix = instructions::quote_swap(&keys, input_mint, amount, 0);
+            let program: SwapQuote = harness.simulate(ix).await.unwrap();
+            assert_eq!(quote::swap(&pool, &input_mint, amount).unwrap(), program, "{amount} in");
+
+            let ix = instructions::quote_swap_exact_out(&keys, input_mint, amount);
+            let program: SwapQuote = harness.simulate(ix).await.unwrap();
+            assert_eq!(quote::swap_exact_out(&pool, &input_mint, amount).unwrap(), program, "{amount} out");
+        }
+    }
+}
+
+#[tokio::test]
+async fn local_quotes_match_twamm_pools_after_execute_twamm() {
+    let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let ix = instructions::initialize_twamm(&harness.keys, harness.payer());
+    harness.send(&[ix], &[]).await.unwrap();
+    harness.keys.twamm_enabled = true;
+    let keys = harness.keys;
+    let ix = instructions::place_long_term_order(&keys, provider.pubkey(), keys.token_x_mint, 1, 100_000_000, 2);
+    harness.send(&[ix], &[&provider.keypair]).await.unwrap();
+    harness.warp_seconds(TWAMM_ORDER_INTERVAL).await;
+
+    // Swaps settle pending long-term sales first, so the stored reserves are stale until then
+    let stale = quote::swap(&harness.liquidity_pool().await, &keys.token_x_mint, 10_000_000).unwrap();
+    let ix = instructions::execute_twamm(&keys, harness.payer());
+    harness.send(&[ix], &[]).await.unwrap();
+    let fresh = quote::swap(&harness.liquidity_pool().await, &keys.token_x_mint, 10_000_000).unwrap();
+    assert!(fresh.amount_out < stale.amount_out);
+
+    let trader = harness.create_user().await;
+    harness.swap(&trader, true, 10_000_000, fresh.amount_out).await.unwrap();
+    assert_eq!(harness.token_balance(&trader.token_y).await, INITIAL_USER_TOKENS + fresh.amount_out);
+}
+
+#[tokio::test]
+async fn program_quotes_settle_pending_long_term_orders() {
+    let (mut harness, provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let ix = instructions::initialize_twamm(&harness.keys, harness.payer());
+    harness.send(&[ix], &[]).await.unwrap();
+    harness.keys.twamm_enabled = true;
+    let keys = harness.keys;
+    let ix = instructions::place_long_term_order(&keys, provider.pubkey(), keys.token_x_mint, 1, 100_000_000, 2);
+    harness.send(&[ix], &[&provider.keypair]).await.unwrap();
+    let trader = harness.create_user().await;
+    harness.warp_seconds(TWAMM_ORDER_INTERVAL).await;
+
+    // The pool account still holds the reserves from before the pending sales
+    let stale = quote::swap(&harness.liquidity_pool().await, &keys.token_x_mint, 10_000_000).unwrap();
+    let ix = instructions::quote_swap(&keys, keys.token_x_mint, 10_000_000, 0);
+    let program: SwapQuote = harness.simulate(ix).await.unwrap();
+    assert!(program.amount_out < stale.amount_out);
+
+    harness.swap(&trader, true, 10_000_000, program.amount_out).await.unwrap();
+    assert_eq!(harness.token_balance(&trader.token_y).await, INITIAL_USER_TOKENS + program.amount_out);
+}
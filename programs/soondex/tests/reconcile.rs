@@ -0,0 +1,70 @@
+mod common;
+
+use common::*;
+use soondex::ErrorCode;
+use soondex_client::instructions;
+
+const DONATION: u64 = 5_000_000;
+
+#[tokio::test]
+async fn sync_adopts_donated_balances() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let caller = harness.create_user().await;
+    let (token_x_mint, vault_x) = (harness.keys.token_x_mint, harness.keys.vault_x());
+    harness.mint_to(&token_x_mint, &vault_x, DONATION).await;
+
+    let ix = instructions::sync(&harness.keys, caller.pubkey());
+    harness.send(&[ix], &[&caller.keypair]).await.unwrap();
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.token_x_reserve, pool.token_y_reserve), (RESERVE_X + DONATION, RESERVE_Y));
+    assert_eq!(pool.lp_token_supply, 2 * RESERVE_X);
+}
+
+#[tokio::test]
+async fn sync_requires_liquidity() {
+    let mut harness = Harness::with_pool().await;
+    let caller = harness.create_user().await;
+
+    let ix = instructions::sync(&harness.keys, caller.pubkey());
+    assert_error(harness.send(&[ix], &[&caller.keypair]).await, ErrorCode::NoLiquidity);
+}
+
+#[tokio::test]
+async fn skim_sends_the_excess_to_the_recipient() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let caller = harness.create_user().await;
+    let recipient = harness.create_user().await;
+    let (token_y_mint, vault_y) = (harness.keys.token_y_mint, harness.keys.vault_y());
+    harness.mint_to(&token_y_mint, &vault_y, DONATION).await;
+
+    let ix = instructions::skim(&harness.keys, caller.pubkey(), recipient.pubkey());
+    harness.send(&[ix], &[&caller.keypair]).await.unwrap();
+
+    assert_eq!(harness.token_balance(&recipient.token_x).await, INITIAL_USER_TOKENS);
+    assert_eq!(harness.token_balance(&recipient.token_y).await, INITIAL_USER_TOKENS + DONATION);
+    assert_eq!(harness.token_balance(&vault_y).await, RESERVE_Y);
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.token_x_reserve, pool.token_y_reserve), (RESERVE_X, RESERVE_Y));
+}
+
+#[tokio::test]
+async fn skim_without_excess_fails() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let caller = harness.create_user().await;
+
+    let ix = instructions::skim(&harness.keys, caller.pubkey(), caller.pubkey());
+    assert_error(harness.send(&[ix], &[&caller.keypair]).await, ErrorCode::NothingToSkim);
+}
+
+#[tokio::test]
+async fn staking_cannot_use_a_reserve_vault() {
+    let mut harness = Harness::with_pool().await;
+    let staker = harness.create_user().await;
+    let token_x_mint = harness.keys.token_x_mint;
+    let accounts = harness.stake_accounts(&staker, &token_x_mint).await;
+    assert_eq!(accounts.pool_token_account, harness.keys.vault_x());
+
+    let ix = instructions::stake(&harness.keys, staker.pubkey(), accounts, 1_000);
+    assert_error(harness.send(&[ix], &[&staker.keypair]).await, ErrorCode::ReserveVaultNotAllowed);
+}
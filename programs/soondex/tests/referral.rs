@@ -0,0 +1,115 @@
+mod common;
+
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_program_test::BanksClientError;
+use solana_sdk::pubkey::Pubkey;
+use soondex::{ErrorCode, SwapQuote};
+use soondex_client::instructions::{self, Referral, SwapParams};
+
+const AMOUNT_IN: u64 = 10_000_000;
+
+async fn set_max_referral_fee(harness: &mut Harness, max_referral_fee_bps: u64) {
+    let ix = instructions::set_max_referral_fee(&harness.keys, harness.payer(), max_referral_fee_bps);
+    harness.send(&[ix], &[]).await.unwrap();
+}
+
+/// Swaps `AMOUNT_IN` X for Y through `referrer_token`
+async fn referred_swap(
+    harness: &mut Harness,
+    trader: &User,
+    referrer_token: Pubkey,
+    fee_bps: u64,
+    minimum_amount_out: u64,
+) -> Result<(), BanksClientError> {
+    let ix = instructions::swap_tokens(
+        &harness.keys,
+        trader.pubkey(),
+        SwapParams {
+            input_mint: harness.keys.token_x_mint,
+            user_token_in: Some(trader.token_x),
+            user_token_out: Some(trader.token_y),
+            amount_in: AMOUNT_IN,
+            minimum_amount_out,
+            deadline: None,
+            referral: Some(Referral { token_account: referrer_token, fee_bps }),
+            transfer_hook_accounts: vec![],
+        },
+        None,
+    );
+    harness.send(&[ix], &[&trader.keypair]).await
+}
+
+#[tokio::test]
+async fn referrers_are_paid_out_of_the_input_on_top_of_the_swap_fee() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    let token_x_mint = harness.keys.token_x_mint;
+    let referrer_token = harness.create_token_account(&Pubkey::new_unique(), &token_x_mint, &spl_token::ID).await;
+    set_max_referral_fee(&mut harness, 50).await;
+
+    let referral_fee = soondex_math::swap_fee(AMOUNT_IN, 30).unwrap();
+    let ix = instructions::quote_swap(&harness.keys, token_x_mint, AMOUNT_IN, 30);
+    let quote: SwapQuote = harness.simulate(ix).await.unwrap();
+    assert_eq!((quote.amount_in, quote.amount_in_received), (AMOUNT_IN, AMOUNT_IN - referral_fee));
+    let trader_x = harness.token_balance(&trader.token_x).await;
+    let trader_y = harness.token_balance(&trader.token_y).await;
+
+    referred_swap(&mut harness, &trader, referrer_token, 30, quote.amount_out).await.unwrap();
+
+    assert_eq!(harness.token_balance(&referrer_token).await, referral_fee);
+    assert_eq!(harness.token_balance(&trader.token_x).await, trader_x - AMOUNT_IN);
+    assert_eq!(harness.token_balance(&trader.token_y).await, trader_y + quote.amount_out);
+    assert_eq!(harness.liquidity_pool().await.token_x_reserve, RESERVE_X + AMOUNT_IN - referral_fee);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn referral_fees_are_capped_by_the_pool() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    let token_x_mint = harness.keys.token_x_mint;
+    let referrer_token = harness.create_token_account(&Pubkey::new_unique(), &token_x_mint, &spl_token::ID).await;
+
+    // Referral fees start disabled, for quotes as for swaps
+    assert_eq!(harness.liquidity_pool().await.max_referral_fee_bps, 0);
+    let ix = instructions::quote_swap(&harness.keys, token_x_mint, AMOUNT_IN, 10);
+    assert_error(harness.simulate::<SwapQuote>(ix).await.map(drop), ErrorCode::ReferralFeeTooHigh);
+    assert_error(referred_swap(&mut harness, &trader, referrer_token, 10, 1).await, ErrorCode::ReferralFeeTooHigh);
+    referred_swap(&mut harness, &trader, referrer_token, 0, 1).await.unwrap();
+    assert_eq!(harness.token_balance(&referrer_token).await, 0);
+
+    set_max_referral_fee(&mut harness, 20).await;
+    assert_error(referred_swap(&mut harness, &trader, referrer_token, 21, 1).await, ErrorCode::ReferralFeeTooHigh);
+    referred_swap(&mut harness, &trader, referrer_token, 20, 1).await.unwrap();
+    assert_eq!(harness.token_balance(&referrer_token).await, soondex_math::swap_fee(AMOUNT_IN, 20).unwrap());
+}
+
+#[tokio::test]
+async fn referrers_are_paid_in_the_input_mint() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    let token_y_mint = harness.keys.token_y_mint;
+    let referrer_token = harness.create_token_account(&Pubkey::new_unique(), &token_y_mint, &spl_token::ID).await;
+    set_max_referral_fee(&mut harness, 50).await;
+
+    assert_error(
+        referred_swap(&mut harness, &trader, referrer_token, 30, 1).await,
+        ErrorCode::InvalidTokenAccountMint,
+    );
+}
+
+#[tokio::test]
+async fn referral_cap_is_set_by_admins_within_bounds() {
+    let mut harness = Harness::with_pool().await;
+    let outsider = harness.create_user().await;
+
+    let ix = instructions::set_max_referral_fee(&harness.keys, outsider.pubkey(), 100);
+    assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::Unauthorized);
+
+    let ix = instructions::set_max_referral_fee(&harness.keys, harness.payer(), soondex::MAX_REFERRAL_FEE_RATE + 1);
+    assert_error(harness.send(&[ix], &[]).await, ErrorCode::InvalidFeeRate);
+
+    set_max_referral_fee(&mut harness, soondex::MAX_REFERRAL_FEE_RATE).await;
+    assert_eq!(harness.liquidity_pool().await.max_referral_fee_bps, soondex::MAX_REFERRAL_FEE_RATE);
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_program_test::BanksClientError;
+use soondex::ErrorCode;
+use soondex_client::instructions::{self, StakeAccounts};
+
+const STAKE_AMOUNT: u64 = 1_000_000;
+const REWARD_RATE: u64 = 10;
+const WARP_SECONDS: i64 = 1_000;
+
+/// Pool with a separate stake mint; `staker` holds `INITIAL_USER_TOKENS` of it
+async fn staking_pool() -> (Harness, User, StakeAccounts) {
+    let mut harness = Harness::with_pool().await;
+    let staker = harness.create_user().await;
+    let stake_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+    let accounts = harness.stake_accounts(&staker, &stake_mint).await;
+    harness.mint_to(&stake_mint, &accounts.user_token_account, INITIAL_USER_TOKENS).await;
+    (harness, staker, accounts)
+}
+
+async fn stake(harness: &mut Harness, staker: &User, accounts: StakeAccounts, amount: u64) -> Result<(), BanksClientError> {
+    let ix = instructions::stake(&harness.keys, staker.pubkey(), accounts, amount);
+    harness.send(&[ix], &[&staker.keypair]).await
+}
+
+async fn unstake(harness: &mut Harness, staker: &User, accounts: StakeAccounts, amount: u64) -> Result<(), BanksClientError> {
+    let ix = instructions::unstake(&harness.keys, staker.pubkey(), accounts, amount);
+    harness.send(&[ix], &[&staker.keypair]).await
+}
+
+#[tokio::test]
+async fn stake_records_position_and_moves_tokens() {
+    let (mut harness, staker, accounts) = staking_pool().await;
+    stake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+
+    let user_state = harness.user_state(&staker).await;
+    assert_eq!(user_state.amount_staked, STAKE_AMOUNT);
+    assert_eq!(harness.liquidity_pool().await.total_staked, STAKE_AMOUNT);
+    assert_eq!(harness.token_balance(&accounts.pool_token_account).await, STAKE_AMOUNT);
+}
+
+#[tokio::test]
+async fn unstake_pays_rewards_for_elapsed_time() {
+    let (mut harness, staker, accounts) = staking_pool().await;
+    let ix = instructions::set_reward_rate(&harness.keys, harness.payer(), REWARD_RATE);
+    harness.send(&[ix], &[]).await.unwrap();
+    harness.mint_to(&accounts.token_mint, &accounts.pool_token_account, INITIAL_USER_TOKENS).await;
+    stake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+    let staked_at = harness.user_state(&staker).await.last_stake_timestamp;
+
+    harness.warp_seconds(WARP_SECONDS).await;
+    unstake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+
+    let user_state = harness.user_state(&staker).await;
+    let elapsed = user_state.last_stake_timestamp - staked_at;
+    assert!(elapsed >= WARP_SECONDS);
+    let rewards = soondex_math::staking_rewards(STAKE_AMOUNT, REWARD_RATE, elapsed).unwrap();
+    assert!(rewards > 0);
+
+    assert_eq!(user_state.amount_staked, 0);
+    assert_eq!(harness.liquidity_pool().await.total_staked, 0);
+    assert_eq!(harness.token_balance(&accounts.user_token_account).await, INITIAL_USER_TOKENS + rewards);
+}
+
+#[tokio::test]
+async fn partial_unstake_pays_rewards_on_the_whole_stake() {
+    let (mut harness, staker, accounts) = staking_pool().await;
+    let ix = instructions::set_reward_rate(&harness.keys, harness.payer(), REWARD_RATE);
+    harness.send(&[ix], &[]).await.unwrap();
+    harness.mint_to(&accounts.token_mint, &accounts.pool_token_account, INITIAL_USER_TOKENS).await;
+    stake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+    let first_staked_at = harness.user_state(&staker).await.last_stake_timestamp;
+
+    // Staking again carries the first stake's rewards forward
+    harness.warp_seconds(WARP_SECONDS).await;
+    stake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+    let user_state = harness.user_state(&staker).await;
+    let carried = soondex_math::staking_rewards(STAKE_AMOUNT, REWARD_RATE, user_state.last_stake_timestamp - first_staked_at).unwrap();
+    assert!(carried > 0);
+    assert_eq!(user_state.rewards_earned, carried);
+
+    harness.warp_seconds(WARP_SECONDS).await;
+    unstake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+    let unstaked = harness.user_state(&staker).await;
+    let elapsed = unstaked.last_stake_timestamp - user_state.last_stake_timestamp;
+    let rewards = carried + soondex_math::staking_rewards(2 * STAKE_AMOUNT, REWARD_RATE, elapsed).unwrap();
+    assert_eq!((unstaked.amount_staked, unstaked.rewards_earned), (STAKE_AMOUNT, 0));
+    assert_eq!(
+        harness.token_balance(&accounts.user_token_account).await,
+        INITIAL_USER_TOKENS - STAKE_AMOUNT + rewards,
+    );
+
+    // The tokens still staked earn from the partial unstake on
+    harness.warp_seconds(WARP_SECONDS).await;
+    unstake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+    let elapsed = harness.user_state(&staker).await.last_stake_timestamp - unstaked.last_stake_timestamp;
+    let remaining_rewards = soondex_math::staking_rewards(STAKE_AMOUNT, REWARD_RATE, elapsed).unwrap();
+    assert!(remaining_rewards > 0);
+    assert_eq!(
+        harness.token_balance(&accounts.user_token_account).await,
+        INITIAL_USER_TOKENS + rewards + remaining_rewards,
+    );
+}
+
+#[tokio::test]
+async fn rate_changes_only_apply_from_then_on() {
+    let (mut harness, staker, accounts) = staking_pool().await;
+    let ix = instructions::set_reward_rate(&harness.keys, harness.payer(), REWARD_RATE);
+    harness.send(&[ix], &[]).await.unwrap();
+    harness.mint_to(&accounts.token_mint, &accounts.pool_token_account, INITIAL_USER_TOKENS).await;
+    stake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+    let staked_at = harness.user_state(&staker).await.last_stake_timestamp;
+
+    // Stopping rewards keeps what the stake already earned and pays nothing after
+    harness.warp_seconds(WARP_SECONDS).await;
+    let ix = instructions::set_reward_rate(&harness.keys, harness.payer(), 0);
+    harness.send(&[ix], &[]).await.unwrap();
+    let stopped_at = harness.liquidity_pool().await.reward_cumulative_last_update;
+    harness.warp_seconds(WARP_SECONDS).await;
+    unstake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+
+    assert!(stopped_at - staked_at >= WARP_SECONDS);
+    let rewards = soondex_math::staking_rewards(STAKE_AMOUNT, REWARD_RATE, stopped_at - staked_at).unwrap();
+    assert!(rewards > 0);
+    assert_eq!(harness.token_balance(&accounts.user_token_account).await, INITIAL_USER_TOKENS + rewards);
+}
+
+#[tokio::test]
+async fn stake_amounts_must_be_positive() {
+    let (mut harness, staker, accounts) = staking_pool().await;
+    assert_error(stake(&mut harness, &staker, accounts, 0).await, ErrorCode::InvalidStakeAmount);
+    assert_error(unstake(&mut harness, &staker, accounts, 0).await, ErrorCode::InvalidStakeAmount);
+}
+
+#[tokio::test]
+async fn unstake_is_limited_by_stake() {
+    let (mut harness, staker, accounts) = staking_pool().await;
+    stake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+    assert_error(
+        unstake(&mut harness, &staker, accounts, STAKE_AMOUNT + 1).await,
+        ErrorCode::InsufficientStake,
+    );
+}
+
+#[tokio::test]
+async fn stake_total_overflow_is_rejected() {
+    let (mut harness, staker, accounts) = staking_pool().await;
+    stake(&mut harness, &staker, accounts, STAKE_AMOUNT).await.unwrap();
+    assert_error(stake(&mut harness, &staker, accounts, u64::MAX).await, ErrorCode::MathOverflow);
+}
+
+#[tokio::test]
+async fn claim_pays_liquidity_rewards_for_elapsed_time() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let reward_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+    let accounts = harness.reward_accounts(&provider, &reward_mint, INITIAL_USER_TOKENS).await;
+    let ix = instructions::set_reward_rate(&harness.keys, harness.payer(), REWARD_RATE);
+    harness.send(&[ix], &[]).await.unwrap();
+    let pool = harness.liquidity_pool().await;
+    let (position, rate_set_at) = (pool.lp_tokens[0].clone(), pool.reward_cumulative_last_update);
+
+    harness.warp_seconds(WARP_SECONDS).await;
+    let ix = instructions::claim_rewards(&harness.keys, provider.pubkey(), accounts);
+    harness.send(&[ix], &[&provider.keypair]).await.unwrap();
+
+    let claimed_at = harness.liquidity_pool().await.lp_tokens[0].last_reward_claim;
+    let rewards = soondex_math::staking_rewards(position.amount, REWARD_RATE, claimed_at - rate_set_at).unwrap();
+    assert!(rewards > 0);
+    assert_eq!(harness.token_balance(&accounts.user_reward_account).await, rewards);
+}
+
+#[tokio::test]
+async fn repeat_deposits_keep_unclaimed_liquidity_rewards() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let reward_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+    let accounts = harness.reward_accounts(&provider, &reward_mint, INITIAL_USER_TOKENS).await;
+    let ix = instructions::set_reward_rate(&harness.keys, harness.payer(), REWARD_RATE);
+    harness.send(&[ix], &[]).await.unwrap();
+    let pool = harness.liquidity_pool().await;
+    let (position, rate_set_at) = (pool.lp_tokens[0].clone(), pool.reward_cumulative_last_update);
+
+    harness.warp_seconds(WARP_SECONDS).await;
+    harness.add_liquidity(&provider, 1_000_000, 4_000_000).await.unwrap();
+    let deposited_at = harness.liquidity_pool().await.reward_cumulative_last_update;
+    let ix = instructions::claim_rewards(&harness.keys, provider.pubkey(), accounts);
+    harness.send(&[ix], &[&provider.keypair]).await.unwrap();
+
+    // The deposit joined the existing position without dropping what it had earned
+    assert_eq!(harness.liquidity_pool().await.lp_tokens.len(), 2);
+    let earned = soondex_math::staking_rewards(position.amount, REWARD_RATE, deposited_at - rate_set_at).unwrap();
+    assert!(earned > 0);
+    assert!(harness.token_balance(&accounts.user_reward_account).await >= earned);
+}
+
+#[tokio::test]
+async fn claim_requires_a_rate() {
+    let (mut harness, provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let reward_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+    let accounts = harness.reward_accounts(&provider, &reward_mint, INITIAL_USER_TOKENS).await;
+
+    harness.warp_seconds(WARP_SECONDS).await;
+    let ix = instructions::claim_rewards(&harness.keys, provider.pubkey(), accounts);
+    assert_error(harness.send(&[ix], &[&provider.keypair]).await, ErrorCode::NoRewardsAvailable);
+}
+
+#[tokio::test]
+async fn claim_requires_a_position() {
+    let (mut harness, _provider) = Harness::with_liquidity(1_000_000, 4_000_000).await;
+    let outsider = harness.create_user().await;
+    let reward_mint = create_mint(&mut harness.context, &spl_token::ID, &[]).await;
+    let accounts = harness.reward_accounts(&outsider, &reward_mint, INITIAL_USER_TOKENS).await;
+
+    let ix = instructions::claim_rewards(&harness.keys, outsider.pubkey(), accounts);
+    assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::NoLiquidity);
+}
@@ -0,0 +1,305 @@
+mod common;
+
+use anchor_lang::InstructionData;
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use soondex::{ErrorCode, SwapQuote};
+use soondex_client::{
+    instructions::{self, FlashSwapCallback, SwapParams},
+    PoolKeys,
+};
+
+async fn quote_swap(harness: &mut Harness, x_to_y: bool, amount_in: u64) -> SwapQuote {
+    let input_mint = if x_to_y { harness.keys.token_x_mint } else { harness.keys.token_y_mint };
+    let ix = instructions::quote_swap(&harness.keys, input_mint, amount_in, 0);
+    harness.simulate(ix).await.unwrap()
+}
+
+#[tokio::test]
+async fn fee_tiers_of_a_pair_price_swaps_differently() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let standard = quote_swap(&mut harness, true, 10_000_000).await;
+
+    // Same pair and reserves in the 1% tier
+    let keys = harness.keys;
+    harness.keys = PoolKeys::new(keys.token_x_mint, keys.token_y_mint, 100);
+    harness.initialize_pool().await.unwrap();
+    let provider = harness.create_user().await;
+    harness.add_liquidity(&provider, RESERVE_X, RESERVE_Y).await.unwrap();
+    let high_tier = quote_swap(&mut harness, true, 10_000_000).await;
+
+    assert_eq!(harness.liquidity_pool().await.swap_fee_rate, 100);
+    assert_eq!(high_tier.fee_amount, soondex_math::swap_fee(10_000_000, 100).unwrap());
+    assert!(high_tier.fee_amount > standard.fee_amount);
+    assert!(high_tier.amount_out < standard.amount_out);
+}
+
+#[tokio::test]
+async fn swap_pays_quoted_output_and_keeps_fee_in_pool() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let quote = quote_swap(&mut harness, true, 10_000_000).await;
+    assert_eq!(quote.fee_amount, soondex_math::swap_fee(10_000_000, FEE_RATE).unwrap());
+    harness.swap(&trader, true, 10_000_000, quote.amount_out).await.unwrap();
+
+    assert_eq!(harness.token_balance(&trader.token_x).await, INITIAL_USER_TOKENS - 10_000_000);
+    assert_eq!(harness.token_balance(&trader.token_y).await, INITIAL_USER_TOKENS + quote.amount_out);
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!(pool.token_x_reserve, RESERVE_X + 10_000_000);
+    assert_eq!(pool.token_y_reserve, RESERVE_Y - quote.amount_out);
+    assert!(pool.token_x_reserve as u128 * pool.token_y_reserve as u128 >= RESERVE_X as u128 * RESERVE_Y as u128);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn swaps_work_in_both_directions() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let quote = quote_swap(&mut harness, false, 40_000_000).await;
+    harness.swap(&trader, false, 40_000_000, quote.amount_out).await.unwrap();
+    assert_eq!(harness.token_balance(&trader.token_x).await, INITIAL_USER_TOKENS + quote.amount_out);
+
+    let pool = harness.liquidity_pool().await;
+    assert_eq!(pool.token_y_reserve, RESERVE_Y + 40_000_000);
+    assert_eq!(pool.token_x_reserve, RESERVE_X - quote.amount_out);
+    assert_eq!(pool.event_sequence, 3);
+}
+
+#[tokio::test]
+async fn exact_out_quote_delivers_requested_amount() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let ix = instructions::quote_swap_exact_out(&harness.keys, harness.keys.token_x_mint, 50_000_000);
+    let quote: SwapQuote = harness.simulate(ix).await.unwrap();
+    assert!(quote.amount_out >= 50_000_000);
+
+    harness.swap(&trader, true, quote.amount_in, 50_000_000).await.unwrap();
+    assert_eq!(harness.token_balance(&trader.token_y).await, INITIAL_USER_TOKENS + quote.amount_out);
+}
+
+#[tokio::test]
+async fn swap_enforces_minimum_output() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let quote = quote_swap(&mut harness, true, 10_000_000).await;
+    assert_error(
+        harness.swap(&trader, true, 10_000_000, quote.amount_out + 1).await,
+        ErrorCode::ExcessiveSlippage,
+    );
+}
+
+#[tokio::test]
+async fn swap_rejects_zero_amounts_and_dust() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    assert_error(harness.swap(&trader, true, 0, 1).await, ErrorCode::InvalidSwapInput);
+    assert_error(harness.swap(&trader, true, 1_000, 0).await, ErrorCode::InvalidSwapInput);
+    // One unit of Y buys less than one unit of X
+    assert_error(harness.swap(&trader, false, 1, 1).await, ErrorCode::InvalidSwapInput);
+}
+
+#[tokio::test]
+async fn swap_rejects_same_input_and_output() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let mut ix = swap_instruction(&harness, &trader, None);
+    ix.data = soondex::instruction::SwapTokens {
+        input_token: harness.keys.token_x_mint,
+        output_token: harness.keys.token_x_mint,
+        amount_in: 1_000,
+        minimum_amount_out: 1,
+        callback_data: None,
+        deadline: None,
+        referral_fee_bps: 0,
+        hook_account_count: 0,
+    }
+    .data();
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::InvalidTokenPair);
+}
+
+#[tokio::test]
+async fn swap_requires_output_account() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let ix = instructions::swap_tokens(
+        &harness.keys,
+        trader.pubkey(),
+        SwapParams {
+            input_mint: harness.keys.token_x_mint,
+            user_token_in: Some(trader.token_x),
+            user_token_out: None,
+            amount_in: 1_000_000,
+            minimum_amount_out: 1,
+            deadline: None,
+            referral: None,
+            transfer_hook_accounts: vec![],
+        },
+        None,
+    );
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::MissingTokenAccount);
+}
+
+#[tokio::test]
+async fn swap_fails_after_its_deadline() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    let now = harness.clock().await.unix_timestamp;
+    let swap = |deadline| {
+        instructions::swap_tokens(
+            &harness.keys,
+            trader.pubkey(),
+            SwapParams {
+                input_mint: harness.keys.token_x_mint,
+                user_token_in: Some(trader.token_x),
+                user_token_out: Some(trader.token_y),
+                amount_in: 1_000_000,
+                minimum_amount_out: 1,
+                deadline: Some(deadline),
+                referral: None,
+                transfer_hook_accounts: vec![],
+            },
+            None,
+        )
+    };
+    let (expired, current) = (swap(now - 1), swap(now));
+
+    assert_error(harness.send(&[expired], &[&trader.keypair]).await, ErrorCode::Expired);
+    harness.send(&[current], &[&trader.keypair]).await.unwrap();
+}
+
+#[tokio::test]
+async fn quotes_reject_foreign_mints() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let ix = instructions::quote_swap(&harness.keys, Pubkey::new_unique(), 1_000, 0);
+    assert_error(harness.simulate::<SwapQuote>(ix).await.map(drop), ErrorCode::InvalidToken);
+}
+
+/// Swaps 10_000_000 X for Y, optionally as a flash swap
+fn swap_instruction(harness: &Harness, trader: &User, callback: Option<FlashSwapCallback>) -> Instruction {
+    instructions::swap_tokens(
+        &harness.keys,
+        trader.pubkey(),
+        SwapParams {
+            input_mint: harness.keys.token_x_mint,
+            user_token_in: Some(trader.token_x),
+            user_token_out: Some(trader.token_y),
+            amount_in: 10_000_000,
+            minimum_amount_out: 1,
+            deadline: None,
+            referral: None,
+            transfer_hook_accounts: vec![],
+        },
+        callback,
+    )
+}
+
+/// Accounts the test receiver uses to pay the swap input from the trader's X account
+fn repay_accounts(harness: &Harness, trader: &User) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(trader.token_x, false),
+        AccountMeta::new(harness.keys.vault_x(), false),
+        AccountMeta::new_readonly(harness.keys.token_x_mint, false),
+        AccountMeta::new_readonly(trader.pubkey(), true),
+        AccountMeta::new_readonly(spl_token::ID, false),
+    ]
+}
+
+#[tokio::test]
+async fn flash_swap_pays_output_before_callback_repays_input() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    let quote = quote_swap(&mut harness, true, 10_000_000).await;
+
+    let callback = FlashSwapCallback {
+        program: FLASH_RECEIVER_ID,
+        data: vec![1],
+        accounts: repay_accounts(&harness, &trader),
+    };
+    let ix = swap_instruction(&harness, &trader, Some(callback));
+    harness.send(&[ix], &[&trader.keypair]).await.unwrap();
+
+    assert_eq!(harness.token_balance(&trader.token_x).await, INITIAL_USER_TOKENS - 10_000_000);
+    assert_eq!(harness.token_balance(&trader.token_y).await, INITIAL_USER_TOKENS + quote.amount_out);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn flash_swap_callback_gets_only_the_accounts_after_the_hook_accounts() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+    let quote = quote_swap(&mut harness, true, 10_000_000).await;
+
+    // The receiver reads its repay accounts by position, so a leaked hook account would break it
+    let callback = FlashSwapCallback {
+        program: FLASH_RECEIVER_ID,
+        data: vec![1],
+        accounts: repay_accounts(&harness, &trader),
+    };
+    let params = SwapParams {
+        input_mint: harness.keys.token_x_mint,
+        user_token_in: Some(trader.token_x),
+        user_token_out: Some(trader.token_y),
+        amount_in: 10_000_000,
+        minimum_amount_out: 1,
+        deadline: None,
+        referral: None,
+        transfer_hook_accounts: vec![AccountMeta::new_readonly(Pubkey::new_unique(), false)],
+    };
+    let ix = instructions::swap_tokens(&harness.keys, trader.pubkey(), params, Some(callback));
+    harness.send(&[ix], &[&trader.keypair]).await.unwrap();
+    assert_eq!(harness.token_balance(&trader.token_y).await, INITIAL_USER_TOKENS + quote.amount_out);
+
+    let mut ix = swap_instruction(&harness, &trader, None);
+    ix.data = soondex::instruction::SwapTokens {
+        input_token: harness.keys.token_x_mint,
+        output_token: harness.keys.token_y_mint,
+        amount_in: 1_000,
+        minimum_amount_out: 1,
+        callback_data: None,
+        deadline: None,
+        referral_fee_bps: 0,
+        hook_account_count: 1,
+    }
+    .data();
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::InvalidHookAccountCount);
+}
+
+#[tokio::test]
+async fn flash_swap_fails_when_callback_does_not_repay() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let callback = FlashSwapCallback {
+        program: FLASH_RECEIVER_ID,
+        data: vec![],
+        accounts: repay_accounts(&harness, &trader),
+    };
+    let ix = swap_instruction(&harness, &trader, Some(callback));
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::FlashSwapNotRepaid);
+}
+
+#[tokio::test]
+async fn flash_swap_requires_external_callback_program() {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let trader = harness.create_user().await;
+
+    let callback = FlashSwapCallback {
+        program: soondex::ID,
+        data: vec![1],
+        accounts: vec![],
+    };
+    let ix = swap_instruction(&harness, &trader, Some(callback));
+    assert_error(harness.send(&[ix], &[&trader.keypair]).await, ErrorCode::MissingCallbackProgram);
+}
@@ -0,0 +1,255 @@
+mod common;
+
+use common::*;
+use solana_program_test::BanksClientError;
+use solana_sdk::instruction::AccountMeta;
+use soondex::{ErrorCode, LongTermOrder, TwammState, TWAMM_MAX_INTERVALS, TWAMM_ORDER_INTERVAL};
+use soondex_client::{instructions, PoolKeys};
+
+const ORDER_ID: u64 = 3;
+const ORDER_AMOUNT: u64 = 10_000_000;
+
+/// A pool with liquidity and long-term orders enabled
+async fn twamm_pool() -> Harness {
+    let (mut harness, _provider) = Harness::with_liquidity(RESERVE_X, RESERVE_Y).await;
+    let ix = instructions::initialize_twamm(&harness.keys, harness.payer());
+    harness.send(&[ix], &[]).await.unwrap();
+    harness.keys.twamm_enabled = true;
+    harness
+}
+
+async fn place_order(
+    harness: &mut Harness,
+    owner: &User,
+    sell_x: bool,
+    amount_in: u64,
+    intervals: u64,
+) -> Result<(), BanksClientError> {
+    let input_mint = if sell_x { harness.keys.token_x_mint } else { harness.keys.token_y_mint };
+    let ix =
+        instructions::place_long_term_order(&harness.keys, owner.pubkey(), input_mint, ORDER_ID, amount_in, intervals);
+    harness.send(&[ix], &[&owner.keypair]).await
+}
+
+async fn withdraw_order(harness: &mut Harness, user: &User, owner: &User, sell_x: bool) -> Result<(), BanksClientError> {
+    let input_mint = if sell_x { harness.keys.token_x_mint } else { harness.keys.token_y_mint };
+    let ix =
+        instructions::withdraw_long_term_order(&harness.keys, user.pubkey(), owner.pubkey(), input_mint, ORDER_ID);
+    harness.send(&[ix], &[&user.keypair]).await
+}
+
+async fn order(harness: &mut Harness, owner: &User) -> LongTermOrder {
+    let keys = harness.keys;
+    harness.anchor_account(&keys.long_term_order(&owner.pubkey(), ORDER_ID)).await
+}
+
+#[tokio::test]
+async fn placing_an_order_sets_its_rate_and_expiry() {
+    let mut harness = twamm_pool().await;
+    let owner = harness.create_user().await;
+    let now = harness.clock().await.unix_timestamp;
+    place_order(&mut harness, &owner, true, ORDER_AMOUNT, 2).await.unwrap();
+
+    let order = order(&mut harness, &owner).await;
+    let expiry = (now / TWAMM_ORDER_INTERVAL + 2) * TWAMM_ORDER_INTERVAL;
+    assert_eq!((order.owner, order.order_id, order.expiry), (owner.pubkey(), ORDER_ID, expiry));
+    assert_eq!((order.input_mint, order.output_mint), (harness.keys.token_x_mint, harness.keys.token_y_mint));
+    assert_eq!(order.sell_rate, ORDER_AMOUNT as u128 * 1_000_000_000 / (expiry - now) as u128);
+
+    let keys = harness.keys;
+    let twamm: TwammState = harness.anchor_account(&keys.twamm()).await;
+    assert_eq!(twamm.sell_rate_x, order.sell_rate);
+    let pool = harness.liquidity_pool().await;
+    assert_eq!((pool.token_x_reserve, pool.twamm_token_x_balance), (RESERVE_X, ORDER_AMOUNT));
+    assert_eq!(harness.token_balance(&owner.token_x).await, INITIAL_USER_TOKENS - ORDER_AMOUNT);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn order_duration_is_bounded() {
+    let mut harness = twamm_pool().await;
+    let owner = harness.create_user().await;
+    assert_error(place_order(&mut harness, &owner, true, ORDER_AMOUNT, 0).await, ErrorCode::InvalidOrderDuration);
+    assert_error(place_order(&mut harness, &owner, true, ORDER_AMOUNT, 43).await, ErrorCode::InvalidOrderDuration);
+}
+
+#[tokio::test]
+async fn expiries_held_by_unwithdrawn_orders_are_unavailable() {
+    let mut harness = twamm_pool().await;
+    let owner = harness.create_user().await;
+    let later = harness.create_user().await;
+    place_order(&mut harness, &owner, true, ORDER_AMOUNT, 1).await.unwrap();
+    harness.warp_seconds(TWAMM_ORDER_INTERVAL).await;
+
+    // A full-length order now expires one ring length after the expired order
+    let intervals = TWAMM_MAX_INTERVALS as u64;
+    assert_error(
+        place_order(&mut harness, &later, true, ORDER_AMOUNT, intervals).await,
+        ErrorCode::TwammExpiryUnavailable,
+    );
+    withdraw_order(&mut harness, &owner, &owner, true).await.unwrap();
+    place_order(&mut harness, &later, true, ORDER_AMOUNT, intervals).await.unwrap();
+}
+
+#[tokio::test]
+async fn orders_are_only_managed_through_their_own_pool_and_owner() {
+    let mut harness = twamm_pool().await;
+    let owner = harness.create_user().await;
+    let outsider = harness.create_user().await;
+    place_order(&mut harness, &owner, true, ORDER_AMOUNT, 2).await.unwrap();
+    let keys = harness.keys;
+
+    // The outsider's own token accounts, but the owner's order
+    let mut ix =
+        instructions::withdraw_long_term_order(&keys, outsider.pubkey(), outsider.pubkey(), keys.token_x_mint, ORDER_ID);
+    ix.accounts[2] = AccountMeta::new(keys.long_term_order(&owner.pubkey(), ORDER_ID), false);
+    assert_error(harness.send(&[ix], &[&outsider.keypair]).await, ErrorCode::InvalidLongTermOrder);
+
+    // An order selling a mint this pool doesn't trade, placed on the pool that does
+    let other_mint = create_mint(&mut harness.context, &keys.token_y_program, &[]).await;
+    harness.keys = PoolKeys::new(keys.token_y_mint, other_mint, FEE_RATE);
+    harness.initialize_pool().await.unwrap();
+    let provider = harness.create_user().await;
+    harness.add_liquidity(&provider, RESERVE_X, RESERVE_Y).await.unwrap();
+    harness.send(&[instructions::initialize_twamm(&harness.keys, harness.payer())], &[]).await.unwrap();
+    harness.keys.twamm_enabled = true;
+    let other_keys = harness.keys;
+    let other_owner = harness.create_user().await;
+    let ix = instructions::place_long_term_order(&other_keys, other_owner.pubkey(), other_mint, ORDER_ID, ORDER_AMOUNT, 2);
+    harness.send(&[ix], &[&other_owner.keypair]).await.unwrap();
+
+    harness.keys = keys;
+    harness.create_token_account(&other_owner.pubkey(), &keys.token_x_mint, &keys.token_x_program).await;
+    let mut ix = instructions::withdraw_long_term_order(
+        &keys,
+        other_owner.pubkey(),
+        other_owner.pubkey(),
+        keys.token_y_mint,
+        ORDER_ID,
+    );
+    ix.accounts[2] = AccountMeta::new(other_keys.long_term_order(&other_owner.pubkey(), ORDER_ID), false);
+    assert_error(harness.send(&[ix], &[&other_owner.keypair]).await, ErrorCode::InvalidLongTermOrder);
+}
+
+#[tokio::test]
+async fn expired_orders_pay_out_their_proceeds_to_anyone_withdrawing() {
+    let mut harness = twamm_pool().await;
+    let owner = harness.create_user().await;
+    let keeper = harness.create_user().await;
+    place_order(&mut harness, &owner, true, ORDER_AMOUNT, 2).await.unwrap();
+
+    assert_error(withdraw_order(&mut harness, &keeper, &owner, true).await, ErrorCode::Unauthorized);
+    harness.warp_seconds(3 * TWAMM_ORDER_INTERVAL).await;
+    withdraw_order(&mut harness, &keeper, &owner, true).await.unwrap();
+
+    // The spot price pays 40M Y; fees and the order's own price impact take a little
+    let proceeds = harness.token_balance(&owner.token_y).await - INITIAL_USER_TOKENS;
+    assert!(proceeds > 39_000_000 && proceeds < 40_000_000, "{proceeds}");
+    assert_eq!(harness.token_balance(&keeper.token_y).await, INITIAL_USER_TOKENS);
+    let keys = harness.keys;
+    assert!(harness.account(&keys.long_term_order(&owner.pubkey(), ORDER_ID)).await.is_none());
+
+    let pool = harness.liquidity_pool().await;
+    assert!(pool.token_x_reserve > RESERVE_X && pool.token_x_reserve <= RESERVE_X + ORDER_AMOUNT);
+    assert!(pool.token_y_reserve < RESERVE_Y - proceeds + 1);
+    assert!(pool.twamm_token_x_balance < 10 && pool.twamm_token_y_balance < 10);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn owners_can_withdraw_proceeds_while_the_order_runs() {
+    let mut harness = twamm_pool().await;
+    let owner = harness.create_user().await;
+    place_order(&mut harness, &owner, true, ORDER_AMOUNT, 4).await.unwrap();
+
+    harness.warp_seconds(TWAMM_ORDER_INTERVAL).await;
+    withdraw_order(&mut harness, &owner, &owner, true).await.unwrap();
+    let first = harness.token_balance(&owner.token_y).await - INITIAL_USER_TOKENS;
+    assert!(first > 0);
+    assert!(order(&mut harness, &owner).await.earnings_factor_last > 0);
+
+    harness.warp_seconds(TWAMM_ORDER_INTERVAL).await;
+    withdraw_order(&mut harness, &owner, &owner, true).await.unwrap();
+    assert!(harness.token_balance(&owner.token_y).await - INITIAL_USER_TOKENS > first);
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn cancelling_refunds_the_unsold_input() {
+    let mut harness = twamm_pool().await;
+    let owner = harness.create_user().await;
+    let outsider = harness.create_user().await;
+    place_order(&mut harness, &owner, true, ORDER_AMOUNT, 4).await.unwrap();
+    let expiry = order(&mut harness, &owner).await.expiry;
+    harness.warp_seconds(TWAMM_ORDER_INTERVAL).await;
+
+    let ix = instructions::cancel_long_term_order(&harness.keys, owner.pubkey(), harness.keys.token_x_mint, ORDER_ID);
+    let mut outsider_ix = ix.clone();
+    outsider_ix.accounts[3] = AccountMeta::new_readonly(outsider.pubkey(), true);
+    assert_error(harness.send(&[outsider_ix], &[&outsider.keypair]).await, ErrorCode::Unauthorized);
+    harness.send(&[ix], &[&owner.keypair]).await.unwrap();
+
+    // Roughly the share of the order's duration left is refunded, the rest was sold
+    let now = harness.clock().await.unix_timestamp;
+    let refunded = harness.token_balance(&owner.token_x).await - (INITIAL_USER_TOKENS - ORDER_AMOUNT);
+    assert!(refunded < ORDER_AMOUNT && refunded > 0);
+    let expected = ORDER_AMOUNT * (expiry - now) as u64 / (4 * TWAMM_ORDER_INTERVAL) as u64;
+    assert!(refunded.abs_diff(expected) < ORDER_AMOUNT / 4);
+    assert!(harness.token_balance(&owner.token_y).await > INITIAL_USER_TOKENS);
+
+    let keys = harness.keys;
+    let twamm: TwammState = harness.anchor_account(&keys.twamm()).await;
+    assert_eq!(twamm.sell_rate_x, 0);
+    assert!(harness.account(&keys.long_term_order(&owner.pubkey(), ORDER_ID)).await.is_none());
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn opposing_orders_trade_with_each_other() {
+    let mut harness = twamm_pool().await;
+    let seller_x = harness.create_user().await;
+    let seller_y = harness.create_user().await;
+    place_order(&mut harness, &seller_x, true, ORDER_AMOUNT, 1).await.unwrap();
+    place_order(&mut harness, &seller_y, false, 4 * ORDER_AMOUNT, 1).await.unwrap();
+
+    harness.warp_seconds(2 * TWAMM_ORDER_INTERVAL).await;
+    withdraw_order(&mut harness, &seller_x, &seller_x, true).await.unwrap();
+    withdraw_order(&mut harness, &seller_y, &seller_y, false).await.unwrap();
+
+    // Matched flows don't pay the pool's fee or move its price much
+    let proceeds_y = harness.token_balance(&seller_x.token_y).await - INITIAL_USER_TOKENS;
+    let proceeds_x = harness.token_balance(&seller_y.token_x).await - INITIAL_USER_TOKENS;
+    assert!(proceeds_y > 4 * ORDER_AMOUNT * 99 / 100, "{proceeds_y}");
+    assert!(proceeds_x > ORDER_AMOUNT * 99 / 100, "{proceeds_x}");
+    harness.assert_vaults_cover_reserves().await;
+}
+
+#[tokio::test]
+async fn pools_with_long_term_orders_require_their_state() {
+    let mut harness = twamm_pool().await;
+    let trader = harness.create_user().await;
+    harness.keys.twamm_enabled = false;
+    assert_error(harness.swap(&trader, true, 1_000_000, 1).await, ErrorCode::MissingTwammState);
+
+    harness.keys.twamm_enabled = true;
+    harness.swap(&trader, true, 1_000_000, 1).await.unwrap();
+}
+
+#[tokio::test]
+async fn swaps_settle_pending_orders_first() {
+    let mut harness = twamm_pool().await;
+    let owner = harness.create_user().await;
+    let trader = harness.create_user().await;
+    place_order(&mut harness, &owner, true, ORDER_AMOUNT, 1).await.unwrap();
+    harness.warp_seconds(2 * TWAMM_ORDER_INTERVAL).await;
+
+    harness.swap(&trader, false, 1_000_000, 1).await.unwrap();
+    let pool = harness.liquidity_pool().await;
+    // The order sold its X before the swap bought some back
+    assert!(pool.token_x_reserve > RESERVE_X);
+    assert!(pool.twamm_token_y_balance > 0);
+    let keys = harness.keys;
+    let twamm: TwammState = harness.anchor_account(&keys.twamm()).await;
+    assert_eq!(twamm.sell_rate_x, 0);
+    assert_eq!(twamm.last_virtual_order_time, harness.clock().await.unix_timestamp);
+}
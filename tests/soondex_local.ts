@@ -4,6 +4,11 @@ import { Soondex } from "../target/types/soondex";
 import { assert } from "chai";
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ExtensionType,
+  getMintLen,
+  createInitializeMintInstruction,
+  createInitializeNonTransferableMintInstruction,
   createMint,
   getAssociatedTokenAddress,
   ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -12,7 +17,13 @@ import {
   mintTo,
   getAccount,
 } from "@solana/spl-token";
-import { Keypair, SystemProgram, PublicKey } from "@solana/web3.js";
+import {
+  Keypair,
+  SystemProgram,
+  PublicKey,
+  Transaction,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+} from "@solana/web3.js";
 
 describe("Soondex DEX", () => {
   const provider = anchor.AnchorProvider.env();
@@ -32,6 +43,28 @@ describe("Soondex DEX", () => {
     return userStatePDA;
 };
 
+  const FEE_RATE = new anchor.BN(25);
+
+  const getPoolAddress = (mintX: PublicKey, mintY: PublicKey, feeRate: anchor.BN): [PublicKey, number] =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintX.toBuffer(), mintY.toBuffer(), feeRate.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+  const getPoolRegistryAddresses = async (): Promise<{ poolRegistry: PublicKey; poolRegistryPage: PublicKey }> => {
+    const [poolRegistry] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_registry")],
+      program.programId
+    );
+    const registry = await program.account.poolRegistry.fetchNullable(poolRegistry);
+    const page = registry ? registry.poolCount.divn(100) : new anchor.BN(0);
+    const [poolRegistryPage] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_registry"), page.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    return { poolRegistry, poolRegistryPage };
+  };
+
   let tokenXMint: PublicKey;
   let tokenYMint: PublicKey;
   let liquidityPoolPDA: PublicKey;
@@ -77,15 +110,16 @@ describe("Soondex DEX", () => {
       null,
       9
     );
+    // Pools require canonical mint ordering
+    if (Buffer.compare(tokenXMint.toBuffer(), tokenYMint.toBuffer()) > 0) {
+      [tokenXMint, tokenYMint] = [tokenYMint, tokenXMint];
+    }
     console.log("✓ Created token mints:", {
       tokenX: tokenXMint.toString(),
       tokenY: tokenYMint.toString()
     });
 
-    [liquidityPoolPDA, bump] = PublicKey.findProgramAddressSync(
-      [Buffer.from("pool"), tokenXMint.toBuffer(), tokenYMint.toBuffer()],
-      program.programId
-    );
+    [liquidityPoolPDA, bump] = getPoolAddress(tokenXMint, tokenYMint, FEE_RATE);
     console.log("✓ Generated liquidity pool PDA:", liquidityPoolPDA.toString());
 
     poolTokenXAccount = await getAssociatedTokenAddress(tokenXMint, liquidityPoolPDA, true);
@@ -149,15 +183,16 @@ describe("Soondex DEX", () => {
       .initializePool(
         tokenXMint,
         tokenYMint,
-        new anchor.BN(25),
+        FEE_RATE,
 
       )
       .accountsStrict({
         liquidityPool: liquidityPoolPDA,
+        ...(await getPoolRegistryAddresses()),
         payer: wallet.publicKey,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
-        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
         tokenXMint,
         tokenYMint,
         poolTokenXAccount,
@@ -194,7 +229,8 @@ describe("Soondex DEX", () => {
         tokenXMint,
         tokenYMint,
         params.amountX,
-        params.amountY
+        params.amountY,
+        null
       )
       .accountsStrict({
         tokenXMint,
@@ -205,11 +241,24 @@ describe("Soondex DEX", () => {
         userTokenYAccount: userTokenYAccount,
         poolTokenXAccount: poolTokenXAccount,
         poolTokenYAccount: poolTokenYAccount,
-        tokenProgram: TOKEN_PROGRAM_ID
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tempWsolAccount: null,
+        systemProgram: SystemProgram.programId,
+        twamm: null
       })
       .rpc();
     
     console.log("Add Liquidity TX:", tx);
+
+    // The first deposit locks MINIMUM_LIQUIDITY LP tokens for good
+    const [lockedLiquidity] = PublicKey.findProgramAddressSync(
+      [Buffer.from("locked_liquidity"), liquidityPoolPDA.toBuffer()],
+      program.programId
+    );
+    const pool = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+    const locked = pool.lpTokens.find(balance => balance.owner.equals(lockedLiquidity));
+    assert.equal(locked.amount.toNumber(), 1_000);
     console.log("✓ Initial liquidity added successfully");
 });
 
@@ -228,7 +277,11 @@ describe("Soondex DEX", () => {
         params.inputToken,
         params.outputToken,
         params.amountIn,
-        params.minimumAmountOut
+        params.minimumAmountOut,
+        null,
+        null,
+        new anchor.BN(0),
+        0
       )
       .accountsStrict({
         liquidityPool: liquidityPoolPDA,
@@ -237,12 +290,16 @@ describe("Soondex DEX", () => {
         userTokenOut: userTokenYAccount,
         poolTokenX: poolTokenXAccount,
         poolTokenY: poolTokenYAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
         tokenXMint,
         tokenYMint,
-        wsolAccount: null,
+        tempWsolAccount: null,
         systemProgram: SystemProgram.programId,
-        nativeMint: NATIVE_MINT
+        nativeMint: NATIVE_MINT,
+        callbackProgram: null,
+        twamm: null,
+        referrerToken: null
       })
       .signers([wallet.payer])
       .rpc();
@@ -251,6 +308,121 @@ describe("Soondex DEX", () => {
     console.log("✓ Swap executed successfully");
 });
 
+  it("Swap event carries pool state", async () => {
+    console.log("\n=== Checking Swap Event ===");
+
+    const poolBefore = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+
+    const tx = await program.methods
+      .swapTokens(tokenXMint, tokenYMint, new anchor.BN(1_000_000), new anchor.BN(1), null, null, new anchor.BN(0), 0)
+      .accountsStrict({
+        liquidityPool: liquidityPoolPDA,
+        user: wallet.publicKey,
+        userTokenIn: userTokenXAccount,
+        userTokenOut: userTokenYAccount,
+        poolTokenX: poolTokenXAccount,
+        poolTokenY: poolTokenYAccount,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tokenXMint,
+        tokenYMint,
+        tempWsolAccount: null,
+        systemProgram: SystemProgram.programId,
+        nativeMint: NATIVE_MINT,
+        callbackProgram: null,
+        twamm: null,
+        referrerToken: null
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const txDetails = await provider.connection.getTransaction(tx, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const eventParser = new anchor.EventParser(program.programId, program.coder);
+    const events = [...eventParser.parseLogs(txDetails.meta.logMessages)];
+    const swapEvent = events.find((event) => event.name === "tokensSwapped");
+    assert.ok(swapEvent, "Swap event not emitted");
+
+    const poolAfter = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+    assert.ok(swapEvent.data.pool.equals(liquidityPoolPDA));
+    assert.ok(swapEvent.data.user.equals(wallet.publicKey));
+    assert.ok(swapEvent.data.inputMint.equals(tokenXMint));
+    assert.ok(swapEvent.data.outputMint.equals(tokenYMint));
+    assert.ok(swapEvent.data.feeAmount.gtn(0));
+    assert.ok(swapEvent.data.tokenXReserve.eq(poolAfter.tokenXReserve));
+    assert.ok(swapEvent.data.tokenYReserve.eq(poolAfter.tokenYReserve));
+    assert.ok(swapEvent.data.sequence.eq(poolBefore.eventSequence.addn(1)));
+
+    console.log("✓ Swap event carries pool state");
+});
+
+  it("Quote instructions match execution", async () => {
+    console.log("\n=== Checking Quotes ===");
+
+    const quoteAccounts = { liquidityPool: liquidityPoolPDA, tokenXMint, tokenYMint, twamm: null };
+    const amountIn = new anchor.BN(2_000_000);
+
+    const poolBefore = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+    const quote = await program.methods
+      .quoteSwap(tokenXMint, amountIn, new anchor.BN(0))
+      .accountsStrict(quoteAccounts)
+      .view();
+    const exactOutQuote = await program.methods
+      .quoteSwapExactOut(tokenXMint, quote.amountOutReceived)
+      .accountsStrict(quoteAccounts)
+      .view();
+    assert.ok(exactOutQuote.amountIn.lte(amountIn));
+    assert.ok(exactOutQuote.amountOutReceived.gte(quote.amountOutReceived));
+
+    // Quotes must not touch pool state
+    const poolAfterQuote = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+    assert.ok(poolAfterQuote.tokenXReserve.eq(poolBefore.tokenXReserve));
+    assert.ok(poolAfterQuote.eventSequence.eq(poolBefore.eventSequence));
+
+    const balanceBefore = await getAccount(provider.connection, userTokenYAccount);
+    await program.methods
+      .swapTokens(tokenXMint, tokenYMint, amountIn, quote.amountOutReceived, null, null, new anchor.BN(0), 0)
+      .accountsStrict({
+        liquidityPool: liquidityPoolPDA,
+        user: wallet.publicKey,
+        userTokenIn: userTokenXAccount,
+        userTokenOut: userTokenYAccount,
+        poolTokenX: poolTokenXAccount,
+        poolTokenY: poolTokenYAccount,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tokenXMint,
+        tokenYMint,
+        tempWsolAccount: null,
+        systemProgram: SystemProgram.programId,
+        nativeMint: NATIVE_MINT,
+        callbackProgram: null,
+        twamm: null,
+        referrerToken: null
+      })
+      .rpc();
+    const balanceAfter = await getAccount(provider.connection, userTokenYAccount);
+    assert.equal(
+      (balanceAfter.amount - balanceBefore.amount).toString(),
+      quote.amountOutReceived.toString()
+    );
+
+    const addQuote = await program.methods
+      .quoteAddLiquidity(new anchor.BN(1_000_000), new anchor.BN(1_000_000))
+      .accountsStrict(quoteAccounts)
+      .view();
+    assert.ok(addQuote.lpTokens.gtn(0));
+
+    const removeQuote = await program.methods
+      .quoteRemoveLiquidity(new anchor.BN(1_000_000), new anchor.BN(1_000_000))
+      .accountsStrict(quoteAccounts)
+      .view();
+    assert.ok(removeQuote.lpTokens.gtn(0));
+
+    console.log("✓ Quotes match execution");
+});
+
 it("SOL/Token Swap", async () => {
   console.log("\n=== Testing SOL/Token Swap ===");
   
@@ -270,7 +442,11 @@ it("SOL/Token Swap", async () => {
       NATIVE_MINT,
       tokenYMint,
       solAmount,
-      minTokenOut
+      minTokenOut,
+      null,
+      null,
+      new anchor.BN(0),
+      0
     )
     .accountsStrict({
       liquidityPool: liquidityPoolPDA,
@@ -279,12 +455,16 @@ it("SOL/Token Swap", async () => {
       userTokenOut: userTokenYAccount,
       poolTokenX: poolTokenXAccount,
       poolTokenY: poolTokenYAccount,
-      tokenProgram: TOKEN_PROGRAM_ID,
+      tokenXProgram: TOKEN_PROGRAM_ID,
+      tokenYProgram: TOKEN_PROGRAM_ID,
       tokenXMint,
       tokenYMint,
-      wsolAccount: null,
+      tempWsolAccount: null,
       systemProgram: SystemProgram.programId,
-      nativeMint: NATIVE_MINT
+      nativeMint: NATIVE_MINT,
+      callbackProgram: null,
+      twamm: null,
+      referrerToken: null
     })
 
   // Get final balances
@@ -310,7 +490,8 @@ it("Remove Liquidity", async () => {
       tokenXMint,
       tokenYMint,
       amount,
-      amount
+      amount,
+      null
     )
     .accountsStrict({
       liquidityPool: liquidityPoolPDA,
@@ -319,19 +500,60 @@ it("Remove Liquidity", async () => {
       userTokenYAccount,
       poolTokenXAccount,
       poolTokenYAccount,
-      tokenProgram: TOKEN_PROGRAM_ID,
+      tokenXProgram: TOKEN_PROGRAM_ID,
+      tokenYProgram: TOKEN_PROGRAM_ID,
       tokenXMint,
-      tokenYMint
+      tokenYMint,
+      tempWsolAccount: null,
+      systemProgram: SystemProgram.programId,
+      twamm: null
     })
     .rpc();
   
   console.log("Remove Liquidity TX:", tx);
 });
 
+let stakeMint: PublicKey;
+let userStakeAccount: PublicKey;
+let poolStakeAccount: PublicKey;
+
 it("Stake Tokens", async () => {
   console.log("\n=== Staking Tokens ===");
   const stakeAmount = new anchor.BN(20_000_000);
-  
+
+  // Stake vaults cannot be the pool's reserve vaults, so stake a separate mint
+  stakeMint = await createMint(
+    provider.connection,
+    mintAuthority,
+    mintAuthority.publicKey,
+    null,
+    9
+  );
+  userStakeAccount = await createAssociatedTokenAccount(
+    provider.connection,
+    wallet.payer,
+    stakeMint,
+    wallet.publicKey
+  );
+  poolStakeAccount = await createAssociatedTokenAccount(
+    provider.connection,
+    wallet.payer,
+    stakeMint,
+    liquidityPoolPDA,
+    undefined,
+    TOKEN_PROGRAM_ID,
+    ASSOCIATED_TOKEN_PROGRAM_ID,
+    true
+  );
+  await mintTo(
+    provider.connection,
+    mintAuthority,
+    stakeMint,
+    userStakeAccount,
+    mintAuthority.publicKey,
+    1_000_000_000
+  );
+
   const userStatePDA = await getUserStateAddress(liquidityPoolPDA);
   
   const tx = await program.methods
@@ -340,9 +562,9 @@ it("Stake Tokens", async () => {
       liquidityPool: liquidityPoolPDA,
       userState: userStatePDA,
       user: wallet.publicKey,
-      userTokenAccount: userTokenXAccount,
-      poolTokenAccount: poolTokenXAccount,
-      tokenMint: tokenXMint,
+      userTokenAccount: userStakeAccount,
+      poolTokenAccount: poolStakeAccount,
+      tokenMint: stakeMint,
       tokenXMint,
       tokenYMint,
       tokenProgram: TOKEN_PROGRAM_ID,
@@ -365,9 +587,9 @@ it("Unstake Tokens", async () => {
       liquidityPool: liquidityPoolPDA,
       userState: userStatePDA,
       user: wallet.publicKey,
-      userTokenAccount: userTokenXAccount,
-      poolTokenAccount: poolTokenXAccount,
-      tokenMint: tokenXMint,
+      userTokenAccount: userStakeAccount,
+      poolTokenAccount: poolStakeAccount,
+      tokenMint: stakeMint,
       tokenXMint,
       tokenYMint,
       tokenProgram: TOKEN_PROGRAM_ID,
@@ -387,7 +609,11 @@ it("Edge Case: Attempt max possible swap", async () => {
           tokenXMint,
           tokenYMint,
           maxAmount,
-          new anchor.BN(0)
+          new anchor.BN(0),
+          null,
+          null,
+          new anchor.BN(0),
+          0
         )
         .accountsStrict({
           liquidityPool: liquidityPoolPDA,
@@ -396,12 +622,16 @@ it("Edge Case: Attempt max possible swap", async () => {
           userTokenOut: userTokenYAccount,
           poolTokenX: poolTokenXAccount,
           poolTokenY: poolTokenYAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenXProgram: TOKEN_PROGRAM_ID,
+          tokenYProgram: TOKEN_PROGRAM_ID,
           tokenXMint: tokenXMint,
           tokenYMint: tokenYMint,
           systemProgram: SystemProgram.programId,
           nativeMint: NATIVE_MINT,
-          wsolAccount: null
+          tempWsolAccount: null,
+          callbackProgram: null,
+          twamm: null,
+          referrerToken: null
       })
       .rpc();
       assert(false, "Expected transaction to fail");
@@ -410,6 +640,248 @@ it("Edge Case: Attempt max possible swap", async () => {
   }
 });
 
+it("Edge Case: Flash swap without callback program", async () => {
+  console.log("\n=== Testing Edge Case: Flash Swap Without Callback ===");
+
+  try {
+    await program.methods
+      .swapTokens(
+        tokenXMint,
+        tokenYMint,
+        new anchor.BN(1_000_000),
+        new anchor.BN(1),
+        Buffer.from([]),
+        null,
+        new anchor.BN(0),
+        0
+      )
+      .accountsStrict({
+        liquidityPool: liquidityPoolPDA,
+        user: wallet.publicKey,
+        userTokenIn: userTokenXAccount,
+        userTokenOut: userTokenYAccount,
+        poolTokenX: poolTokenXAccount,
+        poolTokenY: poolTokenYAccount,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tokenXMint,
+        tokenYMint,
+        systemProgram: SystemProgram.programId,
+        nativeMint: NATIVE_MINT,
+        tempWsolAccount: null,
+        callbackProgram: null,
+        twamm: null,
+        referrerToken: null
+      })
+      .rpc();
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("MissingCallbackProgram"));
+  }
+});
+
+it("Edge Case: Reject swapped pool vaults", async () => {
+  console.log("\n=== Testing Edge Case: Swapped Pool Vaults ===");
+
+  try {
+    await program.methods
+      .swapTokens(tokenXMint, tokenYMint, new anchor.BN(1_000_000), new anchor.BN(1), null, null, new anchor.BN(0), 0)
+      .accountsStrict({
+        liquidityPool: liquidityPoolPDA,
+        user: wallet.publicKey,
+        userTokenIn: userTokenXAccount,
+        userTokenOut: userTokenYAccount,
+        poolTokenX: poolTokenYAccount,
+        poolTokenY: poolTokenXAccount,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tokenXMint,
+        tokenYMint,
+        systemProgram: SystemProgram.programId,
+        nativeMint: NATIVE_MINT,
+        tempWsolAccount: null,
+        callbackProgram: null,
+        twamm: null,
+        referrerToken: null
+      })
+      .rpc();
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("InvalidPoolVault"));
+  }
+});
+
+it("Edge Case: Reject a swap past its deadline", async () => {
+  console.log("\n=== Testing Edge Case: Expired Swap ===");
+  const deadline = new anchor.BN(Math.floor(Date.now() / 1000) - 3600);
+
+  try {
+    await program.methods
+      .swapTokens(tokenXMint, tokenYMint, new anchor.BN(1_000_000), new anchor.BN(1), null, deadline, new anchor.BN(0), 0)
+      .accountsStrict({
+        liquidityPool: liquidityPoolPDA,
+        user: wallet.publicKey,
+        userTokenIn: userTokenXAccount,
+        userTokenOut: userTokenYAccount,
+        poolTokenX: poolTokenXAccount,
+        poolTokenY: poolTokenYAccount,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tokenXMint,
+        tokenYMint,
+        systemProgram: SystemProgram.programId,
+        nativeMint: NATIVE_MINT,
+        tempWsolAccount: null,
+        callbackProgram: null,
+        twamm: null,
+        referrerToken: null
+      })
+      .rpc();
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("Expired"));
+  }
+});
+
+it("Sync and Skim donated tokens", async () => {
+  console.log("\n=== Sync and Skim ===");
+  const donation = 1_000_000;
+
+  await mintTo(
+    provider.connection,
+    mintAuthority,
+    tokenYMint,
+    poolTokenYAccount,
+    mintAuthority.publicKey,
+    donation
+  );
+  const before = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+  const userYBefore = await getAccount(provider.connection, userTokenYAccount);
+
+  const skimTx = await program.methods
+    .skim()
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      user: wallet.publicKey,
+      poolTokenXAccount,
+      poolTokenYAccount,
+      recipientTokenXAccount: userTokenXAccount,
+      recipientTokenYAccount: userTokenYAccount,
+      tokenXMint,
+      tokenYMint,
+      tokenXProgram: TOKEN_PROGRAM_ID,
+      tokenYProgram: TOKEN_PROGRAM_ID,
+    })
+    .rpc();
+  console.log("Skim TX:", skimTx);
+
+  const userYAfter = await getAccount(provider.connection, userTokenYAccount);
+  assert.equal(Number(userYAfter.amount - userYBefore.amount), donation);
+
+  await mintTo(
+    provider.connection,
+    mintAuthority,
+    tokenYMint,
+    poolTokenYAccount,
+    mintAuthority.publicKey,
+    donation
+  );
+  const syncTx = await program.methods
+    .sync()
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      user: wallet.publicKey,
+      poolTokenXAccount,
+      poolTokenYAccount,
+    })
+    .rpc();
+  console.log("Sync TX:", syncTx);
+
+  const after = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+  assert.equal(after.tokenYReserve.sub(before.tokenYReserve).toNumber(), donation);
+});
+
+it("Edge Case: Reject unsupported Token-2022 mint extensions", async () => {
+  console.log("\n=== Testing Edge Case: Non-Transferable Mint ===");
+
+  const mintKeypair = Keypair.generate();
+  const mintLen = getMintLen([ExtensionType.NonTransferable]);
+  const lamports = await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+  await provider.sendAndConfirm(
+    new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: wallet.publicKey,
+        newAccountPubkey: mintKeypair.publicKey,
+        space: mintLen,
+        lamports,
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      createInitializeNonTransferableMintInstruction(mintKeypair.publicKey, TOKEN_2022_PROGRAM_ID),
+      createInitializeMintInstruction(mintKeypair.publicKey, 9, wallet.publicKey, null, TOKEN_2022_PROGRAM_ID)
+    ),
+    [mintKeypair]
+  );
+
+  const badMintIsX = Buffer.compare(mintKeypair.publicKey.toBuffer(), tokenYMint.toBuffer()) < 0;
+  const [badMintX, badMintY] = badMintIsX
+    ? [mintKeypair.publicKey, tokenYMint]
+    : [tokenYMint, mintKeypair.publicKey];
+  const [badPoolPDA] = getPoolAddress(badMintX, badMintY, FEE_RATE);
+
+  try {
+    await program.methods
+      .initializePool(badMintX, badMintY, FEE_RATE)
+      .accountsStrict({
+        liquidityPool: badPoolPDA,
+        ...(await getPoolRegistryAddresses()),
+        payer: wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+        tokenXProgram: badMintIsX ? TOKEN_2022_PROGRAM_ID : TOKEN_PROGRAM_ID,
+        tokenYProgram: badMintIsX ? TOKEN_PROGRAM_ID : TOKEN_2022_PROGRAM_ID,
+        tokenXMint: badMintX,
+        tokenYMint: badMintY,
+        poolTokenXAccount,
+        poolTokenYAccount,
+        protocolWallet: protocolWallet.publicKey,
+      })
+      .rpc();
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("UnsupportedMintExtension"));
+  }
+});
+
+it("Edge Case: Reject non-canonical mint order", async () => {
+  console.log("\n=== Testing Edge Case: Reversed Mint Order ===");
+  const [reversedPoolPDA] = getPoolAddress(tokenYMint, tokenXMint, FEE_RATE);
+
+  try {
+    await program.methods
+      .initializePool(tokenYMint, tokenXMint, FEE_RATE)
+      .accountsStrict({
+        liquidityPool: reversedPoolPDA,
+        ...(await getPoolRegistryAddresses()),
+        payer: wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tokenXMint: tokenYMint,
+        tokenYMint: tokenXMint,
+        poolTokenXAccount: poolTokenYAccount,
+        poolTokenYAccount: poolTokenXAccount,
+        protocolWallet: protocolWallet.publicKey,
+      })
+      .rpc();
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("InvalidMintOrder"));
+  }
+
+  const { poolRegistryPage } = await getPoolRegistryAddresses();
+  const page = await program.account.poolRegistryPage.fetch(poolRegistryPage);
+  assert(page.pools.some(pool => pool.equals(liquidityPoolPDA)), "Pool should be registered");
+});
+
 it("Multiple Operations Sequence", async () => {
   console.log("\n=== Testing Operation Sequence ===");
   
@@ -426,7 +898,8 @@ it("Multiple Operations Sequence", async () => {
       tokenXMint,
       tokenYMint,
       amount,
-      amountY
+      amountY,
+      null
     )
     .accountsStrict({
       tokenXMint,
@@ -437,7 +910,11 @@ it("Multiple Operations Sequence", async () => {
       userTokenYAccount,
       poolTokenXAccount,
       poolTokenYAccount,
-      tokenProgram: TOKEN_PROGRAM_ID
+      tokenXProgram: TOKEN_PROGRAM_ID,
+      tokenYProgram: TOKEN_PROGRAM_ID,
+      tempWsolAccount: null,
+      systemProgram: SystemProgram.programId,
+      twamm: null
     })
     .rpc();
   
@@ -445,6 +922,53 @@ it("Multiple Operations Sequence", async () => {
 });
 
 
+it("Flash Loan", async () => {
+  console.log("\n=== Testing Flash Loan ===");
+  const borrowAmount = new anchor.BN(1_000_000);
+
+  const flashAccounts = {
+    liquidityPool: liquidityPoolPDA,
+    user: wallet.publicKey,
+    userTokenXAccount,
+    userTokenYAccount,
+    poolTokenXAccount,
+    poolTokenYAccount,
+    tokenXMint,
+    tokenYMint,
+    tokenXProgram: TOKEN_PROGRAM_ID,
+    tokenYProgram: TOKEN_PROGRAM_ID,
+  };
+
+  const poolBefore = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+
+  const borrowIx = await program.methods
+    .flashBorrow(borrowAmount, new anchor.BN(0))
+    .accountsStrict({
+      ...flashAccounts,
+      instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY,
+    })
+    .instruction();
+  const repayIx = await program.methods
+    .flashRepay()
+    .accountsStrict(flashAccounts)
+    .instruction();
+
+  const tx = await provider.sendAndConfirm(new Transaction().add(borrowIx, repayIx));
+  console.log("Flash Loan TX:", tx);
+
+  const poolAfter = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+  assert(poolAfter.tokenXReserve.gt(poolBefore.tokenXReserve), "Flash loan fee should accrue to the pool");
+  assert(!poolAfter.flashLoanActive, "Flash loan should be settled");
+
+  // Borrowing without a matching repay must fail
+  try {
+    await provider.sendAndConfirm(new Transaction().add(borrowIx));
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("MissingFlashRepay") || e.logs?.some((log: string) => log.includes("MissingFlashRepay")));
+  }
+});
+
 it("Admin Management", async () => {
   console.log("\n=== Testing Admin Management ===");
   
@@ -486,5 +1010,303 @@ it("Admin Management", async () => {
     "Removed admin should not be in the admins list");
 });
 
+it("Pool Config", async () => {
+  console.log("\n=== Testing Pool Config ===");
+  const accounts = { liquidityPool: liquidityPoolPDA, authority: wallet.publicKey };
+
+  await program.methods.setSwapFee(new anchor.BN(20)).accountsStrict(accounts).rpc();
+  await program.methods.setRewardRate(new anchor.BN(5)).accountsStrict(accounts).rpc();
+  await program.methods.setPaused(true).accountsStrict(accounts).rpc();
+
+  const pool = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+  assert(pool.swapFeeRate.eqn(20), "Swap fee should be updated");
+  assert(pool.rewardRate.eqn(5), "Reward rate should be updated");
+  assert(pool.paused, "Pool should be paused");
+  console.log("✓ Updated pool config");
+
+  await program.methods.setPaused(false).accountsStrict(accounts).rpc();
+  await program.methods.setSwapFee(new anchor.BN(25)).accountsStrict(accounts).rpc();
+  const restored = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+  assert(!restored.paused && restored.swapFeeRate.eqn(25), "Pool config should be restored");
+  console.log("✓ Restored pool config");
+});
+
+it("Swap Guardrails", async () => {
+  console.log("\n=== Testing Swap Guardrails ===");
+  const accounts = { liquidityPool: liquidityPoolPDA, authority: wallet.publicKey };
+  const pool = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+
+  // At most 1% of the input reserve per swap
+  await program.methods
+    .setSwapGuardrails(new anchor.BN(100), new anchor.BN(0), new anchor.BN(0))
+    .accountsStrict(accounts)
+    .rpc();
+
+  try {
+    await program.methods
+      .swapTokens(tokenXMint, tokenYMint, pool.tokenXReserve.divn(50), new anchor.BN(1), null, null, new anchor.BN(0), 0)
+      .accountsStrict({
+        liquidityPool: liquidityPoolPDA,
+        user: wallet.publicKey,
+        userTokenIn: userTokenXAccount,
+        userTokenOut: userTokenYAccount,
+        poolTokenX: poolTokenXAccount,
+        poolTokenY: poolTokenYAccount,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tokenXMint,
+        tokenYMint,
+        systemProgram: SystemProgram.programId,
+        nativeMint: NATIVE_MINT,
+        tempWsolAccount: null,
+        callbackProgram: null,
+        twamm: null,
+        referrerToken: null
+      })
+      .rpc();
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("SwapTooLarge"));
+  }
+
+  await program.methods
+    .setSwapGuardrails(new anchor.BN(0), new anchor.BN(0), new anchor.BN(0))
+    .accountsStrict(accounts)
+    .rpc();
+  console.log("✓ Guardrails rejected an oversized swap");
+});
+
+it("Limit Orders", async () => {
+  console.log("\n=== Testing Limit Orders ===");
+  const orderId = new anchor.BN(1);
+  const [limitOrder] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("limit_order"),
+      liquidityPoolPDA.toBuffer(),
+      wallet.publicKey.toBuffer(),
+      orderId.toArrayLike(Buffer, "le", 8)
+    ],
+    program.programId
+  );
+  const escrow = await getAssociatedTokenAddress(tokenXMint, limitOrder, true);
+  const balanceBefore = (await getAccount(provider.connection, userTokenXAccount)).amount;
+
+  // A limit no pool price will reach
+  await program.methods
+    .placeLimitOrder(orderId, new anchor.BN(1_000_000), new anchor.BN("1000000000000000"), null)
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      limitOrder,
+      owner: wallet.publicKey,
+      ownerTokenIn: userTokenXAccount,
+      escrow,
+      inputMint: tokenXMint,
+      inputTokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    })
+    .rpc();
+  assert.equal((await getAccount(provider.connection, escrow)).amount.toString(), "1000000");
+
+  try {
+    await program.methods
+      .fillLimitOrder()
+      .accountsStrict({
+        liquidityPool: liquidityPoolPDA,
+        limitOrder,
+        owner: wallet.publicKey,
+        ownerTokenOut: userTokenYAccount,
+        escrow,
+        keeper: wallet.publicKey,
+        keeperTokenIn: userTokenXAccount,
+        poolTokenX: poolTokenXAccount,
+        poolTokenY: poolTokenYAccount,
+        tokenXMint,
+        tokenYMint,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        twamm: null
+      })
+      .rpc();
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("LimitPriceNotReached"));
+  }
+
+  await program.methods
+    .cancelLimitOrder()
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      limitOrder,
+      owner: wallet.publicKey,
+      ownerTokenIn: userTokenXAccount,
+      escrow,
+      inputMint: tokenXMint,
+      inputTokenProgram: TOKEN_PROGRAM_ID
+    })
+    .rpc();
+  assert.equal((await getAccount(provider.connection, userTokenXAccount)).amount, balanceBefore);
+  assert.isNull(await program.account.limitOrder.fetchNullable(limitOrder));
+  console.log("✓ Limit order escrowed, refused below its limit and cancelled");
+});
+
+it("DCA Vaults", async () => {
+  console.log("\n=== Testing DCA Vaults ===");
+  const vaultId = new anchor.BN(1);
+  const [dcaVault] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("dca_vault"),
+      liquidityPoolPDA.toBuffer(),
+      wallet.publicKey.toBuffer(),
+      vaultId.toArrayLike(Buffer, "le", 8)
+    ],
+    program.programId
+  );
+  const inputVault = await getAssociatedTokenAddress(tokenXMint, dcaVault, true);
+  const outputVault = await getAssociatedTokenAddress(tokenYMint, dcaVault, true);
+  const balanceBefore = (await getAccount(provider.connection, userTokenXAccount)).amount;
+
+  // Buy Y with 1 X every hour, accepting up to 3% below the hourly TWAP
+  await program.methods
+    .openDcaVault(vaultId, new anchor.BN(3_000_000), new anchor.BN(1_000_000), new anchor.BN(3_600), new anchor.BN(300))
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      dcaVault,
+      owner: wallet.publicKey,
+      ownerTokenIn: userTokenXAccount,
+      inputVault,
+      outputVault,
+      inputMint: tokenXMint,
+      outputMint: tokenYMint,
+      inputTokenProgram: TOKEN_PROGRAM_ID,
+      outputTokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    })
+    .rpc();
+  assert.equal((await getAccount(provider.connection, inputVault)).amount.toString(), "3000000");
+
+  try {
+    await program.methods
+      .executeDca()
+      .accountsStrict({
+        liquidityPool: liquidityPoolPDA,
+        dcaVault,
+        inputVault,
+        outputVault,
+        keeper: wallet.publicKey,
+        keeperTokenIn: userTokenXAccount,
+        poolTokenX: poolTokenXAccount,
+        poolTokenY: poolTokenYAccount,
+        tokenXMint,
+        tokenYMint,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        twamm: null
+      })
+      .rpc();
+    assert(false, "Expected transaction to fail");
+  } catch (e) {
+    assert(e.message.includes("DcaExecutionNotDue"));
+  }
+
+  await program.methods
+    .closeDcaVault()
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      dcaVault,
+      owner: wallet.publicKey,
+      ownerTokenIn: userTokenXAccount,
+      ownerTokenOut: userTokenYAccount,
+      inputVault,
+      outputVault,
+      inputMint: tokenXMint,
+      outputMint: tokenYMint,
+      inputTokenProgram: TOKEN_PROGRAM_ID,
+      outputTokenProgram: TOKEN_PROGRAM_ID
+    })
+    .rpc();
+  assert.equal((await getAccount(provider.connection, userTokenXAccount)).amount, balanceBefore);
+  assert.isNull(await program.account.dcaVault.fetchNullable(dcaVault));
+  console.log("✓ DCA vault opened, refused an early execution and closed");
+});
+
+// Runs last: once TWAMM is enabled every swap and liquidity change must pass its state
+it("Long-Term Orders", async () => {
+  console.log("\n=== Testing Long-Term Orders ===");
+  const [twamm] = PublicKey.findProgramAddressSync(
+    [Buffer.from("twamm"), liquidityPoolPDA.toBuffer()],
+    program.programId
+  );
+  await program.methods
+    .initializeTwamm()
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      twamm,
+      authority: wallet.publicKey,
+      systemProgram: SystemProgram.programId
+    })
+    .rpc();
+  assert.isTrue((await program.account.liquidityPool.fetch(liquidityPoolPDA)).twammEnabled);
+
+  const orderId = new anchor.BN(1);
+  const [longTermOrder] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("long_term_order"),
+      liquidityPoolPDA.toBuffer(),
+      wallet.publicKey.toBuffer(),
+      orderId.toArrayLike(Buffer, "le", 8)
+    ],
+    program.programId
+  );
+  const amountIn = 1_000_000;
+  const balanceBefore = (await getAccount(provider.connection, userTokenXAccount)).amount;
+
+  await program.methods
+    .placeLongTermOrder(orderId, new anchor.BN(amountIn), new anchor.BN(1))
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      twamm,
+      longTermOrder,
+      owner: wallet.publicKey,
+      ownerTokenIn: userTokenXAccount,
+      poolTokenIn: poolTokenXAccount,
+      inputMint: tokenXMint,
+      inputTokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId
+    })
+    .rpc();
+  const order = await program.account.longTermOrder.fetch(longTermOrder);
+  assert(order.outputMint.equals(tokenYMint));
+  assert.equal(order.expiry.toNumber() % 14_400, 0);
+  const pool = await program.account.liquidityPool.fetch(liquidityPoolPDA);
+  assert.equal(pool.twammTokenXBalance.toNumber(), amountIn);
+
+  // Cancelling right away refunds almost everything; the rest was sold for Y
+  await program.methods
+    .cancelLongTermOrder()
+    .accountsStrict({
+      liquidityPool: liquidityPoolPDA,
+      twamm,
+      longTermOrder,
+      user: wallet.publicKey,
+      owner: wallet.publicKey,
+      ownerTokenIn: userTokenXAccount,
+      ownerTokenOut: userTokenYAccount,
+      poolTokenX: poolTokenXAccount,
+      poolTokenY: poolTokenYAccount,
+      tokenXMint,
+      tokenYMint,
+      tokenXProgram: TOKEN_PROGRAM_ID,
+      tokenYProgram: TOKEN_PROGRAM_ID
+    })
+    .rpc();
+  const balanceAfter = (await getAccount(provider.connection, userTokenXAccount)).amount;
+  assert(balanceAfter <= balanceBefore);
+  assert(balanceAfter > balanceBefore - BigInt(amountIn / 2));
+  assert.isNull(await program.account.longTermOrder.fetchNullable(longTermOrder));
+  console.log("✓ Long-term order placed and cancelled with its unsold input refunded");
+});
+
 
 });
\ No newline at end of file
@@ -32,6 +32,28 @@ describe("Soondex DEX", () => {
     return userStatePDA;
 };
 
+  const FEE_RATE = new anchor.BN(25);
+
+  const getPoolAddress = (mintX: PublicKey, mintY: PublicKey, feeRate: anchor.BN): [PublicKey, number] =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("pool"), mintX.toBuffer(), mintY.toBuffer(), feeRate.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+
+  const getPoolRegistryAddresses = async (): Promise<{ poolRegistry: PublicKey; poolRegistryPage: PublicKey }> => {
+    const [poolRegistry] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_registry")],
+      program.programId
+    );
+    const registry = await program.account.poolRegistry.fetchNullable(poolRegistry);
+    const page = registry ? registry.poolCount.divn(100) : new anchor.BN(0);
+    const [poolRegistryPage] = PublicKey.findProgramAddressSync(
+      [Buffer.from("pool_registry"), page.toArrayLike(Buffer, "le", 8)],
+      program.programId
+    );
+    return { poolRegistry, poolRegistryPage };
+  };
+
   let tokenXMint: PublicKey;
   let tokenYMint: PublicKey;
   let liquidityPoolPDA: PublicKey;
@@ -70,15 +92,16 @@ describe("Soondex DEX", () => {
       null,
       9
     );
+    // Pools require canonical mint ordering
+    if (Buffer.compare(tokenXMint.toBuffer(), tokenYMint.toBuffer()) > 0) {
+      [tokenXMint, tokenYMint] = [tokenYMint, tokenXMint];
+    }
     console.log("✓ Created token mints:", {
       tokenX: tokenXMint.toString(),
       tokenY: tokenYMint.toString()
     });
 
-    [liquidityPoolPDA, bump] = PublicKey.findProgramAddressSync(
-      [Buffer.from("pool"), tokenXMint.toBuffer(), tokenYMint.toBuffer()],
-      program.programId
-    );
+    [liquidityPoolPDA, bump] = getPoolAddress(tokenXMint, tokenYMint, FEE_RATE);
     console.log("✓ Generated liquidity pool PDA:", liquidityPoolPDA.toString());
 
     poolTokenXAccount = await getAssociatedTokenAddress(tokenXMint, liquidityPoolPDA, true);
@@ -142,15 +165,16 @@ describe("Soondex DEX", () => {
       .initializePool(
         tokenXMint,
         tokenYMint,
-        new anchor.BN(25),
+        FEE_RATE,
 
       )
       .accountsStrict({
         liquidityPool: liquidityPoolPDA,
+        ...(await getPoolRegistryAddresses()),
         payer: wallet.publicKey,
         systemProgram: SystemProgram.programId,
-        tokenProgram: TOKEN_PROGRAM_ID,
-        associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
         tokenXMint,
         tokenYMint,
         poolTokenXAccount,
@@ -198,7 +222,11 @@ describe("Soondex DEX", () => {
         userTokenYAccount: userTokenYAccount,
         poolTokenXAccount: poolTokenXAccount,
         poolTokenYAccount: poolTokenYAccount,
-        tokenProgram: TOKEN_PROGRAM_ID
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
+        tempWsolAccount: null,
+        systemProgram: SystemProgram.programId,
+        twamm: null
       })
       .rpc();
     
@@ -221,7 +249,11 @@ describe("Soondex DEX", () => {
         params.inputToken,
         params.outputToken,
         params.amountIn,
-        params.minimumAmountOut
+        params.minimumAmountOut,
+        null,
+        null,
+        new anchor.BN(0),
+        0
       )
       .accountsStrict({
         liquidityPool: liquidityPoolPDA,
@@ -230,12 +262,16 @@ describe("Soondex DEX", () => {
         userTokenOut: userTokenYAccount,
         poolTokenX: poolTokenXAccount,
         poolTokenY: poolTokenYAccount,
-        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenXProgram: TOKEN_PROGRAM_ID,
+        tokenYProgram: TOKEN_PROGRAM_ID,
         tokenXMint,
         tokenYMint,
-        wsolAccount: null,
+        tempWsolAccount: null,
         systemProgram: SystemProgram.programId,
-        nativeMint: NATIVE_MINT
+        nativeMint: NATIVE_MINT,
+        callbackProgram: null,
+        twamm: null,
+        referrerToken: null
       })
       .signers([wallet.payer])
       .rpc();
@@ -263,7 +299,11 @@ it("SOL/Token Swap", async () => {
       NATIVE_MINT,
       tokenYMint,
       solAmount,
-      minTokenOut
+      minTokenOut,
+      null,
+      null,
+      new anchor.BN(0),
+      0
     )
     .accountsStrict({
       liquidityPool: liquidityPoolPDA,
@@ -272,12 +312,16 @@ it("SOL/Token Swap", async () => {
       userTokenOut: userTokenYAccount,
       poolTokenX: poolTokenXAccount,
       poolTokenY: poolTokenYAccount,
-      tokenProgram: TOKEN_PROGRAM_ID,
+      tokenXProgram: TOKEN_PROGRAM_ID,
+      tokenYProgram: TOKEN_PROGRAM_ID,
       tokenXMint,
       tokenYMint,
-      wsolAccount: null,
+      tempWsolAccount: null,
       systemProgram: SystemProgram.programId,
-      nativeMint: NATIVE_MINT
+      nativeMint: NATIVE_MINT,
+      callbackProgram: null,
+      twamm: null,
+      referrerToken: null
     })
 
   // Get final balances
@@ -312,9 +356,13 @@ it("Remove Liquidity", async () => {
       userTokenYAccount,
       poolTokenXAccount,
       poolTokenYAccount,
-      tokenProgram: TOKEN_PROGRAM_ID,
+      tokenXProgram: TOKEN_PROGRAM_ID,
+      tokenYProgram: TOKEN_PROGRAM_ID,
       tokenXMint,
-      tokenYMint
+      tokenYMint,
+      tempWsolAccount: null,
+      systemProgram: SystemProgram.programId,
+      twamm: null
     })
     .rpc();
   
@@ -380,7 +428,11 @@ it("Edge Case: Attempt max possible swap", async () => {
           tokenXMint,
           tokenYMint,
           maxAmount,
-          new anchor.BN(0)
+          new anchor.BN(0),
+          null,
+          null,
+          new anchor.BN(0),
+          0
         )
         .accountsStrict({
           liquidityPool: liquidityPoolPDA,
@@ -389,12 +441,16 @@ it("Edge Case: Attempt max possible swap", async () => {
           userTokenOut: userTokenYAccount,
           poolTokenX: poolTokenXAccount,
           poolTokenY: poolTokenYAccount,
-          tokenProgram: TOKEN_PROGRAM_ID,
+          tokenXProgram: TOKEN_PROGRAM_ID,
+          tokenYProgram: TOKEN_PROGRAM_ID,
           tokenXMint: tokenXMint,
           tokenYMint: tokenYMint,
           systemProgram: SystemProgram.programId,
           nativeMint: NATIVE_MINT,
-          wsolAccount: null
+          tempWsolAccount: null,
+          callbackProgram: null,
+          twamm: null,
+          referrerToken: null
       })
       .rpc();
       assert(false, "Expected transaction to fail");
@@ -430,7 +486,11 @@ it("Multiple Operations Sequence", async () => {
       userTokenYAccount,
       poolTokenXAccount,
       poolTokenYAccount,
-      tokenProgram: TOKEN_PROGRAM_ID
+      tokenXProgram: TOKEN_PROGRAM_ID,
+      tokenYProgram: TOKEN_PROGRAM_ID,
+      tempWsolAccount: null,
+      systemProgram: SystemProgram.programId,
+      twamm: null
     })
     .rpc();
   